@@ -1 +1,30 @@
-pub trait IAssembler {}
+use super::types::Bytecode;
+
+pub trait Assemble<T: Bytecode> {
+    type Error;
+    fn assemble(self) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// Run `write` on a buffer that is grown until everything it writes fits,
+/// returning the bytes that were written.
+///
+/// Scroll only writes into slices, so whatever is written has to be written again
+/// into a larger buffer when it runs out of space.
+pub fn write_to_vec<F>(mut write: F) -> Result<Vec<u8>, scroll::Error>
+where
+    F: FnMut(&mut [u8]) -> Result<usize, scroll::Error>,
+{
+    let mut bytes = vec![0; 1024];
+    loop {
+        match write(&mut bytes) {
+            Ok(size) => {
+                bytes.truncate(size);
+                return Ok(bytes);
+            }
+            Err(scroll::Error::TooBig { .. } | scroll::Error::BadOffset(_)) => {
+                bytes.resize(bytes.len() * 2, 0);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
@@ -0,0 +1,731 @@
+//! A syntax tree of Lua source, which the decompilers build and [`write_chunk`] prints.
+//!
+//! The tree covers every version, constructs a version lacks are never built for it.
+
+use std::fmt::{self, Write};
+
+use crate::listing::Quoted;
+
+/// The statements of a block, in order.
+pub type Block = Vec<Statement>;
+
+/// Words that can't be used as names.
+const KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Precedence of unary operators, only `^` binds tighter.
+const UNARY_PRECEDENCE: u8 = 11;
+
+/// Precedence of expressions that never need parentheses.
+const ATOM_PRECEDENCE: u8 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Nil,
+    Boolean(bool),
+    /// A number of a version without integers, written as an integer when it is integral
+    Number(f64),
+    Integer(i64),
+    Float(f64),
+    String(Vec<u8>),
+    VarArg,
+    /// A local, an upvalue or a global
+    Name(String),
+    Index(Box<Expression>, Box<Expression>),
+    Call(Box<Call>),
+    Function(Box<Function>),
+    Table(Vec<Field>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    /// Parentheses, truncating a call or `...` to its first value
+    Paren(Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Ne,
+    Eq,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Concat,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IDiv,
+    Mod,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Not,
+    Neg,
+    Len,
+    BitNot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub function: Expression,
+    /// The method called on `function`, as `object:method(...)` does
+    pub method: Option<String>,
+    pub arguments: Vec<Expression>,
+}
+
+/// A field of a table constructor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Positional(Expression),
+    Named(Expression, Expression),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub parameters: Vec<String>,
+    pub is_vararg: bool,
+    pub body: Block,
+}
+
+/// The attribute of a local variable, since Lua 5.4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute {
+    Const,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalName {
+    pub name: String,
+    pub attribute: Option<Attribute>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Local {
+        names: Vec<LocalName>,
+        values: Vec<Expression>,
+    },
+    LocalFunction {
+        name: String,
+        function: Function,
+    },
+    Assign {
+        targets: Vec<Expression>,
+        values: Vec<Expression>,
+    },
+    Call(Call),
+    If {
+        condition: Expression,
+        then: Block,
+        otherwise: Block,
+    },
+    While {
+        condition: Expression,
+        body: Block,
+    },
+    Repeat {
+        body: Block,
+        condition: Expression,
+    },
+    NumericFor {
+        variable: String,
+        start: Expression,
+        limit: Expression,
+        step: Option<Expression>,
+        body: Block,
+    },
+    GenericFor {
+        variables: Vec<String>,
+        values: Vec<Expression>,
+        body: Block,
+    },
+    Return(Vec<Expression>),
+    Break,
+    Goto(String),
+    Label(String),
+    /// A note about code that couldn't be decompiled
+    Comment(String),
+}
+
+impl BinaryOperator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOperator::Or => "or",
+            BinaryOperator::And => "and",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::Ne => "~=",
+            BinaryOperator::Eq => "==",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "~",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::Shl => "<<",
+            BinaryOperator::Shr => ">>",
+            BinaryOperator::Concat => "..",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::IDiv => "//",
+            BinaryOperator::Mod => "%",
+            BinaryOperator::Pow => "^",
+        }
+    }
+
+    /// Returns how tightly the operator binds, as the Lua 5.4 manual orders them.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Lt
+            | BinaryOperator::Gt
+            | BinaryOperator::Le
+            | BinaryOperator::Ge
+            | BinaryOperator::Ne
+            | BinaryOperator::Eq => 3,
+            BinaryOperator::BitOr => 4,
+            BinaryOperator::BitXor => 5,
+            BinaryOperator::BitAnd => 6,
+            BinaryOperator::Shl | BinaryOperator::Shr => 7,
+            BinaryOperator::Concat => 8,
+            BinaryOperator::Add | BinaryOperator::Sub => 9,
+            BinaryOperator::Mul
+            | BinaryOperator::Div
+            | BinaryOperator::IDiv
+            | BinaryOperator::Mod => 10,
+            BinaryOperator::Pow => 12,
+        }
+    }
+
+    fn is_right_associative(self) -> bool {
+        matches!(self, BinaryOperator::Concat | BinaryOperator::Pow)
+    }
+}
+
+impl UnaryOperator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOperator::Not => "not ",
+            UnaryOperator::Neg => "-",
+            UnaryOperator::Len => "#",
+            UnaryOperator::BitNot => "~",
+        }
+    }
+}
+
+impl Expression {
+    pub fn binary(operator: BinaryOperator, left: Expression, right: Expression) -> Self {
+        Expression::Binary(operator, Box::new(left), Box::new(right))
+    }
+
+    pub fn unary(operator: UnaryOperator, operand: Expression) -> Self {
+        Expression::Unary(operator, Box::new(operand))
+    }
+
+    pub fn index(object: Expression, key: Expression) -> Self {
+        Expression::Index(Box::new(object), Box::new(key))
+    }
+
+    /// Returns the negation of a condition, which is only used for its truth.
+    ///
+    /// Double negations cancel out and `and`, `or` and `==` are inverted instead of wrapped.
+    pub fn negated(self) -> Self {
+        match self {
+            Expression::Boolean(value) => Expression::Boolean(!value),
+            Expression::Unary(UnaryOperator::Not, operand) => *operand,
+            Expression::Binary(BinaryOperator::Eq, left, right) => {
+                Expression::Binary(BinaryOperator::Ne, left, right)
+            }
+            Expression::Binary(BinaryOperator::Ne, left, right) => {
+                Expression::Binary(BinaryOperator::Eq, left, right)
+            }
+            Expression::Binary(BinaryOperator::And, left, right) => {
+                Expression::binary(BinaryOperator::Or, left.negated(), right.negated())
+            }
+            Expression::Binary(BinaryOperator::Or, left, right) => {
+                Expression::binary(BinaryOperator::And, left.negated(), right.negated())
+            }
+            condition => Expression::unary(UnaryOperator::Not, condition),
+        }
+    }
+
+    /// Returns whether the expression is always truthy, being neither `nil` nor `false`.
+    pub fn is_truthy(&self) -> bool {
+        matches!(
+            self,
+            Expression::Boolean(true)
+                | Expression::Number(_)
+                | Expression::Integer(_)
+                | Expression::Float(_)
+                | Expression::String(_)
+                | Expression::Table(_)
+                | Expression::Function(_)
+        )
+    }
+
+    /// Returns the expression truncated to a single value, as the last one of a list.
+    pub fn truncated(self) -> Self {
+        match self {
+            Expression::Call(_) | Expression::VarArg => Expression::Paren(Box::new(self)),
+            expression => expression,
+        }
+    }
+
+    /// Returns whether evaluating the expression can have an effect, calls being assumed to.
+    pub fn has_effects(&self) -> bool {
+        match self {
+            Expression::Call(_) => true,
+            Expression::Index(object, key) => object.has_effects() || key.has_effects(),
+            Expression::Table(fields) => fields.iter().any(|field| match field {
+                Field::Positional(value) => value.has_effects(),
+                Field::Named(key, value) => key.has_effects() || value.has_effects(),
+            }),
+            Expression::Binary(_, left, right) => left.has_effects() || right.has_effects(),
+            Expression::Unary(_, operand) | Expression::Paren(operand) => operand.has_effects(),
+            _ => false,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::Binary(operator, ..) => operator.precedence(),
+            Expression::Unary(..) => UNARY_PRECEDENCE,
+            // Written as divisions
+            Expression::Number(value) | Expression::Float(value) if !value.is_finite() => 0,
+            Expression::Number(value) | Expression::Float(value) if value.is_sign_negative() => {
+                UNARY_PRECEDENCE
+            }
+            Expression::Integer(value) if *value < 0 => UNARY_PRECEDENCE,
+            _ => ATOM_PRECEDENCE,
+        }
+    }
+
+    /// Returns whether the expression can be indexed or called without parentheses.
+    fn is_prefix(&self) -> bool {
+        matches!(
+            self,
+            Expression::Name(_)
+                | Expression::Index(..)
+                | Expression::Call(_)
+                | Expression::Paren(_)
+        )
+    }
+}
+
+/// Returns whether a string can be written as a name, such as a field after a `.`.
+pub fn is_identifier(name: &[u8]) -> bool {
+    let Some((&first, rest)) = name.split_first() else {
+        return false;
+    };
+
+    (first.is_ascii_alphabetic() || first == b'_')
+        && rest.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'_')
+        && !KEYWORDS.iter().any(|keyword| keyword.as_bytes() == name)
+}
+
+/// Write the body of the main function as a chunk.
+pub fn write_chunk(f: &mut impl Write, body: &Block) -> fmt::Result {
+    Printer { f, indent: 0 }.block(body)
+}
+
+/// Write an expression as source, as comments about it quote it.
+pub fn write_expression(f: &mut impl Write, expression: &Expression) -> fmt::Result {
+    Printer { f, indent: 0 }.expression(expression, 0)
+}
+
+struct Printer<'f, W> {
+    f: &'f mut W,
+    indent: usize,
+}
+
+impl<W: Write> Printer<'_, W> {
+    fn line(&mut self) -> fmt::Result {
+        for _ in 0..self.indent {
+            self.f.write_str("    ")?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &Block) -> fmt::Result {
+        for (index, statement) in block.iter().enumerate() {
+            // Lua only accepts `return` and `break` as the last statement of a block
+            let last = index + 1 == block.len();
+            match statement {
+                Statement::Return(_) | Statement::Break if !last => {
+                    self.line()?;
+                    self.f.write_str("do ")?;
+                    self.terminator(statement)?;
+                    self.f.write_str(" end\n")?;
+                }
+                statement => self.statement(statement)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a `return` or `break`, without ending the line.
+    fn terminator(&mut self, statement: &Statement) -> fmt::Result {
+        match statement {
+            Statement::Return(values) => {
+                self.f.write_str("return")?;
+                if !values.is_empty() {
+                    self.f.write_str(" ")?;
+                    self.list(values)?;
+                }
+                Ok(())
+            }
+            _ => self.f.write_str("break"),
+        }
+    }
+
+    fn nested(&mut self, block: &Block) -> fmt::Result {
+        self.indent += 1;
+        self.block(block)?;
+        self.indent -= 1;
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> fmt::Result {
+        self.line()?;
+        match statement {
+            Statement::Local { names, values } => {
+                self.f.write_str("local ")?;
+                for (index, local) in names.iter().enumerate() {
+                    if index > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    self.f.write_str(&local.name)?;
+                    match local.attribute {
+                        Some(Attribute::Const) => self.f.write_str(" <const>")?,
+                        Some(Attribute::Close) => self.f.write_str(" <close>")?,
+                        None => {}
+                    }
+                }
+                if !values.is_empty() {
+                    self.f.write_str(" = ")?;
+                    self.list(values)?;
+                }
+                self.f.write_str("\n")
+            }
+            Statement::LocalFunction { name, function } => {
+                write!(self.f, "local function {}", name)?;
+                self.function(function, false)?;
+                self.f.write_str("\n")
+            }
+            Statement::Assign { targets, values } => {
+                if let ([target], [Expression::Function(function)]) = (&targets[..], &values[..]) {
+                    if let Some((name, method)) = function_name(target, function) {
+                        write!(self.f, "function {}", name)?;
+                        self.function(function, method)?;
+                        return self.f.write_str("\n");
+                    }
+                }
+
+                self.list(targets)?;
+                self.f.write_str(" = ")?;
+                self.list(values)?;
+                self.f.write_str("\n")
+            }
+            Statement::Call(call) => {
+                // A statement starting with a parenthesis would continue the previous one
+                if !call.function.is_prefix() || matches!(call.function, Expression::Paren(_)) {
+                    self.f.write_str(";")?;
+                }
+                self.call(call)?;
+                self.f.write_str("\n")
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.f.write_str("if ")?;
+                self.expression(condition, 0)?;
+                self.f.write_str(" then\n")?;
+                self.nested(then)?;
+
+                let mut otherwise = otherwise;
+                loop {
+                    match &otherwise[..] {
+                        [] => break,
+                        [Statement::If {
+                            condition,
+                            then,
+                            otherwise: next,
+                        }] => {
+                            self.line()?;
+                            self.f.write_str("elseif ")?;
+                            self.expression(condition, 0)?;
+                            self.f.write_str(" then\n")?;
+                            self.nested(then)?;
+                            otherwise = next;
+                        }
+                        _ => {
+                            self.line()?;
+                            self.f.write_str("else\n")?;
+                            self.nested(otherwise)?;
+                            break;
+                        }
+                    }
+                }
+
+                self.line()?;
+                self.f.write_str("end\n")
+            }
+            Statement::While { condition, body } => {
+                self.f.write_str("while ")?;
+                self.expression(condition, 0)?;
+                self.f.write_str(" do\n")?;
+                self.nested(body)?;
+                self.line()?;
+                self.f.write_str("end\n")
+            }
+            Statement::Repeat { body, condition } => {
+                self.f.write_str("repeat\n")?;
+                self.nested(body)?;
+                self.line()?;
+                self.f.write_str("until ")?;
+                self.expression(condition, 0)?;
+                self.f.write_str("\n")
+            }
+            Statement::NumericFor {
+                variable,
+                start,
+                limit,
+                step,
+                body,
+            } => {
+                write!(self.f, "for {} = ", variable)?;
+                self.expression(start, 0)?;
+                self.f.write_str(", ")?;
+                self.expression(limit, 0)?;
+                if let Some(step) = step {
+                    self.f.write_str(", ")?;
+                    self.expression(step, 0)?;
+                }
+                self.f.write_str(" do\n")?;
+                self.nested(body)?;
+                self.line()?;
+                self.f.write_str("end\n")
+            }
+            Statement::GenericFor {
+                variables,
+                values,
+                body,
+            } => {
+                write!(self.f, "for {} in ", variables.join(", "))?;
+                self.list(values)?;
+                self.f.write_str(" do\n")?;
+                self.nested(body)?;
+                self.line()?;
+                self.f.write_str("end\n")
+            }
+            Statement::Return(_) | Statement::Break => {
+                self.terminator(statement)?;
+                self.f.write_str("\n")
+            }
+            Statement::Goto(label) => writeln!(self.f, "goto {}", label),
+            Statement::Label(label) => writeln!(self.f, "::{}::", label),
+            Statement::Comment(text) => writeln!(self.f, "-- {}", text),
+        }
+    }
+
+    fn list(&mut self, expressions: &[Expression]) -> fmt::Result {
+        for (index, expression) in expressions.iter().enumerate() {
+            if index > 0 {
+                self.f.write_str(", ")?;
+            }
+            self.expression(expression, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Write the parameters and body of a function, leaving `self` out of methods.
+    fn function(&mut self, function: &Function, method: bool) -> fmt::Result {
+        let skip = usize::from(method);
+        let mut parameters: Vec<&str> = function.parameters[skip..]
+            .iter()
+            .map(String::as_str)
+            .collect();
+        if function.is_vararg {
+            parameters.push("...");
+        }
+        writeln!(self.f, "({})", parameters.join(", "))?;
+        self.nested(&function.body)?;
+        self.line()?;
+        self.f.write_str("end")
+    }
+
+    fn call(&mut self, call: &Call) -> fmt::Result {
+        self.prefix(&call.function)?;
+        if let Some(method) = &call.method {
+            write!(self.f, ":{}", method)?;
+        }
+        self.f.write_str("(")?;
+        self.list(&call.arguments)?;
+        self.f.write_str(")")
+    }
+
+    /// Write an expression that is indexed or called.
+    fn prefix(&mut self, expression: &Expression) -> fmt::Result {
+        if expression.is_prefix() {
+            return self.expression(expression, 0);
+        }
+
+        self.f.write_str("(")?;
+        self.expression(expression, 0)?;
+        self.f.write_str(")")
+    }
+
+    /// Write an expression, in parentheses when it binds looser than `precedence`.
+    fn expression(&mut self, expression: &Expression, precedence: u8) -> fmt::Result {
+        if expression.precedence() < precedence {
+            self.f.write_str("(")?;
+            self.expression(expression, 0)?;
+            return self.f.write_str(")");
+        }
+
+        match expression {
+            Expression::Nil => self.f.write_str("nil"),
+            Expression::Boolean(value) => write!(self.f, "{}", value),
+            Expression::Number(value) => self.f.write_str(&number(*value)),
+            Expression::Integer(i64::MIN) => self.f.write_str("math.mininteger"),
+            Expression::Integer(value) => write!(self.f, "{}", value),
+            Expression::Float(value) if value.is_finite() => write!(self.f, "{:?}", value),
+            Expression::Float(value) => self.f.write_str(&number(*value)),
+            Expression::String(bytes) => write!(self.f, "{}", Quoted(bytes)),
+            Expression::VarArg => self.f.write_str("..."),
+            Expression::Name(name) => self.f.write_str(name),
+            Expression::Index(object, key) => {
+                self.prefix(object)?;
+                match &**key {
+                    Expression::String(name) if is_identifier(name) => {
+                        write!(self.f, ".{}", String::from_utf8_lossy(name))
+                    }
+                    key => {
+                        self.f.write_str("[")?;
+                        self.expression(key, 0)?;
+                        self.f.write_str("]")
+                    }
+                }
+            }
+            Expression::Call(call) => self.call(call),
+            Expression::Function(function) => {
+                self.f.write_str("function")?;
+                self.function(function, false)
+            }
+            Expression::Table(fields) => {
+                self.f.write_str("{")?;
+                for (index, field) in fields.iter().enumerate() {
+                    if index > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    match field {
+                        Field::Positional(value) => self.expression(value, 0)?,
+                        Field::Named(Expression::String(name), value) if is_identifier(name) => {
+                            write!(self.f, "{} = ", String::from_utf8_lossy(name))?;
+                            self.expression(value, 0)?;
+                        }
+                        Field::Named(key, value) => {
+                            self.f.write_str("[")?;
+                            self.expression(key, 0)?;
+                            self.f.write_str("] = ")?;
+                            self.expression(value, 0)?;
+                        }
+                    }
+                }
+                self.f.write_str("}")
+            }
+            Expression::Binary(operator, left, right) => {
+                let precedence = operator.precedence();
+                let (left_precedence, right_precedence) = match operator.is_right_associative() {
+                    true => (precedence + 1, precedence),
+                    false => (precedence, precedence + 1),
+                };
+                self.expression(left, left_precedence)?;
+                write!(self.f, " {} ", operator.symbol())?;
+                self.expression(right, right_precedence)
+            }
+            Expression::Unary(operator, operand) => {
+                self.f.write_str(operator.symbol())?;
+                // `- -x` must not become a comment
+                let mut text = String::new();
+                Printer {
+                    f: &mut text,
+                    indent: self.indent,
+                }
+                .expression(operand, UNARY_PRECEDENCE)?;
+                if *operator == UnaryOperator::Neg && text.starts_with('-') {
+                    self.f.write_str(" ")?;
+                }
+                self.f.write_str(&text)
+            }
+            Expression::Paren(inner) => {
+                self.f.write_str("(")?;
+                self.expression(inner, 0)?;
+                self.f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Returns a number as source, non-finite ones being written as divisions.
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "0/0".to_string()
+    } else if value.is_infinite() {
+        match value.is_sign_negative() {
+            true => "-1/0".to_string(),
+            false => "1/0".to_string(),
+        }
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Returns the name a function assigned to a field path is declared with, as
+/// `function a.b:c()` does, and whether it is a method.
+fn function_name(target: &Expression, function: &Function) -> Option<(String, bool)> {
+    let name = path(target)?;
+    let is_method = matches!(target, Expression::Index(..))
+        && function.parameters.first().map(String::as_str) == Some("self");
+    match is_method {
+        true => {
+            let dot = name.rfind('.')?;
+            Some((format!("{}:{}", &name[..dot], &name[dot + 1..]), true))
+        }
+        false => Some((name, false)),
+    }
+}
+
+/// Returns a name followed by fields, like `a.b.c`.
+fn path(expression: &Expression) -> Option<String> {
+    match expression {
+        Expression::Name(name) => Some(name.clone()),
+        Expression::Index(object, key) => match &**key {
+            Expression::String(key) if is_identifier(key) => Some(format!(
+                "{}.{}",
+                path(object)?,
+                String::from_utf8_lossy(key)
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,328 @@
+//! Control flow graphs of prototypes.
+//!
+//! Every version crate has a `cfg` module describing where control goes after each instruction,
+//! [`ControlFlowGraph::build`] turns that into basic blocks.
+
+/// How control reaches the target of an edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Control falls through to the next instruction
+    Fallthrough,
+    /// An unconditional jump
+    Jump,
+    /// The edge followed when the condition tested by a branch holds
+    True,
+    /// The edge followed when the condition tested by a branch doesn't hold
+    False,
+}
+
+/// An edge to an instruction, or to a block in a [`ControlFlowGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+impl Edge {
+    pub fn new(target: usize, kind: EdgeKind) -> Self {
+        Self { target, kind }
+    }
+}
+
+/// Returns the edges of a test, which goes on with the next instruction when its condition
+/// holds and skips it otherwise.
+pub fn skip_next(pc: usize) -> Vec<Edge> {
+    vec![
+        Edge::new(pc + 1, EdgeKind::True),
+        Edge::new(pc + 2, EdgeKind::False),
+    ]
+}
+
+/// A run of instructions only entered at its first one and only left after its last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// The pc of the first instruction
+    pub start: usize,
+    /// The pc after the last instruction
+    pub end: usize,
+    /// The blocks control can go to after this one, an exit having none
+    pub successors: Vec<Edge>,
+    /// The blocks control can come from, in ascending order
+    pub predecessors: Vec<usize>,
+}
+
+impl BasicBlock {
+    /// Returns the pc of the last instruction.
+    pub fn last(&self) -> usize {
+        self.end - 1
+    }
+}
+
+/// A natural loop, the blocks that reach a back edge without going through its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The block every iteration starts at, which dominates the whole loop
+    pub header: usize,
+    /// The blocks jumping back to the header
+    pub latches: Vec<usize>,
+    /// Every block of the loop including the header, in ascending order
+    pub body: Vec<usize>,
+}
+
+/// The basic blocks of a prototype and the edges between them.
+///
+/// Blocks are ordered by their position in the code, the first one being the entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+    /// The immediate dominator of every block
+    dominators: Vec<Option<usize>>,
+}
+
+impl ControlFlowGraph {
+    /// Build the graph of `count` instructions, `successors` returning the edges leaving the
+    /// instruction at a pc.
+    ///
+    /// Edges to a pc outside of the code are dropped.
+    pub fn build(count: usize, successors: impl Fn(usize) -> Vec<Edge>) -> Self {
+        let successors: Vec<Vec<Edge>> = (0..count)
+            .map(|pc| {
+                let mut edges = successors(pc);
+                edges.retain(|edge| edge.target < count);
+                edges
+            })
+            .collect();
+
+        // Blocks start at the entry, at jump targets and after any instruction that doesn't
+        // simply fall through
+        let mut leaders = vec![false; count];
+        if let Some(entry) = leaders.first_mut() {
+            *entry = true;
+        }
+        for (pc, edges) in successors.iter().enumerate() {
+            let falls_through = matches!(
+                edges[..],
+                [Edge { target, kind: EdgeKind::Fallthrough }] if target == pc + 1
+            );
+            if !falls_through {
+                for edge in edges {
+                    leaders[edge.target] = true;
+                }
+                if let Some(next) = leaders.get_mut(pc + 1) {
+                    *next = true;
+                }
+            }
+        }
+
+        let starts: Vec<usize> = (0..count).filter(|&pc| leaders[pc]).collect();
+        let mut blocks: Vec<BasicBlock> = starts
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| BasicBlock {
+                start,
+                end: starts.get(index + 1).copied().unwrap_or(count),
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+            })
+            .collect();
+
+        for index in 0..blocks.len() {
+            let last = blocks[index].last();
+            for edge in &successors[last] {
+                let target = starts.partition_point(|&start| start <= edge.target) - 1;
+                blocks[index].successors.push(Edge::new(target, edge.kind));
+                if !blocks[target].predecessors.contains(&index) {
+                    blocks[target].predecessors.push(index);
+                }
+            }
+        }
+        for block in &mut blocks {
+            block.predecessors.sort_unstable();
+        }
+
+        let mut graph = Self {
+            blocks,
+            dominators: Vec::new(),
+        };
+        graph.dominators = graph.compute_dominators();
+        graph
+    }
+
+    /// Returns the blocks, ordered by their position in the code.
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// Returns the index of the block holding the instruction at `pc`.
+    pub fn block_at(&self, pc: usize) -> Option<usize> {
+        let index = self.blocks.partition_point(|block| block.start <= pc);
+        index
+            .checked_sub(1)
+            .filter(|&index| pc < self.blocks[index].end)
+    }
+
+    /// Returns the blocks in reverse postorder from the entry, leaving unreachable ones out.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.blocks.len());
+        if self.blocks.is_empty() {
+            return order;
+        }
+
+        // Iterative depth first search, remembering how many successors of each block were visited
+        let mut visited = vec![false; self.blocks.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            match self.blocks[*block].successors.get(*next) {
+                Some(edge) => {
+                    *next += 1;
+                    if !visited[edge.target] {
+                        visited[edge.target] = true;
+                        stack.push((edge.target, 0));
+                    }
+                }
+                None => {
+                    order.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    /// Returns the immediate dominator of every block, `None` for the entry and unreachable blocks.
+    pub fn immediate_dominators(&self) -> &[Option<usize>] {
+        &self.dominators
+    }
+
+    /// Find the immediate dominators with the iterative algorithm of Cooper, Harvey and Kennedy.
+    fn compute_dominators(&self) -> Vec<Option<usize>> {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (index, &block) in order.iter().enumerate() {
+            position[block] = index;
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        let Some(&entry) = order.first() else {
+            return idom;
+        };
+        idom[entry] = Some(entry);
+
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while position[a] > position[b] {
+                    a = idom[a].unwrap_or(entry);
+                }
+                while position[b] > position[a] {
+                    b = idom[b].unwrap_or(entry);
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let new_idom = self.blocks[block]
+                    .predecessors
+                    .iter()
+                    .filter(|&&predecessor| idom[predecessor].is_some())
+                    .fold(None, |new_idom, &predecessor| match new_idom {
+                        None => Some(predecessor),
+                        Some(current) => Some(intersect(&idom, predecessor, current)),
+                    });
+                if new_idom.is_some() && idom[block] != new_idom {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        idom[entry] = None;
+        idom
+    }
+
+    /// Returns whether `a` dominates `b`, every path from the entry to `b` going through `a`.
+    ///
+    /// A block dominates itself, unreachable blocks are dominated by nothing.
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        let idom = &self.dominators;
+        if b != 0 && idom[b].is_none() {
+            return false;
+        }
+
+        let mut block = Some(b);
+        while let Some(current) = block {
+            if current == a {
+                return true;
+            }
+            block = idom[current];
+        }
+
+        false
+    }
+
+    /// Returns whether the edge from block `from` to block `to` jumps back to a loop header.
+    pub fn is_back_edge(&self, from: usize, to: usize) -> bool {
+        self.blocks[from]
+            .successors
+            .iter()
+            .any(|edge| edge.target == to)
+            && self.dominates(to, from)
+    }
+
+    /// Returns the natural loops, ordered by their header.
+    ///
+    /// Back edges to the same header make up one loop. Loops entered other than through their
+    /// header, as irreducible control flow does, aren't found.
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = Vec::new();
+
+        for (latch, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                let header = edge.target;
+                if !self.dominates(header, latch) {
+                    continue;
+                }
+
+                let index = match loops.iter().position(|l| l.header == header) {
+                    Some(index) => index,
+                    None => {
+                        loops.push(Loop {
+                            header,
+                            latches: Vec::new(),
+                            body: vec![header],
+                        });
+                        loops.len() - 1
+                    }
+                };
+                let found = &mut loops[index];
+                if !found.latches.contains(&latch) {
+                    found.latches.push(latch);
+                }
+
+                // Walk backwards from the latch until the header
+                let mut stack = vec![latch];
+                while let Some(current) = stack.pop() {
+                    if found.body.contains(&current) {
+                        continue;
+                    }
+                    found.body.push(current);
+                    let predecessors = self.blocks[current].predecessors.iter();
+                    stack.extend(predecessors.filter(|&&block| self.dominates(header, block)));
+                }
+            }
+        }
+
+        for found in &mut loops {
+            found.latches.sort_unstable();
+            found.body.sort_unstable();
+        }
+        loops.sort_by_key(|found| found.header);
+        loops
+    }
+}
@@ -0,0 +1,149 @@
+//! Helpers shared by the decompilers, turning chains of conditional jumps back into `and` and
+//! `or` expressions.
+//!
+//! Compilers lower `a and b or c` to tests each jumping over the operands after them. The
+//! version crates find the tests and what they jump to, the functions here rebuild the
+//! expression, or give up when the jumps don't nest like an expression compiles to.
+
+use crate::ast::{BinaryOperator, Expression};
+
+/// How deep the decompilers nest blocks before giving up on the structure of a function.
+///
+/// `luac` rejects sources nesting deeper than `LUAI_MAXCCALLS`, so only crafted chunks get
+/// there. Their functions are lifted again without control structures, keeping the jumps as
+/// comments, so that neither the stack nor the output grows with the nesting.
+pub const MAX_NESTING: usize = 200;
+
+/// Where a branch of a condition goes when it jumps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// To the branch at this index, past the operands computed before its test
+    Branch(usize),
+    /// To the code run when the whole condition holds
+    True,
+    /// To the code run when the whole condition doesn't hold
+    False,
+}
+
+/// A test of a condition, followed by a jump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    /// The condition under which the jump is taken
+    pub condition: Expression,
+    pub target: Exit,
+}
+
+/// Returns the condition under which a chain of branches reaches [`Exit::True`], falling out of
+/// the last branch going to `fall`.
+pub fn condition(branches: &[Branch], fall: Exit) -> Option<Expression> {
+    let (condition, exit) = chain(branches, 0, branches.len(), fall)?;
+    match exit {
+        Exit::True => Some(condition),
+        Exit::False => Some(condition.negated()),
+        Exit::Branch(_) => None,
+    }
+}
+
+/// Combine the branches `start..end`, which leave either to `fall` or to a single other exit.
+///
+/// Returns the condition under which they leave to the other exit, and that exit.
+fn chain(branches: &[Branch], start: usize, end: usize, fall: Exit) -> Option<(Expression, Exit)> {
+    let branch = branches.get(start)?;
+    let condition = branch.condition.clone();
+    if start + 1 == end {
+        return (branch.target != fall).then_some((condition, branch.target));
+    }
+
+    match branch.target {
+        // The branches up to the target make up an operand of their own
+        Exit::Branch(target) if target > start + 1 && target < end => {
+            let (operand, operand_exit) = chain(branches, start, target, Exit::Branch(target))?;
+            let (rest, exit) = chain(branches, target, end, fall)?;
+            if operand_exit == exit {
+                Some((Expression::binary(BinaryOperator::Or, operand, rest), exit))
+            } else if operand_exit == fall {
+                let operand = operand.negated();
+                Some((Expression::binary(BinaryOperator::And, operand, rest), exit))
+            } else {
+                None
+            }
+        }
+        target => {
+            let (rest, exit) = chain(branches, start + 1, end, fall)?;
+            if target == fall {
+                let condition = condition.negated();
+                Some((
+                    Expression::binary(BinaryOperator::And, condition, rest),
+                    exit,
+                ))
+            } else if target == exit {
+                Some((
+                    Expression::binary(BinaryOperator::Or, condition, rest),
+                    exit,
+                ))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// An operand of an `and` or `or` expression computed into a register.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operand {
+    pub value: Expression,
+    /// Whether the register is tested for being truthy or falsy and the index of the operand
+    /// jumped to then, the number of operands meaning past the last one. The last operand has
+    /// no test.
+    pub jump: Option<(bool, usize)>,
+}
+
+/// Returns the `and` and `or` expression computing the value left in the register.
+pub fn short_circuit(operands: &[Operand]) -> Option<Expression> {
+    let last = operands.len().checked_sub(1)?;
+    value(operands, 0, last, operands.len())
+}
+
+/// Combine the operands `start..=last`, where jumping to `end` leaves with the value tested.
+fn value(operands: &[Operand], start: usize, last: usize, end: usize) -> Option<Expression> {
+    let operand = &operands[start];
+    if start == last {
+        return Some(operand.value.clone());
+    }
+
+    let operator = |truthy| match truthy {
+        true => BinaryOperator::Or,
+        false => BinaryOperator::And,
+    };
+    let (truthy, target) = operand.jump?;
+    if target == end {
+        let rest = value(operands, start + 1, last, end)?;
+        return Some(Expression::binary(
+            operator(truthy),
+            operand.value.clone(),
+            rest,
+        ));
+    }
+
+    // The operands up to the target make up the left operand, the last of which leaves with
+    // the opposite test
+    if target <= start + 1 || target > last {
+        return None;
+    }
+    let (left_truthy, left_target) = operands[target - 1].jump?;
+    if left_target != end || left_truthy == truthy {
+        return None;
+    }
+    let left = value(operands, start, target - 1, target)?;
+    let rest = value(operands, target, last, end)?;
+    Some(Expression::binary(operator(left_truthy), left, rest))
+}
+
+/// Returns a name for a variable without debug information, suffixed with the depth of its
+/// function so closures don't shadow the locals they capture.
+pub fn generated_name(prefix: &str, register: usize, depth: usize) -> String {
+    match depth {
+        0 => format!("{}{}", prefix, register),
+        depth => format!("{}{}_{}", prefix, register, depth),
+    }
+}
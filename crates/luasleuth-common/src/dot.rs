@@ -0,0 +1,106 @@
+//! Helpers shared by the Graphviz DOT renderings of control flow graphs.
+//!
+//! A chunk is rendered as one digraph holding a cluster per prototype, blocks being boxes
+//! listing their instructions.
+
+use std::fmt::{self, Write};
+
+use crate::cfg::{BasicBlock, ControlFlowGraph, Edge, EdgeKind};
+
+/// Columns tabs are expanded to, as labels don't keep them
+const TAB_WIDTH: usize = 8;
+
+/// Write the start of the digraph holding the clusters.
+pub fn write_graph_start(f: &mut impl Write) -> fmt::Result {
+    writeln!(f, "digraph cfg {{")?;
+    writeln!(f, "    node [shape=box, fontname=\"monospace\"];")
+}
+
+/// Write the end of the digraph.
+pub fn write_graph_end(f: &mut impl Write) -> fmt::Result {
+    writeln!(f, "}}")
+}
+
+/// Write the graph of a prototype as a cluster, `id` telling the clusters of a digraph apart.
+///
+/// `block_text` returns the listing of the instructions of a block, one line per instruction.
+/// Blocks that can't be reached from the entry are dashed.
+pub fn write_cluster(
+    f: &mut impl Write,
+    id: usize,
+    title: &str,
+    graph: &ControlFlowGraph,
+    mut block_text: impl FnMut(&BasicBlock) -> Result<String, fmt::Error>,
+) -> fmt::Result {
+    writeln!(f, "    subgraph cluster_{} {{", id)?;
+    writeln!(f, "        label=\"{}\";", escape(title))?;
+
+    let dominators = graph.immediate_dominators();
+    for (index, block) in graph.blocks().iter().enumerate() {
+        let text = block_text(block)?;
+        let label: String = text.lines().map(|line| escape(line) + "\\l").collect();
+        let style = match index != 0 && dominators[index].is_none() {
+            true => ", style=dashed",
+            false => "",
+        };
+        writeln!(
+            f,
+            "        f{}b{} [label=\"{}\"{}];",
+            id, index, label, style
+        )?;
+    }
+
+    for (index, block) in graph.blocks().iter().enumerate() {
+        for edge in &block.successors {
+            writeln!(
+                f,
+                "        f{}b{} -> f{}b{} [label=\"{}\"];",
+                id,
+                index,
+                id,
+                edge.target,
+                edge_label(graph, index, edge)
+            )?;
+        }
+    }
+
+    writeln!(f, "    }}")
+}
+
+/// Returns the label of an edge, back edges of loops being told apart from other branches.
+pub fn edge_label(graph: &ControlFlowGraph, from: usize, edge: &Edge) -> &'static str {
+    if graph.is_back_edge(from, edge.target) {
+        return "loop-back";
+    }
+
+    match edge.kind {
+        EdgeKind::Fallthrough => "fallthrough",
+        EdgeKind::Jump => "jump",
+        EdgeKind::True => "true",
+        EdgeKind::False => "false",
+    }
+}
+
+/// Escape a line for a quoted DOT string, expanding its tabs.
+fn escape(line: &str) -> String {
+    let mut escaped = String::new();
+    let mut column = 0;
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let spaces = TAB_WIDTH - column % TAB_WIDTH;
+                escaped.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+                continue;
+            }
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+        column += 1;
+    }
+
+    escaped
+}
@@ -0,0 +1,275 @@
+//! The error type returned when reading bytecode.
+
+use std::{borrow::Cow, fmt};
+
+/// What went wrong while reading.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The underlying reader failed, usually because the input ended early
+    Scroll(scroll::Error),
+    /// The data that was read is not valid
+    Invalid(Cow<'static, str>),
+}
+
+/// One step of the path to the field that failed to read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Field(&'static str),
+    Index(usize),
+}
+
+/// An error that happened while reading bytecode.
+///
+/// Errors are created relative to the structure being read and gain context as they
+/// travel up through the readers, see [`ErrorContext`].
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    offset: usize,
+    field: Vec<PathSegment>,
+    prototype: Option<Vec<usize>>,
+}
+
+impl Error {
+    /// Create an error for invalid data found at `offset`.
+    pub fn invalid(msg: impl Into<Cow<'static, str>>, offset: usize) -> Self {
+        Self {
+            kind: ErrorKind::Invalid(msg.into()),
+            offset,
+            field: Vec::new(),
+            prototype: None,
+        }
+    }
+
+    /// Returns what went wrong.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Returns the byte offset of the failure.
+    ///
+    /// Once the error left the disassembler this is the absolute offset into the chunk.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the path to the field that failed, relative to its prototype.
+    pub fn field_path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.field {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+            }
+        }
+
+        path
+    }
+
+    /// Returns the path of the prototype the failure happened in, such as `main/3/1`.
+    ///
+    /// LuaJIT dumps its prototypes one after the other, children first and the main function
+    /// last, so the tree isn't known yet when one fails to read. Its errors have no prototype
+    /// path, their field path starting at `prototypes[index]` with `index` counting the
+    /// prototypes in the order they were dumped.
+    pub fn prototype_path(&self) -> Option<String> {
+        let path = self.prototype.as_ref()?;
+
+        let mut result = String::from("main");
+        for index in path {
+            result.push_str(&format!("/{}", index));
+        }
+
+        Some(result)
+    }
+
+    /// Move the error to `start` bytes further into the input.
+    pub fn at(mut self, start: usize) -> Self {
+        self.offset += start;
+        self
+    }
+
+    /// Add a field the error happened in, read at `start`.
+    pub fn within(mut self, start: usize, field: &'static str) -> Self {
+        if self.prototype.is_none() {
+            self.field.insert(0, PathSegment::Field(field));
+        }
+
+        self.at(start)
+    }
+
+    /// Add an element index the error happened in, read at `start`.
+    ///
+    /// Indexes of an error that already crossed a prototype are prototype indexes.
+    pub fn within_index(mut self, start: usize, index: usize) -> Self {
+        match &mut self.prototype {
+            Some(prototype) => prototype.insert(0, index),
+            None => self.field.insert(0, PathSegment::Index(index)),
+        }
+
+        self.at(start)
+    }
+
+    /// Add an element of the list `field` the error happened in, read at `start`.
+    pub fn within_element(self, start: usize, field: &'static str, index: usize) -> Self {
+        self.within_index(start, index).within(0, field)
+    }
+
+    /// Mark the error as having happened inside of a prototype.
+    ///
+    /// Context added afterwards builds the prototype path instead of the field path.
+    pub fn in_prototype(mut self) -> Self {
+        if self.prototype.is_none() {
+            self.prototype = Some(Vec::new());
+        }
+
+        self
+    }
+
+    /// Render the error along with a hex excerpt of `bytes` around the failure.
+    pub fn report(&self, bytes: &[u8]) -> String {
+        const WIDTH: usize = 16;
+
+        let mut report = format!("error: {}\n  --> offset {:#x}", self.kind, self.offset);
+        if let Some(prototype) = self.prototype_path() {
+            report.push_str(&format!(" in prototype {}", prototype));
+        }
+        if !self.field.is_empty() {
+            report.push_str(&format!(", field {}", self.field_path()));
+        }
+        report.push_str("\n\n");
+
+        // The row of the failure and one row on either side, as far as the input goes
+        let row = self.offset - self.offset % WIDTH;
+        for start in (row.saturating_sub(WIDTH)..row + 2 * WIDTH).step_by(WIDTH) {
+            if start >= bytes.len() && start != row {
+                break;
+            }
+
+            let end = (start + WIDTH).min(bytes.len());
+            let hex: Vec<String> = bytes
+                .get(start..end)
+                .unwrap_or_default()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            report.push_str(&format!("  {:08x}  {}\n", start, hex.join(" ")));
+
+            if start == row {
+                let column = 12 + (self.offset - start) * 3;
+                report.push_str(&format!("{:column$}^^\n", ""));
+            }
+        }
+
+        report
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Scroll(error) => error.fmt(f),
+            ErrorKind::Invalid(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {:#x}", self.kind, self.offset)?;
+
+        match (self.prototype_path(), self.field.is_empty()) {
+            (Some(prototype), false) => write!(f, " ({}, {})", prototype, self.field_path()),
+            (Some(prototype), true) => write!(f, " ({})", prototype),
+            (None, false) => write!(f, " ({})", self.field_path()),
+            (None, true) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Scroll(error) => Some(error),
+            ErrorKind::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<scroll::Error> for Error {
+    fn from(value: scroll::Error) -> Self {
+        let kind = match value {
+            scroll::Error::BadInput { msg, .. } => ErrorKind::Invalid(msg.into()),
+            scroll::Error::Custom(msg) => ErrorKind::Invalid(msg.into()),
+            error => ErrorKind::Scroll(error),
+        };
+
+        Self {
+            kind,
+            offset: 0,
+            field: Vec::new(),
+            prototype: None,
+        }
+    }
+}
+
+/// A byte that does not map to any opcode of the instruction set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidOpcode(pub u8);
+
+impl fmt::Display for InvalidOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid opcode value: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidOpcode {}
+
+impl From<InvalidOpcode> for Error {
+    fn from(value: InvalidOpcode) -> Self {
+        Self::invalid(value.to_string(), 0)
+    }
+}
+
+/// Adds context to the error of a read that started at `start`.
+///
+/// Failed reads leave the offset untouched, so `*offset` can be passed after the read:
+///
+/// ```ignore
+/// let tag: u8 = src.gread_with(offset, ctx.endianness).within(*offset, "tag")?;
+/// ```
+pub trait ErrorContext<T> {
+    /// See [`Error::at`]
+    fn at(self, start: usize) -> Result<T, Error>;
+    /// See [`Error::within`]
+    fn within(self, start: usize, field: &'static str) -> Result<T, Error>;
+    /// See [`Error::within_index`]
+    fn within_index(self, start: usize, index: usize) -> Result<T, Error>;
+    /// See [`Error::within_element`]
+    fn within_element(self, start: usize, field: &'static str, index: usize) -> Result<T, Error>;
+}
+
+impl<T, E> ErrorContext<T> for Result<T, E>
+where
+    Error: From<E>,
+{
+    fn at(self, start: usize) -> Result<T, Error> {
+        self.map_err(|error| Error::from(error).at(start))
+    }
+
+    fn within(self, start: usize, field: &'static str) -> Result<T, Error> {
+        self.map_err(|error| Error::from(error).within(start, field))
+    }
+
+    fn within_index(self, start: usize, index: usize) -> Result<T, Error> {
+        self.map_err(|error| Error::from(error).within_index(start, index))
+    }
+
+    fn within_element(self, start: usize, field: &'static str, index: usize) -> Result<T, Error> {
+        self.map_err(|error| Error::from(error).within_element(start, field, index))
+    }
+}
@@ -0,0 +1,110 @@
+//! Check and write data shared by the headers of the official Lua formats.
+
+use scroll::{Endian, Pwrite};
+
+use crate::{read_float, read_signed, write_unsigned};
+
+/// The signature every precompiled chunk starts with
+pub const LUA_SIGNATURE: [u8; 4] = *b"\x1bLua";
+
+/// Bytes used to catch conversion errors, such as text-mode transfers
+pub const LUAC_DATA: [u8; 6] = *b"\x19\x93\r\n\x1a\n";
+
+/// Integer used to detect the byte order and integer format, since Lua 5.3
+pub const LUAC_INT: i64 = 0x5678;
+
+/// Number used to detect the floating point format, since Lua 5.3
+pub const LUAC_NUM: f64 = 370.5;
+
+/// Check that a chunk starts with [`LUA_SIGNATURE`].
+pub fn check_signature(signature: &[u8; 4]) -> Result<(), scroll::Error> {
+    if *signature != LUA_SIGNATURE {
+        return Err(scroll::Error::BadInput {
+            size: signature.len(),
+            msg: "Signature check failed: not a precompiled Lua chunk",
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that the conversion check bytes match [`LUAC_DATA`].
+pub fn check_luac_data(data: &[u8; 6]) -> Result<(), scroll::Error> {
+    if *data != LUAC_DATA {
+        return Err(scroll::Error::BadInput {
+            size: data.len(),
+            msg: "LUAC_DATA check failed: chunk is corrupted or was converted as text",
+        });
+    }
+
+    Ok(())
+}
+
+/// Read [`LUAC_INT`] and infer the byte order of the chunk from it.
+pub fn read_luac_int(
+    src: &[u8],
+    offset: &mut usize,
+    size: u8,
+) -> Result<(i64, Endian), scroll::Error> {
+    for endian in [scroll::LE, scroll::BE] {
+        let mut start = *offset;
+        if read_signed(src, &mut start, size, endian)? == LUAC_INT {
+            *offset = start;
+            return Ok((LUAC_INT, endian));
+        }
+    }
+
+    Err(scroll::Error::BadInput {
+        size: size as usize,
+        msg: "LUAC_INT check failed: unknown byte order or integer format",
+    })
+}
+
+/// Read [`LUAC_NUM`] using the byte order found by [`read_luac_int`].
+pub fn read_luac_num(
+    src: &[u8],
+    offset: &mut usize,
+    size: u8,
+    endian: Endian,
+) -> Result<f64, scroll::Error> {
+    let mut start = *offset;
+    let value = read_float(src, &mut start, size, endian)?;
+    if value != LUAC_NUM {
+        return Err(scroll::Error::BadInput {
+            size: size as usize,
+            msg: "LUAC_NUM check failed: unknown floating point format",
+        });
+    }
+
+    *offset = start;
+    Ok(value)
+}
+
+/// Write [`LUAC_INT`] in the given byte order.
+pub fn write_luac_int(
+    dst: &mut [u8],
+    offset: &mut usize,
+    value: i64,
+    size: u8,
+    endian: Endian,
+) -> Result<usize, scroll::Error> {
+    write_unsigned(dst, offset, value as u64, size, endian)
+}
+
+/// Write [`LUAC_NUM`] in the given byte order.
+pub fn write_luac_num(
+    dst: &mut [u8],
+    offset: &mut usize,
+    value: f64,
+    size: u8,
+    endian: Endian,
+) -> Result<usize, scroll::Error> {
+    match size {
+        4 => dst.gwrite_with(value as f32, offset, endian),
+        8 => dst.gwrite_with(value, offset, endian),
+        _ => Err(scroll::Error::BadInput {
+            size: size as usize,
+            msg: "Unsupported lua_Number size",
+        }),
+    }
+}
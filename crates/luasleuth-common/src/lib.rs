@@ -2,11 +2,27 @@ pub mod types;
 #[macro_use]
 mod macros;
 pub mod assembler;
+pub mod ast;
+pub mod cfg;
+pub mod decompiler;
 pub mod disassembler;
+pub mod dot;
+mod error;
+pub mod header;
+mod limits;
+pub mod listing;
+pub mod resolve;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod text;
+
+pub use error::{Error, ErrorContext, ErrorKind, InvalidOpcode, PathSegment};
+pub use limits::Limits;
 
 use scroll::{ctx, Endian, Pread, Pwrite};
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -31,6 +47,31 @@ pub struct CommonCtx {
     ///
     /// Used when reading integers and alike.
     pub endianness: Endian,
+
+    /// The size of an `int` in bytes.
+    ///
+    /// Used for counts, line numbers and program counters before Lua 5.4.
+    pub size_of_int: u8,
+
+    /// The size of an instruction in bytes.
+    pub size_of_instruction: u8,
+
+    /// The size of a `lua_Number` in bytes.
+    pub size_of_lua_number: u8,
+
+    /// The size of a `lua_Integer` in bytes, only present since Lua 5.3.
+    pub size_of_lua_integer: u8,
+
+    /// Whether `lua_Number` is an integral type instead of a floating point one.
+    ///
+    /// Integral numbers are converted to and from `f64`.
+    pub integral: bool,
+
+    /// The limits the chunk is read with.
+    pub limits: Limits,
+
+    /// How deeply nested the prototype being read is, the main prototype being at depth 0.
+    pub depth: usize,
 }
 
 impl Version {
@@ -46,6 +87,189 @@ impl Version {
     }
 }
 
+impl CommonCtx {
+    /// Read a C `int`, sized by [`CommonCtx::size_of_int`].
+    pub fn read_int(&self, src: &[u8], offset: &mut usize) -> Result<i64, scroll::Error> {
+        read_signed(src, offset, self.size_of_int, self.endianness)
+    }
+
+    /// Write a C `int`, sized by [`CommonCtx::size_of_int`].
+    pub fn write_int(
+        &self,
+        dst: &mut [u8],
+        offset: &mut usize,
+        value: i64,
+    ) -> Result<usize, scroll::Error> {
+        write_unsigned(dst, offset, value as u64, self.size_of_int, self.endianness)
+    }
+
+    /// Read a C `size_t`, sized by [`CommonCtx::size_of_size_t`].
+    pub fn read_size_t(&self, src: &[u8], offset: &mut usize) -> Result<u64, scroll::Error> {
+        read_unsigned(src, offset, self.size_of_size_t, self.endianness)
+    }
+
+    /// Write a C `size_t`, sized by [`CommonCtx::size_of_size_t`].
+    pub fn write_size_t(
+        &self,
+        dst: &mut [u8],
+        offset: &mut usize,
+        value: u64,
+    ) -> Result<usize, scroll::Error> {
+        write_unsigned(dst, offset, value, self.size_of_size_t, self.endianness)
+    }
+
+    /// Read an instruction, sized by [`CommonCtx::size_of_instruction`].
+    ///
+    /// Instructions wider than 32 bits only use their lower 32 bits.
+    pub fn read_instruction(&self, src: &[u8], offset: &mut usize) -> Result<u32, scroll::Error> {
+        let instruction = read_unsigned(src, offset, self.size_of_instruction, self.endianness)?;
+        Ok(instruction as u32)
+    }
+
+    /// Write an instruction, sized by [`CommonCtx::size_of_instruction`].
+    pub fn write_instruction(
+        &self,
+        dst: &mut [u8],
+        offset: &mut usize,
+        value: u32,
+    ) -> Result<usize, scroll::Error> {
+        write_unsigned(
+            dst,
+            offset,
+            value as u64,
+            self.size_of_instruction,
+            self.endianness,
+        )
+    }
+
+    /// Read a `lua_Integer`, sized by [`CommonCtx::size_of_lua_integer`].
+    pub fn read_integer(&self, src: &[u8], offset: &mut usize) -> Result<i64, scroll::Error> {
+        read_signed(src, offset, self.size_of_lua_integer, self.endianness)
+    }
+
+    /// Write a `lua_Integer`, sized by [`CommonCtx::size_of_lua_integer`].
+    pub fn write_integer(
+        &self,
+        dst: &mut [u8],
+        offset: &mut usize,
+        value: i64,
+    ) -> Result<usize, scroll::Error> {
+        write_unsigned(
+            dst,
+            offset,
+            value as u64,
+            self.size_of_lua_integer,
+            self.endianness,
+        )
+    }
+
+    /// Read a `lua_Number`, honoring its size and whether it is integral.
+    pub fn read_number(&self, src: &[u8], offset: &mut usize) -> Result<f64, scroll::Error> {
+        if self.integral {
+            return Ok(read_signed(src, offset, self.size_of_lua_number, self.endianness)? as f64);
+        }
+
+        read_float(src, offset, self.size_of_lua_number, self.endianness)
+    }
+
+    /// Write a `lua_Number`, honoring its size and whether it is integral.
+    pub fn write_number(
+        &self,
+        dst: &mut [u8],
+        offset: &mut usize,
+        value: f64,
+    ) -> Result<usize, scroll::Error> {
+        if self.integral {
+            let value = value as i64 as u64;
+            return write_unsigned(dst, offset, value, self.size_of_lua_number, self.endianness);
+        }
+
+        match self.size_of_lua_number {
+            4 => dst.gwrite_with(value as f32, offset, self.endianness),
+            8 => dst.gwrite_with(value, offset, self.endianness),
+            _ => Err(scroll::Error::BadInput {
+                size: self.size_of_lua_number as usize,
+                msg: "Unsupported lua_Number size",
+            }),
+        }
+    }
+}
+
+/// Read a floating point number that is `size` bytes wide.
+pub(crate) fn read_float(
+    src: &[u8],
+    offset: &mut usize,
+    size: u8,
+    endian: Endian,
+) -> Result<f64, scroll::Error> {
+    match size {
+        4 => Ok(src.gread_with::<f32>(offset, endian)? as f64),
+        8 => src.gread_with::<f64>(offset, endian),
+        _ => Err(scroll::Error::BadInput {
+            size: size as usize,
+            msg: "Unsupported lua_Number size",
+        }),
+    }
+}
+
+/// Read an unsigned integer that is `size` bytes wide.
+fn read_unsigned(
+    src: &[u8],
+    offset: &mut usize,
+    size: u8,
+    endian: Endian,
+) -> Result<u64, scroll::Error> {
+    match size {
+        1 => Ok(src.gread_with::<u8>(offset, endian)? as u64),
+        2 => Ok(src.gread_with::<u16>(offset, endian)? as u64),
+        4 => Ok(src.gread_with::<u32>(offset, endian)? as u64),
+        8 => src.gread_with::<u64>(offset, endian),
+        _ => Err(scroll::Error::BadInput {
+            size: size as usize,
+            msg: "Unsupported integer size",
+        }),
+    }
+}
+
+/// Read a signed integer that is `size` bytes wide.
+pub(crate) fn read_signed(
+    src: &[u8],
+    offset: &mut usize,
+    size: u8,
+    endian: Endian,
+) -> Result<i64, scroll::Error> {
+    match size {
+        1 => Ok(src.gread_with::<i8>(offset, endian)? as i64),
+        2 => Ok(src.gread_with::<i16>(offset, endian)? as i64),
+        4 => Ok(src.gread_with::<i32>(offset, endian)? as i64),
+        8 => src.gread_with::<i64>(offset, endian),
+        _ => Err(scroll::Error::BadInput {
+            size: size as usize,
+            msg: "Unsupported integer size",
+        }),
+    }
+}
+
+/// Write the lower `size` bytes of an integer.
+fn write_unsigned(
+    dst: &mut [u8],
+    offset: &mut usize,
+    value: u64,
+    size: u8,
+    endian: Endian,
+) -> Result<usize, scroll::Error> {
+    match size {
+        1 => dst.gwrite_with(value as u8, offset, endian),
+        2 => dst.gwrite_with(value as u16, offset, endian),
+        4 => dst.gwrite_with(value as u32, offset, endian),
+        8 => dst.gwrite_with(value, offset, endian),
+        _ => Err(scroll::Error::BadInput {
+            size: size as usize,
+            msg: "Unsupported integer size",
+        }),
+    }
+}
+
 impl<'a> ctx::TryFromCtx<'a, Endian> for Version {
     type Error = scroll::Error;
 
@@ -72,3 +296,11 @@ impl ctx::TryIntoCtx<Endian> for Version {
         Ok(*offset)
     }
 }
+
+impl ctx::TryIntoCtx<Endian> for &Version {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: Endian) -> Result<usize, Self::Error> {
+        (*self).try_into_ctx(dst, ctx)
+    }
+}
@@ -0,0 +1,126 @@
+//! Limits on the resources a chunk can make the disassemblers use.
+
+use crate::Error;
+
+/// Limits on what a chunk is allowed to make a disassembler allocate.
+///
+/// Counts and lengths read from a chunk are checked against these limits, and against
+/// the input that is left to read, before anything is allocated for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of elements across all arrays of a chunk, such as
+    /// instructions, constants, upvalues, prototypes and debug information entries.
+    pub max_elements: usize,
+
+    /// The maximum nesting depth of prototypes, the main prototype being at depth 0.
+    pub max_depth: usize,
+
+    /// The maximum length of a single string in bytes.
+    pub max_string_length: usize,
+}
+
+impl Limits {
+    /// No limits, apart from the length of the input.
+    pub const UNLIMITED: Limits = Limits {
+        max_elements: usize::MAX,
+        max_depth: usize::MAX,
+        max_string_length: usize::MAX,
+    };
+
+    /// Check a count of elements read from the input.
+    ///
+    /// Every element takes up at least one byte, so a count can never be larger than
+    /// the `remaining` bytes of input.
+    pub fn check_count(&self, count: u64, remaining: usize) -> Result<usize, Error> {
+        if count > remaining as u64 {
+            return Err(Error::invalid(
+                format!(
+                    "Count of {} elements is larger than the {} bytes left",
+                    count, remaining
+                ),
+                0,
+            ));
+        }
+
+        let count = count as usize;
+        if count > self.max_elements {
+            return Err(Error::invalid(
+                format!(
+                    "Count of {} elements exceeds the limit of {}",
+                    count, self.max_elements
+                ),
+                0,
+            ));
+        }
+
+        Ok(count)
+    }
+
+    /// Check the total number of elements read so far.
+    pub fn check_elements(&self, total: usize) -> Result<(), Error> {
+        if total > self.max_elements {
+            return Err(Error::invalid(
+                format!(
+                    "Chunk contains {} elements, exceeding the limit of {}",
+                    total, self.max_elements
+                ),
+                0,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the nesting depth of a prototype.
+    pub fn check_depth(&self, depth: usize) -> Result<(), Error> {
+        if depth > self.max_depth {
+            return Err(Error::invalid(
+                format!(
+                    "Prototypes are nested deeper than the limit of {}",
+                    self.max_depth
+                ),
+                0,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the length of a string read from the input.
+    pub fn check_string_length(&self, length: u64, remaining: usize) -> Result<usize, Error> {
+        if length > remaining as u64 {
+            return Err(Error::invalid(
+                format!(
+                    "String of {} bytes is larger than the {} bytes left",
+                    length, remaining
+                ),
+                0,
+            ));
+        }
+
+        let length = length as usize;
+        if length > self.max_string_length {
+            return Err(Error::invalid(
+                format!(
+                    "String of {} bytes exceeds the limit of {}",
+                    length, self.max_string_length
+                ),
+                0,
+            ));
+        }
+
+        Ok(length)
+    }
+}
+
+impl Default for Limits {
+    /// Limits that are generous for compiler output, but keep hostile chunks in check.
+    fn default() -> Self {
+        Self {
+            max_elements: 1 << 24,
+            // LUAI_MAXCCALLS, the most the parser of the reference implementation nests
+            max_depth: 200,
+            max_string_length: 1 << 26,
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Helpers shared by the `luac -l -l` style listings of every version.
+
+use std::{borrow::Cow, fmt};
+
+/// Digits `luac` prints numbers with, `LUAI_NUMFFORMAT` being `"%.14g"`
+const NUMBER_PRECISION: i32 = 14;
+
+/// Format a number the way C formats it with `"%.14g"`.
+pub fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        return if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .to_string();
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    // The exponent after rounding decides between the fixed and scientific notations
+    let scientific = format!("{:.*e}", (NUMBER_PRECISION - 1) as usize, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+
+    if !(-4..NUMBER_PRECISION).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", trim_fraction(mantissa), sign, exponent.abs());
+    }
+
+    let fixed = format!("{:.*}", (NUMBER_PRECISION - 1 - exponent) as usize, value);
+    trim_fraction(&fixed).to_string()
+}
+
+/// Format a float constant like Lua 5.3 and later, which mark integral looking floats with `.0`.
+pub fn format_float(value: f64) -> String {
+    let mut formatted = format_number(value);
+    if formatted.bytes().all(|b| b == b'-' || b.is_ascii_digit()) {
+        formatted.push_str(".0");
+    }
+
+    formatted
+}
+
+/// Strip the trailing zeros of the fractional part of a number, like `%g` does.
+fn trim_fraction(number: &str) -> &str {
+    match number.contains('.') {
+        true => number.trim_end_matches('0').trim_end_matches('.'),
+        false => number,
+    }
+}
+
+/// A string quoted and escaped the way `luac` prints string constants.
+pub struct Quoted<'a>(pub &'a [u8]);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for &byte in self.0 {
+            match byte {
+                b'"' => f.write_str("\\\"")?,
+                b'\\' => f.write_str("\\\\")?,
+                0x07 => f.write_str("\\a")?,
+                0x08 => f.write_str("\\b")?,
+                0x0c => f.write_str("\\f")?,
+                b'\n' => f.write_str("\\n")?,
+                b'\r' => f.write_str("\\r")?,
+                b'\t' => f.write_str("\\t")?,
+                0x0b => f.write_str("\\v")?,
+                0x20..=0x7e => write!(f, "{}", byte as char)?,
+                byte => write!(f, "\\{:03}", byte)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Returns the source of a function as its listing header shows it.
+///
+/// File names lose their `@` or `=` prefix, sources of chunks loaded from strings are hidden.
+pub fn source_name(source: Option<&[u8]>) -> Cow<'_, str> {
+    let source = source.unwrap_or(b"=?");
+    match source.first() {
+        Some(b'@' | b'=') => String::from_utf8_lossy(&source[1..]),
+        Some(0x1b) => Cow::Borrowed("(bstring)"),
+        _ => Cow::Borrowed("(string)"),
+    }
+}
+
+/// Returns the plural suffix for a count, as `luac` uses it.
+pub fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// The two line summary printed above the instructions of every function.
+pub struct FunctionHeader<'a> {
+    pub source: Option<&'a [u8]>,
+    pub line_defined: i64,
+    pub last_line_defined: i64,
+    pub instructions: usize,
+    /// The size of the code in bytes, which only Lua 5.1 prints
+    pub code_size: Option<usize>,
+    pub parameters: usize,
+    pub is_vararg: bool,
+    pub slots: usize,
+    pub upvalues: usize,
+    pub locals: usize,
+    pub constants: usize,
+    pub functions: usize,
+}
+
+impl FunctionHeader<'_> {
+    /// Returns the `<source:line,last>` location of the function.
+    pub fn location(&self) -> String {
+        format!(
+            "<{}:{},{}>",
+            source_name(self.source),
+            self.line_defined,
+            self.last_line_defined
+        )
+    }
+
+    /// Returns whether this is the main function and its location, like `main <source:0,0>`.
+    pub fn title(&self) -> String {
+        let kind = if self.line_defined == 0 {
+            "main"
+        } else {
+            "function"
+        };
+        format!("{} {}", kind, self.location())
+    }
+}
+
+impl fmt::Display for FunctionHeader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\n{} ({} instruction{}",
+            self.title(),
+            self.instructions,
+            plural(self.instructions)
+        )?;
+        if let Some(size) = self.code_size {
+            write!(f, ", {} bytes", size)?;
+        }
+        writeln!(f, ")")?;
+
+        writeln!(
+            f,
+            "{}{} param{}, {} slot{}, {} upvalue{}, {} local{}, {} constant{}, {} function{}",
+            self.parameters,
+            if self.is_vararg { "+" } else { "" },
+            plural(self.parameters),
+            self.slots,
+            plural(self.slots),
+            self.upvalues,
+            plural(self.upvalues),
+            self.locals,
+            plural(self.locals),
+            self.constants,
+            plural(self.constants),
+            self.functions,
+            plural(self.functions)
+        )
+    }
+}
+
+/// Write the position, line and mnemonic columns that start every instruction line.
+///
+/// Lines that are unknown, or not positive, are shown as `[-]`.
+pub fn write_instruction_start(
+    f: &mut impl fmt::Write,
+    pc: usize,
+    line: Option<i64>,
+    mnemonic: &str,
+) -> fmt::Result {
+    write!(f, "\t{}\t", pc + 1)?;
+    match line {
+        Some(line) if line > 0 => write!(f, "[{}]\t", line)?,
+        _ => f.write_str("[-]\t")?,
+    }
+    write!(f, "{:<9}\t", mnemonic)
+}
+
+/// Returns the mnemonic of an opcode, its `Debug` name without the `OP_` prefix.
+pub fn mnemonic(opcode: impl fmt::Debug) -> String {
+    let name = format!("{:?}", opcode);
+    match name.strip_prefix("OP_") {
+        Some(stripped) => stripped.to_string(),
+        None => name,
+    }
+}
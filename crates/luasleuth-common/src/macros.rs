@@ -1,14 +1,14 @@
 #[macro_export]
 macro_rules! try_gread_vec_with {
     ($src:ident, $offset:ident, $cap:expr, $ctx:expr) => {{
-        let mut vec = Vec::with_capacity($cap as usize);
+        let mut vec = Vec::with_capacity(($cap as usize).min($src.len()));
         for _ in 0..$cap {
             vec.push($src.gread_with($offset, $ctx)?);
         }
         vec
     }};
     ($src:ident, $offset:ident, $cap:expr; ctx = offset) => {{
-        let mut vec = Vec::with_capacity($cap as usize);
+        let mut vec = Vec::with_capacity(($cap as usize).min($src.len()));
         for _ in 0..$cap {
             vec.push($src.gread_with($offset, *$offset)?);
         }
@@ -16,7 +16,7 @@ macro_rules! try_gread_vec_with {
     }};
 
     ($src:expr, $offset:ident, $cap:expr, $ctx:expr) => {{
-        let mut vec = Vec::with_capacity($cap as usize);
+        let mut vec = Vec::with_capacity(($cap as usize).min($src.len()));
         for _ in 0..$cap {
             vec.push($src.gread_with($offset, $ctx)?);
         }
@@ -24,7 +24,7 @@ macro_rules! try_gread_vec_with {
     }};
 
     ($src:expr, $offset:ident, $cap:expr; ctx = offset) => {{
-        let mut vec = Vec::with_capacity($cap as usize);
+        let mut vec = Vec::with_capacity(($cap as usize).min($src.len()));
         for _ in 0..$cap {
             vec.push($src.gread_with($offset, *$offset)?);
         }
@@ -0,0 +1,47 @@
+//! Operands of an instruction resolved against the prototype holding it.
+//!
+//! Every version crate has a `resolve` module turning the raw operands of an instruction into
+//! [`ResolvedOperand`]s, leaving the rendering to the caller.
+
+/// An instruction operand, along with what it refers to in its prototype.
+///
+/// `K` is how the version crate refers to a constant, usually a reference to its `Constant` type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResolvedOperand<'p, K> {
+    /// A register, named after the local variable living in it at that pc
+    Register { index: u32, name: Option<&'p [u8]> },
+    /// A constant, without a value when the index is out of range
+    Constant { index: u32, value: Option<K> },
+    /// An upvalue, named when the chunk keeps its debug information
+    Upvalue { index: u32, name: Option<&'p [u8]> },
+    /// The pc of the instruction jumped to, counted from 0
+    Jump { target: i64 },
+    /// A child prototype
+    Prototype { index: u32 },
+    /// A value used as is, such as a count, a flag or an immediate integer
+    Literal(i64),
+}
+
+/// Returns the name of the local variable living in `register` at `pc`.
+///
+/// Locals are given as `(name, start_pc, end_pc)` in the order they are declared, the n-th one
+/// active at a pc living in register n, as `luaF_getlocalname` finds them.
+pub fn local_name<'p>(
+    locals: impl IntoIterator<Item = (&'p [u8], u64, u64)>,
+    register: u32,
+    pc: u64,
+) -> Option<&'p [u8]> {
+    locals
+        .into_iter()
+        .filter(|&(_, start_pc, end_pc)| start_pc <= pc && pc < end_pc)
+        .nth(register as usize)
+        .map(|(name, ..)| name)
+}
+
+/// Returns the pc the first jump operand of an instruction goes to, if it is in range.
+pub fn jump_target<K>(operands: &[ResolvedOperand<'_, K>]) -> Option<usize> {
+    operands.iter().find_map(|operand| match operand {
+        ResolvedOperand::Jump { target } => usize::try_from(*target).ok(),
+        _ => None,
+    })
+}
@@ -0,0 +1,177 @@
+//! Serde support for the types that have no natural serde representation.
+//!
+//! Sizes are serialized as they were read, but re-derived from the data when deserializing,
+//! so that edited documents don't have to keep them in sync.
+
+use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::types::{
+    leb128::{Sleb128, Uleb128},
+    Array, LuaString,
+};
+
+/// Raw bytes, serialized as a string when they are valid UTF-8 and as an array of bytes otherwise.
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(self.0) {
+            Ok(string) => serializer.serialize_str(string),
+            Err(_) => serializer.collect_seq(self.0),
+        }
+    }
+}
+
+/// Owned [`Bytes`], accepting either representation.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OwnedBytes {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<OwnedBytes> for Vec<u8> {
+    fn from(value: OwnedBytes) -> Self {
+        match value {
+            OwnedBytes::Text(text) => text.into_bytes(),
+            OwnedBytes::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+/// A sized string, whose `size` is ignored.
+#[derive(Deserialize)]
+struct SizedString {
+    data: OwnedBytes,
+}
+
+/// Deserialize the data of a sized string written by [`sized_string`].
+pub fn sized_string_data<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    Ok(SizedString::deserialize(deserializer)?.data.into())
+}
+
+/// Serialize a sized string as `{ "size": ..., "data": ... }`, `data` being [`Bytes`].
+pub fn sized_string<S: Serializer>(
+    name: &'static str,
+    size: usize,
+    data: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut state = serializer.serialize_struct(name, 2)?;
+    state.serialize_field("size", &size)?;
+    state.serialize_field("data", &Bytes(data))?;
+    state.end()
+}
+
+impl Serialize for LuaString<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        sized_string("LuaString", self.size, &self.data, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LuaString<'_> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = sized_string_data(deserializer)?;
+        Ok(LuaString {
+            size: data.len(),
+            data: data.into(),
+        })
+    }
+}
+
+/// An array, whose `size` is ignored.
+#[derive(Deserialize)]
+struct SizedArray<T> {
+    data: Vec<T>,
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Array<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(SizedArray::deserialize(deserializer)?.data.into())
+    }
+}
+
+/// A leb128 integer, whose `count` is ignored.
+#[derive(Deserialize)]
+struct Leb128<T> {
+    value: T,
+}
+
+impl<'de> Deserialize<'de> for Uleb128 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value: u64 = Leb128::deserialize(deserializer)?.value;
+        Ok(Uleb128::encoded(value))
+    }
+}
+
+impl<'de> Deserialize<'de> for Sleb128 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value: i64 = Leb128::deserialize(deserializer)?.value;
+        Ok(Sleb128::encoded(value))
+    }
+}
+
+/// A byte order as `"little"` or `"big"`, for `#[serde(with)]`.
+pub mod endian {
+    use scroll::Endian;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(endian: &Endian, serializer: S) -> Result<S::Ok, S::Error> {
+        match endian {
+            Endian::Little => serializer.serialize_str("little"),
+            Endian::Big => serializer.serialize_str("big"),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Endian, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "little" => Ok(Endian::Little),
+            "big" => Ok(Endian::Big),
+            other => Err(D::Error::unknown_variant(other, &["little", "big"])),
+        }
+    }
+}
+
+/// A float as a number when it is finite, for `#[serde(with)]`.
+///
+/// JSON has no representation for the others, so the infinities are `"inf"` and `"-inf"`, NaN is
+/// `"nan"`, and any other NaN is its bits as a hex string, such as `"0xfff8000000000000"`.
+pub mod float {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        match *value {
+            value if value.is_finite() => serializer.serialize_f64(value),
+            f64::INFINITY => serializer.serialize_str("inf"),
+            f64::NEG_INFINITY => serializer.serialize_str("-inf"),
+            value if value.to_bits() == f64::NAN.to_bits() => serializer.serialize_str("nan"),
+            value => serializer.serialize_str(&format!("{:#018x}", value.to_bits())),
+        }
+    }
+
+    /// Either of the forms written by [`serialize`].
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Float {
+        Number(f64),
+        Text(String),
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        let text = match Float::deserialize(deserializer)? {
+            Float::Number(value) => return Ok(value),
+            Float::Text(text) => text,
+        };
+
+        match text.as_str() {
+            "inf" => Ok(f64::INFINITY),
+            "-inf" => Ok(f64::NEG_INFINITY),
+            "nan" => Ok(f64::NAN),
+            _ => text
+                .strip_prefix("0x")
+                .and_then(|bits| u64::from_str_radix(bits, 16).ok())
+                .map(f64::from_bits)
+                .ok_or_else(|| D::Error::custom(format!("invalid float {:?}", text))),
+        }
+    }
+}
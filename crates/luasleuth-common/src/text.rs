@@ -0,0 +1,569 @@
+//! Parsing of the textual assembly language.
+//!
+//! The syntax is shared by every format, each format crate turns a parsed [`Chunk`] into
+//! its own bytecode. See `docs/assembly.md` for a description of the language.
+
+use std::{collections::HashMap, fmt};
+
+use crate::types::Escaped;
+
+/// An error found while parsing or building a chunk written in assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextError {
+    /// The line the error is on, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl TextError {
+    pub fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// An operand of a directive or instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Integer(i64),
+    Number(f64),
+    String(Vec<u8>),
+    /// A bare word, such as a label, a function name, `nil`, `true` or `false`
+    Name(String),
+}
+
+impl Operand {
+    /// Returns the operand as an integer of type `T`, failing when it is not one or out of range.
+    pub fn integer<T: TryFrom<i64>>(&self, line: usize) -> Result<T, TextError> {
+        match self {
+            Operand::Integer(value) => T::try_from(*value)
+                .map_err(|_| TextError::new(line, format!("Operand {} is out of range", value))),
+            _ => Err(TextError::new(
+                line,
+                format!("Expected an integer, found {}", self),
+            )),
+        }
+    }
+
+    /// Returns the operand as an unsigned integer that fits in `bits` bits.
+    pub fn unsigned(&self, line: usize, bits: u8) -> Result<u32, TextError> {
+        let value: i64 = self.integer(line)?;
+        if !(0..1 << bits).contains(&value) {
+            return Err(TextError::new(
+                line,
+                format!("Operand {} does not fit in {} bits", value, bits),
+            ));
+        }
+
+        Ok(value as u32)
+    }
+
+    /// Returns the operand as a number, integers being converted.
+    pub fn number(&self, line: usize) -> Result<f64, TextError> {
+        match self {
+            Operand::Integer(value) => Ok(*value as f64),
+            Operand::Number(value) => Ok(*value),
+            _ => Err(TextError::new(
+                line,
+                format!("Expected a number, found {}", self),
+            )),
+        }
+    }
+
+    /// Returns the bytes of a string operand.
+    pub fn string(&self, line: usize) -> Result<&[u8], TextError> {
+        match self {
+            Operand::String(value) => Ok(value),
+            _ => Err(TextError::new(
+                line,
+                format!("Expected a string, found {}", self),
+            )),
+        }
+    }
+
+    /// Returns the bytes of a string or name operand, as used for variable names.
+    pub fn identifier(&self, line: usize) -> Result<&[u8], TextError> {
+        match self {
+            Operand::String(value) => Ok(value),
+            Operand::Name(value) => Ok(value.as_bytes()),
+            _ => Err(TextError::new(
+                line,
+                format!("Expected a name or string, found {}", self),
+            )),
+        }
+    }
+
+    /// Returns the word of a name operand.
+    pub fn name(&self, line: usize) -> Result<&str, TextError> {
+        match self {
+            Operand::Name(value) => Ok(value),
+            _ => Err(TextError::new(
+                line,
+                format!("Expected a name, found {}", self),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Integer(value) => write!(f, "{}", value),
+            Operand::Number(value) => write!(f, "{:?}", value),
+            Operand::String(value) => write!(f, "\"{}\"", Escaped(value)),
+            Operand::Name(value) => f.write_str(value),
+        }
+    }
+}
+
+/// A directive or an instruction: a name followed by its operands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub line: usize,
+    /// The mnemonic of an instruction, or the name of a directive without its dot
+    pub name: String,
+    pub operands: Vec<Operand>,
+}
+
+impl Statement {
+    /// Create an error located at the statement.
+    pub fn error(&self, message: impl Into<String>) -> TextError {
+        TextError::new(self.line, message)
+    }
+
+    /// Returns the operand at `index`, failing when the statement has fewer operands.
+    pub fn operand(&self, index: usize) -> Result<&Operand, TextError> {
+        self.operands
+            .get(index)
+            .ok_or_else(|| self.error(format!("{} is missing operand {}", self.name, index + 1)))
+    }
+
+    /// Fail when the statement has more than `count` operands.
+    pub fn expect_at_most(&self, count: usize) -> Result<(), TextError> {
+        if self.operands.len() > count {
+            return Err(self.error(format!(
+                "{} takes at most {} operands, found {}",
+                self.name,
+                count,
+                self.operands.len()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the error for a directive the format does not know.
+    pub fn unknown_directive(&self) -> TextError {
+        self.error(format!("Unknown directive .{}", self.name))
+    }
+}
+
+/// An entry of a function body, in the order it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Directive(Statement),
+    Label { line: usize, name: String },
+    Instruction(Statement),
+    Function(Function),
+}
+
+/// A function, started by `.function` and ended by `.end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub line: usize,
+    pub name: String,
+    pub items: Vec<Item>,
+}
+
+impl Function {
+    /// Returns the instructions of the function, in order.
+    pub fn instructions(&self) -> impl Iterator<Item = &Statement> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Instruction(statement) => Some(statement),
+            _ => None,
+        })
+    }
+
+    /// Returns the functions nested directly in this one, in order.
+    pub fn children(&self) -> impl Iterator<Item = &Function> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Function(function) => Some(function),
+            _ => None,
+        })
+    }
+
+    /// Collect the labels and child functions operands of this function can refer to.
+    pub fn scope(&self) -> Result<Scope<'_>, TextError> {
+        let mut scope = Scope {
+            labels: HashMap::new(),
+            children: HashMap::new(),
+        };
+
+        let mut pc = 0;
+        for item in &self.items {
+            match item {
+                Item::Instruction(_) => pc += 1,
+                Item::Label { line, name } => {
+                    if scope.labels.insert(name, pc).is_some() {
+                        return Err(TextError::new(
+                            *line,
+                            format!("Label {} is defined more than once", name),
+                        ));
+                    }
+                }
+                Item::Function(function) => {
+                    let index = scope.children.len();
+                    if scope.children.insert(&function.name, index).is_some() {
+                        return Err(TextError::new(
+                            function.line,
+                            format!("Function {} is defined more than once", function.name),
+                        ));
+                    }
+                }
+                Item::Directive(_) => {}
+            }
+        }
+
+        Ok(scope)
+    }
+}
+
+/// Labels and child functions of a function, used to resolve operands that name them.
+#[derive(Debug)]
+pub struct Scope<'a> {
+    labels: HashMap<&'a str, usize>,
+    children: HashMap<&'a str, usize>,
+}
+
+impl Scope<'_> {
+    /// Returns the instruction index an operand refers to, either by label or by index.
+    pub fn position(&self, operand: &Operand, line: usize) -> Result<i64, TextError> {
+        match operand {
+            Operand::Name(name) => self
+                .labels
+                .get(name.as_str())
+                .map(|&pc| pc as i64)
+                .ok_or_else(|| TextError::new(line, format!("Unknown label {}", name))),
+            operand => operand.integer(line),
+        }
+    }
+
+    /// Returns the jump offset an operand refers to, relative to the instruction after `pc`.
+    ///
+    /// Labels are converted to offsets, integers are taken as is.
+    pub fn jump(&self, operand: &Operand, pc: usize, line: usize) -> Result<i64, TextError> {
+        match operand {
+            Operand::Name(_) => Ok(self.position(operand, line)? - (pc as i64 + 1)),
+            operand => operand.integer(line),
+        }
+    }
+
+    /// Returns the index of the child function an operand refers to, either by name or by index.
+    pub fn child(&self, operand: &Operand, line: usize) -> Result<i64, TextError> {
+        match operand {
+            Operand::Name(name) => self
+                .children
+                .get(name.as_str())
+                .map(|&index| index as i64)
+                .ok_or_else(|| TextError::new(line, format!("Unknown function {}", name))),
+            operand => operand.integer(line),
+        }
+    }
+}
+
+/// A whole chunk, made of its main function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub main: Function,
+}
+
+/// Lookup of opcodes by their mnemonic.
+///
+/// Mnemonics are the names of the variants of a format's `Opcode` enum. They are matched
+/// without regard to case, and the `OP_` prefix of the official formats can be left out.
+pub struct Mnemonics<O> {
+    opcodes: HashMap<String, O>,
+}
+
+impl<O: Copy + fmt::Debug + TryFrom<u8>> Mnemonics<O> {
+    pub fn new() -> Self {
+        let opcodes = (0..=u8::MAX)
+            .filter_map(|value| O::try_from(value).ok())
+            .map(|opcode| (normalize_mnemonic(&format!("{:?}", opcode)), opcode))
+            .collect();
+
+        Self { opcodes }
+    }
+
+    /// Returns the opcode of an instruction.
+    pub fn find(&self, instruction: &Statement) -> Result<O, TextError> {
+        self.opcodes
+            .get(&normalize_mnemonic(&instruction.name))
+            .copied()
+            .ok_or_else(|| instruction.error(format!("Unknown mnemonic {}", instruction.name)))
+    }
+}
+
+impl<O: Copy + fmt::Debug + TryFrom<u8>> Default for Mnemonics<O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn normalize_mnemonic(mnemonic: &str) -> String {
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    match mnemonic.strip_prefix("OP_") {
+        Some(stripped) => stripped.to_string(),
+        None => mnemonic,
+    }
+}
+
+/// Parse a chunk written in assembly.
+pub fn parse(source: &str) -> Result<Chunk, TextError> {
+    // Functions that are still open, the innermost one last
+    let mut open: Vec<Function> = Vec::new();
+    let mut main: Option<Function> = None;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut operands = tokenize(text, line)?.into_iter();
+
+        let Some(first) = operands.next() else {
+            continue;
+        };
+        let Operand::Name(mut word) = first else {
+            return Err(TextError::new(
+                line,
+                format!("Expected a directive, label or mnemonic, found {}", first),
+            ));
+        };
+
+        if let Some(name) = word.strip_suffix(':') {
+            let function = open
+                .last_mut()
+                .ok_or_else(|| TextError::new(line, "Label outside of a function"))?;
+            function.items.push(Item::Label {
+                line,
+                name: name.to_string(),
+            });
+
+            // An instruction can follow the label on the same line
+            word = match operands.next() {
+                Some(Operand::Name(word)) => word,
+                Some(operand) => {
+                    return Err(TextError::new(
+                        line,
+                        format!("Expected a mnemonic, found {}", operand),
+                    ))
+                }
+                None => continue,
+            };
+        }
+
+        let statement = |name: &str, operands: std::vec::IntoIter<Operand>| Statement {
+            line,
+            name: name.to_string(),
+            operands: operands.collect(),
+        };
+
+        match word.strip_prefix('.') {
+            Some("function") => {
+                let statement = statement("function", operands);
+                statement.expect_at_most(1)?;
+                if main.is_some() {
+                    return Err(statement.error("A chunk has a single main function"));
+                }
+
+                open.push(Function {
+                    line,
+                    name: statement.operand(0)?.name(line)?.to_string(),
+                    items: Vec::new(),
+                });
+            }
+            Some("end") => {
+                statement("end", operands).expect_at_most(0)?;
+                let function = open
+                    .pop()
+                    .ok_or_else(|| TextError::new(line, ".end without a function to end"))?;
+
+                match open.last_mut() {
+                    Some(parent) => parent.items.push(Item::Function(function)),
+                    None => main = Some(function),
+                }
+            }
+            Some(name) => {
+                let function = open
+                    .last_mut()
+                    .ok_or_else(|| TextError::new(line, "Directive outside of a function"))?;
+                function
+                    .items
+                    .push(Item::Directive(statement(name, operands)));
+            }
+            None => {
+                let function = open
+                    .last_mut()
+                    .ok_or_else(|| TextError::new(line, "Instruction outside of a function"))?;
+                function
+                    .items
+                    .push(Item::Instruction(statement(&word, operands)));
+            }
+        }
+    }
+
+    if let Some(function) = open.last() {
+        return Err(TextError::new(
+            function.line,
+            format!("Function {} is missing its .end", function.name),
+        ));
+    }
+
+    let main = main.ok_or_else(|| TextError::new(1, "The chunk has no main function"))?;
+    Ok(Chunk { main })
+}
+
+/// Split a line into operands, leaving out its comment.
+fn tokenize(text: &str, line: usize) -> Result<Vec<Operand>, TextError> {
+    let mut operands = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() || c == ',' => {
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                operands.push(Operand::String(read_string(&mut chars, line)?));
+            }
+            _ => {
+                let mut end = text.len();
+                while let Some(&(index, c)) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, ',' | ';' | '"') {
+                        end = index;
+                        break;
+                    }
+                    chars.next();
+                }
+
+                operands.push(parse_word(&text[start..end]));
+            }
+        }
+    }
+
+    Ok(operands)
+}
+
+/// Classify a bare word as an integer, a number or a name.
+fn parse_word(word: &str) -> Operand {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+
+    let integer = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|value| value as i64),
+        None if digits.bytes().all(|b| b.is_ascii_digit()) => digits.parse::<i64>().ok(),
+        None => None,
+    };
+    if let Some(integer) = integer {
+        return Operand::Integer(if negative {
+            integer.wrapping_neg()
+        } else {
+            integer
+        });
+    }
+
+    let is_number = digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        || matches!(digits, "inf" | "nan");
+    match word.parse::<f64>() {
+        Ok(number) if is_number => Operand::Number(number),
+        _ => Operand::Name(word.to_string()),
+    }
+}
+
+/// Read the rest of a string literal, after its opening quote.
+fn read_string(
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+    line: usize,
+) -> Result<Vec<u8>, TextError> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let Some((_, c)) = chars.next() else {
+            return Err(TextError::new(line, "Unterminated string"));
+        };
+
+        match c {
+            '"' => return Ok(bytes),
+            '\\' => {
+                let Some((_, escape)) = chars.next() else {
+                    return Err(TextError::new(line, "Unterminated string"));
+                };
+
+                let byte = match escape {
+                    'n' => b'\n',
+                    'r' => b'\r',
+                    't' => b'\t',
+                    'a' => 0x07,
+                    'b' => 0x08,
+                    'f' => 0x0c,
+                    'v' => 0x0b,
+                    '\\' => b'\\',
+                    '"' => b'"',
+                    '\'' => b'\'',
+                    'x' => {
+                        let mut value = 0;
+                        for _ in 0..2 {
+                            let digit = chars
+                                .next()
+                                .and_then(|(_, c)| c.to_digit(16))
+                                .ok_or_else(|| TextError::new(line, "Invalid \\x escape"))?;
+                            value = value * 16 + digit;
+                        }
+                        value as u8
+                    }
+                    c if c.is_ascii_digit() => {
+                        // Up to three decimal digits, as in Lua
+                        let mut value = c.to_digit(10).unwrap_or_default();
+                        for _ in 0..2 {
+                            match chars.peek().and_then(|(_, c)| c.to_digit(10)) {
+                                Some(digit) => {
+                                    value = value * 10 + digit;
+                                    chars.next();
+                                }
+                                None => break,
+                            }
+                        }
+                        u8::try_from(value)
+                            .map_err(|_| TextError::new(line, "Decimal escape too large"))?
+                    }
+                    c => {
+                        return Err(TextError::new(
+                            line,
+                            format!("Invalid escape sequence \\{}", c),
+                        ))
+                    }
+                };
+                bytes.push(byte);
+            }
+            c => {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+    }
+}
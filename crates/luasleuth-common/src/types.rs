@@ -4,13 +4,15 @@ mod string;
 mod unsigned;
 
 pub use array::Array;
-pub use string::LuaString;
+pub use string::{Escaped, LuaString};
 pub use unsigned::*;
 
+use crate::InvalidOpcode;
+
 /// Helper trait for implementing instruction encoding/decoding.
-pub trait Packable {
-    /// Decode an instruction from its raw value
-    fn decode(raw: u32) -> Self;
+pub trait Packable: Sized {
+    /// Decode an instruction from its raw value, failing if its opcode is unknown
+    fn decode(raw: u32) -> Result<Self, InvalidOpcode>;
     /// Encode an instruction into its raw value
     fn encode(inst: Self) -> u32;
 }
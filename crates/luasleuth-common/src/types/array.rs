@@ -1,43 +1,43 @@
-use crate::{try_gread_vec_with, try_gwrite_vec_with, CommonCtx};
+use crate::{try_gwrite_vec_with, CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
 use super::LuaUnsigned;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Array<T> {
     pub size: usize,
     pub data: Vec<T>,
 }
 
 impl<T> Array<T> {
-    pub fn read_size(
-        src: &[u8],
-        offset: &mut usize,
-        ctx: CommonCtx,
-    ) -> Result<usize, scroll::Error> {
+    /// Read the size of an array, checking it against the limits of `ctx`.
+    pub fn read_size(src: &[u8], offset: &mut usize, ctx: CommonCtx) -> Result<usize, Error> {
         let size = match ctx.lua_version.into_tuple() {
-            (5, 1) => src.gread_with::<i32>(offset, ctx.endianness)? as usize,
-            (5, 2) => src.gread_with::<i32>(offset, ctx.endianness)? as usize,
-            (5, 3) => src.gread_with::<i32>(offset, ctx.endianness)? as usize,
-            (5, 4) => src.gread_with::<LuaUnsigned>(offset, ctx.endianness)?.value,
-            _ => return Err(scroll::Error::Custom("Unsupported Lua version".into())),
+            (5, 1) => ctx.read_int(src, offset)? as u64,
+            (5, 2) => ctx.read_int(src, offset)? as u64,
+            (5, 3) => ctx.read_int(src, offset)? as u64,
+            (5, 4) => src.gread_with::<LuaUnsigned>(offset, ctx.endianness)?.value as u64,
+            _ => return Err(Error::invalid("Unsupported Lua version", 0)),
         };
 
-        Ok(size)
+        ctx.limits.check_count(size, src.len() - *offset)
     }
 
+    /// Write the size of an array, which is taken from `data` rather than the stored `size`.
     pub fn write_size(
         &self,
         dst: &mut [u8],
         offset: &mut usize,
         ctx: CommonCtx,
     ) -> Result<usize, scroll::Error> {
+        let size = self.data.len();
         let bytes_written = match ctx.lua_version.into_tuple() {
-            (5, 1) => dst.gwrite_with(self.size as i32, offset, ctx.endianness)?,
-            (5, 2) => dst.gwrite_with(self.size as i32, offset, ctx.endianness)?,
-            (5, 3) => dst.gwrite_with(self.size as i32, offset, ctx.endianness)?,
+            (5, 1) => ctx.write_int(dst, offset, size as i64)?,
+            (5, 2) => ctx.write_int(dst, offset, size as i64)?,
+            (5, 3) => ctx.write_int(dst, offset, size as i64)?,
             (5, 4) => {
-                let size = LuaUnsigned::new(self.size);
+                let size = LuaUnsigned::new(size);
                 dst.gwrite_with(size, offset, ctx.endianness)?
             }
             _ => return Err(scroll::Error::Custom("Unsupported Lua version".into())),
@@ -63,26 +63,35 @@ impl<T> From<Vec<T>> for Array<T> {
 }
 
 impl ctx::TryFromCtx<'_, CommonCtx> for Array<i32> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'_ [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
         let size = Array::<i32>::read_size(src, offset, ctx)?; // Is the generic really needed?
-        let data: Vec<i32> = try_gread_vec_with!(src, offset, size, ctx.endianness);
+
+        let mut data = Vec::with_capacity(size);
+        for index in 0..size {
+            data.push(ctx.read_int(src, offset).within_index(*offset, index)? as i32);
+        }
 
         Ok((Self { size, data }, *offset))
     }
 }
 
 impl ctx::TryFromCtx<'_, CommonCtx> for Array<u8> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'_ [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
         let size = Array::<u8>::read_size(src, offset, ctx)?; // Is the generic really needed?
-        let data: Vec<u8> = try_gread_vec_with!(src, offset, size, ctx.endianness);
+        let data: Vec<u8> = src
+            .get(*offset..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or_else(|| Error::invalid("Array extends past the end of the input", *offset))?
+            .to_vec();
+        *offset += size;
 
         Ok((Self { size, data }, *offset))
     }
@@ -95,7 +104,9 @@ impl ctx::TryIntoCtx<CommonCtx> for Array<i32> {
         let offset = &mut 0;
 
         Self::write_size(&self, dst, offset, ctx)?;
-        try_gwrite_vec_with!(dst, offset, self.data, ctx.endianness);
+        for value in self.data {
+            ctx.write_int(dst, offset, value as i64)?;
+        }
 
         Ok(*offset)
     }
@@ -114,16 +125,22 @@ impl ctx::TryIntoCtx<CommonCtx> for Array<u8> {
     }
 }
 
-impl<'a, T: 'a> ctx::TryFromCtx<'a, CommonCtx> for Array<T>
+impl<'a, T: 'a, E> ctx::TryFromCtx<'a, CommonCtx> for Array<T>
 where
-    T: ctx::TryFromCtx<'a, CommonCtx, Error = scroll::Error>,
+    T: ctx::TryFromCtx<'a, CommonCtx, Error = E>,
+    E: From<scroll::Error>,
+    Error: From<E>,
 {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
         let size = Array::<T>::read_size(src, offset, ctx)?; // Is the generic really needed?
-        let data: Vec<T> = try_gread_vec_with!(src, offset, size, ctx);
+
+        let mut data = Vec::with_capacity(size);
+        for index in 0..size {
+            data.push(src.gread_with(offset, ctx).within_index(*offset, index)?);
+        }
 
         Ok((Array { size, data }, *offset))
     }
@@ -1,4 +1,6 @@
 // Code copied from: https://github.com/Sculas/scroll/blob/998df554f374e1f13e166c720a1f69a2db06a53e/src/leb128.rs
+// Kept as upstream wrote it, importing the legacy `u8` module
+#![allow(clippy::legacy_numeric_constants)]
 
 use core::convert::{AsRef, From};
 use core::u8;
@@ -6,6 +8,7 @@ use scroll::ctx::{TryFromCtx, TryIntoCtx};
 use scroll::{Error, Pread, Pwrite};
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 /// An unsigned leb128 integer
 pub struct Uleb128 {
     value: u64,
@@ -33,6 +36,16 @@ impl Uleb128 {
         dst.gwrite(Uleb128 { value, count: 0 }, offset)?;
         Ok(())
     }
+
+    /// Create a Uleb128 whose size is the size of its encoding
+    pub fn encoded(value: u64) -> Self {
+        let count = &mut 0;
+        Self::write(&mut [0; 10], count, value).expect("a u64 fits in 10 bytes");
+        Self {
+            value,
+            count: *count,
+        }
+    }
 }
 
 impl AsRef<u64> for Uleb128 {
@@ -70,6 +83,7 @@ impl From<usize> for Uleb128 {
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 /// An signed leb128 integer
 pub struct Sleb128 {
     value: i64,
@@ -97,6 +111,16 @@ impl Sleb128 {
         dst.gwrite(Sleb128 { value, count: 0 }, offset)?;
         Ok(())
     }
+
+    /// Create a Sleb128 whose size is the size of its encoding
+    pub fn encoded(value: i64) -> Self {
+        let count = &mut 0;
+        Self::write(&mut [0; 10], count, value).expect("an i64 fits in 10 bytes");
+        Self {
+            value,
+            count: *count,
+        }
+    }
 }
 
 impl AsRef<i64> for Sleb128 {
@@ -1,77 +1,109 @@
-use crate::{types::LuaUnsigned, CommonCtx};
-use scroll::{
-    ctx::{self, StrCtx},
-    Pread, Pwrite,
-};
+use crate::{types::LuaUnsigned, CommonCtx, Error};
+use scroll::{ctx, Pread, Pwrite};
+use std::{borrow::Cow, fmt};
 
 /// An official Lua string
-#[derive(Debug)]
+///
+/// Lua strings are arbitrary bytes, which don't have to be valid UTF-8.
+#[derive(Clone, Default)]
 pub struct LuaString<'a> {
     pub size: usize,
-    pub data: &'a str,
+    pub data: Cow<'a, [u8]>,
 }
 
 impl<'a> LuaString<'a> {
-    /// Read a Lua 5.1/5.2 string.
+    /// Returns the raw bytes of the string.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the string if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+
+    /// Returns the string with invalid UTF-8 sequences replaced by `U+FFFD`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.data)
+    }
+
+    /// Returns a displayable form of the string with control characters and invalid
+    /// UTF-8 escaped, as they would be in a Lua string literal.
+    pub fn escaped(&self) -> Escaped<'_> {
+        Escaped(&self.data)
+    }
+
+    /// Read a Lua 5.1/5.2 string, which is `None` for a `NULL` string.
     pub fn read_lua51_string(
         src: &'a [u8],
         offset: &mut usize,
         ctx: CommonCtx,
-    ) -> Result<Self, scroll::Error> {
-        let size: usize = match ctx.size_of_size_t {
-            4 => src.gread_with::<i32>(offset, ctx.endianness)? as usize,
-            8 => src.gread_with::<i64>(offset, ctx.endianness)? as usize,
-            _ => unimplemented!(),
-        };
+    ) -> Result<Option<Self>, Error> {
+        let size = ctx.read_size_t(src, offset)?;
+        let size = ctx.limits.check_string_length(size, src.len() - *offset)?;
 
         if size == 0 {
-            return Ok(LuaString { size: 0, data: "" });
+            return Ok(None);
         }
 
         let size = size - 1; // Remove the null byte
-        let data: &str = src.gread_with(offset, StrCtx::Length(size))?;
-        *offset += 1; // null terminator
+        let data: &[u8] = src.gread_with(offset, size)?;
+        src.gread_with::<u8>(offset, ctx.endianness)?; // null terminator
 
-        Ok(LuaString { size, data })
+        Ok(Some(LuaString {
+            size,
+            data: data.into(),
+        }))
     }
 
-    /// Read a Lua 5.3 string.
+    /// Read a Lua 5.3 string, which is `None` for a `NULL` string.
     pub fn read_lua53_string(
         src: &'a [u8],
         offset: &mut usize,
         ctx: CommonCtx,
-    ) -> Result<Self, scroll::Error> {
-        let size = src.gread_with::<u8>(offset, ctx.endianness)? as usize;
-        if size == 0xFF {
-            return Self::read_lua51_string(src, offset, ctx);
-        }
+    ) -> Result<Option<Self>, Error> {
+        // Sizes that don't fit in a byte follow a 0xFF marker as a `size_t`
+        let size = match src.gread_with::<u8>(offset, ctx.endianness)? {
+            0xFF => ctx.read_size_t(src, offset)?,
+            size => size as u64,
+        };
 
         if size == 0 {
-            return Ok(LuaString { size: 0, data: "" });
+            return Ok(None);
         }
 
-        // I dont know why we're doing size - 1, but if i dont it fucks up.
-        let size = size - 1;
-        let data: &str = src.gread_with(offset, StrCtx::Length(size))?;
+        // The size includes the null terminator, which is not dumped
+        let size = ctx
+            .limits
+            .check_string_length(size - 1, src.len() - *offset)?;
+        let data: &[u8] = src.gread_with(offset, size)?;
 
-        Ok(LuaString { size, data })
+        Ok(Some(LuaString {
+            size,
+            data: data.into(),
+        }))
     }
 
-    /// Read a Lua 5.4 string.
+    /// Read a Lua 5.4 string, which is `None` for a `NULL` string.
     pub fn read_lua54_string(
         src: &'a [u8],
         offset: &mut usize,
         ctx: CommonCtx,
-    ) -> Result<Self, scroll::Error> {
+    ) -> Result<Option<Self>, Error> {
         let size: LuaUnsigned = src.gread_with(offset, ctx.endianness)?;
         if size.value == 0 {
-            return Ok(LuaString { size: 0, data: "" });
+            return Ok(None);
         }
 
-        let size = size.value - 1;
-        let data: &str = src.gread_with(offset, StrCtx::Length(size))?;
+        let size = ctx
+            .limits
+            .check_string_length(size.value as u64 - 1, src.len() - *offset)?;
+        let data: &[u8] = src.gread_with(offset, size)?;
 
-        Ok(LuaString { size, data })
+        Ok(Some(LuaString {
+            size,
+            data: data.into(),
+        }))
     }
 
     /// Write a Lua 5.1/5.2 string.
@@ -81,13 +113,9 @@ impl<'a> LuaString<'a> {
         offset: &mut usize,
         ctx: CommonCtx,
     ) -> Result<usize, scroll::Error> {
-        match ctx.size_of_size_t {
-            4 => dst.gwrite_with(self.size as i32 + 1, offset, ctx.endianness)?,
-            8 => dst.gwrite_with(self.size as i64 + 1, offset, ctx.endianness)?,
-            _ => unimplemented!(),
-        };
+        ctx.write_size_t(dst, offset, self.data.len() as u64 + 1)?;
 
-        dst.gwrite_with(self.data, offset, ())?;
+        dst.gwrite_with(&*self.data, offset, ())?;
         dst.gwrite_with(b'\0', offset, ctx.endianness)?;
 
         Ok(*offset)
@@ -96,36 +124,59 @@ impl<'a> LuaString<'a> {
     /// Write a Lua 5.3 string.
     pub fn write_lua53_string(
         self,
-        _dst: &mut [u8],
-        _offset: &mut usize,
-        _ctx: CommonCtx,
+        dst: &mut [u8],
+        offset: &mut usize,
+        ctx: CommonCtx,
     ) -> Result<usize, scroll::Error> {
-        todo!()
+        let size = self.data.len() + 1;
+        if size < 0xFF {
+            dst.gwrite_with(size as u8, offset, ctx.endianness)?;
+        } else {
+            dst.gwrite_with(0xFFu8, offset, ctx.endianness)?;
+            ctx.write_size_t(dst, offset, size as u64)?;
+        }
+
+        dst.gwrite_with(&*self.data, offset, ())?;
+
+        Ok(*offset)
     }
 
     /// Write a Lua 5.4 string.
     pub fn write_lua54_string(
         self,
-        _dst: &mut [u8],
-        _offset: &mut usize,
-        _ctx: CommonCtx,
+        dst: &mut [u8],
+        offset: &mut usize,
+        ctx: CommonCtx,
     ) -> Result<usize, scroll::Error> {
-        todo!()
+        dst.gwrite_with(LuaUnsigned::new(self.data.len() + 1), offset, ctx)?;
+        dst.gwrite_with(&*self.data, offset, ())?;
+
+        Ok(*offset)
     }
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for LuaString<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
+
+    /// Read a string, where a `NULL` string is read as an empty one.
+    fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
+        let (string, size) = Option::<LuaString>::try_from_ctx(src, ctx)?;
+        Ok((string.unwrap_or_default(), size))
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Option<LuaString<'a>> {
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
         let string = match ctx.lua_version.into_tuple() {
-            (5, 1) => Self::read_lua51_string(src, offset, ctx)?,
-            (5, 2) => Self::read_lua51_string(src, offset, ctx)?, // Lua 5.1 and 5.2 string dumping is the exact same.
-            (5, 3) => Self::read_lua53_string(src, offset, ctx)?,
-            (5, 4) => Self::read_lua54_string(src, offset, ctx)?,
-            _ => unimplemented!(),
+            (5, 1) => LuaString::read_lua51_string(src, offset, ctx)?,
+            (5, 2) => LuaString::read_lua51_string(src, offset, ctx)?, // Lua 5.1 and 5.2 string dumping is the exact same.
+            (5, 3) => LuaString::read_lua53_string(src, offset, ctx)?,
+            (5, 4) => LuaString::read_lua54_string(src, offset, ctx)?,
+            _ => return Err(Error::invalid("Unsupported Lua version", 0)),
         };
 
         Ok((string, *offset))
@@ -142,16 +193,43 @@ impl ctx::TryIntoCtx<CommonCtx> for LuaString<'_> {
             (5, 2) => self.write_lua51_string(dst, offset, ctx),
             (5, 3) => self.write_lua53_string(dst, offset, ctx),
             (5, 4) => self.write_lua54_string(dst, offset, ctx),
-            _ => unimplemented!(),
+            _ => Err(scroll::Error::Custom("Unsupported Lua version".into())),
+        }
+    }
+}
+
+impl ctx::TryIntoCtx<CommonCtx> for Option<LuaString<'_>> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
+        if let Some(string) = self {
+            return dst.pwrite_with(string, 0, ctx);
         }
+
+        // A `NULL` string is dumped as just a size of 0
+        let offset = &mut 0;
+        match ctx.lua_version.into_tuple() {
+            (5, 1) | (5, 2) => ctx.write_size_t(dst, offset, 0)?,
+            (5, 3) => dst.gwrite_with(0u8, offset, ctx.endianness)?,
+            (5, 4) => dst.gwrite_with(LuaUnsigned::new(0), offset, ctx)?,
+            _ => return Err(scroll::Error::Custom("Unsupported Lua version".into())),
+        };
+
+        Ok(*offset)
     }
 }
 
 impl<'a> From<&'a str> for LuaString<'a> {
     fn from(value: &'a str) -> Self {
+        Self::from(value.as_bytes())
+    }
+}
+
+impl<'a> From<&'a [u8]> for LuaString<'a> {
+    fn from(value: &'a [u8]) -> Self {
         Self {
             size: value.len(),
-            data: value,
+            data: value.into(),
         }
     }
 }
@@ -164,14 +242,60 @@ impl PartialEq for LuaString<'_> {
 
 impl PartialEq<&str> for LuaString<'_> {
     fn eq(&self, other: &&str) -> bool {
-        self.data == *other && self.size == other.len()
+        *self.data == *other.as_bytes() && self.size == other.len()
     }
 }
 
 impl Eq for LuaString<'_> {}
 
-impl std::fmt::Display for LuaString<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.data)
+impl fmt::Debug for LuaString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LuaString")
+            .field("size", &self.size)
+            .field("data", &format_args!("\"{}\"", self.escaped()))
+            .finish()
+    }
+}
+
+impl fmt::Display for LuaString<'_> {
+    /// Writes the string lossily, see [`LuaString::to_string_lossy`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+/// Displays bytes the way they would be written inside a quoted Lua string literal.
+///
+/// Quotes, backslashes and the common whitespace escapes are escaped with a backslash,
+/// other control characters and bytes that are not part of valid UTF-8 are written
+/// as decimal `\ddd` escapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Escaped<'a>(pub &'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '\\' => f.write_str("\\\\")?,
+                    '"' => f.write_str("\\\"")?,
+                    '\n' => f.write_str("\\n")?,
+                    '\r' => f.write_str("\\r")?,
+                    '\t' => f.write_str("\\t")?,
+                    c if c.is_control() => {
+                        for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                            write!(f, "\\{:03}", byte)?;
+                        }
+                    }
+                    c => write!(f, "{}", c)?,
+                }
+            }
+
+            for byte in chunk.invalid() {
+                write!(f, "\\{:03}", byte)?;
+            }
+        }
+
+        Ok(())
     }
 }
@@ -2,7 +2,12 @@ use scroll::{ctx, Endian, Error, Pread, Pwrite, Result};
 
 use crate::CommonCtx;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct LuaUnsigned {
     pub value: usize,
 }
@@ -44,23 +49,26 @@ impl<'a> ctx::TryFromCtx<'a, Endian> for LuaUnsigned {
 impl ctx::TryIntoCtx<Endian> for LuaUnsigned {
     type Error = Error;
 
-    fn try_into_ctx(self, dst: &mut [u8], _ctx: Endian) -> Result<usize> {
+    fn try_into_ctx(self, dst: &mut [u8], ctx: Endian) -> Result<usize> {
         let offset = &mut 0;
-        let mut x = self.value;
 
+        // Split the value into 7 bit groups, least significant first
+        let mut groups = [0u8; (usize::BITS as usize).div_ceil(7)];
+        let mut count = 0;
+        let mut x = self.value;
         loop {
-            let mut byte = (x & 0x7f) as u8;
+            groups[count] = (x & 0x7f) as u8;
+            count += 1;
             x >>= 7;
-
-            // If this is the last byte, set the highest bit
             if x == 0 {
-                byte |= 0x80; // Set high bit to mark end
-                dst.gwrite_with(byte, offset, _ctx)?;
                 break;
             }
+        }
 
-            // More bytes coming, leave high bit as 0
-            dst.gwrite_with(byte, offset, _ctx)?;
+        // Groups are written most significant first, the last one having its highest bit set
+        for (index, &group) in groups[..count].iter().enumerate().rev() {
+            let byte = if index == 0 { group | 0x80 } else { group };
+            dst.gwrite_with(byte, offset, ctx)?;
         }
 
         Ok(*offset)
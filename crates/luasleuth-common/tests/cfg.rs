@@ -0,0 +1,76 @@
+use luasleuth_common::cfg::{skip_next, ControlFlowGraph, Edge, EdgeKind};
+
+/// A loop around an if statement, followed by dead code:
+///
+/// ```text
+/// 0: test           -> 1 true, 2 false
+/// 1: jump 3
+/// 2: fallthrough
+/// 3: loop back to 0 -> 0 true, 4 false
+/// 4: return
+/// 5: dead
+/// ```
+fn graph() -> ControlFlowGraph {
+    ControlFlowGraph::build(6, |pc| match pc {
+        0 => skip_next(0),
+        1 => vec![Edge::new(3, EdgeKind::Jump)],
+        3 => vec![Edge::new(0, EdgeKind::True), Edge::new(4, EdgeKind::False)],
+        4 => vec![],
+        pc => vec![Edge::new(pc + 1, EdgeKind::Fallthrough)],
+    })
+}
+
+#[test]
+fn test_splits_blocks_at_branches_and_targets() {
+    let graph = graph();
+    let bounds: Vec<(usize, usize)> = graph
+        .blocks()
+        .iter()
+        .map(|block| (block.start, block.end))
+        .collect();
+
+    assert_eq!(bounds, [(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6)]);
+    assert_eq!(
+        graph.blocks()[0].successors,
+        [Edge::new(1, EdgeKind::True), Edge::new(2, EdgeKind::False)]
+    );
+    assert_eq!(graph.blocks()[3].predecessors, [1, 2]);
+    assert_eq!(graph.blocks()[0].predecessors, [3]);
+    assert_eq!(graph.block_at(3), Some(3));
+    assert_eq!(graph.block_at(6), None);
+}
+
+#[test]
+fn test_finds_dominators_and_loops() {
+    let graph = graph();
+
+    assert_eq!(
+        graph.immediate_dominators(),
+        [None, Some(0), Some(0), Some(0), Some(3), None]
+    );
+    assert!(graph.dominates(0, 4));
+    assert!(!graph.dominates(1, 3));
+    assert!(!graph.dominates(0, 5));
+    assert!(graph.is_back_edge(3, 0));
+    assert!(!graph.is_back_edge(0, 1));
+
+    let loops = graph.loops();
+    assert_eq!(loops.len(), 1);
+    assert_eq!(loops[0].header, 0);
+    assert_eq!(loops[0].latches, [3]);
+    assert_eq!(loops[0].body, [0, 1, 2, 3]);
+}
+
+#[test]
+fn test_drops_edges_out_of_the_code() {
+    let graph = ControlFlowGraph::build(2, |pc| vec![Edge::new(pc + 5, EdgeKind::Jump)]);
+
+    assert_eq!(graph.blocks().len(), 2);
+    assert!(graph
+        .blocks()
+        .iter()
+        .all(|block| block.successors.is_empty()));
+    assert!(ControlFlowGraph::build(0, |_| Vec::new())
+        .blocks()
+        .is_empty());
+}
@@ -0,0 +1,98 @@
+use luasleuth_common::{
+    ast::{write_chunk, BinaryOperator, Expression, LocalName, Statement, UnaryOperator},
+    decompiler::{condition, short_circuit, Branch, Exit, Operand},
+};
+
+fn name(name: &str) -> Expression {
+    Expression::Name(name.to_string())
+}
+
+fn source(statements: Vec<Statement>) -> String {
+    let mut source = String::new();
+    write_chunk(&mut source, &statements).unwrap();
+    source
+}
+
+#[test]
+fn test_condition_rebuilds_and_or_chains() {
+    // `if a and b or c then`: `a` falsy tries `c`, `b` truthy enters the body
+    let branches = vec![
+        Branch {
+            condition: Expression::unary(UnaryOperator::Not, name("a")),
+            target: Exit::Branch(2),
+        },
+        Branch {
+            condition: name("b"),
+            target: Exit::True,
+        },
+        Branch {
+            condition: Expression::unary(UnaryOperator::Not, name("c")),
+            target: Exit::False,
+        },
+    ];
+    let condition = condition(&branches, Exit::True).unwrap();
+
+    let statement = Statement::If {
+        condition,
+        then: vec![],
+        otherwise: vec![],
+    };
+    assert_eq!(source(vec![statement]), "if a and b or c then\nend\n");
+}
+
+#[test]
+fn test_condition_rejects_jumps_leaving_to_the_fall_through() {
+    let branches = vec![Branch {
+        condition: name("a"),
+        target: Exit::True,
+    }];
+
+    assert_eq!(condition(&branches, Exit::True), None);
+}
+
+#[test]
+fn test_short_circuit_groups_operands() {
+    // `(a or b) and c`: `a` truthy skips `b`, the value of `a or b` falsy leaves
+    let operands = vec![
+        Operand {
+            value: name("a"),
+            jump: Some((true, 2)),
+        },
+        Operand {
+            value: name("b"),
+            jump: Some((false, 3)),
+        },
+        Operand {
+            value: name("c"),
+            jump: None,
+        },
+    ];
+    let value = short_circuit(&operands).unwrap();
+
+    let statement = Statement::Local {
+        names: vec![LocalName {
+            name: "x".to_string(),
+            attribute: None,
+        }],
+        values: vec![value],
+    };
+    assert_eq!(source(vec![statement]), "local x = (a or b) and c\n");
+}
+
+#[test]
+fn test_printer_parenthesizes_by_precedence() {
+    let sum = Expression::binary(BinaryOperator::Add, name("a"), name("b"));
+    let power = Expression::binary(BinaryOperator::Pow, name("c"), name("d"));
+    let value = Expression::binary(
+        BinaryOperator::Mul,
+        sum,
+        Expression::unary(UnaryOperator::Neg, power),
+    );
+    let statements = vec![Statement::Return(vec![value]), Statement::Break];
+
+    // Only the last statement of a block can be a `return`
+    assert_eq!(
+        source(statements),
+        "do return (a + b) * -c ^ d end\nbreak\n"
+    );
+}
@@ -0,0 +1,25 @@
+use luasleuth_common::{
+    cfg::{ControlFlowGraph, Edge, EdgeKind},
+    dot::write_cluster,
+};
+
+#[test]
+fn test_cluster_escapes_labels_and_expands_tabs() {
+    // A loop jumping back to its start, then a return
+    let graph = ControlFlowGraph::build(2, |pc| match pc {
+        0 => vec![Edge::new(0, EdgeKind::True), Edge::new(1, EdgeKind::False)],
+        _ => vec![],
+    });
+
+    let mut dot = String::new();
+    write_cluster(&mut dot, 3, "main <\"a\\b\">", &graph, |block| {
+        Ok(format!("\t{}\tLOADK\t\"x\"\n", block.start))
+    })
+    .unwrap();
+
+    assert!(dot.starts_with("    subgraph cluster_3 {\n        label=\"main <\\\"a\\\\b\\\">\";\n"));
+    assert!(dot.contains("f3b0 [label=\"        0       LOADK   \\\"x\\\"\\l\"];"));
+    assert!(dot.contains("f3b0 -> f3b0 [label=\"loop-back\"];"));
+    assert!(dot.contains("f3b0 -> f3b1 [label=\"false\"];"));
+    assert!(dot.ends_with("    }\n"));
+}
@@ -0,0 +1,49 @@
+use luasleuth_common::{Error, ErrorContext};
+
+#[test]
+fn test_error_paths_across_prototypes() {
+    // An invalid tag in the 13th constant of the second child of the fourth child of main
+    let error = Error::invalid("Invalid constant type", 0)
+        .within(0, "tag")
+        .within_index(20, 12)
+        .within(100, "constants")
+        .in_prototype()
+        .within_index(50, 1)
+        .within(200, "prototypes")
+        .in_prototype()
+        .within_index(10, 3)
+        .within(300, "prototypes")
+        .in_prototype()
+        .at(12);
+
+    assert_eq!(error.offset(), 692);
+    assert_eq!(error.field_path(), "constants[12].tag");
+    assert_eq!(error.prototype_path().as_deref(), Some("main/3/1"));
+}
+
+#[test]
+fn test_error_context_on_results() {
+    let bytes = [0u8; 2];
+    let offset = &mut 1;
+
+    let result: Result<u32, Error> =
+        scroll::Pread::gread(&bytes[..], offset).within(*offset, "size");
+    let error = result.unwrap_err();
+    assert_eq!(error.offset(), 1);
+    assert_eq!(error.field_path(), "size");
+    assert_eq!(error.prototype_path(), None);
+}
+
+#[test]
+fn test_error_report_marks_failing_byte() {
+    let bytes: Vec<u8> = (0..64).collect();
+    let report = Error::invalid("Bad byte", 0x21)
+        .within(0, "value")
+        .report(&bytes);
+
+    assert!(report.starts_with("error: Bad byte\n  --> offset 0x21"));
+    assert!(report.contains("00000020  20 21 22"));
+    assert!(report.contains("00000010"));
+    assert!(report.contains("00000030"));
+    assert!(report.contains("^^"));
+}
@@ -0,0 +1,24 @@
+use luasleuth_common::listing::{format_float, format_number, source_name, Quoted};
+
+#[test]
+fn test_format_number_like_printf() {
+    assert_eq!(format_number(1.0), "1");
+    assert_eq!(format_number(0.1), "0.1");
+    assert_eq!(format_number(-2.5), "-2.5");
+    assert_eq!(format_number(1e15), "1e+15");
+    assert_eq!(format_number(123456789012345.0), "1.2345678901234e+14");
+    assert_eq!(format_number(0.0001), "0.0001");
+    assert_eq!(format_number(0.00001), "1e-05");
+    assert_eq!(format_number(f64::INFINITY), "inf");
+    assert_eq!(format_number(-0.0), "-0");
+    assert_eq!(format_float(3.0), "3.0");
+    assert_eq!(format_float(1e100), "1e+100");
+}
+
+#[test]
+fn test_quotes_and_sources() {
+    assert_eq!(Quoted(b"a\"b\n\x01").to_string(), "\"a\\\"b\\n\\001\"");
+    assert_eq!(source_name(Some(b"@main.lua")), "main.lua");
+    assert_eq!(source_name(Some(b"return 1")), "(string)");
+    assert_eq!(source_name(None), "?");
+}
@@ -0,0 +1,13 @@
+use luasleuth_common::resolve::local_name;
+
+#[test]
+fn test_local_name_counts_active_locals_only() {
+    let locals: [(&[u8], u64, u64); 3] = [(b"a", 0, 10), (b"b", 2, 4), (b"c", 5, 10)];
+
+    assert_eq!(local_name(locals, 0, 0), Some(&b"a"[..]));
+    assert_eq!(local_name(locals, 1, 0), None);
+    assert_eq!(local_name(locals, 1, 3), Some(&b"b"[..]));
+    // `b` is dead by then, so `c` takes its register
+    assert_eq!(local_name(locals, 1, 6), Some(&b"c"[..]));
+    assert_eq!(local_name(locals, 0, 10), None);
+}
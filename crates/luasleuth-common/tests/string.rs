@@ -0,0 +1,112 @@
+use luasleuth_common::{
+    types::{Escaped, LuaString, LuaUnsigned},
+    CommonCtx, Limits, Version,
+};
+use scroll::{Pread, Pwrite, LE};
+
+fn ctx(minor: u8) -> CommonCtx {
+    CommonCtx {
+        size_of_size_t: 8,
+        lua_version: Version::new(5, minor),
+        endianness: LE,
+        size_of_int: 4,
+        size_of_instruction: 4,
+        size_of_lua_number: 8,
+        size_of_lua_integer: 8,
+        integral: false,
+        limits: Limits::default(),
+        depth: 0,
+    }
+}
+
+/// Write a string and check it is read back from exactly the expected bytes.
+fn assert_round_trip(string: &[u8], ctx: CommonCtx, expected: &[u8]) {
+    let mut bytes = vec![0; expected.len() + 16];
+    let size = bytes
+        .pwrite_with(LuaString::from(string), 0, ctx)
+        .expect("Failed to write string");
+    assert_eq!(&bytes[..size], expected);
+
+    let offset = &mut 0;
+    let read: LuaString = bytes[..size]
+        .gread_with(offset, ctx)
+        .expect("Failed to read string");
+    assert_eq!(read.as_bytes(), string);
+    assert_eq!(*offset, size);
+}
+
+#[test]
+fn test_escapes_bytes_like_a_lua_string_literal() {
+    let escaped = |bytes: &[u8]| Escaped(bytes).to_string();
+
+    assert_eq!(escaped(b"print"), "print");
+    assert_eq!(escaped(b"say \"hi\"\\\n"), "say \\\"hi\\\"\\\\\\n");
+    assert_eq!(escaped(b"\0\x1b[0m\x7f"), "\\000\\027[0m\\127");
+    assert_eq!(escaped("héllo".as_bytes()), "héllo");
+    assert_eq!(escaped(b"h\xe9llo\xc3"), "h\\233llo\\195");
+}
+
+#[test]
+fn test_string_is_only_utf8_when_valid() {
+    let string = LuaString::from("hello");
+    assert_eq!(string.as_str(), Some("hello"));
+    assert_eq!(string, "hello");
+
+    let string = LuaString::from(&b"\x8a\x01key"[..]);
+    assert_eq!(string.size, 5);
+    assert_eq!(string.as_str(), None);
+    assert_eq!(string.to_string_lossy(), "\u{fffd}\u{1}key");
+    assert_eq!(
+        format!("{:?}", string),
+        "LuaString { size: 5, data: \"\\138\\001key\" }"
+    );
+}
+
+#[test]
+fn test_writes_lua53_strings() {
+    assert_round_trip(b"", ctx(3), b"\x01");
+    assert_round_trip(b"print", ctx(3), b"\x06print");
+
+    // Sizes from 0xFF up are stored after a marker byte
+    let long = [b'a'; 254];
+    let mut expected = vec![0xFF];
+    expected.extend(255u64.to_le_bytes());
+    expected.extend(long);
+    assert_round_trip(&long, ctx(3), &expected);
+
+    let short = [b'a'; 253];
+    let mut expected = vec![0xFE];
+    expected.extend(short);
+    assert_round_trip(&short, ctx(3), &expected);
+}
+
+#[test]
+fn test_writes_lua54_strings() {
+    assert_round_trip(b"", ctx(4), b"\x81");
+    assert_round_trip(b"print", ctx(4), b"\x86print");
+
+    let long = [b'a'; 200];
+    let mut expected = vec![0x01, 0xC9];
+    expected.extend(long);
+    assert_round_trip(&long, ctx(4), &expected);
+}
+
+#[test]
+fn test_lua_unsigned_is_written_most_significant_group_first() {
+    for (value, expected) in [
+        (0, &[0x80][..]),
+        (0x7f, &[0xff]),
+        (0x80, &[0x01, 0x80]),
+        (0x3fff, &[0x7f, 0xff]),
+        (0x4000, &[0x01, 0x00, 0x80]),
+    ] {
+        let mut bytes = [0; 8];
+        let size = bytes
+            .pwrite_with(LuaUnsigned::new(value), 0, LE)
+            .expect("Failed to write unsigned");
+        assert_eq!(&bytes[..size], expected, "Writing {:#x}", value);
+
+        let read: LuaUnsigned = bytes.pread_with(0, LE).expect("Failed to read unsigned");
+        assert_eq!(read, value);
+    }
+}
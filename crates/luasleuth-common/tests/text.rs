@@ -0,0 +1,88 @@
+use luasleuth_common::text::{parse, Item, Operand};
+
+#[test]
+fn test_parses_functions_labels_and_operands() {
+    let source = r#"
+    ; a comment on its own line
+    .function main
+        .const "a\tb\x41\065\"", 1.5   ; trailing comment
+    top: MOVE 0, 1 -2
+        JMP 0x10 top
+        .function child
+        .end
+    .end
+    "#;
+
+    let chunk = parse(source).expect("Failed to parse assembly");
+    let main = &chunk.main;
+    assert_eq!(main.name, "main");
+    assert_eq!(main.line, 3);
+
+    let Item::Directive(constant) = &main.items[0] else {
+        panic!("Expected a directive, got {:?}", main.items[0]);
+    };
+    assert_eq!(constant.name, "const");
+    assert_eq!(
+        constant.operands,
+        [Operand::String(b"a\tbAA\"".to_vec()), Operand::Number(1.5)]
+    );
+
+    assert_eq!(
+        main.items[1],
+        Item::Label {
+            line: 5,
+            name: "top".to_string()
+        }
+    );
+
+    let instructions: Vec<_> = main.instructions().collect();
+    assert_eq!(instructions[0].name, "MOVE");
+    assert_eq!(
+        instructions[0].operands,
+        [
+            Operand::Integer(0),
+            Operand::Integer(1),
+            Operand::Integer(-2)
+        ]
+    );
+    assert_eq!(
+        instructions[1].operands,
+        [Operand::Integer(16), Operand::Name("top".to_string())]
+    );
+
+    assert_eq!(
+        main.children().map(|child| &child.name).collect::<Vec<_>>(),
+        ["child"]
+    );
+    assert_eq!(
+        main.scope()
+            .unwrap()
+            .position(&Operand::Name("top".into()), 6)
+            .unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_reports_malformed_assembly() {
+    let error = |source: &str| parse(source).expect_err("Expected a syntax error");
+
+    assert_eq!(
+        error(".function main\n").message,
+        "Function main is missing its .end"
+    );
+    assert_eq!(error(".end\n").message, ".end without a function to end");
+    assert_eq!(
+        error("MOVE 0 1 0\n").message,
+        "Instruction outside of a function"
+    );
+    assert_eq!(error(".function main\n.const \"abc\n.end\n").line, 2);
+    assert_eq!(
+        error(".function main\n.end\n.function other\n.end\n").message,
+        "A chunk has a single main function"
+    );
+
+    let chunk = parse(".function main\na:\na:\n.end\n").unwrap();
+    let duplicate = chunk.main.scope().expect_err("Expected a duplicate label");
+    assert_eq!(duplicate.line, 3);
+}
@@ -0,0 +1,1944 @@
+//! Decompilation of Lua 5.1 chunks back to source.
+//!
+//! Statements are recovered from the shapes `luac` compiles them to: tests followed by jumps for
+//! conditions, `FORPREP` and `TFORLOOP` for `for` loops and backward jumps for `while` and
+//! `repeat`. Values computed into temporary registers are folded into the expression using
+//! them. Locals are named after the debug information, or given generated names when the chunk
+//! is stripped. Jumps that fit no shape are kept as comments.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
+
+use luasleuth_common::{
+    ast::{
+        is_identifier, write_chunk, write_expression, BinaryOperator, Block, Call, Expression,
+        Field, Function, LocalName, Statement, UnaryOperator,
+    },
+    decompiler::{condition, generated_name, short_circuit, Branch, Exit, Operand, MAX_NESTING},
+    resolve::local_name,
+};
+
+use crate::types::{
+    constants::Constant,
+    instructions::{constants::SIZE_B, Instruction, Opcode},
+    Bytecode, Prototype,
+};
+
+/// Marks a `B` or `C` operand as a constant index instead of a register
+const BITRK: u16 = 1 << (SIZE_B - 1);
+
+/// Set in `is_vararg` when the function takes `...`
+const VARARG_ISVARARG: u8 = 2;
+
+/// Registers an instruction can refer to, counting those past `A` that `B` and `C` reach
+const REGISTERS: usize = 1024;
+
+/// The source of a chunk, as [`decompile`] recovers it.
+pub struct Source<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Source<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Source<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_chunk(f, &decompile(self.bytecode).body)
+    }
+}
+
+/// Decompile the main function of a chunk, along with the closures it creates.
+pub fn decompile(bytecode: &Bytecode) -> Function {
+    Lifter::new(&bytecode.prototype, 0, Vec::new()).function()
+}
+
+/// What a register holds that no statement has used yet.
+#[derive(Debug, Clone)]
+enum Pending {
+    /// A value, `shared` when read more than once and so declared as a local by its first read.
+    /// `order` tells which of the values was computed first.
+    Value {
+        value: Expression,
+        shared: bool,
+        order: usize,
+    },
+    /// The method `SELF` looked up, called with the object in the next register
+    Method(Expression, Expression),
+    /// The object `SELF` passes to its method
+    Object,
+}
+
+/// A call or `...` whose values fill consecutive registers.
+#[derive(Debug, Clone)]
+struct Multi {
+    register: usize,
+    count: usize,
+    value: Expression,
+}
+
+#[derive(Debug)]
+struct Loop {
+    head: usize,
+    /// The pc of the jump back to the head
+    back: usize,
+    /// The pc `break` jumps to
+    exit: usize,
+    /// Whether the loop is a `repeat`, whose condition is found while lifting its body
+    repeat: bool,
+    until: Option<Expression>,
+}
+
+/// The state speculative lifting rolls back to.
+struct Snapshot {
+    pending: Vec<Option<Pending>>,
+    multi: Option<Multi>,
+    open: Option<usize>,
+    generated: Vec<Option<String>>,
+    captured: Vec<bool>,
+    scopes: Vec<Vec<usize>>,
+}
+
+struct Lifter<'p, 'a> {
+    prototype: &'p Prototype<'a>,
+    code: &'p [Instruction],
+    depth: usize,
+    upvalues: Vec<String>,
+    pending: Vec<Option<Pending>>,
+    multi: Option<Multi>,
+    /// The register a call or `...` left a variable number of values from
+    open: Option<usize>,
+    /// The order of the value computed last
+    order: usize,
+    /// Names given to registers without debug information
+    generated: Vec<Option<String>>,
+    /// Which of the named registers closures capture
+    captured: Vec<bool>,
+    /// The registers named in each block being lifted
+    scopes: Vec<Vec<usize>>,
+    /// The pc each block being lifted ends at
+    ends: Vec<usize>,
+    /// Which debug locals were declared
+    declared: Vec<bool>,
+    loops: Vec<Loop>,
+    /// The pcs of the backward jumps to each pc
+    back_edges: BTreeMap<usize, Vec<usize>>,
+    /// A register kept temporary while a value is computed into it, even if a local lives there
+    capture: Option<usize>,
+    /// The local the next closure is declared as, as `local function` does
+    local_function: Option<(usize, String)>,
+    /// Set when blocks nest deeper than [`MAX_NESTING`], giving up on the structure
+    too_deep: bool,
+    /// Whether control flow is kept as comments instead of being lifted into statements
+    flat: bool,
+}
+
+impl<'p, 'a> Lifter<'p, 'a> {
+    fn new(prototype: &'p Prototype<'a>, depth: usize, upvalues: Vec<String>) -> Self {
+        let code = &prototype.code.data[..];
+        let mut back_edges: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut pc = 0;
+        while pc < code.len() {
+            if let Instruction::iAsBx(Opcode::OP_JMP, _, sbx) = code[pc] {
+                // The jump after `TFORLOOP` belongs to the generic `for`
+                let loops_back =
+                    pc > 0 && matches!(code[pc - 1], Instruction::iABC(Opcode::OP_TFORLOOP, ..));
+                let target = pc as i64 + 1 + sbx as i64;
+                if !loops_back && (0..=pc as i64).contains(&target) {
+                    back_edges.entry(target as usize).or_default().push(pc);
+                }
+            }
+            pc = next_pc(prototype, pc);
+        }
+
+        let locals = prototype.debug_info.local_variables.data.len();
+        let parameters = prototype.number_of_parameters as usize;
+        let mut lifter = Self {
+            prototype,
+            code,
+            depth,
+            upvalues,
+            pending: vec![None; REGISTERS],
+            multi: None,
+            open: None,
+            order: 0,
+            generated: vec![None; REGISTERS],
+            captured: vec![false; REGISTERS],
+            scopes: Vec::new(),
+            ends: Vec::new(),
+            declared: (0..locals).map(|index| index < parameters).collect(),
+            loops: Vec::new(),
+            back_edges,
+            capture: None,
+            local_function: None,
+            too_deep: false,
+            flat: false,
+        };
+        for register in 0..parameters {
+            if lifter.local_name(register, 0).is_none() {
+                lifter.generated[register] = Some(generated_name("a", register, depth));
+            }
+        }
+
+        lifter
+    }
+
+    fn function(mut self) -> Function {
+        let parameters = (0..self.prototype.number_of_parameters as usize)
+            .map(|register| {
+                self.name(register, 0)
+                    .unwrap_or_else(|| generated_name("a", register, self.depth))
+            })
+            .collect();
+        let is_vararg = self.prototype.is_vararg & VARARG_ISVARARG != 0;
+        let mut body = self.block(0, self.code.len());
+        if self.too_deep {
+            let mut lifter = Lifter::new(self.prototype, self.depth, self.upvalues.clone());
+            lifter.flat = true;
+            body = lifter.block(0, lifter.code.len());
+        }
+
+        Function {
+            parameters,
+            is_vararg,
+            body,
+        }
+    }
+
+    /// Lift the statements of `start..end`, which control enters at `start` and leaves at `end`.
+    fn block(&mut self, start: usize, end: usize) -> Block {
+        if self.ends.len() == MAX_NESTING {
+            self.too_deep = true;
+        }
+        if self.too_deep {
+            return Vec::new();
+        }
+
+        let end = end.min(self.code.len());
+        self.scopes.push(Vec::new());
+        self.ends.push(end);
+
+        let mut out = Vec::new();
+        let mut pc = start;
+        while pc < end && !self.too_deep {
+            pc = self.statement(&mut out, pc, end).max(pc + 1);
+        }
+
+        // Values nothing used are only kept when computing them has effects
+        self.flush_multi(&mut out);
+        self.settle(&mut out, Expression::has_effects);
+        self.open = None;
+
+        self.ends.pop();
+        for register in self.scopes.pop().unwrap_or_default() {
+            self.generated[register] = None;
+        }
+        out
+    }
+
+    /// Lift the statement starting at `pc`, returning the pc after it.
+    fn statement(&mut self, out: &mut Block, pc: usize, end: usize) -> usize {
+        self.declare_locals(out, pc);
+        if self.flat {
+            return match self.code[pc] {
+                Instruction::iAsBx(Opcode::OP_JMP, ..) => self.jump_note(out, pc),
+                _ if self.is_test(pc) && pc + 1 < end && self.jump_target(pc + 1).is_some() => {
+                    self.conditional_note(out, pc)
+                }
+                _ => self.instruction(out, pc),
+            };
+        }
+        if let Some(back) = self.loop_at(pc, end) {
+            return self.lift_loop(out, pc, back);
+        }
+
+        match self.code[pc] {
+            Instruction::iAsBx(Opcode::OP_FORPREP, a, sbx) => {
+                self.numeric_for(out, pc, a as usize, sbx, end)
+            }
+            Instruction::iAsBx(Opcode::OP_JMP, ..) => self.jump(out, pc, end),
+            _ if self.is_test(pc) && pc + 1 < end && self.jump_target(pc + 1).is_some() => {
+                self.conditional(out, pc, end)
+            }
+            _ => self.instruction(out, pc),
+        }
+    }
+
+    /// Lift an instruction that isn't part of a control structure, returning the pc after it.
+    fn instruction(&mut self, out: &mut Block, pc: usize) -> usize {
+        match self.code[pc] {
+            Instruction::iABC(opcode, a, b, c) => {
+                let a = a as usize;
+                match opcode {
+                    Opcode::OP_MOVE => {
+                        let value = self.read(out, b as usize, pc);
+                        self.assign(out, a, value, pc);
+                    }
+                    Opcode::OP_LOADBOOL => {
+                        self.assign(out, a, Expression::Boolean(b != 0), pc);
+                        if c != 0 {
+                            let note = format!("skips instruction {}", pc + 2);
+                            self.emit(out, Statement::Comment(note));
+                            return pc + 2;
+                        }
+                    }
+                    Opcode::OP_LOADNIL => {
+                        for register in a..=b as usize {
+                            self.assign(out, register, Expression::Nil, pc);
+                        }
+                    }
+                    Opcode::OP_GETUPVAL => {
+                        let upvalue = self.upvalue(b as usize);
+                        self.assign(out, a, upvalue, pc);
+                    }
+                    Opcode::OP_GETTABLE => {
+                        let object = self.read(out, b as usize, pc);
+                        let key = self.register_or_constant(out, c, pc);
+                        self.assign(out, a, Expression::index(object, key), pc);
+                    }
+                    Opcode::OP_SETUPVAL => {
+                        let value = self.read(out, a, pc);
+                        let upvalue = self.upvalue(b as usize);
+                        self.emit(out, assignment(upvalue, value));
+                    }
+                    Opcode::OP_SETTABLE => self.set_table(out, a, b, c, pc),
+                    Opcode::OP_NEWTABLE => self.assign(out, a, Expression::Table(Vec::new()), pc),
+                    Opcode::OP_SELF => {
+                        let object = self.read(out, b as usize, pc);
+                        let key = self.register_or_constant(out, c, pc);
+                        self.pending[a] = Some(Pending::Method(object, key));
+                        self.pending[a + 1] = Some(Pending::Object);
+                    }
+                    Opcode::OP_ADD
+                    | Opcode::OP_SUB
+                    | Opcode::OP_MUL
+                    | Opcode::OP_DIV
+                    | Opcode::OP_MOD
+                    | Opcode::OP_POW => {
+                        let left = self.register_or_constant(out, b, pc);
+                        let right = self.register_or_constant(out, c, pc);
+                        let operator = binary_operator(opcode);
+                        self.assign(out, a, Expression::binary(operator, left, right), pc);
+                    }
+                    Opcode::OP_UNM | Opcode::OP_NOT | Opcode::OP_LEN => {
+                        let operand = self.read(out, b as usize, pc);
+                        let operator = match opcode {
+                            Opcode::OP_UNM => UnaryOperator::Neg,
+                            Opcode::OP_NOT => UnaryOperator::Not,
+                            _ => UnaryOperator::Len,
+                        };
+                        self.assign(out, a, Expression::unary(operator, operand), pc);
+                    }
+                    Opcode::OP_CONCAT => {
+                        let mut values: Vec<Expression> = Vec::new();
+                        for register in b as usize..=c as usize {
+                            values.push(self.read(out, register, pc));
+                        }
+                        // `..` is right associative
+                        let last = values.pop().unwrap_or(Expression::Nil);
+                        let value = values.into_iter().rev().fold(last, |right, left| {
+                            Expression::binary(BinaryOperator::Concat, left, right)
+                        });
+                        self.assign(out, a, value, pc);
+                    }
+                    Opcode::OP_CALL => {
+                        let call = self.call(out, a, b, pc);
+                        if c == 1 {
+                            self.emit(out, Statement::Call(call));
+                            return pc + 1;
+                        }
+                        let call = Expression::Call(Box::new(call));
+                        match c {
+                            0 => {
+                                self.set_value(a, call, false);
+                                self.open = Some(a);
+                            }
+                            2 => self.assign(out, a, call, pc),
+                            c => self.set_multi(out, a, c as usize - 1, call),
+                        }
+                    }
+                    Opcode::OP_TAILCALL => {
+                        let call = self.call(out, a, b, pc);
+                        let values = vec![Expression::Call(Box::new(call))];
+                        self.emit(out, Statement::Return(values));
+                        // The `RETURN` after a tail call is never reached
+                        if let Some(Instruction::iABC(Opcode::OP_RETURN, ..)) =
+                            self.code.get(pc + 1)
+                        {
+                            return pc + 2;
+                        }
+                    }
+                    Opcode::OP_RETURN => {
+                        let count = (b != 0).then(|| b as usize - 1);
+                        let values = self.list(out, a, count, pc);
+                        // Every function ends with a `return` the source doesn't need
+                        if b != 1 || pc + 1 != self.code.len() {
+                            self.emit(out, Statement::Return(values));
+                        }
+                    }
+                    Opcode::OP_SETLIST => {
+                        let count = (b != 0).then_some(b as usize);
+                        let values = self.list(out, a + 1, count, pc);
+                        match self.table_mut(a) {
+                            Some(fields) => {
+                                fields.extend(values.into_iter().map(Field::Positional))
+                            }
+                            None => {
+                                let note = "SETLIST outside of a table constructor".to_string();
+                                self.emit(out, Statement::Comment(note));
+                            }
+                        }
+                        // The block number is stored in place of the next instruction
+                        if c == 0 {
+                            return pc + 2;
+                        }
+                    }
+                    Opcode::OP_CLOSE => {}
+                    Opcode::OP_VARARG => match b {
+                        0 => {
+                            self.set_value(a, Expression::VarArg, false);
+                            self.open = Some(a);
+                        }
+                        1 => {}
+                        2 => self.assign(out, a, Expression::VarArg, pc),
+                        b => self.set_multi(out, a, b as usize - 1, Expression::VarArg),
+                    },
+                    _ => self.unstructured(out, pc),
+                }
+            }
+            Instruction::iABx(opcode, a, bx) => {
+                let a = a as usize;
+                match opcode {
+                    Opcode::OP_LOADK => {
+                        let constant = self.constant(bx as usize);
+                        self.assign(out, a, constant, pc);
+                    }
+                    Opcode::OP_GETGLOBAL => {
+                        let global = self.global(bx as usize);
+                        self.assign(out, a, global, pc);
+                    }
+                    Opcode::OP_SETGLOBAL => {
+                        let value = self.read(out, a, pc);
+                        let global = self.global(bx as usize);
+                        self.emit(out, assignment(global, value));
+                    }
+                    Opcode::OP_CLOSURE => return self.closure(out, pc, a, bx as usize),
+                    _ => self.unstructured(out, pc),
+                }
+            }
+            Instruction::iAsBx(..) => self.unstructured(out, pc),
+        }
+
+        pc + 1
+    }
+
+    /// Keep an instruction no statement accounts for as a comment.
+    fn unstructured(&mut self, out: &mut Block, pc: usize) {
+        let note = format!(
+            "unstructured {:?} at instruction {}",
+            opcode(self.code[pc]),
+            pc + 1
+        );
+        self.emit(out, Statement::Comment(note));
+    }
+
+    fn set_table(&mut self, out: &mut Block, a: usize, b: u16, c: u16, pc: usize) {
+        // Fields of a table constructor
+        if !self.named(a, pc) && self.table_mut(a).is_some() {
+            let key = self.register_or_constant(out, b, pc);
+            let value = self.register_or_constant(out, c, pc);
+            if let Some(fields) = self.table_mut(a) {
+                fields.push(Field::Named(key, value));
+            }
+            return;
+        }
+
+        let object = self.read(out, a, pc);
+        let key = self.register_or_constant(out, b, pc);
+        let value = self.register_or_constant(out, c, pc);
+        self.emit(out, assignment(Expression::index(object, key), value));
+    }
+
+    /// Build the call of the function in register `a`, its arguments following it.
+    fn call(&mut self, out: &mut Block, a: usize, b: u16, pc: usize) -> Call {
+        let count = (b != 0).then(|| b as usize - 1);
+        match self.pending[a].take() {
+            Some(Pending::Method(object, key)) => {
+                self.pending[a + 1] = None;
+                let count = count.map(|count| count.saturating_sub(1));
+                let arguments = self.list(out, a + 2, count, pc);
+                match key {
+                    Expression::String(name) if is_identifier(&name) => Call {
+                        function: object,
+                        method: Some(String::from_utf8_lossy(&name).into_owned()),
+                        arguments,
+                    },
+                    key => Call {
+                        function: Expression::index(object.clone(), key),
+                        method: None,
+                        arguments: [object].into_iter().chain(arguments).collect(),
+                    },
+                }
+            }
+            pending => {
+                self.pending[a] = pending;
+                let function = self.read(out, a, pc);
+                let arguments = self.list(out, a + 1, count, pc);
+                Call {
+                    function,
+                    method: None,
+                    arguments,
+                }
+            }
+        }
+    }
+
+    /// Read `count` registers from `first`, or up to the open register when `None`.
+    fn list(
+        &mut self,
+        out: &mut Block,
+        first: usize,
+        count: Option<usize>,
+        pc: usize,
+    ) -> Vec<Expression> {
+        let end = match count {
+            Some(count) => first + count,
+            None => self.open.take().map_or(first, |open| open + 1),
+        };
+
+        let mut values = Vec::new();
+        for register in first..end.min(REGISTERS) {
+            values.push(self.read(out, register, pc));
+        }
+        if count.is_some() {
+            if let Some(last) = values.pop() {
+                values.push(last.truncated());
+            }
+        }
+
+        values
+    }
+
+    fn closure(&mut self, out: &mut Block, pc: usize, a: usize, index: usize) -> usize {
+        let Some(child) = self.prototype.prototypes.data.get(index) else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+        let count = child.number_of_upvalues as usize;
+        let local_function = self
+            .local_function
+            .take()
+            .filter(|(register, _)| *register == a);
+
+        // The instructions after the closure say what it captures
+        let mut names = Vec::new();
+        let mut recursive = None;
+        for upvalue in 0..count {
+            let name = match self.code.get(pc + 1 + upvalue) {
+                Some(Instruction::iABC(Opcode::OP_MOVE, _, b, _)) => {
+                    let b = *b as usize;
+                    if b == a && local_function.is_none() && !self.named(a, pc) {
+                        // A stripped `local function` capturing itself
+                        let name = generated_name("r", a, self.depth);
+                        recursive = Some(name.clone());
+                        Some(name)
+                    } else {
+                        Some(self.capture_register(out, b, pc))
+                    }
+                }
+                Some(Instruction::iABC(Opcode::OP_GETUPVAL, _, b, _)) => {
+                    match self.upvalue(*b as usize) {
+                        Expression::Name(name) => Some(name),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            names.push(name);
+        }
+
+        // The names of what the closure captures, or else the ones its debug information keeps
+        let debug_names = &child.debug_info.upvalues.data;
+        let upvalues = names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let debug_name = debug_names.get(index);
+                let debug_name = debug_name.map(|name| String::from_utf8_lossy(name.as_bytes()));
+                name.or_else(|| debug_name.map(|name| name.into_owned()))
+                    .unwrap_or_else(|| generated_name("u", index, self.depth + 1))
+            })
+            .collect();
+        let function = Lifter::new(child, self.depth + 1, upvalues).function();
+
+        if let Some((_, name)) = local_function {
+            self.emit(out, Statement::LocalFunction { name, function });
+        } else if let Some(name) = recursive {
+            self.flush(out);
+            self.name_register(a, name.clone());
+            self.captured[a] = true;
+            out.push(Statement::LocalFunction { name, function });
+        } else {
+            let function = Expression::Function(Box::new(function));
+            self.assign(out, a, function, pc);
+        }
+
+        pc + 1 + count
+    }
+
+    /// Returns the name of a register a closure captures, declaring a local for its value.
+    fn capture_register(&mut self, out: &mut Block, register: usize, pc: usize) -> String {
+        if let Some(name) = self.name(register, pc) {
+            return name;
+        }
+        self.materialize_pending(out, register);
+        self.captured[register] = true;
+        self.register_name(register, pc)
+    }
+
+    /// Declare the debug locals starting at `pc`, with the values their registers hold.
+    fn declare_locals(&mut self, out: &mut Block, pc: usize) {
+        let mut new = Vec::new();
+        let mut register = 0;
+        for (index, local) in self
+            .prototype
+            .debug_info
+            .local_variables
+            .data
+            .iter()
+            .enumerate()
+        {
+            if !is_active(local.start_pc, local.end_pc, pc) {
+                continue;
+            }
+            if local.start_pc as usize == pc && !self.declared[index] {
+                self.declared[index] = true;
+                // Locals of the loops are declared by their statements
+                let name = local.name.as_bytes();
+                if !name.starts_with(b"(") {
+                    new.push((register, String::from_utf8_lossy(name).into_owned()));
+                }
+            }
+            register += 1;
+        }
+        if new.is_empty() {
+            return;
+        }
+
+        if let [(register, ref name)] = new[..] {
+            let closure = matches!(
+                self.code.get(pc),
+                Some(&Instruction::iABx(Opcode::OP_CLOSURE, a, _)) if a as usize == register
+            );
+            if closure && self.pending[register].is_none() {
+                self.local_function = Some((register, name.clone()));
+                return;
+            }
+        }
+
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        let mut covered = 0;
+        for (register, name) in new {
+            names.push(LocalName {
+                name,
+                attribute: None,
+            });
+            if covered > 0 {
+                covered -= 1;
+                continue;
+            }
+
+            match self.multi.take() {
+                Some(multi) if multi.register == register => {
+                    covered = multi.count - 1;
+                    values.push(multi.value);
+                }
+                multi => {
+                    self.multi = multi;
+                    values.push(self.take_value(register).unwrap_or(Expression::Nil));
+                }
+            }
+        }
+
+        // `local a, b = 1` leaves `b` nil, unless values come from a call
+        while let [.., Expression::Nil] = values[..] {
+            match values.len() {
+                1 => values.clear(),
+                len => match values[len - 2] {
+                    Expression::Call(_) | Expression::VarArg => break,
+                    _ => {
+                        values.pop();
+                    }
+                },
+            }
+        }
+
+        self.emit(out, Statement::Local { names, values });
+    }
+
+    /// Returns the pc of the backward jump closing a loop starting at `pc`, if one does.
+    fn loop_at(&self, pc: usize, end: usize) -> Option<usize> {
+        let sources = self.back_edges.get(&pc)?;
+        sources
+            .iter()
+            .copied()
+            .filter(|&back| back < end)
+            .filter(|&back| !self.loops.iter().any(|l| l.head == pc && l.back == back))
+            .max()
+    }
+
+    /// Lift a `while` or `repeat` loop from `head` to the backward jump at `back`.
+    fn lift_loop(&mut self, out: &mut Block, head: usize, back: usize) -> usize {
+        self.flush(out);
+        let exit = back + 1;
+
+        // A `repeat` ends with the jump of its condition
+        if back > head && self.is_test(back - 1) {
+            self.loops.push(Loop {
+                head,
+                back,
+                exit,
+                repeat: true,
+                until: None,
+            });
+            let body = self.block(head, exit);
+            let until = self.loops.pop().and_then(|l| l.until);
+            let statement = match until {
+                Some(condition) => Statement::Repeat { body, condition },
+                None => Statement::While {
+                    condition: Expression::Boolean(true),
+                    body,
+                },
+            };
+            self.emit(out, statement);
+            return exit;
+        }
+
+        self.loops.push(Loop {
+            head,
+            back,
+            exit,
+            repeat: false,
+            until: None,
+        });
+
+        // A `while` starts with its condition, jumping to the exit
+        let condition = self.speculate(out, |lifter, out| {
+            let mut test = head;
+            while test < back && lifter.is_expression(test) {
+                test += 1;
+            }
+            if test >= back || !lifter.is_test(test) {
+                return None;
+            }
+
+            let tests = lifter.scan_tests(test, back);
+            (0..tests.len()).rev().find_map(|last| {
+                let tests = &tests[..=last];
+                let body = tests[last] + 2;
+                if lifter.jump_target(tests[last] + 1) != Some(exit) {
+                    return None;
+                }
+                let label = |target| match target {
+                    target if target == exit => Some(Exit::False),
+                    target if target == body => Some(Exit::True),
+                    _ => None,
+                };
+                let exits = lifter.exits(tests, label, Exit::True)?;
+
+                let mut pc = head;
+                while pc < tests[0] {
+                    pc = lifter.instruction(out, pc);
+                }
+                Some((lifter.evaluate(out, tests, &exits, Exit::True), body))
+            })
+        });
+
+        let statement = match condition {
+            Some((condition, body)) => Statement::While {
+                condition,
+                body: self.block(body, back),
+            },
+            None => Statement::While {
+                condition: Expression::Boolean(true),
+                body: self.block(head, back),
+            },
+        };
+        self.loops.pop();
+        self.emit(out, statement);
+
+        exit
+    }
+
+    fn numeric_for(&mut self, out: &mut Block, pc: usize, a: usize, sbx: i32, end: usize) -> usize {
+        let forloop = pc as i64 + 1 + sbx as i64;
+        let closes = usize::try_from(forloop).ok().filter(|&forloop| {
+            forloop < end
+                && matches!(
+                    self.code[forloop],
+                    Instruction::iAsBx(Opcode::OP_FORLOOP, b, _) if b as usize == a
+                )
+        });
+        let Some(forloop) = closes else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+
+        let start = self.read(out, a, pc);
+        let limit = self.read(out, a + 1, pc);
+        let step = self.read(out, a + 2, pc);
+        self.flush(out);
+
+        let variable = self.loop_variable(a + 3, pc + 1, "i");
+        self.loops.push(Loop {
+            head: forloop,
+            back: forloop,
+            exit: forloop + 1,
+            repeat: false,
+            until: None,
+        });
+        let body = self.block(pc + 1, forloop);
+        self.loops.pop();
+        self.generated[a + 3] = None;
+
+        let step = (step != Expression::Number(1.0)).then_some(step);
+        self.emit(
+            out,
+            Statement::NumericFor {
+                variable,
+                start,
+                limit,
+                step,
+                body,
+            },
+        );
+
+        forloop + 1
+    }
+
+    fn generic_for(&mut self, out: &mut Block, pc: usize, tforloop: usize) -> usize {
+        let Instruction::iABC(_, a, _, c) = self.code[tforloop] else {
+            unreachable!()
+        };
+        let a = a as usize;
+
+        // The iterator, its state and the control variable, usually from a single call
+        let values = match self.multi.take() {
+            Some(multi) if multi.register == a && multi.count == 3 => vec![multi.value],
+            multi => {
+                self.multi = multi;
+                let mut values = vec![
+                    self.read(out, a, pc),
+                    self.read(out, a + 1, pc),
+                    self.read(out, a + 2, pc),
+                ];
+                while values.len() > 1 && values.last() == Some(&Expression::Nil) {
+                    values.pop();
+                }
+                values
+            }
+        };
+        self.flush(out);
+
+        let variables = (0..(c as usize).max(1))
+            .map(|index| {
+                let prefix = if index == 0 { "k" } else { "v" };
+                self.loop_variable(a + 3 + index, pc + 1, prefix)
+            })
+            .collect();
+        self.loops.push(Loop {
+            head: tforloop,
+            back: tforloop + 1,
+            exit: tforloop + 2,
+            repeat: false,
+            until: None,
+        });
+        let body = self.block(pc + 1, tforloop);
+        self.loops.pop();
+        for index in 0..(c as usize).max(1) {
+            self.generated[a + 3 + index] = None;
+        }
+
+        self.emit(
+            out,
+            Statement::GenericFor {
+                variables,
+                values,
+                body,
+            },
+        );
+
+        tforloop + 2
+    }
+
+    /// Returns the name of a loop variable, declaring its debug local.
+    fn loop_variable(&mut self, register: usize, pc: usize, prefix: &str) -> String {
+        for (index, local) in self
+            .prototype
+            .debug_info
+            .local_variables
+            .data
+            .iter()
+            .enumerate()
+        {
+            if local.start_pc as usize == pc {
+                self.declared[index] = true;
+            }
+        }
+
+        match self.local_name(register, pc) {
+            Some(name) => name,
+            None => {
+                let name = generated_name(prefix, register, self.depth);
+                self.generated[register] = Some(name.clone());
+                name
+            }
+        }
+    }
+
+    /// Lift a jump that doesn't follow a test.
+    fn jump(&mut self, out: &mut Block, pc: usize, end: usize) -> usize {
+        let Some(target) = self.jump_target(pc) else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+
+        // A generic `for` jumps to its `TFORLOOP`, followed by the jump back to the body
+        let generic_for = target + 1 < end
+            && matches!(
+                self.code[target],
+                Instruction::iABC(Opcode::OP_TFORLOOP, ..)
+            )
+            && self.jump_target(target + 1) == Some(pc + 1);
+        if generic_for {
+            return self.generic_for(out, pc, target);
+        }
+
+        if self.loops.last().is_some_and(|l| l.exit == target) {
+            self.emit(out, Statement::Break);
+            return pc + 1;
+        }
+        self.jump_note(out, pc)
+    }
+
+    /// Keep a jump as a comment naming its target, returning the pc after it.
+    fn jump_note(&mut self, out: &mut Block, pc: usize) -> usize {
+        match self.jump_target(pc) {
+            Some(target) if target != pc + 1 => {
+                let note = format!("jump to instruction {}", target + 1);
+                self.emit(out, Statement::Comment(note));
+            }
+            Some(_) => {}
+            None => self.unstructured(out, pc),
+        }
+
+        pc + 1
+    }
+
+    /// Lift a test followed by a jump, as an `if`, the end of a `repeat` or a value.
+    fn conditional(&mut self, out: &mut Block, pc: usize, end: usize) -> usize {
+        let tests = self.scan_tests(pc, end);
+
+        if let Some(&Loop {
+            head,
+            back,
+            exit,
+            repeat: true,
+            ..
+        }) = self.loops.last()
+        {
+            for last in (0..tests.len()).rev() {
+                let tests = &tests[..=last];
+                if tests[last] + 1 != back {
+                    continue;
+                }
+                let label = |target| match target {
+                    target if target == head => Some(Exit::False),
+                    target if target == exit => Some(Exit::True),
+                    _ => None,
+                };
+                if let Some(exits) = self.exits(tests, label, Exit::True) {
+                    let condition = self.evaluate(out, tests, &exits, Exit::True);
+                    if let Some(l) = self.loops.last_mut() {
+                        l.until = Some(condition);
+                    }
+                    return exit;
+                }
+            }
+        }
+
+        if let Some(next) = self.short_circuit_value(out, pc, end) {
+            return next;
+        }
+        if let Some(next) = self.comparison_value(out, &tests, end) {
+            return next;
+        }
+
+        for last in (0..tests.len()).rev() {
+            let tests = &tests[..=last];
+            let body = tests[last] + 2;
+            let Some(otherwise) = self.jump_target(tests[last] + 1) else {
+                continue;
+            };
+            if otherwise < body || otherwise > end {
+                continue;
+            }
+            let label = |target| match target {
+                target if target == otherwise => Some(Exit::False),
+                target if target == body => Some(Exit::True),
+                _ => None,
+            };
+            if let Some(exits) = self.exits(tests, label, Exit::True) {
+                return self.if_statement(out, tests, &exits, body, otherwise, end);
+            }
+        }
+
+        self.conditional_note(out, pc)
+    }
+
+    /// Keep a test and its jump as a comment, returning the pc after them.
+    fn conditional_note(&mut self, out: &mut Block, pc: usize) -> usize {
+        // A value tested by jumps that fit no shape is kept in a local the jumps can't lose
+        if let Instruction::iABC(Opcode::OP_TEST, a, ..) = self.code[pc] {
+            let a = a as usize;
+            self.materialize_pending(out, a);
+        }
+        let condition = self.test(out, pc);
+        let target = self.jump_target(pc + 1).unwrap_or_default();
+        let mut note = String::from("if ");
+        let _ = write_expression(&mut note, &condition);
+        let _ = write!(note, " then jump to instruction {}", target + 1);
+        self.emit(out, Statement::Comment(note));
+        pc + 2
+    }
+
+    fn if_statement(
+        &mut self,
+        out: &mut Block,
+        tests: &[usize],
+        exits: &[Exit],
+        body: usize,
+        otherwise: usize,
+        end: usize,
+    ) -> usize {
+        let condition = self.evaluate(out, tests, exits, Exit::True);
+        self.flush(out);
+
+        // The `then` block of an `if` with an `else` ends jumping over it
+        let mut then_end = otherwise;
+        let mut next = otherwise;
+        if otherwise > body && !(otherwise >= 2 && self.is_test(otherwise - 2)) {
+            if let Some(target) = self.jump_target(otherwise - 1) {
+                if target > otherwise && target <= end {
+                    then_end = otherwise - 1;
+                    next = target;
+                }
+            }
+        }
+
+        // Branches computing a value into the same temporary register
+        let register = (then_end > body)
+            .then(|| self.expression_register(then_end - 1))
+            .flatten()
+            .filter(|&register| !self.named(register, then_end - 1));
+        if let Some(register) = register {
+            // `c and a or b`, `luac` testing `c` alone when `a` is a constant
+            if next > otherwise {
+                let values = self.speculate(out, |lifter, out| {
+                    lifter.capture = Some(register);
+                    let then = lifter.branch_value(out, body, then_end, register)?;
+                    let otherwise = lifter.branch_value(out, otherwise, next, register)?;
+                    then.is_truthy().then_some((then, otherwise))
+                });
+                if let Some((then, otherwise)) = values {
+                    let and = Expression::binary(BinaryOperator::And, condition, then);
+                    let value = Expression::binary(BinaryOperator::Or, and, otherwise);
+                    self.assign(out, register, value, next - 1);
+                    return next;
+                }
+            }
+
+            // Otherwise the register is declared for the branches to assign
+            if self.reads_after(register, next - 1, false) > 0 {
+                self.declare(out, register, Vec::new());
+            }
+        }
+
+        let then = self.block(body, then_end);
+        let otherwise = self.block(otherwise, next);
+        self.emit(
+            out,
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            },
+        );
+
+        next
+    }
+
+    /// Returns the value a branch of an `if` only computes into `register`.
+    fn branch_value(
+        &mut self,
+        out: &mut Block,
+        start: usize,
+        end: usize,
+        register: usize,
+    ) -> Option<Expression> {
+        let mut pc = start;
+        while pc < end {
+            if !self.is_expression(pc) || self.starts_local(pc) {
+                return None;
+            }
+            pc = self.instruction(out, pc);
+        }
+
+        let value = self.take_value(register)?;
+        self.pending.iter().all(Option::is_none).then_some(value)
+    }
+
+    /// Lift `a and b or c` computed into a register, returning the pc after it.
+    fn short_circuit_value(&mut self, out: &mut Block, pc: usize, end: usize) -> Option<usize> {
+        let register = match self.code[pc] {
+            Instruction::iABC(Opcode::OP_TEST, a, ..) if !self.named(a as usize, pc) => a,
+            Instruction::iABC(Opcode::OP_TESTSET, a, ..) => a,
+            _ => return None,
+        } as usize;
+
+        let (value, next) = self.speculate(out, |lifter, out| {
+            lifter.capture = Some(register);
+            lifter.value_chain(out, pc, end, register)
+        })?;
+        self.assign(out, register, value, next - 1);
+        Some(next)
+    }
+
+    /// Lift the operands computed into `register` and the tests between them.
+    fn value_chain(
+        &mut self,
+        out: &mut Block,
+        pc: usize,
+        end: usize,
+        register: usize,
+    ) -> Option<(Expression, usize)> {
+        let mut operands = Vec::new();
+        let mut targets = Vec::new();
+        // The pc each operand after the first starts at
+        let mut starts = Vec::new();
+        // The pc of the test after each operand, when it tests the register
+        let mut tests = Vec::new();
+        let mut furthest = 0;
+
+        let mut test = pc;
+        loop {
+            let (value, truthy) = match self.code[test] {
+                Instruction::iABC(Opcode::OP_TEST, _, _, c) => (self.take_value(register)?, c),
+                Instruction::iABC(Opcode::OP_TESTSET, _, b, c) => {
+                    (self.read(out, b as usize, test), c)
+                }
+                _ => return None,
+            };
+            let target = self.jump_target(test + 1)?;
+            if target <= test + 2 || target > end {
+                return None;
+            }
+            furthest = furthest.max(target);
+            operands.push(Operand {
+                value,
+                jump: Some((truthy != 0, 0)),
+            });
+            targets.push(target);
+            starts.push(test + 2);
+            let tests_register = matches!(self.code[test], Instruction::iABC(Opcode::OP_TEST, ..));
+            tests.push(tests_register.then_some(test));
+
+            let mut pc = test + 2;
+            loop {
+                let tests_register = match self.code[pc.min(end - 1)] {
+                    Instruction::iABC(Opcode::OP_TEST | Opcode::OP_TESTSET, a, ..) => {
+                        a as usize == register && self.jump_target(pc + 1).is_some()
+                    }
+                    _ => false,
+                };
+                // A jump landing on a test makes the operands before it a group of their own,
+                // `(a or b) and c`
+                let grouped = tests_register && targets.contains(&pc);
+                if pc == furthest && !grouped {
+                    let value = self.take_value(register)?;
+                    operands.push(Operand { value, jump: None });
+
+                    let count = operands.len();
+                    for (operand, target) in operands.iter_mut().zip(targets) {
+                        let index = match target == furthest {
+                            true => count,
+                            // Landing on the test after an operand is the same as past it, as
+                            // the value jumping there can't pass the test
+                            false => {
+                                let operand =
+                                    starts.iter().zip(&tests).position(|(&start, &test)| {
+                                        start == target || test == Some(target)
+                                    });
+                                operand? + 1
+                            }
+                        };
+                        if let Some((_, jump)) = &mut operand.jump {
+                            *jump = index;
+                        }
+                    }
+                    return Some((short_circuit(&operands)?, furthest));
+                }
+                if pc > furthest || pc >= end {
+                    return None;
+                }
+
+                if tests_register {
+                    test = pc;
+                    break;
+                }
+                if !self.is_expression(pc) {
+                    return None;
+                }
+                pc = self.instruction(out, pc);
+            }
+        }
+    }
+
+    /// Lift comparisons computed into a register, which `luac` loads with two `LOADBOOL`s.
+    fn comparison_value(&mut self, out: &mut Block, tests: &[usize], end: usize) -> Option<usize> {
+        for last in (0..tests.len()).rev() {
+            let tests = &tests[..=last];
+            let falsy = tests[last] + 2;
+            if falsy + 2 > end {
+                continue;
+            }
+            let register = match (self.code[falsy], self.code[falsy + 1]) {
+                (
+                    Instruction::iABC(Opcode::OP_LOADBOOL, a, 0, 1),
+                    Instruction::iABC(Opcode::OP_LOADBOOL, b, 1, 0),
+                ) if a == b => a as usize,
+                _ => continue,
+            };
+
+            let label = |target| match target {
+                target if target == falsy + 1 => Some(Exit::True),
+                target if target == falsy => Some(Exit::False),
+                _ => None,
+            };
+            if let Some(exits) = self.exits(tests, label, Exit::False) {
+                let value = self.evaluate(out, tests, &exits, Exit::False);
+                self.assign(out, register, value, falsy + 1);
+                return Some(falsy + 2);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the pcs of the tests starting at `pc`, each followed by a jump, with only
+    /// expressions between them.
+    ///
+    /// A condition nests as deep as it has tests, so longer chains are cut at [`MAX_NESTING`].
+    fn scan_tests(&self, pc: usize, end: usize) -> Vec<usize> {
+        let mut tests = vec![pc];
+        while tests.len() < MAX_NESTING {
+            let mut next = tests[tests.len() - 1] + 2;
+            while next < end && self.is_expression(next) && !self.starts_local(next) {
+                next += 1;
+            }
+            let follows = next + 1 < end
+                && self.is_test(next)
+                && !self.starts_local(next)
+                && self.jump_target(next + 1).is_some();
+            if !follows {
+                return tests;
+            }
+            tests.push(next);
+        }
+        tests
+    }
+
+    /// Returns where each test jumps to, `label` naming the exits of the whole chain.
+    ///
+    /// Returns `None` when the jumps don't nest like a condition does.
+    fn exits(
+        &self,
+        tests: &[usize],
+        label: impl Fn(usize) -> Option<Exit>,
+        fall: Exit,
+    ) -> Option<Vec<Exit>> {
+        let mut exits = Vec::new();
+        for (index, &test) in tests.iter().enumerate() {
+            let target = self.jump_target(test + 1)?;
+            let exit = match (index + 2..tests.len()).find(|&next| tests[next - 1] + 2 == target) {
+                Some(next) => Exit::Branch(next),
+                None => label(target)?,
+            };
+            exits.push(exit);
+        }
+
+        let branches: Vec<Branch> = exits
+            .iter()
+            .map(|&target| Branch {
+                condition: Expression::Boolean(true),
+                target,
+            })
+            .collect();
+        condition(&branches, fall)?;
+        Some(exits)
+    }
+
+    /// Lift the tests of a chain and the expressions between them into a condition.
+    fn evaluate(
+        &mut self,
+        out: &mut Block,
+        tests: &[usize],
+        exits: &[Exit],
+        fall: Exit,
+    ) -> Expression {
+        let mut branches = Vec::new();
+        for (index, (&test, &target)) in tests.iter().zip(exits).enumerate() {
+            if index > 0 {
+                let mut pc = tests[index - 1] + 2;
+                while pc < test {
+                    pc = self.instruction(out, pc);
+                }
+            }
+            let condition = self.test(out, test);
+            branches.push(Branch { condition, target });
+        }
+
+        condition(&branches, fall).unwrap_or(Expression::Boolean(true))
+    }
+
+    /// Returns the condition under which the test at `pc` takes the jump after it.
+    fn test(&mut self, out: &mut Block, pc: usize) -> Expression {
+        let (condition, expected) = match self.code[pc] {
+            Instruction::iABC(
+                opcode @ (Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE),
+                a,
+                b,
+                c,
+            ) => {
+                let left = self.register_or_constant(out, b, pc);
+                let right = self.register_or_constant(out, c, pc);
+                // `luac` puts the constant of `x > 0` on the left
+                let swapped = b & BITRK != 0 && c & BITRK == 0;
+                let operator = match (opcode, swapped) {
+                    (Opcode::OP_EQ, _) => BinaryOperator::Eq,
+                    (Opcode::OP_LT, false) => BinaryOperator::Lt,
+                    (Opcode::OP_LT, true) => BinaryOperator::Gt,
+                    (_, false) => BinaryOperator::Le,
+                    (_, true) => BinaryOperator::Ge,
+                };
+                let condition = match swapped {
+                    true => Expression::binary(operator, right, left),
+                    false => Expression::binary(operator, left, right),
+                };
+                (condition, a != 0)
+            }
+            Instruction::iABC(Opcode::OP_TEST, a, _, c) => (self.read(out, a as usize, pc), c != 0),
+            Instruction::iABC(Opcode::OP_TESTSET, _, b, c) => {
+                (self.read(out, b as usize, pc), c != 0)
+            }
+            _ => (Expression::Boolean(true), true),
+        };
+
+        match expected {
+            true => condition,
+            false => condition.negated(),
+        }
+    }
+
+    /// Run `lift`, undoing what it did when it fails or emits statements.
+    fn speculate<T>(
+        &mut self,
+        out: &mut Block,
+        lift: impl FnOnce(&mut Self, &mut Block) -> Option<T>,
+    ) -> Option<T> {
+        let snapshot = Snapshot {
+            pending: self.pending.clone(),
+            multi: self.multi.clone(),
+            open: self.open,
+            generated: self.generated.clone(),
+            captured: self.captured.clone(),
+            scopes: self.scopes.clone(),
+        };
+        let len = out.len();
+
+        let result = lift(self, out);
+        self.capture = None;
+        match result {
+            Some(result) if out.len() == len => Some(result),
+            _ => {
+                self.pending = snapshot.pending;
+                self.multi = snapshot.multi;
+                self.open = snapshot.open;
+                self.generated = snapshot.generated;
+                self.captured = snapshot.captured;
+                self.scopes = snapshot.scopes;
+                out.truncate(len);
+                None
+            }
+        }
+    }
+
+    /// Returns the expression held by a register, using up the value computed into it.
+    fn read(&mut self, out: &mut Block, register: usize, pc: usize) -> Expression {
+        if self
+            .multi
+            .as_ref()
+            .is_some_and(|multi| (multi.register..multi.register + multi.count).contains(&register))
+        {
+            self.flush_multi(out);
+        }
+
+        match self.pending[register].take() {
+            Some(Pending::Value {
+                value,
+                shared,
+                order,
+            }) => {
+                if self.open == Some(register) {
+                    self.open = None;
+                }
+                // Effects happen in the order of the code
+                if value.has_effects() || shared {
+                    self.settle_before(out, order);
+                }
+                if !shared {
+                    return value;
+                }
+
+                self.materialize(out, register, value);
+                Expression::Name(self.register_name(register, pc))
+            }
+            Some(Pending::Method(object, key)) => Expression::index(object, key),
+            Some(Pending::Object) | None => Expression::Name(self.register_name(register, pc)),
+        }
+    }
+
+    fn take_value(&mut self, register: usize) -> Option<Expression> {
+        match self.pending[register].take() {
+            Some(Pending::Value { value, .. }) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn set_value(&mut self, register: usize, value: Expression, shared: bool) {
+        self.order += 1;
+        self.pending[register] = Some(Pending::Value {
+            value,
+            shared,
+            order: self.order,
+        });
+    }
+
+    /// Returns the fields of the table constructor a register holds.
+    fn table_mut(&mut self, register: usize) -> Option<&mut Vec<Field>> {
+        match &mut self.pending[register] {
+            Some(Pending::Value {
+                value: Expression::Table(fields),
+                ..
+            }) => Some(fields),
+            _ => None,
+        }
+    }
+
+    fn register_or_constant(&mut self, out: &mut Block, operand: u16, pc: usize) -> Expression {
+        match operand & BITRK {
+            0 => self.read(out, operand as usize, pc),
+            _ => self.constant((operand & !BITRK) as usize),
+        }
+    }
+
+    /// Store a value into a register, as a statement when a local lives there.
+    fn assign(&mut self, out: &mut Block, register: usize, value: Expression, pc: usize) {
+        if let Some(name) = self.name(register, pc) {
+            if !self.redeclarable(register, pc) {
+                self.emit(out, assignment(Expression::Name(name), value));
+                return;
+            }
+            self.generated[register] = None;
+        }
+
+        let covered = self.multi.as_ref().is_some_and(|multi| {
+            (multi.register..multi.register + multi.count).contains(&register)
+        });
+        if covered {
+            self.flush_multi(out);
+            return self.assign(out, register, value, pc);
+        }
+        if self.materialize_pending(out, register) {
+            return self.assign(out, register, value, pc);
+        }
+
+        if self.capture == Some(register) {
+            return self.set_value(register, value, false);
+        }
+        // Values never used are kept in a local, and so are those used more than once once read
+        let table = matches!(value, Expression::Table(_));
+        match self.reads_after(register, pc, table) {
+            0 => self.materialize(out, register, value),
+            1 => self.set_value(register, value, false),
+            _ => self.set_value(register, value, true),
+        }
+    }
+
+    /// Returns whether a register can be declared as a new local when written, which a stripped
+    /// chunk reusing the register of a local in the block declaring it does.
+    fn redeclarable(&self, register: usize, pc: usize) -> bool {
+        self.local_name(register, pc).is_none()
+            && !self.captured[register]
+            && self
+                .scopes
+                .last()
+                .is_some_and(|scope| scope.contains(&register))
+    }
+
+    fn set_multi(&mut self, out: &mut Block, register: usize, count: usize, value: Expression) {
+        self.flush_multi(out);
+        for register in register..register + count {
+            self.pending[register] = None;
+        }
+        self.multi = Some(Multi {
+            register,
+            count,
+            value,
+        });
+    }
+
+    /// Push a statement, declaring locals for the values still pending so it can't change them.
+    fn emit(&mut self, out: &mut Block, statement: Statement) {
+        self.flush(out);
+        out.push(statement);
+    }
+
+    fn flush(&mut self, out: &mut Block) {
+        self.flush_multi(out);
+        self.settle(out, |_| true);
+        self.open = None;
+    }
+
+    /// Declare locals for the pending values `keep` accepts, in the order they were computed,
+    /// dropping the others.
+    fn settle(&mut self, out: &mut Block, keep: impl Fn(&Expression) -> bool) {
+        for register in self.computed(usize::MAX) {
+            if let Some(value) = self.take_value(register) {
+                if keep(&value) {
+                    self.materialize(out, register, value);
+                }
+            }
+        }
+    }
+
+    /// Declare locals for the pending values with effects computed before the one at `order`.
+    fn settle_before(&mut self, out: &mut Block, order: usize) {
+        for register in self.computed(order) {
+            let effects = matches!(
+                &self.pending[register],
+                Some(Pending::Value { value, .. }) if value.has_effects()
+            );
+            if effects {
+                if let Some(value) = self.take_value(register) {
+                    self.materialize(out, register, value);
+                }
+            }
+        }
+    }
+
+    /// Returns the registers holding values computed before the one at `order`, in the order
+    /// they were computed.
+    fn computed(&self, order: usize) -> Vec<usize> {
+        let mut registers: Vec<(usize, usize)> = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter_map(|(register, pending)| match pending {
+                Some(Pending::Value {
+                    order: computed, ..
+                }) if *computed < order => Some((*computed, register)),
+                _ => None,
+            })
+            .collect();
+        registers.sort_unstable();
+        registers
+            .into_iter()
+            .map(|(_, register)| register)
+            .collect()
+    }
+
+    fn flush_multi(&mut self, out: &mut Block) {
+        let Some(multi) = self.multi.take() else {
+            return;
+        };
+
+        let mut names = Vec::new();
+        for register in multi.register..multi.register + multi.count {
+            let name = generated_name("r", register, self.depth);
+            self.name_register(register, name.clone());
+            names.push(LocalName {
+                name,
+                attribute: None,
+            });
+        }
+        out.push(Statement::Local {
+            names,
+            values: vec![multi.value],
+        });
+    }
+
+    /// Declare a local for the value pending in a register, after those with effects computed
+    /// before it. Returns whether one was pending.
+    fn materialize_pending(&mut self, out: &mut Block, register: usize) -> bool {
+        let Some(Pending::Value { order, .. }) = self.pending[register] else {
+            return false;
+        };
+        self.settle_before(out, order);
+        if let Some(value) = self.take_value(register) {
+            self.materialize(out, register, value);
+        }
+        true
+    }
+
+    /// Declare a local with a generated name for a value.
+    fn materialize(&mut self, out: &mut Block, register: usize, value: Expression) {
+        self.declare(out, register, vec![value]);
+    }
+
+    /// Declare a local with a generated name for a register.
+    fn declare(&mut self, out: &mut Block, register: usize, values: Vec<Expression>) {
+        let name = generated_name("r", register, self.depth);
+        self.name_register(register, name.clone());
+        out.push(Statement::Local {
+            names: vec![LocalName {
+                name,
+                attribute: None,
+            }],
+            values,
+        });
+    }
+
+    fn name_register(&mut self, register: usize, name: String) {
+        self.generated[register] = Some(name);
+        self.captured[register] = false;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(register);
+        }
+    }
+
+    /// Returns the name of the local living in a register at `pc`.
+    fn name(&self, register: usize, pc: usize) -> Option<String> {
+        if self.capture == Some(register) {
+            return None;
+        }
+
+        self.local_name(register, pc)
+            .or_else(|| self.generated[register].clone())
+    }
+
+    fn register_name(&self, register: usize, pc: usize) -> String {
+        self.name(register, pc)
+            .unwrap_or_else(|| generated_name("r", register, self.depth))
+    }
+
+    fn named(&self, register: usize, pc: usize) -> bool {
+        self.name(register, pc).is_some()
+    }
+
+    fn local_name(&self, register: usize, pc: usize) -> Option<String> {
+        let locals = self.prototype.debug_info.local_variables.data.iter();
+        let locals = locals.map(|local| {
+            let range = (local.start_pc.max(0) as u64, local.end_pc.max(0) as u64);
+            (local.name.as_bytes(), range.0, range.1)
+        });
+
+        local_name(locals, register as u32, pc as u64)
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    }
+
+    /// Returns whether a debug local starts at `pc`.
+    fn starts_local(&self, pc: usize) -> bool {
+        let locals = &self.prototype.debug_info.local_variables.data;
+        locals
+            .iter()
+            .any(|local| local.start_pc as i64 == pc as i64)
+    }
+
+    /// Returns whether the debug local starting at `pc` lives in `register`.
+    fn declares(&self, register: usize, pc: usize) -> bool {
+        let locals = &self.prototype.debug_info.local_variables.data;
+        locals
+            .iter()
+            .filter(|local| is_active(local.start_pc, local.end_pc, pc))
+            .nth(register)
+            .is_some_and(|local| local.start_pc as i64 == pc as i64)
+    }
+
+    /// Count how often the value written into `register` at `pc` is read before being
+    /// overwritten, in code order, stopping at two.
+    ///
+    /// Filling a `table` built by a constructor doesn't count as reading it.
+    fn reads_after(&self, register: usize, pc: usize, table: bool) -> usize {
+        // Temporary values don't outlive the block computing them
+        let end = self.ends.last().map_or(self.code.len(), |&end| end);
+        let mut reads = 0;
+        let mut top = None;
+        let mut pc = next_pc(self.prototype, pc);
+        while pc < end && reads < 2 {
+            // The value becomes a local
+            if self.declares(register, pc) {
+                return 1;
+            }
+
+            let (read, written) = self.uses(pc, register, table, top);
+            reads += usize::from(read);
+            if written {
+                break;
+            }
+            top = match self.code[pc] {
+                Instruction::iABC(Opcode::OP_CALL, a, _, 0)
+                | Instruction::iABC(Opcode::OP_VARARG, a, 0, _) => Some(a as usize),
+                _ => top,
+            };
+            pc = next_pc(self.prototype, pc);
+        }
+
+        reads
+    }
+
+    /// Returns whether the instruction at `pc` reads and writes `register`, `top` being the
+    /// register a call or `...` last left a variable number of values from.
+    fn uses(&self, pc: usize, register: usize, table: bool, top: Option<usize>) -> (bool, bool) {
+        let rk = |operand: u16| operand & BITRK == 0 && operand as usize == register;
+        let range = |first: usize, count: usize| (first..first + count).contains(&register);
+        let open = |first: usize, count: u16| match (count, top) {
+            (0, Some(top)) => (first..=top).contains(&register),
+            (0, None) => false,
+            (count, _) => range(first, count as usize - 1),
+        };
+
+        match self.code[pc] {
+            Instruction::iABC(opcode, a, b, c) => {
+                let a = a as usize;
+                let b_register = b as usize == register;
+                match opcode {
+                    Opcode::OP_MOVE | Opcode::OP_UNM | Opcode::OP_NOT | Opcode::OP_LEN => {
+                        (b_register, a == register)
+                    }
+                    Opcode::OP_LOADBOOL | Opcode::OP_GETUPVAL | Opcode::OP_NEWTABLE => {
+                        (false, a == register)
+                    }
+                    Opcode::OP_LOADNIL => (false, (a..=b as usize).contains(&register)),
+                    Opcode::OP_GETTABLE => (b_register || rk(c), a == register),
+                    Opcode::OP_SETUPVAL => (a == register, false),
+                    Opcode::OP_SETTABLE => ((!table && a == register) || rk(b) || rk(c), false),
+                    Opcode::OP_SELF => (b_register || rk(c), range(a, 2)),
+                    Opcode::OP_ADD
+                    | Opcode::OP_SUB
+                    | Opcode::OP_MUL
+                    | Opcode::OP_DIV
+                    | Opcode::OP_MOD
+                    | Opcode::OP_POW => (rk(b) || rk(c), a == register),
+                    Opcode::OP_CONCAT => {
+                        let read = (b as usize..=c as usize).contains(&register);
+                        (read, a == register)
+                    }
+                    Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE => (rk(b) || rk(c), false),
+                    Opcode::OP_TEST => (a == register, false),
+                    Opcode::OP_TESTSET => (b_register, a == register),
+                    Opcode::OP_CALL => {
+                        let written = match c {
+                            0 => a == register,
+                            c => range(a, c as usize - 1),
+                        };
+                        (a == register || open(a + 1, b), written)
+                    }
+                    Opcode::OP_TAILCALL => (a == register || open(a + 1, b), false),
+                    Opcode::OP_RETURN => (open(a, b), false),
+                    Opcode::OP_TFORLOOP => (range(a, 3), range(a + 3, c as usize)),
+                    Opcode::OP_SETLIST => {
+                        let values = match b {
+                            0 => open(a + 1, 0),
+                            b => range(a + 1, b as usize),
+                        };
+                        ((!table && a == register) || values, false)
+                    }
+                    Opcode::OP_VARARG => (false, b == 0 && a <= register || open(a, b)),
+                    _ => (false, false),
+                }
+            }
+            Instruction::iABx(opcode, a, bx) => {
+                let a = a as usize == register;
+                match opcode {
+                    Opcode::OP_SETGLOBAL => (a, false),
+                    Opcode::OP_CLOSURE => {
+                        let captured = self
+                            .prototype
+                            .prototypes
+                            .data
+                            .get(bx as usize)
+                            .map_or(0, |child| child.number_of_upvalues as usize);
+                        let read = (pc + 1..pc + 1 + captured).any(|pc| {
+                            matches!(
+                                self.code.get(pc),
+                                Some(Instruction::iABC(Opcode::OP_MOVE, _, b, _))
+                                    if *b as usize == register
+                            )
+                        });
+                        (read, a)
+                    }
+                    _ => (false, a),
+                }
+            }
+            Instruction::iAsBx(opcode, a, _) => {
+                let a = a as usize;
+                match opcode {
+                    // The loop keeps its own copies of the values
+                    Opcode::OP_FORPREP => (range(a, 3), range(a, 3)),
+                    Opcode::OP_FORLOOP => (range(a, 3), a == register || a + 3 == register),
+                    _ => (false, false),
+                }
+            }
+        }
+    }
+
+    /// Returns whether the instruction at `pc` only computes a value into a temporary register.
+    fn is_expression(&self, pc: usize) -> bool {
+        self.expression_register(pc)
+            .is_some_and(|register| !self.named(register, pc))
+    }
+
+    /// Returns the register the instruction at `pc` computes a value into, if that is all it does.
+    fn expression_register(&self, pc: usize) -> Option<usize> {
+        let written = match self.code[pc] {
+            Instruction::iABC(opcode, a, _, c) => match opcode {
+                Opcode::OP_MOVE
+                | Opcode::OP_LOADNIL
+                | Opcode::OP_GETUPVAL
+                | Opcode::OP_GETTABLE
+                | Opcode::OP_SETTABLE
+                | Opcode::OP_NEWTABLE
+                | Opcode::OP_SELF
+                | Opcode::OP_ADD
+                | Opcode::OP_SUB
+                | Opcode::OP_MUL
+                | Opcode::OP_DIV
+                | Opcode::OP_MOD
+                | Opcode::OP_POW
+                | Opcode::OP_UNM
+                | Opcode::OP_NOT
+                | Opcode::OP_LEN
+                | Opcode::OP_CONCAT
+                | Opcode::OP_VARARG => a,
+                // Loading a boolean can skip an instruction, and calls can be statements
+                Opcode::OP_LOADBOOL if c == 0 => a,
+                Opcode::OP_CALL if c >= 2 => a,
+                // The block number of a long list takes the place of the next instruction
+                Opcode::OP_SETLIST if c != 0 => a,
+                _ => return None,
+            },
+            Instruction::iABx(Opcode::OP_LOADK | Opcode::OP_GETGLOBAL, a, _) => a,
+            _ => return None,
+        };
+
+        Some(written as usize)
+    }
+
+    fn is_test(&self, pc: usize) -> bool {
+        matches!(
+            self.code.get(pc),
+            Some(Instruction::iABC(
+                Opcode::OP_EQ
+                    | Opcode::OP_LT
+                    | Opcode::OP_LE
+                    | Opcode::OP_TEST
+                    | Opcode::OP_TESTSET,
+                ..
+            ))
+        )
+    }
+
+    /// Returns where the `JMP` at `pc` goes, if it is one and its target is in the code.
+    fn jump_target(&self, pc: usize) -> Option<usize> {
+        match self.code.get(pc)? {
+            Instruction::iAsBx(Opcode::OP_JMP, _, sbx) => {
+                let target = pc as i64 + 1 + *sbx as i64;
+                usize::try_from(target)
+                    .ok()
+                    .filter(|&target| target <= self.code.len())
+            }
+            _ => None,
+        }
+    }
+
+    fn constant(&self, index: usize) -> Expression {
+        match self.prototype.constants.data.get(index) {
+            Some(Constant::Boolean(value)) => Expression::Boolean(*value),
+            Some(Constant::Number(value)) => Expression::Number(*value),
+            Some(Constant::String(value)) => Expression::String(value.as_bytes().to_vec()),
+            Some(Constant::Nil) | None => Expression::Nil,
+        }
+    }
+
+    fn global(&self, index: usize) -> Expression {
+        match self.constant(index) {
+            Expression::String(name) if is_identifier(&name) => {
+                Expression::Name(String::from_utf8_lossy(&name).into_owned())
+            }
+            name => Expression::index(Expression::Name("_G".to_string()), name),
+        }
+    }
+
+    fn upvalue(&self, index: usize) -> Expression {
+        let name = self.upvalues.get(index).cloned();
+        Expression::Name(name.unwrap_or_else(|| generated_name("u", index, self.depth)))
+    }
+}
+
+/// Returns the pc of the instruction after the one at `pc`, past the data some keep after them.
+fn next_pc(prototype: &Prototype, pc: usize) -> usize {
+    match prototype.code.data[pc] {
+        Instruction::iABC(Opcode::OP_SETLIST, _, _, 0) => pc + 2,
+        Instruction::iABx(Opcode::OP_CLOSURE, _, bx) => {
+            let captured = prototype.prototypes.data.get(bx as usize);
+            pc + 1 + captured.map_or(0, |child| child.number_of_upvalues as usize)
+        }
+        _ => pc + 1,
+    }
+}
+
+fn is_active(start_pc: i32, end_pc: i32, pc: usize) -> bool {
+    (start_pc as i64) <= pc as i64 && (pc as i64) < end_pc as i64
+}
+
+fn opcode(instruction: Instruction) -> Opcode {
+    match instruction {
+        Instruction::iABC(opcode, ..)
+        | Instruction::iABx(opcode, ..)
+        | Instruction::iAsBx(opcode, ..) => opcode,
+    }
+}
+
+fn binary_operator(opcode: Opcode) -> BinaryOperator {
+    match opcode {
+        Opcode::OP_ADD => BinaryOperator::Add,
+        Opcode::OP_SUB => BinaryOperator::Sub,
+        Opcode::OP_MUL => BinaryOperator::Mul,
+        Opcode::OP_DIV => BinaryOperator::Div,
+        Opcode::OP_MOD => BinaryOperator::Mod,
+        _ => BinaryOperator::Pow,
+    }
+}
+
+fn assignment(target: Expression, value: Expression) -> Statement {
+    Statement::Assign {
+        targets: vec![target],
+        values: vec![value],
+    }
+}
@@ -3,10 +3,11 @@ pub mod debug_info;
 pub mod instructions;
 
 use luasleuth_common::types::{Array, Bytecode as BytecodeTrait, LuaString};
-use luasleuth_common::{CommonCtx, Version};
-use scroll::{ctx, Pread};
+use luasleuth_common::{header, CommonCtx, Error, ErrorContext, Limits, Version};
+use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug, Pread)]
+#[derive(Debug, Clone, Pread, Pwrite)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     pub signature: [u8; 4],
     pub version: Version,
@@ -19,9 +20,58 @@ pub struct Header {
     pub integral_flag: u8,
 }
 
-#[derive(Debug)]
+impl Header {
+    /// Returns the byte order of the chunk, as described by `endianess_flag`.
+    pub fn endianness(&self) -> Result<scroll::Endian, scroll::Error> {
+        match self.endianess_flag {
+            0 => Ok(scroll::BE),
+            1 => Ok(scroll::LE),
+            _ => Err(scroll::Error::BadInput {
+                size: 1,
+                msg: "Invalid endianness flag",
+            }),
+        }
+    }
+
+    /// Returns the context the rest of the chunk is read and written with.
+    pub fn context(&self, limits: Limits) -> Result<CommonCtx, Error> {
+        Ok(CommonCtx {
+            size_of_size_t: self.size_of_size_t,
+            lua_version: self.version,
+            endianness: self.endianness().within(6, "endianess_flag")?,
+            size_of_int: self.size_of_int,
+            size_of_instruction: self.size_of_instruction,
+            size_of_lua_number: self.size_of_lua_number,
+            size_of_lua_integer: 0,
+            integral: self.integral_flag != 0,
+            limits,
+            depth: 0,
+        })
+    }
+}
+
+impl Default for Header {
+    /// The header written by a stock 64-bit little endian build.
+    fn default() -> Self {
+        Self {
+            signature: header::LUA_SIGNATURE,
+            version: Version::new(5, 1),
+            format_version: 0,
+            endianess_flag: 1,
+            size_of_int: 4,
+            size_of_size_t: 8,
+            size_of_instruction: 4,
+            size_of_lua_number: 8,
+            integral_flag: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Prototype<'a> {
-    pub source: LuaString<'a>,
+    /// The name of the source, `None` when stripped or when it is the same as the parent's
+    pub source: Option<LuaString<'a>>,
     pub line_defined: i32,
     pub last_line_defined: i32,
     pub number_of_upvalues: u8,
@@ -34,32 +84,74 @@ pub struct Prototype<'a> {
     pub debug_info: debug_info::DebugInfo<'a>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bytecode<'a> {
     pub header: Header,
     pub prototype: Prototype<'a>,
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Prototype<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
+        Self::read(src, ctx).map_err(Error::in_prototype)
+    }
+}
+
+impl<'a> Prototype<'a> {
+    /// Returns the number of elements in the arrays of this prototype and its children.
+    pub fn element_count(&self) -> usize {
+        self.code.size
+            + self.constants.size
+            + self.prototypes.size
+            + self.debug_info.element_count()
+            + self
+                .prototypes
+                .data
+                .iter()
+                .map(Prototype::element_count)
+                .sum::<usize>()
+    }
+
+    /// Read the fields of a prototype, before the error is marked as being inside of it.
+    fn read(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Error> {
         let offset = &mut 0;
+        ctx.limits.check_depth(ctx.depth)?;
 
-        let source: LuaString = src.gread_with(offset, ctx)?;
-        let line_defined: i32 = src.gread_with(offset, ctx.endianness)?;
-        let last_line_defined: i32 = src.gread_with(offset, ctx.endianness)?;
+        let source: Option<LuaString> = src.gread_with(offset, ctx).within(*offset, "source")?;
+        let line_defined = ctx.read_int(src, offset).within(*offset, "line_defined")? as i32;
+        let last_line_defined = ctx
+            .read_int(src, offset)
+            .within(*offset, "last_line_defined")? as i32;
 
-        let number_of_upvalues: u8 = src.gread_with(offset, ctx.endianness)?;
-        let number_of_parameters: u8 = src.gread_with(offset, ctx.endianness)?;
-        let is_vararg: u8 = src.gread_with(offset, ctx.endianness)?;
-        let max_stack_size: u8 = src.gread_with(offset, ctx.endianness)?;
+        let number_of_upvalues: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "number_of_upvalues")?;
+        let number_of_parameters: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "number_of_parameters")?;
+        let is_vararg: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "is_vararg")?;
+        let max_stack_size: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "max_stack_size")?;
 
-        let code: Array<instructions::Instruction> = src.gread_with(offset, ctx)?;
-        let constants: Array<constants::Constant> = src.gread_with(offset, ctx)?;
-        let prototypes: Array<Prototype> = src.gread_with(offset, ctx)?;
+        let code: Array<instructions::Instruction> =
+            src.gread_with(offset, ctx).within(*offset, "code")?;
+        let constants: Array<constants::Constant> =
+            src.gread_with(offset, ctx).within(*offset, "constants")?;
+        let child_ctx = CommonCtx {
+            depth: ctx.depth + 1,
+            ..ctx
+        };
+        let prototypes: Array<Prototype> = src
+            .gread_with(offset, child_ctx)
+            .within(*offset, "prototypes")?;
 
-        let debug_info: debug_info::DebugInfo = src.gread_with(offset, ctx)?;
+        let debug_info: debug_info::DebugInfo =
+            src.gread_with(offset, ctx).within(*offset, "debug_info")?;
 
         Ok((
             Prototype {
@@ -80,6 +172,51 @@ impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Prototype<'a> {
     }
 }
 
+impl ctx::TryIntoCtx<CommonCtx> for Prototype<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        // Stripped chunks keep the count without the names, so it can only be checked
+        let upvalue_names = self.debug_info.upvalues.data.len();
+        if upvalue_names != 0 && upvalue_names != self.number_of_upvalues as usize {
+            return Err(scroll::Error::Custom(format!(
+                "{} upvalues are declared but {} are named",
+                self.number_of_upvalues, upvalue_names
+            )));
+        }
+
+        dst.gwrite_with(self.source, offset, ctx)?;
+        ctx.write_int(dst, offset, self.line_defined as i64)?;
+        ctx.write_int(dst, offset, self.last_line_defined as i64)?;
+        dst.gwrite_with(self.number_of_upvalues, offset, ctx.endianness)?;
+        dst.gwrite_with(self.number_of_parameters, offset, ctx.endianness)?;
+        dst.gwrite_with(self.is_vararg, offset, ctx.endianness)?;
+        dst.gwrite_with(self.max_stack_size, offset, ctx.endianness)?;
+
+        dst.gwrite_with(self.code, offset, ctx)?;
+        dst.gwrite_with(self.constants, offset, ctx)?;
+        dst.gwrite_with(self.prototypes, offset, ctx)?;
+        dst.gwrite_with(self.debug_info, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<CommonCtx> for Bytecode<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.header, offset, scroll::LE)?;
+        dst.gwrite_with(self.prototype, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
 impl BytecodeTrait for Bytecode<'_> {
     fn identifier() -> &'static str {
         "lua51"
@@ -1,11 +1,12 @@
-use luasleuth_common::{types::LuaString, CommonCtx};
+use luasleuth_common::{types::LuaString, CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Constant<'a> {
     Nil,
     Boolean(bool),
-    Number(f64),
+    Number(#[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64),
     String(LuaString<'a>),
 }
 
@@ -29,26 +30,25 @@ impl Constant<'_> {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Constant<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let tag: u8 = src.gread_with(offset, ctx.endianness)?;
+        let tag: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "tag")?;
 
         let constant = match tag {
             0 => Constant::Nil,
             1 => {
-                let value: u8 = src.gread_with(offset, ctx.endianness)?;
+                let value: u8 = src
+                    .gread_with(offset, ctx.endianness)
+                    .within(*offset, "value")?;
                 Constant::Boolean(value != 0)
             }
-            3 => Constant::Number(src.gread_with(offset, ctx.endianness)?),
-            4 => Constant::String(src.gread_with(offset, ctx)?),
-            _ => {
-                return Err(scroll::Error::BadInput {
-                    size: 1,
-                    msg: "Invalid constant type",
-                })
-            }
+            3 => Constant::Number(ctx.read_number(src, offset).within(*offset, "value")?),
+            4 => Constant::String(src.gread_with(offset, ctx).within(*offset, "value")?),
+            _ => return Err(Error::invalid("Invalid constant type", 0).within(0, "tag")),
         };
 
         Ok((constant, *offset))
@@ -70,7 +70,7 @@ impl ctx::TryIntoCtx<CommonCtx> for Constant<'_> {
                 dst.gwrite_with(value as u8, offset, ctx.endianness)?;
             }
             Constant::Number(value) => {
-                dst.gwrite_with(value, offset, ctx.endianness)?;
+                ctx.write_number(dst, offset, value)?;
             }
             Constant::String(value) => {
                 dst.gwrite_with(value, offset, ctx)?;
@@ -1,10 +1,11 @@
 use luasleuth_common::{
     types::{Array, LuaString},
-    CommonCtx,
+    CommonCtx, Error, ErrorContext,
 };
 use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DebugInfo<'a> {
     /// Map from opcodes to source lines
     pub line_info: Array<i32>,
@@ -14,7 +15,8 @@ pub struct DebugInfo<'a> {
     pub upvalues: Array<LuaString<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LocalVariable<'a> {
     pub name: LuaString<'a>,
     /// First point where variable is active
@@ -23,15 +25,24 @@ pub struct LocalVariable<'a> {
     pub end_pc: i32,
 }
 
+impl DebugInfo<'_> {
+    /// Returns the number of elements in the arrays of the debug information.
+    pub fn element_count(&self) -> usize {
+        self.line_info.size + self.local_variables.size + self.upvalues.size
+    }
+}
+
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for DebugInfo<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let line_info: Array<i32> = src.gread_with(offset, ctx)?;
-        let local_variables: Array<LocalVariable> = src.gread_with(offset, ctx)?;
-        let upvalues: Array<LuaString> = src.gread_with(offset, ctx)?;
+        let line_info: Array<i32> = src.gread_with(offset, ctx).within(*offset, "line_info")?;
+        let local_variables: Array<LocalVariable> = src
+            .gread_with(offset, ctx)
+            .within(*offset, "local_variables")?;
+        let upvalues: Array<LuaString> = src.gread_with(offset, ctx).within(*offset, "upvalues")?;
 
         Ok((
             DebugInfo {
@@ -59,13 +70,13 @@ impl ctx::TryIntoCtx<CommonCtx> for DebugInfo<'_> {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for LocalVariable<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let name: LuaString = src.gread_with(offset, ctx)?;
-        let start_pc: i32 = src.gread_with(offset, ctx.endianness)?;
-        let end_pc: i32 = src.gread_with(offset, ctx.endianness)?;
+        let name: LuaString = src.gread_with(offset, ctx).within(*offset, "name")?;
+        let start_pc = ctx.read_int(src, offset).within(*offset, "start_pc")? as i32;
+        let end_pc = ctx.read_int(src, offset).within(*offset, "end_pc")? as i32;
 
         Ok((
             LocalVariable {
@@ -85,8 +96,8 @@ impl ctx::TryIntoCtx<CommonCtx> for LocalVariable<'_> {
         let offset = &mut 0;
 
         dst.gwrite_with(self.name, offset, ctx)?;
-        dst.gwrite_with(self.start_pc, offset, ctx.endianness)?;
-        dst.gwrite_with(self.end_pc, offset, ctx.endianness)?;
+        ctx.write_int(dst, offset, self.start_pc as i64)?;
+        ctx.write_int(dst, offset, self.end_pc as i64)?;
 
         Ok(*offset)
     }
@@ -1,5 +1,5 @@
-use luasleuth_common::{mask, types::Packable, CommonCtx};
-use scroll::{ctx, Pread, Pwrite};
+use luasleuth_common::{mask, types::Packable, CommonCtx, Error, InvalidOpcode};
+use scroll::ctx;
 
 pub mod constants {
     //! Constants related to Lua 5.1 instructions
@@ -21,7 +21,8 @@ pub mod constants {
     pub const MAXARG_S_BX: u32 = MAXARG_BX >> 1;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum Instruction {
     iABC(Opcode, u8, u16, u16),
@@ -29,7 +30,8 @@ pub enum Instruction {
     iAsBx(Opcode, u8, i32),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 pub enum Opcode {
@@ -79,9 +81,11 @@ impl From<Opcode> for u8 {
     }
 }
 
-impl From<u8> for Opcode {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for Opcode {
+    type Error = InvalidOpcode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let opcode = match value {
             0 => Opcode::OP_MOVE,
             1 => Opcode::OP_LOADK,
             2 => Opcode::OP_LOADBOOL,
@@ -120,21 +124,23 @@ impl From<u8> for Opcode {
             35 => Opcode::OP_CLOSE,
             36 => Opcode::OP_CLOSURE,
             37 => Opcode::OP_VARARG,
-            _ => unreachable!(),
-        }
+            _ => return Err(InvalidOpcode(value)),
+        };
+
+        Ok(opcode)
     }
 }
 
 impl Packable for Instruction {
-    fn decode(raw: u32) -> Instruction {
+    fn decode(raw: u32) -> Result<Instruction, InvalidOpcode> {
         use constants::*;
         use Opcode::*;
 
         let opcode = (raw >> POS_OP) & mask!(SIZE_OP, 0);
         let a = ((raw >> POS_A) & mask!(SIZE_A, 0)) as u8;
 
-        let opcode: Opcode = (opcode as u8).into();
-        match opcode {
+        let opcode = Opcode::try_from(opcode as u8)?;
+        let instruction = match opcode {
             OP_MOVE | OP_LOADBOOL | OP_LOADNIL | OP_GETUPVAL | OP_GETTABLE | OP_SETUPVAL
             | OP_SETTABLE | OP_NEWTABLE | OP_SELF | OP_ADD | OP_SUB | OP_MUL | OP_DIV | OP_MOD
             | OP_POW | OP_UNM | OP_NOT | OP_LEN | OP_CONCAT | OP_EQ | OP_LT | OP_LE | OP_TEST
@@ -152,7 +158,9 @@ impl Packable for Instruction {
                 let sbx = (((raw >> POS_BX) & MAXARG_BX) as i32) - (MAXARG_S_BX as i32);
                 Instruction::iAsBx(opcode, a, sbx)
             }
-        }
+        };
+
+        Ok(instruction)
     }
 
     fn encode(inst: Instruction) -> u32 {
@@ -169,14 +177,14 @@ impl Packable for Instruction {
             }
             Instruction::iABx(opcode, a, bx) => {
                 let opcode = opcode as u32;
-                let a = (a << POS_A) as u32;
+                let a = (a as u32) << POS_A;
                 let bx = bx << POS_BX;
 
                 opcode | a | bx
             }
             Instruction::iAsBx(opcode, a, sbx) => {
                 let opcode = opcode as u32;
-                let a = (a << POS_A) as u32;
+                let a = (a as u32) << POS_A;
                 let sbx = ((sbx + (MAXARG_S_BX as i32)) << POS_BX) as u32;
 
                 opcode | a | sbx
@@ -186,12 +194,12 @@ impl Packable for Instruction {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Instruction {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let instruction: u32 = src.gread_with(offset, ctx.endianness)?;
-        let instruction = Instruction::decode(instruction);
+        let instruction = ctx.read_instruction(src, offset)?;
+        let instruction = Instruction::decode(instruction)?;
 
         Ok((instruction, *offset))
     }
@@ -202,7 +210,7 @@ impl ctx::TryIntoCtx<CommonCtx> for Instruction {
 
     fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
         let offset = &mut 0;
-        dst.gwrite_with(Instruction::encode(self), offset, ctx.endianness)?;
+        ctx.write_instruction(dst, offset, Instruction::encode(self))?;
 
         Ok(*offset)
     }
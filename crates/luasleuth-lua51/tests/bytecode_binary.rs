@@ -1,5 +1,13 @@
-use luasleuth_common::disassembler::Disassemble as _;
+use luasleuth_common::{
+    assembler::Assemble as _, disassembler::Disassemble as _, types::Packable, InvalidOpcode,
+    Limits,
+};
+use luasleuth_lua51::assembler::Assembler;
 use luasleuth_lua51::disassembler::Disassembler;
+use luasleuth_lua51::types::{
+    constants::Constant,
+    instructions::{Instruction, Opcode},
+};
 
 #[test]
 fn test_can_parse_bytecode_file() {
@@ -14,7 +22,10 @@ fn test_can_parse_bytecode_file() {
 
     // Check prototype related values
     let prototype = bytecode.prototype;
-    assert_eq!(prototype.source, "@.\\example.lua"); // Checks size and actual data\
+    assert_eq!(
+        prototype.source.expect("Missing source name"),
+        "@.\\example.lua"
+    ); // Checks size and actual data
     assert_eq!(prototype.line_defined, 0);
     assert_eq!(prototype.last_line_defined, 0);
     assert_eq!(prototype.number_of_upvalues, 0);
@@ -22,3 +33,275 @@ fn test_can_parse_bytecode_file() {
     assert_eq!(prototype.is_vararg, 2);
     assert_eq!(prototype.max_stack_size, 2);
 }
+
+/// Build a chunk returning a single number constant, using the given platform sizes.
+fn build_chunk(
+    big_endian: bool,
+    size_of_int: u8,
+    size_of_size_t: u8,
+    number: &[u8],
+    integral: bool,
+) -> Vec<u8> {
+    let int = |value: u64, size: u8| -> Vec<u8> {
+        let bytes = value.to_le_bytes()[..size as usize].to_vec();
+        if big_endian {
+            bytes.into_iter().rev().collect()
+        } else {
+            bytes
+        }
+    };
+
+    let mut chunk = vec![0x1b, b'L', b'u', b'a', 0x51, 0];
+    chunk.push(!big_endian as u8);
+    chunk.extend([size_of_int, size_of_size_t, 4, number.len() as u8]);
+    chunk.push(integral as u8);
+
+    // Source name, line range and function flags
+    chunk.extend(int(3, size_of_size_t));
+    chunk.extend(b"=t\0");
+    chunk.extend(int(7, size_of_int));
+    chunk.extend(int(9, size_of_int));
+    chunk.extend([0, 0, 2, 2]);
+
+    // RETURN 0 1
+    chunk.extend(int(1, size_of_int));
+    chunk.extend(int(0x0080001e, 4));
+
+    chunk.extend(int(1, size_of_int));
+    chunk.push(3);
+    chunk.extend(number);
+
+    // No prototypes, a single line info entry and no locals or upvalues
+    chunk.extend(int(0, size_of_int));
+    chunk.extend(int(1, size_of_int));
+    chunk.extend(int(9, size_of_int));
+    chunk.extend(int(0, size_of_int));
+    chunk.extend(int(0, size_of_int));
+
+    chunk
+}
+
+#[test]
+fn test_can_parse_big_endian_single_precision_chunk() {
+    let bytes = build_chunk(true, 4, 4, &1.5f32.to_be_bytes(), false);
+    let bytecode = Disassembler::new(&bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    let prototype = bytecode.prototype;
+    assert_eq!(prototype.source.expect("Missing source name"), "=t");
+    assert_eq!(prototype.line_defined, 7);
+    assert_eq!(prototype.last_line_defined, 9);
+    assert_eq!(prototype.code.size, 1);
+    assert!(matches!(
+        prototype.code.data[0],
+        Instruction::iABC(Opcode::OP_RETURN, 0, 1, 0)
+    ));
+    assert!(matches!(prototype.constants.data[0], Constant::Number(n) if n == 1.5));
+    assert_eq!(prototype.debug_info.line_info.data, vec![9]);
+}
+
+#[test]
+fn test_can_parse_integral_chunk_with_wide_int() {
+    let bytes = build_chunk(false, 8, 8, &42i64.to_le_bytes(), true);
+    let bytecode = Disassembler::new(&bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    let prototype = bytecode.prototype;
+    assert_eq!(prototype.line_defined, 7);
+    assert_eq!(prototype.code.size, 1);
+    assert!(matches!(prototype.constants.data[0], Constant::Number(n) if n == 42.0));
+    assert_eq!(prototype.debug_info.line_info.data, vec![9]);
+}
+
+#[test]
+fn test_rejects_invalid_endianness_flag() {
+    let mut bytes = build_chunk(false, 4, 4, &1.5f64.to_le_bytes(), false);
+    bytes[6] = 2;
+
+    assert!(Disassembler::new(&bytes).disassemble().is_err());
+}
+
+#[test]
+fn test_error_reports_location_of_invalid_constant() {
+    let mut bytes = build_chunk(false, 4, 4, &1.5f64.to_le_bytes(), false);
+    bytes[43] = 9; // Constant tag
+
+    let error = Disassembler::new(&bytes).disassemble().unwrap_err();
+    assert_eq!(error.offset(), 43);
+    assert_eq!(error.prototype_path().as_deref(), Some("main"));
+    assert_eq!(error.field_path(), "constants[0].tag");
+    assert_eq!(
+        error.to_string(),
+        "Invalid constant type at offset 0x2b (main, constants[0].tag)"
+    );
+}
+
+#[test]
+fn test_malformed_input_returns_error_instead_of_panicking() {
+    let bytes = include_bytes!("../../../data/bytecode/lua51.bin");
+
+    for length in 0..bytes.len() {
+        let result = Disassembler::new(&bytes[..length]).disassemble();
+        assert!(result.is_err(), "Truncated to {} bytes", length);
+    }
+
+    for index in 0..bytes.len() {
+        for value in [0x00, 0x7f, 0x80, 0xff] {
+            let mut bytes = bytes.to_vec();
+            bytes[index] = value;
+            let _ = Disassembler::new(&bytes).disassemble();
+        }
+    }
+}
+
+#[test]
+fn test_rejects_unknown_opcodes() {
+    assert!(matches!(Opcode::try_from(37), Ok(Opcode::OP_VARARG)));
+    assert_eq!(Opcode::try_from(38).unwrap_err(), InvalidOpcode(38));
+
+    // RETURN 0 1 with its opcode replaced
+    assert_eq!(
+        Instruction::decode(0x0080003f).unwrap_err(),
+        InvalidOpcode(63)
+    );
+}
+
+#[test]
+fn test_rejects_counts_larger_than_the_input() {
+    let mut bytes = build_chunk(false, 4, 4, &1.5f64.to_le_bytes(), false);
+    bytes[39..43].copy_from_slice(&i32::MAX.to_le_bytes()); // Constant count
+
+    let error = Disassembler::new(&bytes).disassemble().unwrap_err();
+    assert_eq!(error.offset(), 39);
+    assert_eq!(error.field_path(), "constants");
+    assert_eq!(
+        error.kind().to_string(),
+        "Count of 2147483647 elements is larger than the 29 bytes left"
+    );
+}
+
+#[test]
+fn test_enforces_limits() {
+    let bytes = include_bytes!("../../../data/bytecode/lua51.bin");
+    let limits = Limits {
+        max_elements: 3,
+        ..Limits::default()
+    };
+
+    let error = Disassembler::new(bytes)
+        .with_limits(limits)
+        .disassemble()
+        .unwrap_err();
+    assert!(error.kind().to_string().ends_with("the limit of 3"));
+
+    let limits = Limits {
+        max_string_length: 4,
+        ..Limits::default()
+    };
+    let error = Disassembler::new(bytes)
+        .with_limits(limits)
+        .disassemble()
+        .unwrap_err();
+    assert_eq!(error.field_path(), "source");
+}
+
+#[test]
+fn test_can_parse_strings_that_are_not_utf8() {
+    let mut bytes = build_chunk(false, 4, 4, &1.5f64.to_le_bytes(), false);
+    bytes[16..18].copy_from_slice(&[0xff, 0x01]);
+
+    let bytecode = Disassembler::new(&bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    let source = bytecode.prototype.source.expect("Missing source name");
+    assert_eq!(source.as_bytes(), [0xff, 0x01]);
+    assert_eq!(source.as_str(), None);
+    assert_eq!(source.to_string(), "\u{fffd}\u{1}");
+    assert_eq!(source.escaped().to_string(), "\\255\\001");
+}
+
+#[test]
+fn test_assembles_chunks_back_to_the_same_bytes() {
+    let chunks = [
+        include_bytes!("../../../data/bytecode/lua51.bin").to_vec(),
+        build_chunk(true, 4, 4, &1.5f32.to_be_bytes(), false),
+        build_chunk(false, 8, 8, &42i64.to_le_bytes(), true),
+    ];
+
+    for bytes in chunks {
+        let bytecode = Disassembler::new(&bytes)
+            .disassemble()
+            .expect("Failed to read bytecode data");
+        let assembled = Assembler::new(bytecode)
+            .assemble()
+            .expect("Failed to assemble bytecode");
+        assert_eq!(assembled, bytes);
+    }
+}
+
+#[test]
+fn test_assembles_modified_bytecode() {
+    let bytes = include_bytes!("../../../data/bytecode/lua51.bin");
+    let mut bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Nest a copy of the main function, which luac dumps without a source name
+    let mut child = bytecode.prototype.clone();
+    child.source = None;
+    child.code.data[0] = Instruction::iABC(Opcode::OP_MOVE, 200, 1, 0);
+    bytecode.prototype.prototypes = vec![child].into();
+
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    let bytecode = Disassembler::new(&assembled)
+        .disassemble()
+        .expect("Failed to read assembled bytecode");
+
+    let child = &bytecode.prototype.prototypes.data[0];
+    assert!(child.source.is_none());
+    assert!(matches!(
+        child.code.data[0],
+        Instruction::iABC(Opcode::OP_MOVE, 200, 1, 0)
+    ));
+
+    let reassembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    assert_eq!(reassembled, assembled);
+}
+
+#[test]
+fn test_rejects_upvalue_names_that_do_not_match_the_count() {
+    let bytes = include_bytes!("../../../data/bytecode/lua51.bin");
+    let mut bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // The count can't be derived from the names, which are stripped with the debug information
+    bytecode.prototype.debug_info.upvalues.data.push("x".into());
+
+    let error = Assembler::new(bytecode).assemble().unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("0 upvalues are declared but 1 are named"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_instruction_encoding_round_trips() {
+    let mut raw = 0x2545_f491u32;
+    for _ in 0..100_000 {
+        raw = raw.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        if let Ok(instruction) = Instruction::decode(raw) {
+            assert_eq!(Instruction::encode(instruction), raw, "{:?}", instruction);
+        }
+    }
+}
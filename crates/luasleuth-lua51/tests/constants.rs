@@ -1,6 +1,7 @@
 use luasleuth_lua51::types::constants::Constant;
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn test_is_constant_tag_valid() {
     assert_eq!(Constant::Nil.get_type(), 0);
     assert_eq!(Constant::Boolean(false).get_type(), 1);
@@ -0,0 +1,51 @@
+//! Control flow graphs of Lua 5.2 prototypes.
+
+use luasleuth_common::{
+    cfg::{skip_next, ControlFlowGraph, Edge, EdgeKind},
+    resolve::jump_target,
+};
+
+use crate::{
+    resolve::resolve,
+    types::{
+        instructions::{Instruction, Opcode},
+        Prototype,
+    },
+};
+
+/// Build the control flow graph of a prototype, leaving its children out.
+pub fn build(prototype: &Prototype) -> ControlFlowGraph {
+    ControlFlowGraph::build(prototype.code.data.len(), |pc| successors(prototype, pc))
+}
+
+/// Returns the edges leaving the instruction at `pc`, to the pcs control can go to next.
+pub fn successors(prototype: &Prototype, pc: usize) -> Vec<Edge> {
+    let Some(&instruction) = prototype.code.data.get(pc) else {
+        return Vec::new();
+    };
+
+    let jump = |kind| {
+        let operands = resolve(prototype, pc)?;
+        Some(Edge::new(jump_target(&operands)?, kind))
+    };
+
+    match instruction {
+        Instruction::iABC(
+            Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE | Opcode::OP_TEST | Opcode::OP_TESTSET,
+            ..,
+        ) => skip_next(pc),
+        Instruction::iABC(Opcode::OP_LOADBOOL, _, _, c) if c != 0 => {
+            vec![Edge::new(pc + 2, EdgeKind::Jump)]
+        }
+        Instruction::iABC(Opcode::OP_RETURN, ..) => Vec::new(),
+        Instruction::iAsBx(Opcode::OP_JMP | Opcode::OP_FORPREP, ..) => {
+            jump(EdgeKind::Jump).into_iter().collect()
+        }
+        // Jump back to the loop body while the loop goes on
+        Instruction::iAsBx(Opcode::OP_FORLOOP | Opcode::OP_TFORLOOP, ..) => jump(EdgeKind::True)
+            .into_iter()
+            .chain([Edge::new(pc + 1, EdgeKind::False)])
+            .collect(),
+        _ => vec![Edge::new(pc + 1, EdgeKind::Fallthrough)],
+    }
+}
@@ -0,0 +1,52 @@
+//! Control flow graphs of a chunk rendered as Graphviz DOT.
+
+use std::fmt::{self, Write};
+
+use luasleuth_common::dot::{write_cluster, write_graph_end, write_graph_start};
+
+use crate::{
+    cfg::build,
+    listing::{header, write_instruction},
+    types::{Bytecode, Prototype},
+};
+
+/// The control flow graphs of a chunk, with a cluster per prototype.
+pub struct Dot<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Dot<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Dot<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_graph_start(f)?;
+        write_prototype(f, &self.bytecode.prototype, &mut 0)?;
+        write_graph_end(f)
+    }
+}
+
+/// Write the cluster of a prototype, then those of its children.
+fn write_prototype(f: &mut impl Write, prototype: &Prototype, id: &mut usize) -> fmt::Result {
+    let graph = build(prototype);
+    let title = header(prototype).title();
+    write_cluster(f, *id, &title, &graph, |block| {
+        let mut text = String::new();
+        let mut pc = block.start;
+        while pc < block.end {
+            pc = write_instruction(&mut text, prototype, pc)?;
+            text.push('\n');
+        }
+        Ok(text)
+    })?;
+    *id += 1;
+
+    for child in &prototype.prototypes.data {
+        write_prototype(f, child, id)?;
+    }
+
+    Ok(())
+}
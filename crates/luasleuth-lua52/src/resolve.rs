@@ -0,0 +1,184 @@
+//! Resolution of instruction operands against their prototype.
+
+use luasleuth_common::resolve::{local_name, ResolvedOperand};
+
+use crate::types::{
+    constants::Constant,
+    instructions::{constants::*, Instruction, Opcode},
+    Prototype,
+};
+
+/// Marks a `B` or `C` operand as a constant index instead of a register
+const BITRK: u16 = 1 << (SIZE_B - 1);
+
+/// An operand of a Lua 5.2 instruction.
+pub type Operand<'p, 'a> = ResolvedOperand<'p, &'p Constant<'a>>;
+
+/// Resolve the operands of the instruction at `pc`, in the order they are encoded.
+///
+/// Operands the instruction doesn't use are left out, while the ones `LOADKX` and `SETLIST`
+/// take from the following `EXTRAARG` are included. Returns `None` when `pc` is out of range.
+pub fn resolve<'p, 'a>(prototype: &'p Prototype<'a>, pc: usize) -> Option<Vec<Operand<'p, 'a>>> {
+    let instruction = *prototype.code.data.get(pc)?;
+    let resolver = Resolver { prototype, pc };
+
+    let operands = match instruction {
+        Instruction::iABC(opcode, a, b, c) => {
+            let a = a as u32;
+            match opcode {
+                Opcode::OP_MOVE | Opcode::OP_UNM | Opcode::OP_NOT | Opcode::OP_LEN => {
+                    vec![resolver.register(a), resolver.register(b as u32)]
+                }
+                Opcode::OP_LOADKX => match resolver.extra_arg() {
+                    Some(ax) => vec![resolver.register(a), resolver.constant(ax)],
+                    None => vec![resolver.register(a)],
+                },
+                Opcode::OP_LOADBOOL
+                | Opcode::OP_NEWTABLE
+                | Opcode::OP_CALL
+                | Opcode::OP_TAILCALL => vec![
+                    resolver.register(a),
+                    Operand::Literal(b as i64),
+                    Operand::Literal(c as i64),
+                ],
+                Opcode::OP_LOADNIL | Opcode::OP_RETURN | Opcode::OP_VARARG => {
+                    vec![resolver.register(a), Operand::Literal(b as i64)]
+                }
+                Opcode::OP_GETUPVAL | Opcode::OP_SETUPVAL => {
+                    vec![resolver.register(a), resolver.upvalue(b as u32)]
+                }
+                Opcode::OP_GETTABUP => vec![
+                    resolver.register(a),
+                    resolver.upvalue(b as u32),
+                    resolver.register_or_constant(c),
+                ],
+                Opcode::OP_SETTABUP => vec![
+                    resolver.upvalue(a),
+                    resolver.register_or_constant(b),
+                    resolver.register_or_constant(c),
+                ],
+                Opcode::OP_GETTABLE | Opcode::OP_SELF => vec![
+                    resolver.register(a),
+                    resolver.register(b as u32),
+                    resolver.register_or_constant(c),
+                ],
+                Opcode::OP_SETTABLE
+                | Opcode::OP_ADD
+                | Opcode::OP_SUB
+                | Opcode::OP_MUL
+                | Opcode::OP_DIV
+                | Opcode::OP_MOD
+                | Opcode::OP_POW => vec![
+                    resolver.register(a),
+                    resolver.register_or_constant(b),
+                    resolver.register_or_constant(c),
+                ],
+                Opcode::OP_CONCAT => vec![
+                    resolver.register(a),
+                    resolver.register(b as u32),
+                    resolver.register(c as u32),
+                ],
+                Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE => vec![
+                    Operand::Literal(a as i64),
+                    resolver.register_or_constant(b),
+                    resolver.register_or_constant(c),
+                ],
+                Opcode::OP_TEST | Opcode::OP_TFORCALL => {
+                    vec![resolver.register(a), Operand::Literal(c as i64)]
+                }
+                Opcode::OP_TESTSET => vec![
+                    resolver.register(a),
+                    resolver.register(b as u32),
+                    Operand::Literal(c as i64),
+                ],
+                Opcode::OP_SETLIST => {
+                    // A block number of 0 is stored in the next instruction instead
+                    let block = match c {
+                        0 => resolver.extra_arg().unwrap_or_default(),
+                        c => c as u32,
+                    };
+                    vec![
+                        resolver.register(a),
+                        Operand::Literal(b as i64),
+                        Operand::Literal(block as i64),
+                    ]
+                }
+                _ => vec![
+                    resolver.register(a),
+                    Operand::Literal(b as i64),
+                    Operand::Literal(c as i64),
+                ],
+            }
+        }
+        Instruction::iABx(opcode, a, bx) => match opcode {
+            Opcode::OP_CLOSURE => vec![
+                resolver.register(a as u32),
+                Operand::Prototype { index: bx },
+            ],
+            _ => vec![resolver.register(a as u32), resolver.constant(bx)],
+        },
+        Instruction::iAsBx(opcode, a, sbx) => {
+            let jump = Operand::Jump {
+                target: pc as i64 + 1 + sbx as i64,
+            };
+            match opcode {
+                // A non zero `A` closes the upvalues from register `A - 1` up
+                Opcode::OP_JMP => vec![Operand::Literal(a as i64), jump],
+                _ => vec![resolver.register(a as u32), jump],
+            }
+        }
+        Instruction::iAx(_, ax) => vec![Operand::Literal(ax as i64)],
+    };
+
+    Some(operands)
+}
+
+struct Resolver<'p, 'a> {
+    prototype: &'p Prototype<'a>,
+    pc: usize,
+}
+
+impl<'p, 'a> Resolver<'p, 'a> {
+    fn register(&self, index: u32) -> Operand<'p, 'a> {
+        let locals = self.prototype.debug_info.local_variables.data.iter();
+        let locals = locals.map(|local| {
+            let (start_pc, end_pc) = (local.start_pc as u64, local.end_pc as u64);
+            (local.name.as_bytes(), start_pc, end_pc)
+        });
+
+        Operand::Register {
+            index,
+            name: local_name(locals, index, self.pc as u64),
+        }
+    }
+
+    fn constant(&self, index: u32) -> Operand<'p, 'a> {
+        Operand::Constant {
+            index,
+            value: self.prototype.constants.data.get(index as usize),
+        }
+    }
+
+    fn register_or_constant(&self, operand: u16) -> Operand<'p, 'a> {
+        match operand & BITRK {
+            0 => self.register(operand as u32),
+            _ => self.constant((operand & !BITRK) as u32),
+        }
+    }
+
+    fn upvalue(&self, index: u32) -> Operand<'p, 'a> {
+        let name = self.prototype.debug_info.upvalues.data.get(index as usize);
+        Operand::Upvalue {
+            index,
+            name: name.map(|name| name.as_bytes()),
+        }
+    }
+
+    /// Returns the argument of the `EXTRAARG` following the instruction.
+    fn extra_arg(&self) -> Option<u32> {
+        match *self.prototype.code.data.get(self.pc + 1)? {
+            Instruction::iAx(Opcode::OP_EXTRAARG, ax) => Some(ax),
+            _ => None,
+        }
+    }
+}
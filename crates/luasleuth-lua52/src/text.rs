@@ -0,0 +1,236 @@
+//! Building Lua 5.2 chunks from assembly, see [`luasleuth_common::text`].
+
+use luasleuth_common::{
+    text::{Chunk, Function, Item, Mnemonics, Operand, Scope, Statement, TextError},
+    types::{LuaString, Packable},
+};
+
+use crate::types::{
+    constants::Constant,
+    debug_info::{DebugInfo, LocalVariable},
+    instructions::{constants::*, Instruction, Opcode},
+    upvalues::Upvalue,
+    Bytecode, Header, Prototype,
+};
+
+/// Marks a `B` or `C` operand as a constant index instead of a register
+const BITRK: u32 = 1 << (SIZE_B - 1);
+
+/// Value of `is_vararg` for functions declared with a bare `.vararg`
+const VARARG_ISVARARG: u8 = 1;
+
+/// Build a chunk from assembly, with the header of a stock 64-bit build.
+pub fn build(chunk: &Chunk) -> Result<Bytecode<'_>, TextError> {
+    let mnemonics = Mnemonics::new();
+
+    Ok(Bytecode {
+        header: Header::default(),
+        prototype: build_prototype(&chunk.main, &mnemonics)?,
+    })
+}
+
+fn build_prototype<'a>(
+    function: &'a Function,
+    mnemonics: &Mnemonics<Opcode>,
+) -> Result<Prototype<'a>, TextError> {
+    let scope = function.scope()?;
+
+    let mut source = None;
+    let mut line_defined = 0;
+    let mut last_line_defined = 0;
+    let mut number_of_parameters = 0;
+    let mut is_vararg = 0;
+    let mut max_stack_size = 2;
+    let mut code = Vec::new();
+    let mut constants = Vec::new();
+    let mut prototypes = Vec::new();
+    let mut lines = Vec::new();
+    let mut local_variables = Vec::new();
+    let mut upvalues = Vec::new();
+    let mut upvalue_names = Vec::new();
+
+    // The line of the instructions that follow, set by `.line`
+    let mut line = None;
+    for item in &function.items {
+        match item {
+            Item::Directive(directive) => {
+                let at = directive.line;
+                match directive.name.as_str() {
+                    "source" => {
+                        directive.expect_at_most(1)?;
+                        source = Some(LuaString::from(directive.operand(0)?.string(at)?));
+                    }
+                    "lines" => {
+                        directive.expect_at_most(2)?;
+                        line_defined = directive.operand(0)?.integer(at)?;
+                        last_line_defined = directive.operand(1)?.integer(at)?;
+                    }
+                    "params" => {
+                        directive.expect_at_most(1)?;
+                        number_of_parameters = directive.operand(0)?.integer(at)?;
+                    }
+                    "vararg" => {
+                        directive.expect_at_most(1)?;
+                        is_vararg = match directive.operands.first() {
+                            Some(operand) => operand.integer(at)?,
+                            None => VARARG_ISVARARG,
+                        };
+                    }
+                    "stack" => {
+                        directive.expect_at_most(1)?;
+                        max_stack_size = directive.operand(0)?.integer(at)?;
+                    }
+                    "upval" => {
+                        directive.expect_at_most(3)?;
+                        upvalue_names.push(LuaString::from(directive.operand(0)?.identifier(at)?));
+                        upvalues.push(Upvalue {
+                            in_stack: directive.operand(1)?.integer(at)?,
+                            index: directive.operand(2)?.integer(at)?,
+                        });
+                    }
+                    "const" => {
+                        directive.expect_at_most(1)?;
+                        constants.push(build_constant(directive.operand(0)?, at)?);
+                    }
+                    "local" => {
+                        directive.expect_at_most(3)?;
+                        local_variables.push(LocalVariable {
+                            name: LuaString::from(directive.operand(0)?.identifier(at)?),
+                            start_pc: pc_operand(&scope, directive.operand(1)?, at)?,
+                            end_pc: pc_operand(&scope, directive.operand(2)?, at)?,
+                        });
+                    }
+                    "line" => {
+                        directive.expect_at_most(1)?;
+                        line = Some(directive.operand(0)?.integer(at)?);
+                    }
+                    _ => return Err(directive.unknown_directive()),
+                }
+            }
+            Item::Instruction(instruction) => {
+                code.push(build_instruction(
+                    instruction,
+                    code.len(),
+                    &scope,
+                    mnemonics,
+                )?);
+                lines.push(line);
+            }
+            Item::Function(child) => prototypes.push(build_prototype(child, mnemonics)?),
+            Item::Label { .. } => {}
+        }
+    }
+
+    // Line information is left out unless `.line` was used, like in stripped chunks
+    let line_info = match lines.iter().any(Option::is_some) {
+        true => lines
+            .into_iter()
+            .map(|line| line.unwrap_or(line_defined))
+            .collect(),
+        false => Vec::new(),
+    };
+
+    Ok(Prototype {
+        line_defined,
+        last_line_defined,
+        number_of_parameters,
+        is_vararg,
+        max_stack_size,
+        code: code.into(),
+        constants: constants.into(),
+        prototypes: prototypes.into(),
+        upvalues: upvalues.into(),
+        debug_info: DebugInfo {
+            source,
+            line_info: line_info.into(),
+            local_variables: local_variables.into(),
+            upvalues: upvalue_names.into(),
+        },
+    })
+}
+
+fn build_constant(operand: &Operand, line: usize) -> Result<Constant<'_>, TextError> {
+    let constant = match operand {
+        Operand::Name(name) if name == "nil" => Constant::Nil,
+        Operand::Name(name) if name == "true" => Constant::Boolean(true),
+        Operand::Name(name) if name == "false" => Constant::Boolean(false),
+        Operand::String(value) => Constant::String(LuaString::from(value.as_slice())),
+        operand => Constant::Number(operand.number(line)?),
+    };
+
+    Ok(constant)
+}
+
+fn build_instruction(
+    instruction: &Statement,
+    pc: usize,
+    scope: &Scope,
+    mnemonics: &Mnemonics<Opcode>,
+) -> Result<Instruction, TextError> {
+    let line = instruction.line;
+    let opcode = mnemonics.find(instruction)?;
+
+    // Every opcode has a fixed format, the one its decoder picks
+    let format = Instruction::decode(u8::from(opcode) as u32)
+        .map_err(|error| instruction.error(error.to_string()))?;
+
+    let built = match format {
+        Instruction::iABC(..) => {
+            instruction.expect_at_most(3)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let b = register_or_constant(instruction.operand(1)?, line)?;
+            let c = register_or_constant(instruction.operand(2)?, line)?;
+            Instruction::iABC(opcode, a as u8, b as u16, c as u16)
+        }
+        Instruction::iABx(..) => {
+            instruction.expect_at_most(2)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let bx = match opcode {
+                Opcode::OP_CLOSURE => scope.child(instruction.operand(1)?, line)?,
+                _ => constant_index(instruction.operand(1)?, line)?,
+            };
+            Instruction::iABx(
+                opcode,
+                a as u8,
+                Operand::Integer(bx).unsigned(line, SIZE_BX)?,
+            )
+        }
+        Instruction::iAsBx(..) => {
+            instruction.expect_at_most(2)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let sbx = scope.jump(instruction.operand(1)?, pc, line)?;
+            let biased = Operand::Integer(sbx + MAXARG_SBX as i64).unsigned(line, SIZE_BX);
+            biased.map_err(|_| instruction.error(format!("Jump of {} is out of range", sbx)))?;
+            Instruction::iAsBx(opcode, a as u8, sbx as i32)
+        }
+        Instruction::iAx(..) => {
+            instruction.expect_at_most(1)?;
+            let ax = instruction.operand(0)?.unsigned(line, SIZE_AX)?;
+            Instruction::iAx(opcode, ax)
+        }
+    };
+
+    Ok(built)
+}
+
+/// Encode a `B` or `C` operand, negative values being constant indexes as `luac -l` lists them.
+fn register_or_constant(operand: &Operand, line: usize) -> Result<u32, TextError> {
+    let value: i64 = operand.integer(line)?;
+    if value < 0 {
+        let index = Operand::Integer(-1 - value).unsigned(line, SIZE_B - 1)?;
+        return Ok(index | BITRK);
+    }
+
+    operand.unsigned(line, SIZE_B)
+}
+
+/// Returns the constant index of a `Bx` operand, which `luac -l` lists as negative.
+fn constant_index(operand: &Operand, line: usize) -> Result<i64, TextError> {
+    let value: i64 = operand.integer(line)?;
+    Ok(if value < 0 { -1 - value } else { value })
+}
+
+/// Returns the instruction index of a `.local` bound, given as a label or an index.
+fn pc_operand(scope: &Scope, operand: &Operand, line: usize) -> Result<i32, TextError> {
+    Operand::Integer(scope.position(operand, line)?).integer(line)
+}
@@ -1,21 +1,39 @@
-use luasleuth_common::CommonCtx;
+use luasleuth_common::{CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug, Pwrite)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Upvalue {
     pub in_stack: u8,
     pub index: u8,
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Upvalue {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let in_stack: u8 = src.gread_with(offset, ctx.endianness)?;
-        let index: u8 = src.gread_with(offset, ctx.endianness)?;
+        let in_stack: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "in_stack")?;
+        let index: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "index")?;
 
         Ok((Self { in_stack, index }, *offset))
     }
 }
+
+impl ctx::TryIntoCtx<CommonCtx> for Upvalue {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.in_stack, offset, ctx.endianness)?;
+        dst.gwrite_with(self.index, offset, ctx.endianness)?;
+
+        Ok(*offset)
+    }
+}
@@ -1,5 +1,10 @@
-use luasleuth_common::disassembler::Disassemble as _;
+use luasleuth_common::{assembler::Assemble as _, disassembler::Disassemble as _, types::Packable};
+use luasleuth_lua52::assembler::Assembler;
 use luasleuth_lua52::disassembler::Disassembler;
+use luasleuth_lua52::types::{
+    constants::Constant,
+    instructions::{Instruction, Opcode},
+};
 
 #[test]
 fn test_can_parse_bytecode_file() {
@@ -14,10 +19,148 @@ fn test_can_parse_bytecode_file() {
 
     // Check prototype related values
     let prototype = bytecode.prototype;
-    assert_eq!(prototype.debug_info.source, "@.\\example.lua"); // Checks size and actual data
+    assert_eq!(
+        prototype.debug_info.source.expect("Missing source name"),
+        "@.\\example.lua"
+    ); // Checks size and actual data
     assert_eq!(prototype.line_defined, 0);
     assert_eq!(prototype.last_line_defined, 0);
     assert_eq!(prototype.number_of_parameters, 0);
     assert_eq!(prototype.is_vararg, 1);
     assert_eq!(prototype.max_stack_size, 2);
 }
+
+/// Build a big endian chunk with 4 byte sizes and a single precision `lua_Number`.
+fn build_big_endian_chunk() -> Vec<u8> {
+    let mut bytes = vec![0x1b, b'L', b'u', b'a', 0x52, 0, 0, 4, 4, 4, 4, 0];
+    bytes.extend(b"\x19\x93\r\n\x1a\n");
+
+    // Line range and function flags
+    bytes.extend(3i32.to_be_bytes());
+    bytes.extend(5i32.to_be_bytes());
+    bytes.extend([0, 1, 2]);
+
+    // RETURN 0 1
+    bytes.extend(1i32.to_be_bytes());
+    bytes.extend(0x0080001fu32.to_be_bytes());
+
+    bytes.extend(1i32.to_be_bytes());
+    bytes.push(3);
+    bytes.extend(1.5f32.to_be_bytes());
+
+    // No prototypes, no upvalues
+    bytes.extend(0i32.to_be_bytes());
+    bytes.extend(0i32.to_be_bytes());
+
+    // Source name, a single line info entry and no locals or upvalue names
+    bytes.extend(3u32.to_be_bytes());
+    bytes.extend(b"=t\0");
+    bytes.extend(1i32.to_be_bytes());
+    bytes.extend(5i32.to_be_bytes());
+    bytes.extend(0i32.to_be_bytes());
+    bytes.extend(0i32.to_be_bytes());
+
+    bytes
+}
+
+#[test]
+fn test_can_parse_big_endian_single_precision_chunk() {
+    let bytes = build_big_endian_chunk();
+    let bytecode = Disassembler::new(&bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    let prototype = bytecode.prototype;
+    assert_eq!(
+        prototype.debug_info.source.expect("Missing source name"),
+        "=t"
+    );
+    assert_eq!(prototype.line_defined, 3);
+    assert_eq!(prototype.last_line_defined, 5);
+    assert_eq!(prototype.code.size, 1);
+    assert!(matches!(
+        prototype.constants.data[0],
+        Constant::Number(n) if n == 1.5
+    ));
+    assert_eq!(prototype.debug_info.line_info.data, vec![5]);
+}
+
+#[test]
+fn test_malformed_input_returns_error_instead_of_panicking() {
+    let bytes = include_bytes!("../../../data/bytecode/lua52.bin");
+
+    for length in 0..bytes.len() {
+        let result = Disassembler::new(&bytes[..length]).disassemble();
+        assert!(result.is_err(), "Truncated to {} bytes", length);
+    }
+
+    for index in 0..bytes.len() {
+        for value in [0x00, 0x7f, 0x80, 0xff] {
+            let mut bytes = bytes.to_vec();
+            bytes[index] = value;
+            let _ = Disassembler::new(&bytes).disassemble();
+        }
+    }
+}
+
+#[test]
+fn test_assembles_chunks_back_to_the_same_bytes() {
+    let chunks = [
+        include_bytes!("../../../data/bytecode/lua52.bin").to_vec(),
+        build_big_endian_chunk(),
+    ];
+
+    for bytes in chunks {
+        let bytecode = Disassembler::new(&bytes)
+            .disassemble()
+            .expect("Failed to read bytecode data");
+        let assembled = Assembler::new(bytecode)
+            .assemble()
+            .expect("Failed to assemble bytecode");
+        assert_eq!(assembled, bytes);
+    }
+}
+
+#[test]
+fn test_assembles_modified_bytecode() {
+    let bytes = include_bytes!("../../../data/bytecode/lua52.bin");
+    let mut bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Nest a stripped copy of the main function
+    let mut child = bytecode.prototype.clone();
+    child.debug_info.source = None;
+    child.code.data[0] = Instruction::iAsBx(Opcode::OP_JMP, 200, -3);
+    bytecode.prototype.prototypes = vec![child].into();
+
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    let bytecode = Disassembler::new(&assembled)
+        .disassemble()
+        .expect("Failed to read assembled bytecode");
+
+    let child = &bytecode.prototype.prototypes.data[0];
+    assert!(child.debug_info.source.is_none());
+    assert!(matches!(
+        child.code.data[0],
+        Instruction::iAsBx(Opcode::OP_JMP, 200, -3)
+    ));
+
+    let reassembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    assert_eq!(reassembled, assembled);
+}
+
+#[test]
+fn test_instruction_encoding_round_trips() {
+    let mut raw = 0x2545_f491u32;
+    for _ in 0..100_000 {
+        raw = raw.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        if let Ok(instruction) = Instruction::decode(raw) {
+            assert_eq!(Instruction::encode(instruction), raw, "{:?}", instruction);
+        }
+    }
+}
@@ -1,3 +1,8 @@
 pub mod assembler;
+pub mod cfg;
 pub mod disassembler;
+pub mod dot;
+pub mod listing;
+pub mod resolve;
+pub mod text;
 pub mod types;
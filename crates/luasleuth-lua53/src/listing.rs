@@ -0,0 +1,309 @@
+//! Listing of a chunk in the layout of `luac -l -l`.
+//!
+//! Functions are not identified by their address like `luac` does, closures refer to the
+//! location of their function instead.
+
+use std::fmt::{self, Write};
+
+use luasleuth_common::{
+    listing::{format_float, mnemonic, write_instruction_start, FunctionHeader, Quoted},
+    types::Packable,
+};
+
+use crate::types::{
+    constants::Constant,
+    instructions::{constants::*, Instruction, Opcode},
+    Bytecode, Prototype,
+};
+
+/// Marks a `B` or `C` operand as a constant index instead of a register
+const BITRK: u16 = 1 << (SIZE_B - 1);
+
+/// A chunk rendered like `luac -l -l` does.
+pub struct Listing<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Listing<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Listing<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prototype = &self.bytecode.prototype;
+        write_function(f, prototype, source(prototype, None))
+    }
+}
+
+/// Returns the source of a function, which children without one share with their parent.
+pub(crate) fn source<'p>(prototype: &'p Prototype, parent: Option<&'p [u8]>) -> Option<&'p [u8]> {
+    prototype
+        .source
+        .as_ref()
+        .map(|source| source.as_bytes())
+        .or(parent)
+}
+
+pub(crate) fn header<'a>(prototype: &Prototype, source: Option<&'a [u8]>) -> FunctionHeader<'a> {
+    FunctionHeader {
+        source,
+        line_defined: prototype.line_defined as i64,
+        last_line_defined: prototype.last_line_defined as i64,
+        instructions: prototype.instructions.size,
+        code_size: None,
+        parameters: prototype.number_of_parameters as usize,
+        is_vararg: prototype.is_vararg != 0,
+        slots: prototype.max_stack_size as usize,
+        upvalues: prototype.upvalues.size,
+        locals: prototype.debug_info.local_variables.size,
+        constants: prototype.constants.size,
+        functions: prototype.prototypes.size,
+    }
+}
+
+fn write_function(f: &mut impl Write, prototype: &Prototype, source: Option<&[u8]>) -> fmt::Result {
+    write!(f, "{}", header(prototype, source))?;
+    write_code(f, prototype, source)?;
+    write_debug(f, prototype)?;
+
+    for child in &prototype.prototypes.data {
+        write_function(f, child, self::source(child, source))?;
+    }
+
+    Ok(())
+}
+
+fn write_code(f: &mut impl Write, prototype: &Prototype, source: Option<&[u8]>) -> fmt::Result {
+    let mut pc = 0;
+    while pc < prototype.instructions.data.len() {
+        pc = write_instruction(f, prototype, source, pc)?;
+        f.write_str("\n")?;
+    }
+
+    Ok(())
+}
+
+/// Write the line of the instruction at `pc`, without its line break.
+///
+/// Returns the pc of the next instruction, past the data some instructions keep after them.
+pub(crate) fn write_instruction(
+    f: &mut impl Write,
+    prototype: &Prototype,
+    source: Option<&[u8]>,
+    mut pc: usize,
+) -> Result<usize, fmt::Error> {
+    let code = &prototype.instructions.data;
+    let instruction = code[pc];
+    let line = prototype.debug_info.line_info.data.get(pc);
+    let opcode = match instruction {
+        Instruction::iABC(opcode, ..)
+        | Instruction::iABx(opcode, ..)
+        | Instruction::iAsBx(opcode, ..)
+        | Instruction::iAx(opcode, ..) => opcode,
+    };
+    write_instruction_start(f, pc, line.map(|&line| line as i64), &mnemonic(opcode))?;
+
+    match instruction {
+        Instruction::iABC(opcode, a, b, c) => {
+            write!(f, "{}", a)?;
+            if uses_b(opcode) {
+                write!(f, " {}", register_or_constant(b))?;
+            }
+            if uses_c(opcode) {
+                write!(f, " {}", register_or_constant(c))?;
+            }
+        }
+        Instruction::iABx(Opcode::OP_CLOSURE, a, bx) => write!(f, "{} {}", a, bx)?,
+        Instruction::iABx(Opcode::OP_LOADKX, a, _) => write!(f, "{}", a)?,
+        Instruction::iABx(_, a, bx) => write!(f, "{} {}", a, -1 - bx as i64)?,
+        Instruction::iAsBx(_, a, sbx) => write!(f, "{} {}", a, sbx)?,
+        Instruction::iAx(_, ax) => write!(f, "{}", -1 - ax as i64)?,
+    }
+
+    let upvalue = |index| UpvalueName(prototype, index);
+    match instruction {
+        Instruction::iABx(Opcode::OP_LOADK, _, bx) => {
+            write!(f, "\t; {}", ConstantAt(prototype, bx as usize))?
+        }
+        Instruction::iABC(Opcode::OP_GETUPVAL | Opcode::OP_SETUPVAL, _, b, _) => {
+            write!(f, "\t; {}", upvalue(b as usize))?
+        }
+        Instruction::iABC(Opcode::OP_GETTABUP, _, b, c) => {
+            write!(f, "\t; {}", upvalue(b as usize))?;
+            if c & BITRK != 0 {
+                write!(f, " {}", ConstantAt(prototype, (c & !BITRK) as usize))?;
+            }
+        }
+        Instruction::iABC(Opcode::OP_SETTABUP, a, b, c) => {
+            write!(f, "\t; {}", upvalue(a as usize))?;
+            for operand in [b, c] {
+                if operand & BITRK != 0 {
+                    write!(f, " {}", ConstantAt(prototype, (operand & !BITRK) as usize))?;
+                }
+            }
+        }
+        Instruction::iABC(Opcode::OP_GETTABLE | Opcode::OP_SELF, _, _, c) if c & BITRK != 0 => {
+            write!(f, "\t; {}", ConstantAt(prototype, (c & !BITRK) as usize))?
+        }
+        Instruction::iABC(
+            Opcode::OP_SETTABLE
+            | Opcode::OP_ADD
+            | Opcode::OP_SUB
+            | Opcode::OP_MUL
+            | Opcode::OP_MOD
+            | Opcode::OP_POW
+            | Opcode::OP_DIV
+            | Opcode::OP_IDIV
+            | Opcode::OP_BAND
+            | Opcode::OP_BOR
+            | Opcode::OP_BXOR
+            | Opcode::OP_SHL
+            | Opcode::OP_SHR
+            | Opcode::OP_EQ
+            | Opcode::OP_LT
+            | Opcode::OP_LE,
+            _,
+            b,
+            c,
+        ) if (b | c) & BITRK != 0 => {
+            write!(
+                f,
+                "\t; {} {}",
+                RegisterOrConstant(prototype, b),
+                RegisterOrConstant(prototype, c)
+            )?;
+        }
+        Instruction::iAsBx(
+            Opcode::OP_JMP | Opcode::OP_FORLOOP | Opcode::OP_FORPREP | Opcode::OP_TFORLOOP,
+            _,
+            sbx,
+        ) => write!(f, "\t; to {}", sbx as i64 + pc as i64 + 2)?,
+        Instruction::iABx(Opcode::OP_CLOSURE, _, bx) => {
+            if let Some(child) = prototype.prototypes.data.get(bx as usize) {
+                let source = self::source(child, source);
+                write!(f, "\t; {}", header(child, source).location())?;
+            }
+        }
+        Instruction::iABC(Opcode::OP_SETLIST, _, _, 0) => {
+            // The block number is stored in the next instruction instead
+            pc += 1;
+            let block = code.get(pc).map_or(0, |&raw| Instruction::encode(raw));
+            write!(f, "\t; {}", block)?;
+        }
+        Instruction::iABC(Opcode::OP_SETLIST, _, _, c) => write!(f, "\t; {}", c)?,
+        Instruction::iAx(Opcode::OP_EXTRAARG, ax) => {
+            write!(f, "\t; {}", ConstantAt(prototype, ax as usize))?
+        }
+        _ => {}
+    }
+
+    Ok(pc + 1)
+}
+
+fn write_debug(f: &mut impl Write, prototype: &Prototype) -> fmt::Result {
+    let constants = &prototype.constants.data;
+    writeln!(f, "constants ({}):", constants.len())?;
+    for index in 0..constants.len() {
+        writeln!(f, "\t{}\t{}", index + 1, ConstantAt(prototype, index))?;
+    }
+
+    let locals = &prototype.debug_info.local_variables.data;
+    writeln!(f, "locals ({}):", locals.len())?;
+    for (index, local) in locals.iter().enumerate() {
+        writeln!(
+            f,
+            "\t{}\t{}\t{}\t{}",
+            index,
+            String::from_utf8_lossy(&local.name.data),
+            local.start_pc + 1,
+            local.end_pc + 1
+        )?;
+    }
+
+    let upvalues = &prototype.upvalues.data;
+    writeln!(f, "upvalues ({}):", upvalues.len())?;
+    for (index, upvalue) in upvalues.iter().enumerate() {
+        writeln!(
+            f,
+            "\t{}\t{}\t{}\t{}",
+            index,
+            UpvalueName(prototype, index),
+            upvalue.in_stack,
+            upvalue.index
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns whether the `B` operand of an `iABC` instruction is used.
+fn uses_b(opcode: Opcode) -> bool {
+    !matches!(opcode, Opcode::OP_TEST | Opcode::OP_TFORCALL)
+}
+
+/// Returns whether the `C` operand of an `iABC` instruction is used.
+fn uses_c(opcode: Opcode) -> bool {
+    !matches!(
+        opcode,
+        Opcode::OP_MOVE
+            | Opcode::OP_LOADNIL
+            | Opcode::OP_GETUPVAL
+            | Opcode::OP_SETUPVAL
+            | Opcode::OP_UNM
+            | Opcode::OP_BNOT
+            | Opcode::OP_NOT
+            | Opcode::OP_LEN
+            | Opcode::OP_RETURN
+            | Opcode::OP_VARARG
+    )
+}
+
+/// Returns a `B` or `C` operand as `luac` shows it, constants being negative.
+fn register_or_constant(operand: u16) -> i32 {
+    match operand & BITRK {
+        0 => operand as i32,
+        _ => -1 - (operand & !BITRK) as i32,
+    }
+}
+
+/// A constant of a prototype, as `luac` prints it.
+struct ConstantAt<'p, 'a>(&'p Prototype<'a>, usize);
+
+impl fmt::Display for ConstantAt<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.constants.data.get(self.1) {
+            Some(Constant::Nil) => f.write_str("nil"),
+            Some(Constant::Boolean(value)) => write!(f, "{}", value),
+            Some(Constant::Float(value)) => f.write_str(&format_float(*value)),
+            Some(Constant::Integer(value)) => write!(f, "{}", value),
+            Some(Constant::String(value)) => write!(f, "{}", Quoted(&value.data)),
+            None => write!(f, "? index={}", self.1),
+        }
+    }
+}
+
+/// A `B` or `C` operand in a comment, `-` for registers.
+struct RegisterOrConstant<'p, 'a>(&'p Prototype<'a>, u16);
+
+impl fmt::Display for RegisterOrConstant<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 & BITRK {
+            0 => f.write_str("-"),
+            _ => write!(f, "{}", ConstantAt(self.0, (self.1 & !BITRK) as usize)),
+        }
+    }
+}
+
+/// The name of an upvalue, `-` when the chunk is stripped.
+struct UpvalueName<'p, 'a>(&'p Prototype<'a>, usize);
+
+impl fmt::Display for UpvalueName<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.debug_info.upvalues.data.get(self.1) {
+            Some(name) => f.write_str(&String::from_utf8_lossy(&name.data)),
+            None => f.write_str("-"),
+        }
+    }
+}
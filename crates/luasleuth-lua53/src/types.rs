@@ -4,12 +4,14 @@ pub mod instructions;
 pub mod upvalues;
 
 use luasleuth_common::{
+    header,
     types::{Array, Bytecode as BytecodeTrait, LuaString},
-    CommonCtx, Version,
+    CommonCtx, Error, ErrorContext, Limits, Version,
 };
-use scroll::{ctx, Pread};
+use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug, Pread)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     pub signature: [u8; 4],
     pub version: Version,
@@ -20,13 +22,38 @@ pub struct Header {
     pub size_of_instruction: u8,
     pub size_of_integer: u8,
     pub size_of_lua_number: u8,
-    pub luac_int: u64,
+    pub luac_int: i64,
     pub luac_num: f64,
+    /// The byte order of the chunk, inferred from `luac_int`
+    #[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::endian"))]
+    pub endianness: scroll::Endian,
 }
 
-#[derive(Debug)]
+impl Default for Header {
+    /// The header written by a stock 64-bit little endian build.
+    fn default() -> Self {
+        Self {
+            signature: header::LUA_SIGNATURE,
+            version: Version::new(5, 3),
+            format_version: 0,
+            error_correction_data: header::LUAC_DATA,
+            size_of_int: 4,
+            size_of_size_t: 8,
+            size_of_instruction: 4,
+            size_of_integer: 8,
+            size_of_lua_number: 8,
+            luac_int: header::LUAC_INT,
+            luac_num: header::LUAC_NUM,
+            endianness: scroll::LE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Prototype<'a> {
-    pub source: LuaString<'a>,
+    /// The name of the source, `None` when stripped or when it is the same as the parent's
+    pub source: Option<LuaString<'a>>,
     pub line_defined: u32,
     pub last_line_defined: u32,
     pub number_of_parameters: u8,
@@ -39,31 +66,191 @@ pub struct Prototype<'a> {
     pub debug_info: debug_info::DebugInfo<'a>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bytecode<'a> {
     pub header: Header,
     pub size_of_upvalues: u8,
     pub prototype: Prototype<'a>,
 }
 
-impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Prototype<'a> {
+impl Header {
+    /// Returns the context the rest of the chunk is read and written with.
+    pub fn context(&self, limits: Limits) -> CommonCtx {
+        CommonCtx {
+            size_of_size_t: self.size_of_size_t,
+            lua_version: self.version,
+            endianness: self.endianness,
+            size_of_int: self.size_of_int,
+            size_of_instruction: self.size_of_instruction,
+            size_of_lua_number: self.size_of_lua_number,
+            size_of_lua_integer: self.size_of_integer,
+            integral: false,
+            limits,
+            depth: 0,
+        }
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, ()> for Header {
+    type Error = Error;
+
+    fn try_from_ctx(src: &'a [u8], _ctx: ()) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+
+        let signature: [u8; 4] = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "signature")?;
+        header::check_signature(&signature).within(0, "signature")?;
+
+        let version: Version = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "version")?;
+        let format_version: u8 = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "format_version")?;
+
+        let error_correction_data: [u8; 6] = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "error_correction_data")?;
+        header::check_luac_data(&error_correction_data).within(6, "error_correction_data")?;
+
+        let size_of_int: u8 = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "size_of_int")?;
+        let size_of_size_t: u8 = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "size_of_size_t")?;
+        let size_of_instruction: u8 = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "size_of_instruction")?;
+        let size_of_integer: u8 = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "size_of_integer")?;
+        let size_of_lua_number: u8 = src
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "size_of_lua_number")?;
+
+        let (luac_int, endianness) =
+            header::read_luac_int(src, offset, size_of_integer).within(*offset, "luac_int")?;
+        let luac_num = header::read_luac_num(src, offset, size_of_lua_number, endianness)
+            .within(*offset, "luac_num")?;
+
+        Ok((
+            Header {
+                signature,
+                version,
+                format_version,
+                error_correction_data,
+                size_of_int,
+                size_of_size_t,
+                size_of_instruction,
+                size_of_integer,
+                size_of_lua_number,
+                luac_int,
+                luac_num,
+                endianness,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl ctx::TryIntoCtx<()> for Header {
     type Error = scroll::Error;
 
+    fn try_into_ctx(self, dst: &mut [u8], _ctx: ()) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.signature, offset, scroll::LE)?;
+        dst.gwrite_with(self.version, offset, scroll::LE)?;
+        dst.gwrite_with(self.format_version, offset, scroll::LE)?;
+        dst.gwrite_with(self.error_correction_data, offset, scroll::LE)?;
+        dst.gwrite_with(self.size_of_int, offset, scroll::LE)?;
+        dst.gwrite_with(self.size_of_size_t, offset, scroll::LE)?;
+        dst.gwrite_with(self.size_of_instruction, offset, scroll::LE)?;
+        dst.gwrite_with(self.size_of_integer, offset, scroll::LE)?;
+        dst.gwrite_with(self.size_of_lua_number, offset, scroll::LE)?;
+
+        header::write_luac_int(
+            dst,
+            offset,
+            self.luac_int,
+            self.size_of_integer,
+            self.endianness,
+        )?;
+        header::write_luac_num(
+            dst,
+            offset,
+            self.luac_num,
+            self.size_of_lua_number,
+            self.endianness,
+        )?;
+
+        Ok(*offset)
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Prototype<'a> {
+    type Error = Error;
+
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
+        Self::read(src, ctx).map_err(Error::in_prototype)
+    }
+}
+
+impl<'a> Prototype<'a> {
+    /// Returns the number of elements in the arrays of this prototype and its children.
+    pub fn element_count(&self) -> usize {
+        self.instructions.size
+            + self.constants.size
+            + self.upvalues.size
+            + self.prototypes.size
+            + self.debug_info.element_count()
+            + self
+                .prototypes
+                .data
+                .iter()
+                .map(Prototype::element_count)
+                .sum::<usize>()
+    }
+
+    /// Read the fields of a prototype, before the error is marked as being inside of it.
+    fn read(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Error> {
         let offset = &mut 0;
+        ctx.limits.check_depth(ctx.depth)?;
 
-        let source: LuaString = src.gread_with(offset, ctx)?;
-        let line_defined: u32 = src.gread_with(offset, ctx.endianness)?;
-        let last_line_defined: u32 = src.gread_with(offset, ctx.endianness)?;
-        let number_of_parameters: u8 = src.gread_with(offset, ctx.endianness)?;
-        let is_vararg: u8 = src.gread_with(offset, ctx.endianness)?;
-        let max_stack_size: u8 = src.gread_with(offset, ctx.endianness)?;
+        let source: Option<LuaString> = src.gread_with(offset, ctx).within(*offset, "source")?;
+        let line_defined = ctx.read_int(src, offset).within(*offset, "line_defined")? as u32;
+        let last_line_defined = ctx
+            .read_int(src, offset)
+            .within(*offset, "last_line_defined")? as u32;
+        let number_of_parameters: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "number_of_parameters")?;
+        let is_vararg: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "is_vararg")?;
+        let max_stack_size: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "max_stack_size")?;
 
-        let instructions: Array<instructions::Instruction> = src.gread_with(offset, ctx)?;
-        let constants: Array<constants::Constant> = src.gread_with(offset, ctx)?;
-        let upvalues: Array<upvalues::Upvalue> = src.gread_with(offset, ctx)?;
-        let prototypes: Array<Prototype> = src.gread_with(offset, ctx)?;
-        let debug_info: debug_info::DebugInfo = src.gread_with(offset, ctx)?;
+        let instructions: Array<instructions::Instruction> = src
+            .gread_with(offset, ctx)
+            .within(*offset, "instructions")?;
+        let constants: Array<constants::Constant> =
+            src.gread_with(offset, ctx).within(*offset, "constants")?;
+        let upvalues: Array<upvalues::Upvalue> =
+            src.gread_with(offset, ctx).within(*offset, "upvalues")?;
+        let child_ctx = CommonCtx {
+            depth: ctx.depth + 1,
+            ..ctx
+        };
+        let prototypes: Array<Prototype> = src
+            .gread_with(offset, child_ctx)
+            .within(*offset, "prototypes")?;
+        let debug_info: debug_info::DebugInfo =
+            src.gread_with(offset, ctx).within(*offset, "debug_info")?;
 
         Ok((
             Prototype {
@@ -84,6 +271,44 @@ impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Prototype<'a> {
     }
 }
 
+impl ctx::TryIntoCtx<CommonCtx> for Prototype<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.source, offset, ctx)?;
+        ctx.write_int(dst, offset, self.line_defined as i64)?;
+        ctx.write_int(dst, offset, self.last_line_defined as i64)?;
+        dst.gwrite_with(self.number_of_parameters, offset, ctx.endianness)?;
+        dst.gwrite_with(self.is_vararg, offset, ctx.endianness)?;
+        dst.gwrite_with(self.max_stack_size, offset, ctx.endianness)?;
+
+        dst.gwrite_with(self.instructions, offset, ctx)?;
+        dst.gwrite_with(self.constants, offset, ctx)?;
+        dst.gwrite_with(self.upvalues, offset, ctx)?;
+        dst.gwrite_with(self.prototypes, offset, ctx)?;
+        dst.gwrite_with(self.debug_info, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<CommonCtx> for Bytecode<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: CommonCtx) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.header, offset, ())?;
+        let size_of_upvalues = self.prototype.upvalues.data.len() as u8;
+        dst.gwrite_with(size_of_upvalues, offset, ctx.endianness)?;
+        dst.gwrite_with(self.prototype, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
 impl BytecodeTrait for Bytecode<'_> {
     fn identifier() -> &'static str {
         "lua53"
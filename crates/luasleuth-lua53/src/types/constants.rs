@@ -1,13 +1,14 @@
-use luasleuth_common::{types::LuaString, CommonCtx};
+use luasleuth_common::{types::LuaString, CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
 const LUAI_MAXSHORTLEN: usize = 40;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Constant<'a> {
     Nil,
     Boolean(bool),
-    Float(f64),
+    Float(#[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64),
     Integer(i64),
     String(LuaString<'a>),
 }
@@ -30,7 +31,7 @@ impl Constant<'_> {
             Constant::Float(_) => 3,
             Constant::Integer(_) => 19,
             Constant::String(string) => {
-                if string.size >= LUAI_MAXSHORTLEN {
+                if string.size > LUAI_MAXSHORTLEN {
                     20
                 } else {
                     4
@@ -41,27 +42,26 @@ impl Constant<'_> {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Constant<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let tag: u8 = src.gread_with(offset, ctx.endianness)?;
+        let tag: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "tag")?;
         let constant = match tag {
             0 => Constant::Nil,
             1 => {
-                let value: u8 = src.gread_with(offset, ctx.endianness)?;
+                let value: u8 = src
+                    .gread_with(offset, ctx.endianness)
+                    .within(*offset, "value")?;
                 Constant::Boolean(value != 0)
             }
-            3 => Constant::Float(src.gread_with(offset, ctx.endianness)?),
-            19 => Constant::Integer(src.gread_with(offset, ctx.endianness)?),
-            4 | 20 => Constant::String(src.gread_with(offset, ctx)?),
-            _ => {
-                return Err(scroll::Error::BadInput {
-                    size: 1,
-                    msg: "Invalid constant type",
-                })
-            }
+            3 => Constant::Float(ctx.read_number(src, offset).within(*offset, "value")?),
+            19 => Constant::Integer(ctx.read_integer(src, offset).within(*offset, "value")?),
+            4 | 20 => Constant::String(src.gread_with(offset, ctx).within(*offset, "value")?),
+            _ => return Err(Error::invalid("Invalid constant type", 0).within(0, "tag")),
         };
 
         Ok((constant, *offset))
@@ -83,10 +83,10 @@ impl ctx::TryIntoCtx<CommonCtx> for Constant<'_> {
                 dst.gwrite_with(value as u8, offset, ctx.endianness)?;
             }
             Constant::Float(value) => {
-                dst.gwrite_with(value, offset, ctx.endianness)?;
+                ctx.write_number(dst, offset, value)?;
             }
             Constant::Integer(value) => {
-                dst.gwrite_with(value, offset, ctx.endianness)?;
+                ctx.write_integer(dst, offset, value)?;
             }
             Constant::String(value) => {
                 dst.gwrite_with(value, offset, ctx)?;
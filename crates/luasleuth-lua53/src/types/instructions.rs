@@ -1,5 +1,5 @@
-use luasleuth_common::{mask, types::Packable, CommonCtx};
-use scroll::{ctx, Pread, Pwrite};
+use luasleuth_common::{mask, types::Packable, CommonCtx, Error, InvalidOpcode};
+use scroll::ctx;
 
 pub mod constants {
     //! Constants related to Lua 5.3 instructions
@@ -23,7 +23,8 @@ pub mod constants {
     pub const MAXARG_SBX: u32 = MAXARG_BX >> 1;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum Instruction {
     iABC(Opcode, u8, u16, u16),
@@ -32,7 +33,8 @@ pub enum Instruction {
     iAx(Opcode, u32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 pub enum Opcode {
@@ -91,9 +93,11 @@ impl From<Opcode> for u8 {
     }
 }
 
-impl From<u8> for Opcode {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for Opcode {
+    type Error = InvalidOpcode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let opcode = match value {
             0 => Opcode::OP_MOVE,
             1 => Opcode::OP_LOADK,
             2 => Opcode::OP_LOADKX,
@@ -142,20 +146,22 @@ impl From<u8> for Opcode {
             45 => Opcode::OP_VARARG,
             46 => Opcode::OP_EXTRAARG,
 
-            _ => unimplemented!("Opcode::from({})", value),
-        }
+            _ => return Err(InvalidOpcode(value)),
+        };
+
+        Ok(opcode)
     }
 }
 
 impl Packable for Instruction {
-    fn decode(raw: u32) -> Instruction {
+    fn decode(raw: u32) -> Result<Instruction, InvalidOpcode> {
         use constants::*;
         use Opcode::*;
 
-        let opcode: Opcode = ((raw & mask!(SIZE_OP, 0)) as u8).into();
+        let opcode = Opcode::try_from((raw & mask!(SIZE_OP, 0)) as u8)?;
         let a = ((raw >> POS_A) & mask!(SIZE_A, 0)) as u8;
 
-        match opcode {
+        let instruction = match opcode {
             // iABC
             OP_MOVE | OP_LOADBOOL | OP_LOADNIL | OP_GETUPVAL | OP_GETTABUP | OP_GETTABLE
             | OP_SETTABUP | OP_SETUPVAL | OP_SETTABLE | OP_NEWTABLE | OP_SELF | OP_ADD | OP_SUB
@@ -182,7 +188,9 @@ impl Packable for Instruction {
                 let ax = (raw >> POS_AX) & mask!(SIZE_AX, 0);
                 Instruction::iAx(opcode, ax)
             }
-        }
+        };
+
+        Ok(instruction)
     }
 
     fn encode(inst: Instruction) -> u32 {
@@ -199,13 +207,13 @@ impl Packable for Instruction {
             }
             Instruction::iABx(opcode, a, bx) => {
                 let opcode = opcode as u32;
-                let a = (a << POS_A) as u32;
+                let a = (a as u32) << POS_A;
                 let bx = bx << POS_BX;
                 opcode | a | bx
             }
             Instruction::iAsBx(opcode, a, sbx) => {
                 let opcode = opcode as u32;
-                let a = (a << POS_A) as u32;
+                let a = (a as u32) << POS_A;
                 let sbx = ((sbx + MAXARG_SBX as i32) << POS_BX) as u32;
                 opcode | a | sbx
             }
@@ -219,12 +227,12 @@ impl Packable for Instruction {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Instruction {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let instruction: u32 = src.gread_with(offset, ctx.endianness)?;
-        let instruction = Instruction::decode(instruction);
+        let instruction = ctx.read_instruction(src, offset)?;
+        let instruction = Instruction::decode(instruction)?;
 
         Ok((instruction, *offset))
     }
@@ -237,7 +245,7 @@ impl ctx::TryIntoCtx<CommonCtx> for Instruction {
         let offset = &mut 0;
 
         let n = Instruction::encode(self);
-        src.gwrite_with(n, offset, ctx.endianness)?;
+        ctx.write_instruction(src, offset, n)?;
 
         Ok(*offset)
     }
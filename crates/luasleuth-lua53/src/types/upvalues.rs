@@ -1,20 +1,25 @@
-use luasleuth_common::CommonCtx;
+use luasleuth_common::{CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Upvalue {
     pub in_stack: u8,
     pub index: u8,
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Upvalue {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let in_stack: u8 = src.gread_with(offset, ctx.endianness)?;
-        let index: u8 = src.gread_with(offset, ctx.endianness)?;
+        let in_stack: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "in_stack")?;
+        let index: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "index")?;
 
         Ok((Self { in_stack, index }, *offset))
     }
@@ -1,5 +1,15 @@
-use luasleuth_common::disassembler::Disassemble as _;
+use luasleuth_common::{
+    assembler::Assemble as _, disassembler::Disassemble as _, types::Packable, CommonCtx, Limits,
+    Version,
+};
+use luasleuth_lua53::assembler::Assembler;
 use luasleuth_lua53::disassembler::Disassembler;
+use luasleuth_lua53::types::{
+    constants::Constant,
+    instructions::{Instruction, Opcode},
+    Header,
+};
+use scroll::{Pread, Pwrite};
 
 #[test]
 fn test_can_parse_bytecode_file() {
@@ -19,10 +29,144 @@ fn test_can_parse_bytecode_file() {
 
     // Check prototype related values
     let prototype = bytecode.prototype;
-    assert_eq!(prototype.source, "@.\\example.lua"); // Checks size and actual data
+    assert_eq!(
+        prototype.source.expect("Missing source name"),
+        "@.\\example.lua"
+    ); // Checks size and actual data
     assert_eq!(prototype.line_defined, 0);
     assert_eq!(prototype.last_line_defined, 0);
     assert_eq!(prototype.number_of_parameters, 0);
     assert_eq!(prototype.is_vararg, 1);
     assert_eq!(prototype.max_stack_size, 2);
 }
+
+#[test]
+fn test_infers_layout_of_big_endian_32_bit_header() {
+    let mut bytes = vec![0x1b, b'L', b'u', b'a', 0x53, 0];
+    bytes.extend(b"\x19\x93\r\n\x1a\n");
+    bytes.extend([4, 4, 4, 4, 4]);
+    bytes.extend(0x5678i32.to_be_bytes());
+    bytes.extend(370.5f32.to_be_bytes());
+
+    let header: Header = bytes.pread(0).expect("Failed to read header");
+    assert_eq!(header.endianness, scroll::BE);
+    assert_eq!(header.size_of_integer, 4);
+    assert_eq!(header.size_of_lua_number, 4);
+
+    let mut written = vec![0; bytes.len()];
+    let size = written.pwrite(header, 0).expect("Failed to write header");
+    assert_eq!(&written[..size], bytes);
+
+    // Swapping the byte order of LUAC_NUM alone must be caught
+    let len = bytes.len();
+    bytes[len - 4..].copy_from_slice(&370.5f32.to_le_bytes());
+    let error = bytes.pread::<Header>(0).unwrap_err();
+    assert!(error.kind().to_string().starts_with("LUAC_NUM"));
+    assert_eq!(error.field_path(), "luac_num");
+    assert_eq!(error.offset(), 21);
+}
+
+#[test]
+fn test_malformed_input_returns_error_instead_of_panicking() {
+    let bytes = include_bytes!("../../../data/bytecode/lua53.bin");
+
+    for length in 0..bytes.len() {
+        let result = Disassembler::new(&bytes[..length]).disassemble();
+        assert!(result.is_err(), "Truncated to {} bytes", length);
+    }
+
+    for index in 0..bytes.len() {
+        for value in [0x00, 0x7f, 0x80, 0xff] {
+            let mut bytes = bytes.to_vec();
+            bytes[index] = value;
+            let _ = Disassembler::new(&bytes).disassemble();
+        }
+    }
+}
+
+#[test]
+fn test_can_write_string_constants() {
+    let ctx = CommonCtx {
+        size_of_size_t: 8,
+        lua_version: Version::new(5, 3),
+        endianness: scroll::LE,
+        size_of_int: 4,
+        size_of_instruction: 4,
+        size_of_lua_number: 8,
+        size_of_lua_integer: 8,
+        integral: false,
+        limits: Limits::default(),
+        depth: 0,
+    };
+
+    // Strings of up to 40 bytes are short strings, longer ones are long strings
+    for (string, tag) in [("a".repeat(40), 4), ("a".repeat(41), 20)] {
+        let mut bytes = [0; 64];
+        let size = bytes
+            .pwrite_with(Constant::String(string.as_str().into()), 0, ctx)
+            .expect("Failed to write constant");
+        assert_eq!(bytes[0], tag);
+        assert_eq!(bytes[1] as usize, string.len() + 1);
+
+        let constant: Constant = bytes[..size]
+            .pread_with(0, ctx)
+            .expect("Failed to read constant");
+        assert!(matches!(constant, Constant::String(s) if s == string.as_str()));
+    }
+}
+
+#[test]
+fn test_assembles_bytecode_file_back_to_the_same_bytes() {
+    let bytes = include_bytes!("../../../data/bytecode/lua53.bin");
+    let bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    assert_eq!(assembled, bytes);
+}
+
+#[test]
+fn test_assembles_modified_bytecode() {
+    let bytes = include_bytes!("../../../data/bytecode/lua53.bin");
+    let mut bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Nest a copy of the main function, which luac dumps without a source name
+    let mut child = bytecode.prototype.clone();
+    child.source = None;
+    child.instructions.data[0] = Instruction::iAsBx(Opcode::OP_JMP, 200, -3);
+    bytecode.prototype.prototypes = vec![child].into();
+
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    let bytecode = Disassembler::new(&assembled)
+        .disassemble()
+        .expect("Failed to read assembled bytecode");
+
+    let child = &bytecode.prototype.prototypes.data[0];
+    assert!(child.source.is_none());
+    assert!(matches!(
+        child.instructions.data[0],
+        Instruction::iAsBx(Opcode::OP_JMP, 200, -3)
+    ));
+
+    let reassembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    assert_eq!(reassembled, assembled);
+}
+
+#[test]
+fn test_instruction_encoding_round_trips() {
+    let mut raw = 0x2545_f491u32;
+    for _ in 0..100_000 {
+        raw = raw.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        if let Ok(instruction) = Instruction::decode(raw) {
+            assert_eq!(Instruction::encode(instruction), raw, "{:?}", instruction);
+        }
+    }
+}
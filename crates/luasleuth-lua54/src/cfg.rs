@@ -0,0 +1,70 @@
+//! Control flow graphs of Lua 5.4 prototypes.
+
+use luasleuth_common::{
+    cfg::{skip_next, ControlFlowGraph, Edge, EdgeKind},
+    resolve::jump_target,
+};
+
+use crate::{
+    resolve::resolve,
+    types::{
+        instructions::{Instruction, Opcode},
+        Prototype,
+    },
+};
+
+/// Build the control flow graph of a prototype, leaving its children out.
+pub fn build(prototype: &Prototype) -> ControlFlowGraph {
+    ControlFlowGraph::build(prototype.instructions.data.len(), |pc| {
+        successors(prototype, pc)
+    })
+}
+
+/// Returns the edges leaving the instruction at `pc`, to the pcs control can go to next.
+///
+/// Tests go on with the next instruction when their comparison matches the `k` bit.
+pub fn successors(prototype: &Prototype, pc: usize) -> Vec<Edge> {
+    let Some(&instruction) = prototype.instructions.data.get(pc) else {
+        return Vec::new();
+    };
+
+    let jump = |kind| {
+        let operands = resolve(prototype, pc)?;
+        Some(Edge::new(jump_target(&operands)?, kind))
+    };
+
+    match instruction {
+        Instruction::iABC(
+            Opcode::OP_EQ
+            | Opcode::OP_LT
+            | Opcode::OP_LE
+            | Opcode::OP_EQK
+            | Opcode::OP_EQI
+            | Opcode::OP_LTI
+            | Opcode::OP_LEI
+            | Opcode::OP_GTI
+            | Opcode::OP_GEI
+            | Opcode::OP_TEST
+            | Opcode::OP_TESTSET,
+            ..,
+        ) => skip_next(pc),
+        Instruction::iABC(Opcode::OP_LFALSESKIP, ..) => vec![Edge::new(pc + 2, EdgeKind::Jump)],
+        Instruction::iABC(Opcode::OP_RETURN | Opcode::OP_RETURN0 | Opcode::OP_RETURN1, ..) => {
+            Vec::new()
+        }
+        Instruction::isJ(Opcode::OP_JMP, _) | Instruction::iAsBx(Opcode::OP_TFORPREP, ..) => {
+            jump(EdgeKind::Jump).into_iter().collect()
+        }
+        // Skips the loop when it doesn't run at all
+        Instruction::iAsBx(Opcode::OP_FORPREP, ..) => [Edge::new(pc + 1, EdgeKind::True)]
+            .into_iter()
+            .chain(jump(EdgeKind::False))
+            .collect(),
+        // Jump back to the loop body while the loop goes on
+        Instruction::iAsBx(Opcode::OP_FORLOOP | Opcode::OP_TFORLOOP, ..) => jump(EdgeKind::True)
+            .into_iter()
+            .chain([Edge::new(pc + 1, EdgeKind::False)])
+            .collect(),
+        _ => vec![Edge::new(pc + 1, EdgeKind::Fallthrough)],
+    }
+}
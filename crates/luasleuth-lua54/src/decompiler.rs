@@ -0,0 +1,2420 @@
+//! Decompilation of Lua 5.4 chunks back to source.
+//!
+//! Statements are recovered from the shapes `luac` compiles them to: tests followed by jumps for
+//! conditions, `FORPREP` and `TFORPREP` for `for` loops and backward jumps for `while` and
+//! `repeat`. Values computed into temporary registers are folded into the expression using
+//! them, immediate operands and the `MMBIN` instructions after arithmetic telling how it was
+//! written. Locals are named after the debug information, or given generated names when the
+//! chunk is stripped. `TBC` and the kind of the upvalues closures capture locals with give back
+//! `<close>` and `<const>`, while constants `luac` folds away can't be recovered.
+//!
+//! `luac` sends jumps landing on other jumps straight to where those go, which is undone when
+//! matching shapes. Jumps that fit no shape become `goto`, the chunk being lifted again with
+//! the labels they go to.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write},
+};
+
+use luasleuth_common::{
+    ast::{
+        is_identifier, write_chunk, write_expression, Attribute, BinaryOperator, Block, Call,
+        Expression, Field, Function, LocalName, Statement, UnaryOperator,
+    },
+    decompiler::{condition, generated_name, short_circuit, Branch, Exit, Operand, MAX_NESTING},
+};
+
+use crate::types::{
+    constants::Constant,
+    instructions::{
+        constants::{OFFSET_SBX, SIZE_C},
+        Instruction, Opcode,
+    },
+    Bytecode, Prototype,
+};
+
+/// Excess-K bias of the signed `sB` and `sC` operands
+const OFFSET_SC: i64 = (1 << (SIZE_C - 1)) - 1;
+
+/// Metamethod event of `MMBINI` after `ADDI` computing `x - 1`
+const TM_SUB: u8 = 7;
+
+/// Metamethod event of `MMBINI` after `SHRI` computing `x << 1`
+const TM_SHL: u8 = 16;
+
+/// Kind of an upvalue capturing a `<const>` local
+const RDKCONST: u8 = 1;
+
+/// Kind of an upvalue capturing a `<close>` local
+const RDKTOCLOSE: u8 = 2;
+
+/// Registers an instruction can refer to, counting those past `A` that `B` and `C` reach
+const REGISTERS: usize = 1024;
+
+/// The source of a chunk, as [`decompile`] recovers it.
+pub struct Source<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Source<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Source<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_chunk(f, &decompile(self.bytecode).body)
+    }
+}
+
+/// Decompile the main function of a chunk, along with the closures it creates.
+pub fn decompile(bytecode: &Bytecode) -> Function {
+    let prototype = &bytecode.prototype;
+    // The main function only captures `_ENV`
+    let upvalues = (0..prototype.upvalues.data.len())
+        .map(
+            |index| match prototype.debug_info.upvalues.data.get(index) {
+                Some(name) => String::from_utf8_lossy(name.as_bytes()).into_owned(),
+                None if index == 0 => "_ENV".to_string(),
+                None => generated_name("u", index, 0),
+            },
+        )
+        .collect();
+    lift(prototype, 0, upvalues)
+}
+
+/// Lift a function, again as long as it has jumps to pcs no label was placed at, and again
+/// without control structures when they nest too deep.
+fn lift(prototype: &Prototype, depth: usize, upvalues: Vec<String>) -> Function {
+    let mut labels = BTreeSet::new();
+    let mut dangling = BTreeSet::new();
+    let mut flat = false;
+    loop {
+        let mut lifter = Lifter::new(prototype, depth, upvalues.clone(), labels, dangling);
+        lifter.flat = flat;
+        let function = lifter.function();
+        if lifter.too_deep {
+            (labels, dangling, flat) = (BTreeSet::new(), BTreeSet::new(), true);
+            continue;
+        }
+
+        let missing: BTreeSet<usize> = lifter.gotos.difference(&lifter.labels).copied().collect();
+        let unplaced: BTreeSet<usize> = lifter.gotos.difference(&lifter.placed).copied().collect();
+        labels = lifter.labels;
+        dangling = lifter.dangling;
+        if !missing.is_empty() {
+            labels.extend(missing);
+        } else if !unplaced.is_empty() {
+            // Jumps into the middle of a statement are kept as comments
+            labels.retain(|pc| !unplaced.contains(pc));
+            dangling.extend(unplaced);
+        } else {
+            return function;
+        }
+    }
+}
+
+/// What a register holds that no statement has used yet.
+#[derive(Debug, Clone)]
+enum Pending {
+    /// A value, `shared` when read more than once and so declared as a local by its first read.
+    /// `order` tells which of the values was computed first.
+    Value {
+        value: Expression,
+        shared: bool,
+        order: usize,
+    },
+    /// The method `SELF` looked up, called with the object in the next register
+    Method(Expression, Expression),
+    /// The object `SELF` passes to its method
+    Object,
+}
+
+/// A call or `...` whose values fill consecutive registers.
+#[derive(Debug, Clone)]
+struct Multi {
+    register: usize,
+    count: usize,
+    value: Expression,
+}
+
+#[derive(Debug)]
+struct Loop {
+    head: usize,
+    /// The pc of the jump back to the head
+    back: usize,
+    /// The pc `break` jumps to
+    exit: usize,
+    /// The pc the body ends at, where jumps going on with the next iteration land
+    next: usize,
+    /// Whether the loop is a `repeat`, whose condition is found while lifting its body
+    repeat: bool,
+    until: Option<Expression>,
+}
+
+/// The state speculative lifting rolls back to.
+struct Snapshot {
+    pending: Vec<Option<Pending>>,
+    multi: Option<Multi>,
+    open: Option<usize>,
+    generated: Vec<Option<String>>,
+    captured: Vec<bool>,
+    scopes: Vec<Vec<usize>>,
+}
+
+struct Lifter<'p, 'a> {
+    prototype: &'p Prototype<'a>,
+    code: &'p [Instruction],
+    depth: usize,
+    upvalues: Vec<String>,
+    pending: Vec<Option<Pending>>,
+    multi: Option<Multi>,
+    /// The register a call or `...` left a variable number of values from
+    open: Option<usize>,
+    /// The order of the value computed last
+    order: usize,
+    /// Names given to registers without debug information
+    generated: Vec<Option<String>>,
+    /// Which of the named registers closures capture
+    captured: Vec<bool>,
+    /// The registers named in each block being lifted
+    scopes: Vec<Vec<usize>>,
+    /// The pc each block being lifted ends at
+    ends: Vec<usize>,
+    /// Which debug locals were declared
+    declared: Vec<bool>,
+    /// The attribute of each debug local
+    attributes: Vec<Option<Attribute>>,
+    loops: Vec<Loop>,
+    /// The pcs of the backward jumps to each pc
+    back_edges: BTreeMap<usize, Vec<usize>>,
+    /// A register kept temporary while a value is computed into it, even if a local lives there
+    capture: Option<usize>,
+    /// The pcs to place labels at, found by lifting the function before
+    labels: BTreeSet<usize>,
+    /// The pcs no label can be placed at, jumps to them being kept as comments
+    dangling: BTreeSet<usize>,
+    /// The pcs `goto` statements go to
+    gotos: BTreeSet<usize>,
+    /// The pcs labels were placed at
+    placed: BTreeSet<usize>,
+    /// Set when blocks nest deeper than [`MAX_NESTING`], giving up on the structure
+    too_deep: bool,
+    /// Whether control flow is kept as `goto` statements instead of being lifted into statements
+    flat: bool,
+}
+
+impl<'p, 'a> Lifter<'p, 'a> {
+    fn new(
+        prototype: &'p Prototype<'a>,
+        depth: usize,
+        upvalues: Vec<String>,
+        labels: BTreeSet<usize>,
+        dangling: BTreeSet<usize>,
+    ) -> Self {
+        let code = &prototype.instructions.data[..];
+        let mut back_edges: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut pc = 0;
+        while pc < code.len() {
+            if let Instruction::isJ(Opcode::OP_JMP, sj) = code[pc] {
+                let target = pc as i64 + 1 + sj as i64;
+                if (0..=pc as i64).contains(&target) {
+                    back_edges.entry(target as usize).or_default().push(pc);
+                }
+            }
+            pc = next_pc(prototype, pc);
+        }
+
+        let locals = prototype.debug_info.local_variables.data.len();
+        let parameters = prototype.number_of_parameters as usize;
+        let mut lifter = Self {
+            prototype,
+            code,
+            depth,
+            upvalues,
+            pending: vec![None; REGISTERS],
+            multi: None,
+            open: None,
+            order: 0,
+            generated: vec![None; REGISTERS],
+            captured: vec![false; REGISTERS],
+            scopes: Vec::new(),
+            ends: Vec::new(),
+            declared: (0..locals).map(|index| index < parameters).collect(),
+            attributes: vec![None; locals],
+            loops: Vec::new(),
+            back_edges,
+            capture: None,
+            labels,
+            dangling,
+            gotos: BTreeSet::new(),
+            placed: BTreeSet::new(),
+            too_deep: false,
+            flat: false,
+        };
+        for register in 0..parameters {
+            if lifter.local_name(register, 0).is_none() {
+                lifter.generated[register] = Some(generated_name("a", register, depth));
+            }
+        }
+
+        // `TBC` marks `<close>` locals, the upvalues capturing locals tell `<const>` ones
+        for (pc, instruction) in code.iter().enumerate() {
+            let marked: Vec<(usize, Attribute)> = match *instruction {
+                Instruction::iABC(Opcode::OP_TBC, a, ..) => vec![(a as usize, Attribute::Close)],
+                Instruction::iABx(Opcode::OP_CLOSURE, _, bx) => prototype
+                    .prototypes
+                    .data
+                    .get(bx as usize)
+                    .map(|child| &child.upvalues.data[..])
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|upvalue| upvalue.in_stack != 0)
+                    .filter_map(|upvalue| match upvalue.kind {
+                        RDKCONST => Some((upvalue.index as usize, Attribute::Const)),
+                        RDKTOCLOSE => Some((upvalue.index as usize, Attribute::Close)),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            for (register, attribute) in marked {
+                if let Some(index) = lifter.local_index(register, pc) {
+                    lifter.attributes[index] = Some(attribute);
+                }
+            }
+        }
+
+        lifter
+    }
+
+    fn function(&mut self) -> Function {
+        let parameters = (0..self.prototype.number_of_parameters as usize)
+            .map(|register| {
+                self.name(register, 0)
+                    .unwrap_or_else(|| generated_name("a", register, self.depth))
+            })
+            .collect();
+        let is_vararg = self.prototype.is_vararg != 0;
+        let body = self.block(0, self.code.len());
+
+        Function {
+            parameters,
+            is_vararg,
+            body,
+        }
+    }
+
+    /// Lift the statements of `start..end`, which control enters at `start` and leaves at `end`.
+    fn block(&mut self, start: usize, end: usize) -> Block {
+        if self.ends.len() == MAX_NESTING {
+            self.too_deep = true;
+        }
+        if self.too_deep {
+            return Vec::new();
+        }
+
+        let end = end.min(self.code.len());
+        self.scopes.push(Vec::new());
+        self.ends.push(end);
+
+        let mut out = Vec::new();
+        let mut pc = start;
+        while pc < end && !self.too_deep {
+            pc = self.statement(&mut out, pc, end).max(pc + 1);
+        }
+
+        // Values nothing used are only kept when computing them has effects
+        self.flush_multi(&mut out);
+        self.settle(&mut out, Expression::has_effects);
+        self.open = None;
+
+        self.ends.pop();
+        for register in self.scopes.pop().unwrap_or_default() {
+            self.generated[register] = None;
+        }
+        out
+    }
+
+    /// Lift the body of a loop, placing the label of jumps going on with the next iteration.
+    fn loop_body(&mut self, start: usize, end: usize) -> Block {
+        let mut body = self.block(start, end);
+        if self.labels.contains(&end) && self.placed.insert(end) {
+            body.push(Statement::Label(label_name(end)));
+        }
+        body
+    }
+
+    /// Lift the statement starting at `pc`, returning the pc after it.
+    fn statement(&mut self, out: &mut Block, pc: usize, end: usize) -> usize {
+        if self.labels.contains(&pc) && self.placed.insert(pc) {
+            self.emit(out, Statement::Label(label_name(pc)));
+        }
+        self.declare_locals(out, pc);
+        if self.flat {
+            return match self.code[pc] {
+                Instruction::isJ(Opcode::OP_JMP, _) => self.jump(out, pc),
+                _ if self.is_test(pc) && pc + 1 < end && self.jump_target(pc + 1).is_some() => {
+                    self.conditional_jump(out, &[pc])
+                }
+                _ => self.instruction(out, pc),
+            };
+        }
+        if let Some(back) = self.loop_at(pc, end) {
+            return self.lift_loop(out, pc, back);
+        }
+
+        match self.code[pc] {
+            Instruction::iAsBx(Opcode::OP_FORPREP, a, sbx) => {
+                self.numeric_for(out, pc, a as usize, sbx, end)
+            }
+            Instruction::iAsBx(Opcode::OP_TFORPREP, a, sbx) => {
+                self.generic_for(out, pc, a as usize, sbx, end)
+            }
+            Instruction::isJ(Opcode::OP_JMP, _) => self.jump(out, pc),
+            _ if self.is_test(pc) && pc + 1 < end && self.jump_target(pc + 1).is_some() => {
+                self.conditional(out, pc, end)
+            }
+            _ => self.instruction(out, pc),
+        }
+    }
+
+    /// Lift an instruction that isn't part of a control structure, returning the pc after it.
+    fn instruction(&mut self, out: &mut Block, pc: usize) -> usize {
+        match self.code[pc] {
+            Instruction::iABC(opcode, a, b, c, k) => {
+                let a = a as usize;
+                match opcode {
+                    Opcode::OP_MOVE => {
+                        let value = self.read(out, b as usize, pc);
+                        self.assign(out, a, value, pc);
+                    }
+                    Opcode::OP_LOADFALSE => self.assign(out, a, Expression::Boolean(false), pc),
+                    Opcode::OP_LOADTRUE => self.assign(out, a, Expression::Boolean(true), pc),
+                    Opcode::OP_LFALSESKIP => {
+                        self.assign(out, a, Expression::Boolean(false), pc);
+                        let note = format!("skips instruction {}", pc + 2);
+                        self.emit(out, Statement::Comment(note));
+                        return pc + 2;
+                    }
+                    Opcode::OP_LOADNIL => {
+                        for register in a..=a + b as usize {
+                            self.assign(out, register, Expression::Nil, pc);
+                        }
+                    }
+                    Opcode::OP_GETUPVAL => {
+                        let upvalue = self.upvalue(b as usize);
+                        self.assign(out, a, upvalue, pc);
+                    }
+                    Opcode::OP_SETUPVAL => {
+                        let value = self.read(out, a, pc);
+                        let upvalue = self.upvalue(b as usize);
+                        self.emit(out, assignment(upvalue, value));
+                    }
+                    Opcode::OP_GETTABUP => {
+                        let value = self.upvalue_field(b as usize, c as usize);
+                        self.assign(out, a, value, pc);
+                    }
+                    Opcode::OP_GETTABLE | Opcode::OP_GETI | Opcode::OP_GETFIELD => {
+                        let object = self.read(out, b as usize, pc);
+                        let key = match opcode {
+                            Opcode::OP_GETTABLE => self.read(out, c as usize, pc),
+                            Opcode::OP_GETI => Expression::Integer(c as i64),
+                            _ => self.constant(c as usize),
+                        };
+                        self.assign(out, a, Expression::index(object, key), pc);
+                    }
+                    Opcode::OP_SETTABUP => {
+                        let value = self.register_or_constant(out, c, k, pc);
+                        let target = self.upvalue_field(a, b as usize);
+                        self.emit(out, assignment(target, value));
+                    }
+                    Opcode::OP_SETTABLE | Opcode::OP_SETI | Opcode::OP_SETFIELD => {
+                        self.set_table(out, pc)
+                    }
+                    Opcode::OP_NEWTABLE => self.assign(out, a, Expression::Table(Vec::new()), pc),
+                    Opcode::OP_SELF => {
+                        let object = self.read(out, b as usize, pc);
+                        let key = self.register_or_constant(out, c, k, pc);
+                        self.pending[a] = Some(Pending::Method(object, key));
+                        self.pending[a + 1] = Some(Pending::Object);
+                    }
+                    Opcode::OP_ADDI
+                    | Opcode::OP_ADDK
+                    | Opcode::OP_SUBK
+                    | Opcode::OP_MULK
+                    | Opcode::OP_MODK
+                    | Opcode::OP_POWK
+                    | Opcode::OP_DIVK
+                    | Opcode::OP_IDIVK
+                    | Opcode::OP_BANDK
+                    | Opcode::OP_BORK
+                    | Opcode::OP_BXORK
+                    | Opcode::OP_SHRI
+                    | Opcode::OP_SHLI
+                    | Opcode::OP_ADD
+                    | Opcode::OP_SUB
+                    | Opcode::OP_MUL
+                    | Opcode::OP_MOD
+                    | Opcode::OP_POW
+                    | Opcode::OP_DIV
+                    | Opcode::OP_IDIV
+                    | Opcode::OP_BAND
+                    | Opcode::OP_BOR
+                    | Opcode::OP_BXOR
+                    | Opcode::OP_SHL
+                    | Opcode::OP_SHR => {
+                        let value = self.arithmetic(out, pc);
+                        self.assign(out, a, value, pc);
+                    }
+                    // Only run when the arithmetic before falls back to a metamethod
+                    Opcode::OP_MMBIN | Opcode::OP_MMBINI | Opcode::OP_MMBINK => {}
+                    Opcode::OP_UNM | Opcode::OP_BNOT | Opcode::OP_NOT | Opcode::OP_LEN => {
+                        let operand = self.read(out, b as usize, pc);
+                        let operator = match opcode {
+                            Opcode::OP_UNM => UnaryOperator::Neg,
+                            Opcode::OP_BNOT => UnaryOperator::BitNot,
+                            Opcode::OP_NOT => UnaryOperator::Not,
+                            _ => UnaryOperator::Len,
+                        };
+                        self.assign(out, a, Expression::unary(operator, operand), pc);
+                    }
+                    Opcode::OP_CONCAT => {
+                        let mut values: Vec<Expression> = Vec::new();
+                        for register in a..a + b as usize {
+                            values.push(self.read(out, register, pc));
+                        }
+                        // `..` is right associative
+                        let last = values.pop().unwrap_or(Expression::Nil);
+                        let value = values.into_iter().rev().fold(last, |right, left| {
+                            Expression::binary(BinaryOperator::Concat, left, right)
+                        });
+                        self.assign(out, a, value, pc);
+                    }
+                    Opcode::OP_CLOSE | Opcode::OP_VARARGPREP => {}
+                    Opcode::OP_TBC => self.close_register(out, a, pc),
+                    Opcode::OP_CALL => {
+                        let call = self.call(out, a, b, pc);
+                        if c == 1 {
+                            self.emit(out, Statement::Call(call));
+                            return pc + 1;
+                        }
+                        let call = Expression::Call(Box::new(call));
+                        match c {
+                            0 => {
+                                self.set_value(a, call, false);
+                                self.open = Some(a);
+                            }
+                            2 => self.assign(out, a, call, pc),
+                            c => self.set_multi(out, a, c as usize - 1, call),
+                        }
+                    }
+                    Opcode::OP_TAILCALL => {
+                        let call = self.call(out, a, b, pc);
+                        let values = vec![Expression::Call(Box::new(call))];
+                        self.emit(out, Statement::Return(values));
+                        // The `RETURN` after a tail call is never reached
+                        if let Some(Instruction::iABC(Opcode::OP_RETURN, ..)) =
+                            self.code.get(pc + 1)
+                        {
+                            return pc + 2;
+                        }
+                    }
+                    Opcode::OP_RETURN | Opcode::OP_RETURN0 | Opcode::OP_RETURN1 => {
+                        let count = match opcode {
+                            Opcode::OP_RETURN0 => Some(0),
+                            Opcode::OP_RETURN1 => Some(1),
+                            _ => (b != 0).then(|| b as usize - 1),
+                        };
+                        let values = self.list(out, a, count, pc);
+                        // Every function ends with a `return` the source doesn't need
+                        if count != Some(0) || pc + 1 != self.code.len() {
+                            self.emit(out, Statement::Return(values));
+                        }
+                    }
+                    Opcode::OP_SETLIST => {
+                        let count = (b != 0).then_some(b as usize);
+                        let values = self.list(out, a + 1, count, pc);
+                        match self.table_mut(a) {
+                            Some(fields) => {
+                                fields.extend(values.into_iter().map(Field::Positional))
+                            }
+                            None => {
+                                let note = "SETLIST outside of a table constructor".to_string();
+                                self.emit(out, Statement::Comment(note));
+                            }
+                        }
+                    }
+                    Opcode::OP_VARARG => match c {
+                        0 => {
+                            self.set_value(a, Expression::VarArg, false);
+                            self.open = Some(a);
+                        }
+                        1 => {}
+                        2 => self.assign(out, a, Expression::VarArg, pc),
+                        c => self.set_multi(out, a, c as usize - 1, Expression::VarArg),
+                    },
+                    _ => self.unstructured(out, pc),
+                }
+            }
+            Instruction::iABx(opcode, a, bx) => {
+                let a = a as usize;
+                match opcode {
+                    Opcode::OP_LOADK => {
+                        let constant = self.constant(bx as usize);
+                        self.assign(out, a, constant, pc);
+                    }
+                    Opcode::OP_LOADKX => {
+                        let constant = match self.code.get(pc + 1) {
+                            Some(&Instruction::iAx(Opcode::OP_EXTRAARG, ax)) => {
+                                self.constant(ax as usize)
+                            }
+                            _ => Expression::Nil,
+                        };
+                        self.assign(out, a, constant, pc);
+                    }
+                    Opcode::OP_CLOSURE => return self.closure(out, pc, a, bx as usize),
+                    _ => self.unstructured(out, pc),
+                }
+            }
+            Instruction::iAsBx(opcode, a, sbx) => {
+                let a = a as usize;
+                match opcode {
+                    Opcode::OP_LOADI => self.assign(out, a, Expression::Integer(sbx as i64), pc),
+                    Opcode::OP_LOADF => self.assign(out, a, Expression::Float(sbx as f64), pc),
+                    _ => self.unstructured(out, pc),
+                }
+            }
+            Instruction::iAx(..) | Instruction::isJ(..) => self.unstructured(out, pc),
+        }
+
+        next_pc(self.prototype, pc)
+    }
+
+    /// Keep an instruction no statement accounts for as a comment.
+    fn unstructured(&mut self, out: &mut Block, pc: usize) {
+        let note = format!(
+            "unstructured {:?} at instruction {}",
+            opcode(self.code[pc]),
+            pc + 1
+        );
+        self.emit(out, Statement::Comment(note));
+    }
+
+    /// Returns the value the arithmetic instruction at `pc` computes.
+    ///
+    /// The `MMBINI` or `MMBINK` after an instruction with a constant operand tells the operands
+    /// were swapped, and which operator `x - 1` and `x << 1` were with their immediates negated.
+    fn arithmetic(&mut self, out: &mut Block, pc: usize) -> Expression {
+        let Instruction::iABC(opcode, _, b, c, _) = self.code[pc] else {
+            return Expression::Nil;
+        };
+        let mut operator = binary_operator(opcode);
+        let left = self.read(out, b as usize, pc);
+
+        let (right, swapped) = match opcode {
+            Opcode::OP_ADDI | Opcode::OP_SHRI | Opcode::OP_SHLI => {
+                let mut immediate = c as i64 - OFFSET_SC;
+                let mut swapped = matches!(opcode, Opcode::OP_SHLI);
+                if let Some(&Instruction::iABC(Opcode::OP_MMBINI, _, sb, event, k)) =
+                    self.code.get(pc + 1)
+                {
+                    match (opcode, event) {
+                        (Opcode::OP_ADDI, TM_SUB) | (Opcode::OP_SHRI, TM_SHL) => {
+                            operator = match event {
+                                TM_SUB => BinaryOperator::Sub,
+                                _ => BinaryOperator::Shl,
+                            };
+                            immediate = sb as i64 - OFFSET_SC;
+                        }
+                        _ => {}
+                    }
+                    swapped = k != 0;
+                }
+                (Expression::Integer(immediate), swapped)
+            }
+            Opcode::OP_ADDK
+            | Opcode::OP_SUBK
+            | Opcode::OP_MULK
+            | Opcode::OP_MODK
+            | Opcode::OP_POWK
+            | Opcode::OP_DIVK
+            | Opcode::OP_IDIVK
+            | Opcode::OP_BANDK
+            | Opcode::OP_BORK
+            | Opcode::OP_BXORK => {
+                let swapped = matches!(
+                    self.code.get(pc + 1),
+                    Some(Instruction::iABC(Opcode::OP_MMBINK, _, _, _, 1))
+                );
+                (self.constant(c as usize), swapped)
+            }
+            _ => (self.read(out, c as usize, pc), false),
+        };
+
+        match swapped {
+            true => Expression::binary(operator, right, left),
+            false => Expression::binary(operator, left, right),
+        }
+    }
+
+    fn set_table(&mut self, out: &mut Block, pc: usize) {
+        let Instruction::iABC(opcode, a, b, c, k) = self.code[pc] else {
+            return;
+        };
+        let a = a as usize;
+
+        // Fields of a table constructor
+        let constructor = !self.named(a, pc) && self.table_mut(a).is_some();
+        let object = (!constructor).then(|| self.read(out, a, pc));
+        let key = match opcode {
+            Opcode::OP_SETTABLE => self.read(out, b as usize, pc),
+            Opcode::OP_SETI => Expression::Integer(b as i64),
+            _ => self.constant(b as usize),
+        };
+        let value = self.register_or_constant(out, c, k, pc);
+
+        let object = match object {
+            Some(object) => object,
+            None => match self.table_mut(a) {
+                Some(fields) => return fields.push(Field::Named(key, value)),
+                // Computing the field declared the table to keep effects in order
+                None => self.read(out, a, pc),
+            },
+        };
+        self.emit(out, assignment(Expression::index(object, key), value));
+    }
+
+    /// Build the call of the function in register `a`, its arguments following it.
+    fn call(&mut self, out: &mut Block, a: usize, b: u8, pc: usize) -> Call {
+        let count = (b != 0).then(|| b as usize - 1);
+        match self.pending[a].take() {
+            Some(Pending::Method(object, key)) => {
+                self.pending[a + 1] = None;
+                let count = count.map(|count| count.saturating_sub(1));
+                let arguments = self.list(out, a + 2, count, pc);
+                match key {
+                    Expression::String(name) if is_identifier(&name) => Call {
+                        function: object,
+                        method: Some(String::from_utf8_lossy(&name).into_owned()),
+                        arguments,
+                    },
+                    key => Call {
+                        function: Expression::index(object.clone(), key),
+                        method: None,
+                        arguments: [object].into_iter().chain(arguments).collect(),
+                    },
+                }
+            }
+            pending => {
+                self.pending[a] = pending;
+                let function = self.read(out, a, pc);
+                let arguments = self.list(out, a + 1, count, pc);
+                Call {
+                    function,
+                    method: None,
+                    arguments,
+                }
+            }
+        }
+    }
+
+    /// Read `count` registers from `first`, or up to the open register when `None`.
+    fn list(
+        &mut self,
+        out: &mut Block,
+        first: usize,
+        count: Option<usize>,
+        pc: usize,
+    ) -> Vec<Expression> {
+        let end = match count {
+            Some(count) => first + count,
+            None => self.open.take().map_or(first, |open| open + 1),
+        };
+
+        let mut values = Vec::new();
+        for register in first..end.min(REGISTERS) {
+            values.push(self.read(out, register, pc));
+        }
+        if count.is_some() {
+            if let Some(last) = values.pop() {
+                values.push(last.truncated());
+            }
+        }
+
+        values
+    }
+
+    fn closure(&mut self, out: &mut Block, pc: usize, a: usize, index: usize) -> usize {
+        let Some(child) = self.prototype.prototypes.data.get(index) else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+
+        // A `local function` is declared after its closure, which captures the local itself
+        let mut names = Vec::new();
+        let mut recursive = None;
+        for upvalue in &child.upvalues.data {
+            let index = upvalue.index as usize;
+            let name = match upvalue.in_stack {
+                0 => match self.upvalue(index) {
+                    Expression::Name(name) => Some(name),
+                    _ => None,
+                },
+                _ if index == a && !self.named(a, pc) => {
+                    let name = match self.declares(a, pc + 1) {
+                        true => self.local_name(a, pc + 1),
+                        false => None,
+                    };
+                    let name = name.unwrap_or_else(|| generated_name("r", a, self.depth));
+                    recursive = Some(name.clone());
+                    Some(name)
+                }
+                _ => Some(self.capture_register(out, index, pc)),
+            };
+            names.push(name);
+        }
+
+        // The names of what the closure captures, or else the ones its debug information keeps
+        let debug_names = &child.debug_info.upvalues.data;
+        let upvalues = names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let debug_name = debug_names.get(index);
+                let debug_name = debug_name.map(|name| String::from_utf8_lossy(name.as_bytes()));
+                name.or_else(|| debug_name.map(|name| name.into_owned()))
+                    .unwrap_or_else(|| generated_name("u", index, self.depth + 1))
+            })
+            .collect();
+        let function = lift(child, self.depth + 1, upvalues);
+
+        match recursive {
+            Some(name) => {
+                self.flush(out);
+                match self.local_index(a, pc + 1) {
+                    Some(index) if self.declares(a, pc + 1) => self.declared[index] = true,
+                    _ => {
+                        self.name_register(a, name.clone());
+                        self.captured[a] = true;
+                    }
+                }
+                out.push(Statement::LocalFunction { name, function });
+            }
+            None => {
+                let function = Expression::Function(Box::new(function));
+                self.assign(out, a, function, pc);
+            }
+        }
+
+        pc + 1
+    }
+
+    /// Returns the name of a register a closure captures, declaring a local for its value.
+    fn capture_register(&mut self, out: &mut Block, register: usize, pc: usize) -> String {
+        if let Some(name) = self.name(register, pc) {
+            return name;
+        }
+        self.materialize_pending(out, register);
+        self.captured[register] = true;
+        self.register_name(register, pc)
+    }
+
+    /// Mark a register to be closed, which debug locals already are by their declaration.
+    fn close_register(&mut self, out: &mut Block, register: usize, pc: usize) {
+        if self.local_name(register, pc).is_some() {
+            return;
+        }
+
+        let value = self.read(out, register, pc);
+        let name = generated_name("r", register, self.depth);
+        self.emit(
+            out,
+            Statement::Local {
+                names: vec![LocalName {
+                    name: name.clone(),
+                    attribute: Some(Attribute::Close),
+                }],
+                values: vec![value],
+            },
+        );
+        self.name_register(register, name);
+    }
+
+    /// Declare the debug locals starting at `pc`, with the values their registers hold.
+    fn declare_locals(&mut self, out: &mut Block, pc: usize) {
+        let mut new = Vec::new();
+        let mut register = 0;
+        for (index, local) in self
+            .prototype
+            .debug_info
+            .local_variables
+            .data
+            .iter()
+            .enumerate()
+        {
+            if !is_active(local.start_pc.value, local.end_pc.value, pc) {
+                continue;
+            }
+            if local.start_pc.value == pc && !self.declared[index] {
+                self.declared[index] = true;
+                // Locals of the loops are declared by their statements
+                let name = local.name.as_bytes();
+                if !name.starts_with(b"(") {
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    new.push((register, name, self.attributes[index]));
+                }
+            }
+            register += 1;
+        }
+        if new.is_empty() {
+            return;
+        }
+
+        // `local function` is declared once its closure is created
+        if let [(register, ref name, None)] = new[..] {
+            let closure = matches!(
+                self.pending[register],
+                Some(Pending::Value {
+                    value: Expression::Function(_),
+                    ..
+                })
+            );
+            if closure {
+                if let Some(Expression::Function(function)) = self.take_value(register) {
+                    let name = name.clone();
+                    let function = *function;
+                    self.emit(out, Statement::LocalFunction { name, function });
+                    return;
+                }
+            }
+        }
+
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        let mut covered = 0;
+        for (register, name, attribute) in new {
+            names.push(LocalName { name, attribute });
+            if covered > 0 {
+                covered -= 1;
+                continue;
+            }
+
+            match self.multi.take() {
+                Some(multi) if multi.register == register => {
+                    covered = multi.count - 1;
+                    values.push(multi.value);
+                }
+                multi => {
+                    self.multi = multi;
+                    values.push(self.take_value(register).unwrap_or(Expression::Nil));
+                }
+            }
+        }
+
+        // `local a, b = 1` leaves `b` nil, unless values come from a call
+        while let [.., Expression::Nil] = values[..] {
+            match values.len() {
+                1 => values.clear(),
+                len => match values[len - 2] {
+                    Expression::Call(_) | Expression::VarArg => break,
+                    _ => {
+                        values.pop();
+                    }
+                },
+            }
+        }
+
+        self.emit(out, Statement::Local { names, values });
+    }
+
+    /// Returns the pc of the backward jump closing a loop starting at `pc`, if one does.
+    fn loop_at(&self, pc: usize, end: usize) -> Option<usize> {
+        let sources = self.back_edges.get(&pc)?;
+        sources
+            .iter()
+            .copied()
+            .filter(|&back| back < end)
+            .filter(|&back| !self.loops.iter().any(|l| l.head == pc && l.back == back))
+            .max()
+    }
+
+    /// Lift a `while` or `repeat` loop from `head` to the backward jump at `back`.
+    fn lift_loop(&mut self, out: &mut Block, head: usize, back: usize) -> usize {
+        self.flush(out);
+        let exit = back + 1;
+
+        // A `repeat` ends with the jump of its condition
+        if back > head && self.is_test(back - 1) {
+            self.loops.push(Loop {
+                head,
+                back,
+                exit,
+                next: head,
+                repeat: true,
+                until: None,
+            });
+            let body = self.block(head, exit);
+            let until = self.loops.pop().and_then(|l| l.until);
+            let statement = match until {
+                Some(condition) => Statement::Repeat { body, condition },
+                None => Statement::While {
+                    condition: Expression::Boolean(true),
+                    body,
+                },
+            };
+            self.emit(out, statement);
+            return exit;
+        }
+
+        self.loops.push(Loop {
+            head,
+            back,
+            exit,
+            next: back,
+            repeat: false,
+            until: None,
+        });
+
+        // A `while` starts with its condition, jumping to the exit
+        let condition = self.speculate(out, |lifter, out| {
+            let mut test = head;
+            while test < back && lifter.is_expression(test) {
+                test = next_pc(lifter.prototype, test);
+            }
+            if test >= back || !lifter.is_test(test) {
+                return None;
+            }
+
+            let tests = lifter.scan_tests(test, back);
+            let exit_at = lifter.final_target(exit);
+            (0..tests.len()).rev().find_map(|last| {
+                let tests = &tests[..=last];
+                let body = tests[last] + 2;
+                let target = lifter.jump_target(tests[last] + 1)?;
+                if lifter.final_target(target) != exit_at {
+                    return None;
+                }
+                let body_at = lifter.final_target(body);
+                let label = |target| match target {
+                    target if target == exit_at => Some(Exit::False),
+                    target if target == body_at => Some(Exit::True),
+                    _ => None,
+                };
+                let exits = lifter.exits(tests, label, Exit::True)?;
+
+                let mut pc = head;
+                while pc < tests[0] {
+                    pc = lifter.instruction(out, pc);
+                }
+                Some((lifter.evaluate(out, tests, &exits, Exit::True), body))
+            })
+        });
+
+        let statement = match condition {
+            Some((condition, body)) => Statement::While {
+                condition,
+                body: self.loop_body(body, back),
+            },
+            None => Statement::While {
+                condition: Expression::Boolean(true),
+                body: self.loop_body(head, back),
+            },
+        };
+        self.loops.pop();
+        self.emit(out, statement);
+
+        exit
+    }
+
+    fn numeric_for(&mut self, out: &mut Block, pc: usize, a: usize, sbx: i32, end: usize) -> usize {
+        // `FORPREP` skips the loop to past its `FORLOOP`
+        let forloop = pc as i64 + sbx as i64 + OFFSET_SBX as i64 + 1;
+        let closes = usize::try_from(forloop).ok().filter(|&forloop| {
+            forloop < end
+                && matches!(
+                    self.code[forloop],
+                    Instruction::iAsBx(Opcode::OP_FORLOOP, b, _) if b as usize == a
+                )
+        });
+        let Some(forloop) = closes else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+
+        let start = self.read(out, a, pc);
+        let limit = self.read(out, a + 1, pc);
+        let step = self.read(out, a + 2, pc);
+        self.flush(out);
+
+        let variable = self.loop_variable(a + 3, pc + 1, "i");
+        self.loops.push(Loop {
+            head: forloop,
+            back: forloop,
+            exit: forloop + 1,
+            next: forloop,
+            repeat: false,
+            until: None,
+        });
+        let body = self.loop_body(pc + 1, forloop);
+        self.loops.pop();
+        self.generated[a + 3] = None;
+
+        let step = (step != Expression::Integer(1)).then_some(step);
+        self.emit(
+            out,
+            Statement::NumericFor {
+                variable,
+                start,
+                limit,
+                step,
+                body,
+            },
+        );
+
+        forloop + 1
+    }
+
+    fn generic_for(&mut self, out: &mut Block, pc: usize, a: usize, sbx: i32, end: usize) -> usize {
+        // `TFORPREP` jumps to the call of the iterator, followed by the jump back to the body
+        let tforcall = pc as i64 + sbx as i64 + OFFSET_SBX as i64 + 1;
+        let closes = usize::try_from(tforcall).ok().filter(|&tforcall| {
+            tforcall + 1 < end
+                && matches!(
+                    self.code[tforcall],
+                    Instruction::iABC(Opcode::OP_TFORCALL, b, ..) if b as usize == a
+                )
+                && matches!(
+                    self.code[tforcall + 1],
+                    Instruction::iAsBx(Opcode::OP_TFORLOOP, b, sbx)
+                        if b as usize == a
+                            && tforcall as i64 + 2 - (sbx as i64 + OFFSET_SBX as i64)
+                                == pc as i64 + 1
+                )
+        });
+        let Some(tforcall) = closes else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+        let Instruction::iABC(_, _, _, c, _) = self.code[tforcall] else {
+            unreachable!()
+        };
+
+        // The iterator, its state, the control variable and the value closed after the loop,
+        // usually from a single call
+        let values = match self.multi.take() {
+            Some(multi) if multi.register == a && multi.count == 4 => vec![multi.value],
+            multi => {
+                self.multi = multi;
+                let mut values = Vec::new();
+                for register in a..a + 4 {
+                    values.push(self.read(out, register, pc));
+                }
+                while values.len() > 1 && values.last() == Some(&Expression::Nil) {
+                    values.pop();
+                }
+                values
+            }
+        };
+        self.flush(out);
+
+        let variables = (0..(c as usize).max(1))
+            .map(|index| {
+                let prefix = if index == 0 { "k" } else { "v" };
+                self.loop_variable(a + 4 + index, pc + 1, prefix)
+            })
+            .collect();
+        self.loops.push(Loop {
+            head: tforcall,
+            back: tforcall + 1,
+            exit: tforcall + 2,
+            next: tforcall,
+            repeat: false,
+            until: None,
+        });
+        let body = self.loop_body(pc + 1, tforcall);
+        self.loops.pop();
+        for index in 0..(c as usize).max(1) {
+            self.generated[a + 4 + index] = None;
+        }
+
+        self.emit(
+            out,
+            Statement::GenericFor {
+                variables,
+                values,
+                body,
+            },
+        );
+
+        tforcall + 2
+    }
+
+    /// Returns the name of a loop variable, declaring its debug local.
+    fn loop_variable(&mut self, register: usize, pc: usize, prefix: &str) -> String {
+        for (index, local) in self
+            .prototype
+            .debug_info
+            .local_variables
+            .data
+            .iter()
+            .enumerate()
+        {
+            if local.start_pc.value == pc {
+                self.declared[index] = true;
+            }
+        }
+
+        match self.local_name(register, pc) {
+            Some(name) => name,
+            None => {
+                let name = generated_name(prefix, register, self.depth);
+                self.generated[register] = Some(name.clone());
+                name
+            }
+        }
+    }
+
+    /// Lift a jump that doesn't follow a test.
+    fn jump(&mut self, out: &mut Block, pc: usize) -> usize {
+        let Some(target) = self.jump_target(pc) else {
+            self.unstructured(out, pc);
+            return pc + 1;
+        };
+
+        if target != pc + 1 {
+            let statement = self.jump_to(target);
+            self.emit(out, statement);
+        }
+
+        pc + 1
+    }
+
+    /// Returns the statement jumping to `target`, a `break` or else a `goto`.
+    fn jump_to(&mut self, target: usize) -> Statement {
+        let target_at = self.final_target(target);
+        let label = match self.loops.last() {
+            Some(l) if self.final_target(l.exit) == target_at => return Statement::Break,
+            // Going on with the next iteration, to the end of the body
+            Some(l) if self.final_target(l.next) == target_at => l.next,
+            _ => target,
+        };
+
+        if self.dangling.contains(&label) {
+            return Statement::Comment(format!("jump to instruction {}", target + 1));
+        }
+        self.gotos.insert(label);
+        Statement::Goto(label_name(label))
+    }
+
+    /// Lift a test followed by a jump, as an `if`, the end of a `repeat` or a value.
+    fn conditional(&mut self, out: &mut Block, pc: usize, end: usize) -> usize {
+        let tests = self.scan_tests(pc, end);
+
+        if let Some(&Loop {
+            head,
+            back,
+            exit,
+            repeat: true,
+            ..
+        }) = self.loops.last()
+        {
+            let (head_at, exit_at) = (self.final_target(head), self.final_target(exit));
+            for last in (0..tests.len()).rev() {
+                let tests = &tests[..=last];
+                if tests[last] + 1 != back {
+                    continue;
+                }
+                let label = |target| match target {
+                    target if target == head_at => Some(Exit::False),
+                    target if target == exit_at => Some(Exit::True),
+                    _ => None,
+                };
+                if let Some(exits) = self.exits(tests, label, Exit::True) {
+                    let condition = self.evaluate(out, tests, &exits, Exit::True);
+                    if let Some(l) = self.loops.last_mut() {
+                        l.until = Some(condition);
+                    }
+                    return exit;
+                }
+            }
+        }
+
+        if let Some(next) = self.short_circuit_value(out, pc, end) {
+            return next;
+        }
+        if let Some(next) = self.comparison_value(out, &tests, end) {
+            return next;
+        }
+
+        // A `TESTSET` sets a value, the tests of an `if` stop before it
+        let conditions = tests
+            .iter()
+            .position(|&test| matches!(self.code[test], Instruction::iABC(Opcode::OP_TESTSET, ..)))
+            .unwrap_or(tests.len());
+        for last in (0..conditions).rev() {
+            let tests = &tests[..=last];
+            let body = tests[last] + 2;
+            let Some(target) = self.jump_target(tests[last] + 1) else {
+                continue;
+            };
+            let Some(otherwise) = self.landing(target, body, end) else {
+                continue;
+            };
+            let (otherwise_at, body_at) = (self.final_target(otherwise), self.final_target(body));
+            let label = |target| match target {
+                target if target == otherwise_at => Some(Exit::False),
+                target if target == body_at => Some(Exit::True),
+                _ => None,
+            };
+            if let Some(exits) = self.exits(tests, label, Exit::True) {
+                return self.if_statement(out, tests, &exits, body, otherwise, end);
+            }
+        }
+
+        self.conditional_jump(out, &tests)
+    }
+
+    /// Lift tests followed by jumps that fit no control structure, as an `if` around a `goto`
+    /// or else a comment, returning the pc after them.
+    fn conditional_jump(&mut self, out: &mut Block, tests: &[usize]) -> usize {
+        let pc = tests[0];
+        // A value tested by jumps that fit no shape is kept in a local the jumps can't lose
+        if let Instruction::iABC(Opcode::OP_TEST, a, ..) = self.code[pc] {
+            let a = a as usize;
+            if !self.named(a, pc) && self.reads_after(a, pc, false) > 0 {
+                self.materialize_pending(out, a);
+            }
+        }
+
+        // Otherwise the jump is taken when the condition holds, as `luac` does for
+        // `if x then break end`
+        let sets = tests
+            .iter()
+            .any(|&test| matches!(self.code[test], Instruction::iABC(Opcode::OP_TESTSET, ..)));
+        for last in (0..tests.len()).rev().filter(|_| !sets) {
+            let tests = &tests[..=last];
+            let body = tests[last] + 2;
+            let Some(target) = self.jump_target(tests[last] + 1) else {
+                continue;
+            };
+            let (target_at, body_at) = (self.final_target(target), self.final_target(body));
+            let label = |target| match target {
+                target if target == target_at => Some(Exit::True),
+                target if target == body_at => Some(Exit::False),
+                _ => None,
+            };
+            if let Some(exits) = self.exits(tests, label, Exit::False) {
+                let condition = self.evaluate(out, tests, &exits, Exit::False);
+                let statement = self.jump_to(target);
+                self.emit(
+                    out,
+                    Statement::If {
+                        condition,
+                        then: vec![statement],
+                        otherwise: Vec::new(),
+                    },
+                );
+                return body;
+            }
+        }
+
+        let condition = self.test(out, pc);
+        let target = self.jump_target(pc + 1).unwrap_or_default();
+        let mut note = String::from("if ");
+        let _ = write_expression(&mut note, &condition);
+        let _ = write!(note, " then jump to instruction {}", target + 1);
+        self.emit(out, Statement::Comment(note));
+        pc + 2
+    }
+
+    fn if_statement(
+        &mut self,
+        out: &mut Block,
+        tests: &[usize],
+        exits: &[Exit],
+        body: usize,
+        otherwise: usize,
+        end: usize,
+    ) -> usize {
+        let condition = self.evaluate(out, tests, exits, Exit::True);
+        self.flush(out);
+
+        // The `then` block of an `if` with an `else` ends jumping over it
+        let mut then_end = otherwise;
+        let mut next = otherwise;
+        if otherwise > body && !(otherwise >= 2 && self.is_test(otherwise - 2)) {
+            let target = self.jump_target(otherwise - 1);
+            let landing = target.and_then(|target| self.landing(target, otherwise + 1, end));
+            if let Some(target) = landing {
+                then_end = otherwise - 1;
+                next = target;
+            }
+        }
+
+        // Branches computing a value into the same temporary register
+        let register = (then_end > body)
+            .then(|| self.expression_register(then_end - 1))
+            .flatten()
+            .filter(|&register| !self.named(register, then_end - 1));
+        if let Some(register) = register {
+            // `c and a or b`, `luac` testing `c` alone when `a` is a constant
+            if next > otherwise {
+                let values = self.speculate(out, |lifter, out| {
+                    lifter.capture = Some(register);
+                    let then = lifter.branch_value(out, body, then_end, register)?;
+                    let otherwise = lifter.branch_value(out, otherwise, next, register)?;
+                    then.is_truthy().then_some((then, otherwise))
+                });
+                if let Some((then, otherwise)) = values {
+                    let and = Expression::binary(BinaryOperator::And, condition, then);
+                    let value = Expression::binary(BinaryOperator::Or, and, otherwise);
+                    self.assign(out, register, value, next - 1);
+                    return next;
+                }
+            }
+
+            // Otherwise the register is declared for the branches to assign
+            if self.reads_after(register, next - 1, false) > 0 {
+                self.declare(out, register, Vec::new());
+            }
+        }
+
+        let then = self.block(body, then_end);
+        let otherwise = self.block(otherwise, next);
+        // A jump to the next iteration leaves `then` empty, it is read as the condition failing
+        let (condition, then, otherwise) = if then.is_empty() && !otherwise.is_empty() {
+            (condition.negated(), otherwise, Vec::new())
+        } else {
+            (condition, then, otherwise)
+        };
+        self.emit(
+            out,
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            },
+        );
+
+        next
+    }
+
+    /// Returns the value a branch of an `if` only computes into `register`.
+    fn branch_value(
+        &mut self,
+        out: &mut Block,
+        start: usize,
+        end: usize,
+        register: usize,
+    ) -> Option<Expression> {
+        let mut pc = start;
+        while pc < end {
+            if !self.is_expression(pc) || self.starts_local(pc) {
+                return None;
+            }
+            pc = self.instruction(out, pc);
+        }
+
+        let value = self.take_value(register)?;
+        self.pending.iter().all(Option::is_none).then_some(value)
+    }
+
+    /// Lift `a and b or c` computed into a register, returning the pc after it.
+    fn short_circuit_value(&mut self, out: &mut Block, pc: usize, end: usize) -> Option<usize> {
+        let register = match self.code[pc] {
+            Instruction::iABC(Opcode::OP_TEST, a, ..) if !self.named(a as usize, pc) => a,
+            Instruction::iABC(Opcode::OP_TESTSET, a, ..) => a,
+            // `a and b or c` tests the local `a` before setting the register to `b`
+            Instruction::iABC(Opcode::OP_TEST, ..) => self
+                .scan_tests(pc, end)
+                .into_iter()
+                .find_map(|test| match self.code[test] {
+                    Instruction::iABC(Opcode::OP_TESTSET, a, ..) => Some(a),
+                    _ => None,
+                })?,
+            _ => return None,
+        } as usize;
+
+        let (value, next) = self.speculate(out, |lifter, out| {
+            lifter.capture = Some(register);
+            lifter.value_chain(out, pc, end, register)
+        })?;
+        self.assign(out, register, value, next - 1);
+        Some(next)
+    }
+
+    /// Lift the operands computed into `register` and the tests between them.
+    fn value_chain(
+        &mut self,
+        out: &mut Block,
+        pc: usize,
+        end: usize,
+        register: usize,
+    ) -> Option<(Expression, usize)> {
+        let mut operands = Vec::new();
+        let mut targets = Vec::new();
+        // The pc each operand after the first starts at
+        let mut starts = Vec::new();
+        // The pc of the test after each operand, when it tests the register
+        let mut tests = Vec::new();
+        let mut furthest = 0;
+        // Whether the first operand is left in the register, which a local tested by `TEST`
+        // isn't, so its jump can't go past the other operands
+        let set = match self.code[pc] {
+            Instruction::iABC(Opcode::OP_TEST, a, ..) => a as usize == register,
+            _ => true,
+        };
+
+        let mut test = pc;
+        loop {
+            let (value, truthy) = match self.code[test] {
+                Instruction::iABC(Opcode::OP_TEST, a, _, _, k) if a as usize != register => {
+                    (self.read(out, a as usize, test), k)
+                }
+                Instruction::iABC(Opcode::OP_TEST, _, _, _, k) => (self.take_value(register)?, k),
+                Instruction::iABC(Opcode::OP_TESTSET, _, b, _, k) => {
+                    (self.read(out, b as usize, test), k)
+                }
+                _ => return None,
+            };
+            let target = self.jump_target(test + 1)?;
+            let target = self.landing(target, test + 3, end)?;
+            furthest = furthest.max(target);
+            operands.push(Operand {
+                value,
+                jump: Some((truthy != 0, 0)),
+            });
+            targets.push(target);
+            starts.push(test + 2);
+            let tests_register = match self.code[test] {
+                Instruction::iABC(Opcode::OP_TEST, a, ..) => a as usize == register,
+                _ => false,
+            };
+            tests.push(tests_register.then_some(test));
+
+            let mut pc = test + 2;
+            loop {
+                let tests_register = match self.code[pc.min(end - 1)] {
+                    Instruction::iABC(Opcode::OP_TEST | Opcode::OP_TESTSET, a, ..) => {
+                        a as usize == register && self.jump_target(pc + 1).is_some()
+                    }
+                    _ => false,
+                };
+                // A jump landing on a test makes the operands before it a group of their own,
+                // `(a or b) and c`
+                let grouped = tests_register && targets.contains(&pc);
+                if pc == furthest && !grouped {
+                    if !set && targets[0] == furthest {
+                        return None;
+                    }
+                    let value = self.take_value(register)?;
+                    operands.push(Operand { value, jump: None });
+
+                    let count = operands.len();
+                    for (operand, target) in operands.iter_mut().zip(targets) {
+                        let index = match target == furthest {
+                            true => count,
+                            // Landing on the test after an operand is the same as past it, as
+                            // the value jumping there can't pass the test
+                            false => {
+                                let operand =
+                                    starts.iter().zip(&tests).position(|(&start, &test)| {
+                                        start == target || test == Some(target)
+                                    });
+                                operand? + 1
+                            }
+                        };
+                        if let Some((_, jump)) = &mut operand.jump {
+                            *jump = index;
+                        }
+                    }
+                    return Some((short_circuit(&operands)?, furthest));
+                }
+                if pc > furthest || pc >= end {
+                    return None;
+                }
+
+                if tests_register {
+                    test = pc;
+                    break;
+                }
+                if !self.is_expression(pc) {
+                    return None;
+                }
+                pc = self.instruction(out, pc);
+            }
+        }
+    }
+
+    /// Lift comparisons computed into a register, which `luac` loads with `LFALSESKIP` and
+    /// `LOADTRUE`.
+    fn comparison_value(&mut self, out: &mut Block, tests: &[usize], end: usize) -> Option<usize> {
+        for last in (0..tests.len()).rev() {
+            let tests = &tests[..=last];
+            let falsy = tests[last] + 2;
+            if falsy + 2 > end {
+                continue;
+            }
+            let register = match (self.code[falsy], self.code[falsy + 1]) {
+                (
+                    Instruction::iABC(Opcode::OP_LFALSESKIP, a, ..),
+                    Instruction::iABC(Opcode::OP_LOADTRUE, b, ..),
+                ) if a == b => a as usize,
+                _ => continue,
+            };
+
+            let label = |target| match target {
+                target if target == falsy + 1 => Some(Exit::True),
+                target if target == falsy => Some(Exit::False),
+                _ => None,
+            };
+            if let Some(exits) = self.exits(tests, label, Exit::False) {
+                let value = self.evaluate(out, tests, &exits, Exit::False);
+                self.assign(out, register, value, falsy + 1);
+                return Some(falsy + 2);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the pcs of the tests starting at `pc`, each followed by a jump, with only
+    /// expressions between them.
+    ///
+    /// A condition nests as deep as it has tests, so longer chains are cut at [`MAX_NESTING`].
+    fn scan_tests(&self, pc: usize, end: usize) -> Vec<usize> {
+        let mut tests = vec![pc];
+        while tests.len() < MAX_NESTING {
+            let mut next = tests[tests.len() - 1] + 2;
+            while next < end && self.is_expression(next) && !self.starts_local(next) {
+                next = next_pc(self.prototype, next);
+            }
+            let follows = next + 1 < end
+                && self.is_test(next)
+                && !self.starts_local(next)
+                && self.jump_target(next + 1).is_some();
+            if !follows {
+                return tests;
+            }
+            tests.push(next);
+        }
+        tests
+    }
+
+    /// Returns where each test jumps to, `label` naming the exits of the whole chain from the
+    /// pc a jump ends up at.
+    ///
+    /// Returns `None` when the jumps don't nest like a condition does.
+    fn exits(
+        &self,
+        tests: &[usize],
+        label: impl Fn(usize) -> Option<Exit>,
+        fall: Exit,
+    ) -> Option<Vec<Exit>> {
+        let mut exits = Vec::new();
+        for (index, &test) in tests.iter().enumerate() {
+            let target = self.jump_target(test + 1)?;
+            let exit = match (index + 2..tests.len()).find(|&next| tests[next - 1] + 2 == target) {
+                Some(next) => Exit::Branch(next),
+                None => label(self.final_target(target))?,
+            };
+            exits.push(exit);
+        }
+
+        let branches: Vec<Branch> = exits
+            .iter()
+            .map(|&target| Branch {
+                condition: Expression::Boolean(true),
+                target,
+            })
+            .collect();
+        condition(&branches, fall)?;
+        Some(exits)
+    }
+
+    /// Lift the tests of a chain and the expressions between them into a condition.
+    fn evaluate(
+        &mut self,
+        out: &mut Block,
+        tests: &[usize],
+        exits: &[Exit],
+        fall: Exit,
+    ) -> Expression {
+        let mut branches = Vec::new();
+        for (index, (&test, &target)) in tests.iter().zip(exits).enumerate() {
+            if index > 0 {
+                let mut pc = tests[index - 1] + 2;
+                while pc < test {
+                    pc = self.instruction(out, pc);
+                }
+            }
+            let condition = self.test(out, test);
+            branches.push(Branch { condition, target });
+        }
+
+        condition(&branches, fall).unwrap_or(Expression::Boolean(true))
+    }
+
+    /// Returns the condition under which the test at `pc` takes the jump after it.
+    fn test(&mut self, out: &mut Block, pc: usize) -> Expression {
+        let Instruction::iABC(opcode, a, b, c, k) = self.code[pc] else {
+            return Expression::Boolean(true);
+        };
+        let a = a as usize;
+        let immediate = || {
+            let immediate = b as i64 - OFFSET_SC;
+            match c {
+                0 => Expression::Integer(immediate),
+                _ => Expression::Float(immediate as f64),
+            }
+        };
+
+        let condition = match opcode {
+            Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE => {
+                let left = self.read(out, a, pc);
+                let right = self.read(out, b as usize, pc);
+                Expression::binary(comparison_operator(opcode), left, right)
+            }
+            Opcode::OP_EQK => {
+                let left = self.read(out, a, pc);
+                Expression::binary(BinaryOperator::Eq, left, self.constant(b as usize))
+            }
+            Opcode::OP_EQI | Opcode::OP_LTI | Opcode::OP_LEI | Opcode::OP_GTI | Opcode::OP_GEI => {
+                let left = self.read(out, a, pc);
+                Expression::binary(comparison_operator(opcode), left, immediate())
+            }
+            Opcode::OP_TEST => self.read(out, a, pc),
+            Opcode::OP_TESTSET => self.read(out, b as usize, pc),
+            _ => return Expression::Boolean(true),
+        };
+
+        match k {
+            0 => condition.negated(),
+            _ => condition,
+        }
+    }
+
+    /// Run `lift`, undoing what it did when it fails or emits statements.
+    fn speculate<T>(
+        &mut self,
+        out: &mut Block,
+        lift: impl FnOnce(&mut Self, &mut Block) -> Option<T>,
+    ) -> Option<T> {
+        let snapshot = Snapshot {
+            pending: self.pending.clone(),
+            multi: self.multi.clone(),
+            open: self.open,
+            generated: self.generated.clone(),
+            captured: self.captured.clone(),
+            scopes: self.scopes.clone(),
+        };
+        let len = out.len();
+
+        let result = lift(self, out);
+        self.capture = None;
+        match result {
+            Some(result) if out.len() == len => Some(result),
+            _ => {
+                self.pending = snapshot.pending;
+                self.multi = snapshot.multi;
+                self.open = snapshot.open;
+                self.generated = snapshot.generated;
+                self.captured = snapshot.captured;
+                self.scopes = snapshot.scopes;
+                out.truncate(len);
+                None
+            }
+        }
+    }
+
+    /// Returns the expression held by a register, using up the value computed into it.
+    fn read(&mut self, out: &mut Block, register: usize, pc: usize) -> Expression {
+        if self
+            .multi
+            .as_ref()
+            .is_some_and(|multi| (multi.register..multi.register + multi.count).contains(&register))
+        {
+            self.flush_multi(out);
+        }
+
+        match self.pending[register].take() {
+            Some(Pending::Value {
+                value,
+                shared,
+                order,
+            }) => {
+                if self.open == Some(register) {
+                    self.open = None;
+                }
+                // Effects happen in the order of the code
+                if value.has_effects() || shared {
+                    self.settle_before(out, order);
+                }
+                if !shared {
+                    return value;
+                }
+
+                self.materialize(out, register, value);
+                Expression::Name(self.register_name(register, pc))
+            }
+            Some(Pending::Method(object, key)) => Expression::index(object, key),
+            Some(Pending::Object) | None => Expression::Name(self.register_name(register, pc)),
+        }
+    }
+
+    fn take_value(&mut self, register: usize) -> Option<Expression> {
+        match self.pending[register].take() {
+            Some(Pending::Value { value, .. }) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn set_value(&mut self, register: usize, value: Expression, shared: bool) {
+        self.order += 1;
+        self.pending[register] = Some(Pending::Value {
+            value,
+            shared,
+            order: self.order,
+        });
+    }
+
+    /// Returns the fields of the table constructor a register holds.
+    fn table_mut(&mut self, register: usize) -> Option<&mut Vec<Field>> {
+        match &mut self.pending[register] {
+            Some(Pending::Value {
+                value: Expression::Table(fields),
+                ..
+            }) => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// Returns a `C` operand, which the `k` bit marks as a constant index.
+    fn register_or_constant(&mut self, out: &mut Block, c: u8, k: u8, pc: usize) -> Expression {
+        match k {
+            0 => self.read(out, c as usize, pc),
+            _ => self.constant(c as usize),
+        }
+    }
+
+    /// Store a value into a register, as a statement when a local lives there.
+    fn assign(&mut self, out: &mut Block, register: usize, value: Expression, pc: usize) {
+        if let Some(name) = self.name(register, pc) {
+            if !self.redeclarable(register, pc) {
+                self.emit(out, assignment(Expression::Name(name), value));
+                return;
+            }
+            self.generated[register] = None;
+        }
+
+        let covered = self.multi.as_ref().is_some_and(|multi| {
+            (multi.register..multi.register + multi.count).contains(&register)
+        });
+        if covered {
+            self.flush_multi(out);
+            return self.assign(out, register, value, pc);
+        }
+        if self.materialize_pending(out, register) {
+            return self.assign(out, register, value, pc);
+        }
+
+        if self.capture == Some(register) {
+            return self.set_value(register, value, false);
+        }
+        // Values never used are kept in a local, and so are those used more than once once read
+        let table = matches!(value, Expression::Table(_));
+        match self.reads_after(register, pc, table) {
+            0 => self.materialize(out, register, value),
+            1 => self.set_value(register, value, false),
+            _ => self.set_value(register, value, true),
+        }
+    }
+
+    /// Returns whether a register can be declared as a new local when written, which a stripped
+    /// chunk reusing the register of a local in the block declaring it does.
+    fn redeclarable(&self, register: usize, pc: usize) -> bool {
+        self.local_name(register, pc).is_none()
+            && !self.captured[register]
+            && self
+                .scopes
+                .last()
+                .is_some_and(|scope| scope.contains(&register))
+    }
+
+    fn set_multi(&mut self, out: &mut Block, register: usize, count: usize, value: Expression) {
+        self.flush_multi(out);
+        for register in register..register + count {
+            self.pending[register] = None;
+        }
+        self.multi = Some(Multi {
+            register,
+            count,
+            value,
+        });
+    }
+
+    /// Push a statement, declaring locals for the values still pending so it can't change them.
+    fn emit(&mut self, out: &mut Block, statement: Statement) {
+        self.flush(out);
+        out.push(statement);
+    }
+
+    fn flush(&mut self, out: &mut Block) {
+        self.flush_multi(out);
+        self.settle(out, |_| true);
+        self.open = None;
+    }
+
+    /// Declare locals for the pending values `keep` accepts, in the order they were computed,
+    /// dropping the others.
+    fn settle(&mut self, out: &mut Block, keep: impl Fn(&Expression) -> bool) {
+        for register in self.computed(usize::MAX) {
+            if let Some(value) = self.take_value(register) {
+                if keep(&value) {
+                    self.materialize(out, register, value);
+                }
+            }
+        }
+    }
+
+    /// Declare locals for the pending values with effects computed before the one at `order`.
+    fn settle_before(&mut self, out: &mut Block, order: usize) {
+        for register in self.computed(order) {
+            let effects = matches!(
+                &self.pending[register],
+                Some(Pending::Value { value, .. }) if value.has_effects()
+            );
+            if effects {
+                if let Some(value) = self.take_value(register) {
+                    self.materialize(out, register, value);
+                }
+            }
+        }
+    }
+
+    /// Returns the registers holding values computed before the one at `order`, in the order
+    /// they were computed.
+    fn computed(&self, order: usize) -> Vec<usize> {
+        let mut registers: Vec<(usize, usize)> = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter_map(|(register, pending)| match pending {
+                Some(Pending::Value {
+                    order: computed, ..
+                }) if *computed < order => Some((*computed, register)),
+                _ => None,
+            })
+            .collect();
+        registers.sort_unstable();
+        registers
+            .into_iter()
+            .map(|(_, register)| register)
+            .collect()
+    }
+
+    fn flush_multi(&mut self, out: &mut Block) {
+        let Some(multi) = self.multi.take() else {
+            return;
+        };
+
+        let mut names = Vec::new();
+        for register in multi.register..multi.register + multi.count {
+            let name = generated_name("r", register, self.depth);
+            self.name_register(register, name.clone());
+            names.push(LocalName {
+                name,
+                attribute: None,
+            });
+        }
+        out.push(Statement::Local {
+            names,
+            values: vec![multi.value],
+        });
+    }
+
+    /// Declare a local for the value pending in a register, after those with effects computed
+    /// before it. Returns whether one was pending.
+    fn materialize_pending(&mut self, out: &mut Block, register: usize) -> bool {
+        let Some(Pending::Value { order, .. }) = self.pending[register] else {
+            return false;
+        };
+        self.settle_before(out, order);
+        if let Some(value) = self.take_value(register) {
+            self.materialize(out, register, value);
+        }
+        true
+    }
+
+    /// Declare a local with a generated name for a value.
+    fn materialize(&mut self, out: &mut Block, register: usize, value: Expression) {
+        self.declare(out, register, vec![value]);
+    }
+
+    /// Declare a local with a generated name for a register.
+    fn declare(&mut self, out: &mut Block, register: usize, values: Vec<Expression>) {
+        let name = generated_name("r", register, self.depth);
+        self.name_register(register, name.clone());
+        out.push(Statement::Local {
+            names: vec![LocalName {
+                name,
+                attribute: None,
+            }],
+            values,
+        });
+    }
+
+    fn name_register(&mut self, register: usize, name: String) {
+        self.generated[register] = Some(name);
+        self.captured[register] = false;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(register);
+        }
+    }
+
+    /// Returns the name of the local living in a register at `pc`.
+    fn name(&self, register: usize, pc: usize) -> Option<String> {
+        if self.capture == Some(register) {
+            return None;
+        }
+
+        self.local_name(register, pc)
+            .or_else(|| self.generated[register].clone())
+    }
+
+    fn register_name(&self, register: usize, pc: usize) -> String {
+        self.name(register, pc)
+            .unwrap_or_else(|| generated_name("r", register, self.depth))
+    }
+
+    fn named(&self, register: usize, pc: usize) -> bool {
+        self.name(register, pc).is_some()
+    }
+
+    fn local_name(&self, register: usize, pc: usize) -> Option<String> {
+        let index = self.local_index(register, pc)?;
+        let local = &self.prototype.debug_info.local_variables.data[index];
+        Some(String::from_utf8_lossy(local.name.as_bytes()).into_owned())
+    }
+
+    /// Returns the index of the debug local living in a register at `pc`.
+    fn local_index(&self, register: usize, pc: usize) -> Option<usize> {
+        let locals = self.prototype.debug_info.local_variables.data.iter();
+        locals
+            .enumerate()
+            .filter(|(_, local)| is_active(local.start_pc.value, local.end_pc.value, pc))
+            .nth(register)
+            .map(|(index, _)| index)
+    }
+
+    /// Returns whether a debug local starts at `pc`.
+    fn starts_local(&self, pc: usize) -> bool {
+        let locals = &self.prototype.debug_info.local_variables.data;
+        locals.iter().any(|local| local.start_pc.value == pc)
+    }
+
+    /// Returns whether the debug local starting at `pc` lives in `register`.
+    fn declares(&self, register: usize, pc: usize) -> bool {
+        let locals = &self.prototype.debug_info.local_variables.data;
+        locals
+            .iter()
+            .filter(|local| is_active(local.start_pc.value, local.end_pc.value, pc))
+            .nth(register)
+            .is_some_and(|local| local.start_pc.value == pc)
+    }
+
+    /// Count how often the value written into `register` at `pc` is read before being
+    /// overwritten, in code order, stopping at two.
+    ///
+    /// Filling a `table` built by a constructor doesn't count as reading it.
+    fn reads_after(&self, register: usize, pc: usize, table: bool) -> usize {
+        // Temporary values don't outlive the block computing them
+        let end = self.ends.last().map_or(self.code.len(), |&end| end);
+        let mut reads = 0;
+        let mut top = None;
+        let mut pc = next_pc(self.prototype, pc);
+        while pc < end && reads < 2 {
+            // The value becomes a local
+            if self.declares(register, pc) {
+                return 1;
+            }
+
+            let (read, written) = self.uses(pc, register, table, top);
+            reads += usize::from(read);
+            if written {
+                break;
+            }
+            top = match self.code[pc] {
+                Instruction::iABC(Opcode::OP_CALL | Opcode::OP_VARARG, a, _, 0, _) => {
+                    Some(a as usize)
+                }
+                _ => top,
+            };
+            // The `RETURN` after a tail call is never reached
+            if let Instruction::iABC(Opcode::OP_TAILCALL, ..) = self.code[pc] {
+                if let Some(Instruction::iABC(Opcode::OP_RETURN, ..)) = self.code.get(pc + 1) {
+                    pc += 1;
+                }
+            }
+            pc = next_pc(self.prototype, pc);
+        }
+
+        reads
+    }
+
+    /// Returns whether the instruction at `pc` reads and writes `register`, `top` being the
+    /// register a call or `...` last left a variable number of values from.
+    fn uses(&self, pc: usize, register: usize, table: bool, top: Option<usize>) -> (bool, bool) {
+        let rk = |operand: u8, k: u8| k == 0 && operand as usize == register;
+        let range = |first: usize, count: usize| (first..first + count).contains(&register);
+        let open = |first: usize, count: u8| match (count, top) {
+            (0, Some(top)) => (first..=top).contains(&register),
+            (0, None) => false,
+            (count, _) => range(first, count as usize - 1),
+        };
+
+        match self.code[pc] {
+            Instruction::iABC(opcode, a, b, c, k) => {
+                let a = a as usize;
+                let b_register = b as usize == register;
+                let c_register = c as usize == register;
+                match opcode {
+                    Opcode::OP_MOVE
+                    | Opcode::OP_GETTABLE
+                    | Opcode::OP_GETI
+                    | Opcode::OP_GETFIELD
+                    | Opcode::OP_ADDI
+                    | Opcode::OP_ADDK
+                    | Opcode::OP_SUBK
+                    | Opcode::OP_MULK
+                    | Opcode::OP_MODK
+                    | Opcode::OP_POWK
+                    | Opcode::OP_DIVK
+                    | Opcode::OP_IDIVK
+                    | Opcode::OP_BANDK
+                    | Opcode::OP_BORK
+                    | Opcode::OP_BXORK
+                    | Opcode::OP_SHRI
+                    | Opcode::OP_SHLI
+                    | Opcode::OP_UNM
+                    | Opcode::OP_BNOT
+                    | Opcode::OP_NOT
+                    | Opcode::OP_LEN => {
+                        let read =
+                            b_register || (matches!(opcode, Opcode::OP_GETTABLE) && c_register);
+                        (read, a == register)
+                    }
+                    Opcode::OP_LOADFALSE
+                    | Opcode::OP_LFALSESKIP
+                    | Opcode::OP_LOADTRUE
+                    | Opcode::OP_GETUPVAL
+                    | Opcode::OP_GETTABUP
+                    | Opcode::OP_NEWTABLE => (false, a == register),
+                    Opcode::OP_LOADNIL => (false, (a..=a + b as usize).contains(&register)),
+                    Opcode::OP_SETUPVAL | Opcode::OP_TBC | Opcode::OP_TEST => {
+                        (a == register, false)
+                    }
+                    Opcode::OP_SETTABUP => (rk(c, k), false),
+                    Opcode::OP_SETTABLE | Opcode::OP_SETI | Opcode::OP_SETFIELD => {
+                        let key = matches!(opcode, Opcode::OP_SETTABLE) && b_register;
+                        ((!table && a == register) || key || rk(c, k), false)
+                    }
+                    Opcode::OP_SELF => (b_register || rk(c, k), range(a, 2)),
+                    Opcode::OP_ADD
+                    | Opcode::OP_SUB
+                    | Opcode::OP_MUL
+                    | Opcode::OP_MOD
+                    | Opcode::OP_POW
+                    | Opcode::OP_DIV
+                    | Opcode::OP_IDIV
+                    | Opcode::OP_BAND
+                    | Opcode::OP_BOR
+                    | Opcode::OP_BXOR
+                    | Opcode::OP_SHL
+                    | Opcode::OP_SHR => (b_register || c_register, a == register),
+                    Opcode::OP_CONCAT => (range(a, b as usize), a == register),
+                    Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE => {
+                        (a == register || b_register, false)
+                    }
+                    Opcode::OP_EQK
+                    | Opcode::OP_EQI
+                    | Opcode::OP_LTI
+                    | Opcode::OP_LEI
+                    | Opcode::OP_GTI
+                    | Opcode::OP_GEI => (a == register, false),
+                    Opcode::OP_TESTSET => (b_register, a == register),
+                    Opcode::OP_CALL => {
+                        let written = match c {
+                            0 => a == register,
+                            c => range(a, c as usize - 1),
+                        };
+                        (a == register || open(a + 1, b), written)
+                    }
+                    Opcode::OP_TAILCALL => (a == register || open(a + 1, b), false),
+                    Opcode::OP_RETURN => (open(a, b), false),
+                    Opcode::OP_RETURN1 => (a == register, false),
+                    Opcode::OP_TFORCALL => (range(a, 4), range(a + 4, c as usize)),
+                    Opcode::OP_SETLIST => {
+                        let values = match b {
+                            0 => open(a + 1, 0),
+                            b => range(a + 1, b as usize),
+                        };
+                        ((!table && a == register) || values, false)
+                    }
+                    Opcode::OP_VARARG => (false, c == 0 && a <= register || open(a, c)),
+                    _ => (false, false),
+                }
+            }
+            Instruction::iABx(opcode, a, bx) => {
+                let a = a as usize == register;
+                match opcode {
+                    Opcode::OP_CLOSURE => {
+                        let child = self.prototype.prototypes.data.get(bx as usize);
+                        let read = child.is_some_and(|child| {
+                            child.upvalues.data.iter().any(|upvalue| {
+                                upvalue.in_stack != 0 && upvalue.index as usize == register
+                            })
+                        });
+                        (read, a)
+                    }
+                    _ => (false, a),
+                }
+            }
+            Instruction::iAsBx(opcode, a, _) => {
+                let a = a as usize;
+                match opcode {
+                    // The loop keeps its own copies of the values
+                    Opcode::OP_FORPREP | Opcode::OP_FORLOOP => (range(a, 3), range(a, 4)),
+                    Opcode::OP_TFORPREP => (range(a, 4), range(a, 4)),
+                    Opcode::OP_TFORLOOP => (a + 4 == register, a + 2 == register),
+                    _ => (false, a == register),
+                }
+            }
+            Instruction::iAx(..) | Instruction::isJ(..) => (false, false),
+        }
+    }
+
+    /// Returns whether the instruction at `pc` only computes a value into a temporary register.
+    fn is_expression(&self, pc: usize) -> bool {
+        self.expression_register(pc)
+            .is_some_and(|register| !self.named(register, pc))
+    }
+
+    /// Returns the register the instruction at `pc` computes a value into, if that is all it does.
+    fn expression_register(&self, pc: usize) -> Option<usize> {
+        let written = match self.code[pc] {
+            Instruction::iABC(opcode, a, _, c, _) => match opcode {
+                Opcode::OP_MOVE
+                | Opcode::OP_LOADFALSE
+                | Opcode::OP_LOADTRUE
+                | Opcode::OP_LOADNIL
+                | Opcode::OP_GETUPVAL
+                | Opcode::OP_GETTABUP
+                | Opcode::OP_GETTABLE
+                | Opcode::OP_GETI
+                | Opcode::OP_GETFIELD
+                | Opcode::OP_SETTABLE
+                | Opcode::OP_SETI
+                | Opcode::OP_SETFIELD
+                | Opcode::OP_NEWTABLE
+                | Opcode::OP_SELF
+                | Opcode::OP_ADDI
+                | Opcode::OP_ADDK
+                | Opcode::OP_SUBK
+                | Opcode::OP_MULK
+                | Opcode::OP_MODK
+                | Opcode::OP_POWK
+                | Opcode::OP_DIVK
+                | Opcode::OP_IDIVK
+                | Opcode::OP_BANDK
+                | Opcode::OP_BORK
+                | Opcode::OP_BXORK
+                | Opcode::OP_SHRI
+                | Opcode::OP_SHLI
+                | Opcode::OP_ADD
+                | Opcode::OP_SUB
+                | Opcode::OP_MUL
+                | Opcode::OP_MOD
+                | Opcode::OP_POW
+                | Opcode::OP_DIV
+                | Opcode::OP_IDIV
+                | Opcode::OP_BAND
+                | Opcode::OP_BOR
+                | Opcode::OP_BXOR
+                | Opcode::OP_SHL
+                | Opcode::OP_SHR
+                | Opcode::OP_UNM
+                | Opcode::OP_BNOT
+                | Opcode::OP_NOT
+                | Opcode::OP_LEN
+                | Opcode::OP_CONCAT
+                | Opcode::OP_SETLIST
+                | Opcode::OP_VARARG => a,
+                // The metamethod fallback belongs to the arithmetic before it
+                Opcode::OP_MMBIN | Opcode::OP_MMBINI | Opcode::OP_MMBINK if pc > 0 => {
+                    return self.expression_register(pc - 1)
+                }
+                // Calls can be statements
+                Opcode::OP_CALL if c >= 2 => a,
+                _ => return None,
+            },
+            Instruction::iABx(Opcode::OP_LOADK | Opcode::OP_LOADKX, a, _)
+            | Instruction::iAsBx(Opcode::OP_LOADI | Opcode::OP_LOADF, a, _) => a,
+            _ => return None,
+        };
+
+        Some(written as usize)
+    }
+
+    fn is_test(&self, pc: usize) -> bool {
+        matches!(
+            self.code.get(pc),
+            Some(Instruction::iABC(
+                Opcode::OP_EQ
+                    | Opcode::OP_LT
+                    | Opcode::OP_LE
+                    | Opcode::OP_EQK
+                    | Opcode::OP_EQI
+                    | Opcode::OP_LTI
+                    | Opcode::OP_LEI
+                    | Opcode::OP_GTI
+                    | Opcode::OP_GEI
+                    | Opcode::OP_TEST
+                    | Opcode::OP_TESTSET,
+                ..
+            ))
+        )
+    }
+
+    /// Returns where the `JMP` at `pc` goes, if it is one and its target is in the code.
+    fn jump_target(&self, pc: usize) -> Option<usize> {
+        match self.code.get(pc)? {
+            Instruction::isJ(Opcode::OP_JMP, sj) => {
+                let target = pc as i64 + 1 + *sj as i64;
+                usize::try_from(target)
+                    .ok()
+                    .filter(|&target| target <= self.code.len())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the pc control ends up at from `pc`, following the jumps there.
+    fn final_target(&self, pc: usize) -> usize {
+        let mut target = pc;
+        // Jumps going round in circles stop somewhere
+        for _ in 0..self.code.len() {
+            match self.jump_target(target) {
+                Some(next) if next != target => target = next,
+                _ => break,
+            }
+        }
+        target
+    }
+
+    /// Returns the pc in `start..=end` a jump to `target` lands on, which is the last jump
+    /// there leading to `target` when `target` is outside.
+    fn landing(&self, target: usize, start: usize, end: usize) -> Option<usize> {
+        if (start..=end).contains(&target) {
+            return Some(target);
+        }
+
+        let target = self.final_target(target);
+        (start..=end)
+            .rev()
+            .find(|&pc| self.jump_target(pc).is_some() && self.final_target(pc) == target)
+    }
+
+    fn constant(&self, index: usize) -> Expression {
+        match self.prototype.constants.data.get(index) {
+            Some(Constant::Boolean(value)) => Expression::Boolean(*value),
+            Some(Constant::Float(value)) => Expression::Float(*value),
+            Some(Constant::Integer(value)) => Expression::Integer(*value),
+            Some(Constant::String(value)) => Expression::String(value.as_bytes().to_vec()),
+            Some(Constant::Nil) | None => Expression::Nil,
+        }
+    }
+
+    fn upvalue(&self, index: usize) -> Expression {
+        let name = self.upvalues.get(index).cloned();
+        Expression::Name(name.unwrap_or_else(|| generated_name("u", index, self.depth)))
+    }
+
+    /// Returns the field of the table an upvalue holds, a global when it is `_ENV`.
+    fn upvalue_field(&self, upvalue: usize, key: usize) -> Expression {
+        let table = self.upvalue(upvalue);
+        match (&table, self.constant(key)) {
+            (Expression::Name(env), Expression::String(name))
+                if env == "_ENV" && is_identifier(&name) =>
+            {
+                Expression::Name(String::from_utf8_lossy(&name).into_owned())
+            }
+            (_, key) => Expression::index(table, key),
+        }
+    }
+}
+
+/// Returns the pc of the instruction after the one at `pc`, past the `EXTRAARG` some keep after
+/// them.
+fn next_pc(prototype: &Prototype, pc: usize) -> usize {
+    match prototype.instructions.data.get(pc + 1) {
+        Some(Instruction::iAx(Opcode::OP_EXTRAARG, _)) => pc + 2,
+        _ => pc + 1,
+    }
+}
+
+fn is_active(start_pc: usize, end_pc: usize, pc: usize) -> bool {
+    start_pc <= pc && pc < end_pc
+}
+
+fn opcode(instruction: Instruction) -> Opcode {
+    match instruction {
+        Instruction::iABC(opcode, ..)
+        | Instruction::iABx(opcode, ..)
+        | Instruction::iAsBx(opcode, ..)
+        | Instruction::iAx(opcode, ..)
+        | Instruction::isJ(opcode, ..) => opcode,
+    }
+}
+
+fn binary_operator(opcode: Opcode) -> BinaryOperator {
+    match opcode {
+        Opcode::OP_ADDI | Opcode::OP_ADDK | Opcode::OP_ADD => BinaryOperator::Add,
+        Opcode::OP_SUBK | Opcode::OP_SUB => BinaryOperator::Sub,
+        Opcode::OP_MULK | Opcode::OP_MUL => BinaryOperator::Mul,
+        Opcode::OP_MODK | Opcode::OP_MOD => BinaryOperator::Mod,
+        Opcode::OP_POWK | Opcode::OP_POW => BinaryOperator::Pow,
+        Opcode::OP_DIVK | Opcode::OP_DIV => BinaryOperator::Div,
+        Opcode::OP_IDIVK | Opcode::OP_IDIV => BinaryOperator::IDiv,
+        Opcode::OP_BANDK | Opcode::OP_BAND => BinaryOperator::BitAnd,
+        Opcode::OP_BORK | Opcode::OP_BOR => BinaryOperator::BitOr,
+        Opcode::OP_BXORK | Opcode::OP_BXOR => BinaryOperator::BitXor,
+        Opcode::OP_SHLI | Opcode::OP_SHL => BinaryOperator::Shl,
+        _ => BinaryOperator::Shr,
+    }
+}
+
+fn comparison_operator(opcode: Opcode) -> BinaryOperator {
+    match opcode {
+        Opcode::OP_EQ | Opcode::OP_EQI => BinaryOperator::Eq,
+        Opcode::OP_LT | Opcode::OP_LTI => BinaryOperator::Lt,
+        Opcode::OP_LE | Opcode::OP_LEI => BinaryOperator::Le,
+        Opcode::OP_GTI => BinaryOperator::Gt,
+        _ => BinaryOperator::Ge,
+    }
+}
+
+fn assignment(target: Expression, value: Expression) -> Statement {
+    Statement::Assign {
+        targets: vec![target],
+        values: vec![value],
+    }
+}
+
+/// Returns the name of the label placed at `pc`.
+fn label_name(pc: usize) -> String {
+    format!("label_{}", pc + 1)
+}
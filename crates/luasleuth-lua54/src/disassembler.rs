@@ -1,31 +1,43 @@
 use crate::types::{Bytecode, Header, Prototype};
-use luasleuth_common::{disassembler::Disassemble, CommonCtx};
+use luasleuth_common::{disassembler::Disassemble, Error, ErrorContext, Limits};
 use scroll::Pread;
 
 pub struct Disassembler<'a> {
     bytes: &'a [u8],
+    limits: Limits,
 }
 
 impl<'a> Disassembler<'a> {
     pub fn new(bytes: &'a [u8]) -> Self {
-        Self { bytes }
+        Self {
+            bytes,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Set the limits the chunk is read with, instead of the default ones.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
     }
 }
 
 impl<'a> Disassemble<Bytecode<'a>> for Disassembler<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn disassemble(self) -> Result<Bytecode<'a>, Self::Error> {
         let offset = &mut 0;
 
-        let header: Header = self.bytes.gread_with(offset, scroll::LE)?;
-        let size_of_upvalues: u8 = self.bytes.gread_with(offset, scroll::LE)?;
-        let ctx = CommonCtx {
-            size_of_size_t: 0,
-            lua_version: header.version,
-            endianness: scroll::LE,
-        };
-        let prototype: Prototype = self.bytes.gread_with(offset, ctx)?;
+        let header: Header = self.bytes.gread(offset).within(*offset, "header")?;
+        let size_of_upvalues: u8 = self
+            .bytes
+            .gread_with(offset, header.endianness)
+            .within(*offset, "size_of_upvalues")?;
+        let ctx = header.context(self.limits);
+        let prototype: Prototype = self.bytes.gread_with(offset, ctx).at(*offset)?;
+        self.limits
+            .check_elements(prototype.element_count())
+            .at(*offset)?;
 
         Ok(Bytecode {
             header,
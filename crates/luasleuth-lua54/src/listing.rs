@@ -0,0 +1,384 @@
+//! Listing of a chunk in the layout of `luac -l -l`.
+//!
+//! Functions are not identified by their address like `luac` does, closures refer to the
+//! location of their function instead.
+
+use std::fmt::{self, Write};
+
+use luasleuth_common::{
+    listing::{format_float, mnemonic, write_instruction_start, FunctionHeader, Quoted},
+    types::Packable,
+};
+
+use crate::types::{
+    constants::Constant,
+    instructions::{constants::*, Instruction, Opcode},
+    Bytecode, Prototype,
+};
+
+/// Offset of the signed `sB` and `sC` operands
+const OFFSET_SC: i32 = (1 << (SIZE_C - 1)) - 1;
+
+/// Names of the metamethods, in the order of their `TM_*` index
+const EVENT_NAMES: [&str; 25] = [
+    "__index",
+    "__newindex",
+    "__gc",
+    "__mode",
+    "__len",
+    "__eq",
+    "__add",
+    "__sub",
+    "__mul",
+    "__mod",
+    "__pow",
+    "__div",
+    "__idiv",
+    "__band",
+    "__bor",
+    "__bxor",
+    "__shl",
+    "__shr",
+    "__unm",
+    "__bnot",
+    "__lt",
+    "__le",
+    "__concat",
+    "__call",
+    "__close",
+];
+
+/// A chunk rendered like `luac -l -l` does.
+pub struct Listing<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Listing<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Listing<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prototype = &self.bytecode.prototype;
+        write_function(f, prototype, source(prototype, None))
+    }
+}
+
+/// Returns the source of a function, which children without one share with their parent.
+pub(crate) fn source<'p>(prototype: &'p Prototype, parent: Option<&'p [u8]>) -> Option<&'p [u8]> {
+    prototype
+        .source
+        .as_ref()
+        .map(|source| source.as_bytes())
+        .or(parent)
+}
+
+pub(crate) fn header<'a>(prototype: &Prototype, source: Option<&'a [u8]>) -> FunctionHeader<'a> {
+    FunctionHeader {
+        source,
+        line_defined: prototype.line_defined.value as i64,
+        last_line_defined: prototype.last_line_defined.value as i64,
+        instructions: prototype.instructions.size,
+        code_size: None,
+        parameters: prototype.number_of_parameters as usize,
+        is_vararg: prototype.is_vararg != 0,
+        slots: prototype.max_stack_size as usize,
+        upvalues: prototype.upvalues.size,
+        locals: prototype.debug_info.local_variables.size,
+        constants: prototype.constants.size,
+        functions: prototype.prototypes.size,
+    }
+}
+
+fn write_function(f: &mut impl Write, prototype: &Prototype, source: Option<&[u8]>) -> fmt::Result {
+    write!(f, "{}", header(prototype, source))?;
+    write_code(f, prototype, source)?;
+    write_debug(f, prototype)?;
+
+    for child in &prototype.prototypes.data {
+        write_function(f, child, self::source(child, source))?;
+    }
+
+    Ok(())
+}
+
+fn write_code(f: &mut impl Write, prototype: &Prototype, source: Option<&[u8]>) -> fmt::Result {
+    for pc in 0..prototype.instructions.data.len() {
+        write_instruction(f, prototype, source, pc)?;
+        f.write_str("\n")?;
+    }
+
+    Ok(())
+}
+
+/// Write the line of the instruction at `pc`, without its line break.
+pub(crate) fn write_instruction(
+    f: &mut impl Write,
+    prototype: &Prototype,
+    source: Option<&[u8]>,
+    pc: usize,
+) -> fmt::Result {
+    let code = &prototype.instructions.data;
+    let line_defined = prototype.line_defined.value;
+    let instruction = code[pc];
+    let opcode = match instruction {
+        Instruction::iABC(opcode, ..)
+        | Instruction::iABx(opcode, ..)
+        | Instruction::iAsBx(opcode, ..)
+        | Instruction::iAx(opcode, ..)
+        | Instruction::isJ(opcode, ..) => opcode,
+    };
+    let line = prototype.debug_info.line_for_pc(line_defined, pc);
+    write_instruction_start(f, pc, line, &mnemonic(opcode))?;
+
+    // The `Ax` operand of the `EXTRAARG` that follows an instruction
+    let extra_arg = || {
+        code.get(pc + 1)
+            .map_or(0, |&next| Instruction::encode(next) >> POS_AX)
+    };
+    let constant = |index: u32| ConstantAt(prototype, index as usize);
+    let upvalue = |index: u32| UpvalueName(prototype, index as usize);
+
+    match instruction {
+        Instruction::iABC(opcode, a, b, c, k) => {
+            let isk = if k != 0 { "k" } else { "" };
+            let sb = b as i32 - OFFSET_SC;
+            let sc = c as i32 - OFFSET_SC;
+
+            match opcode {
+                Opcode::OP_MOVE
+                | Opcode::OP_UNM
+                | Opcode::OP_BNOT
+                | Opcode::OP_NOT
+                | Opcode::OP_LEN
+                | Opcode::OP_CONCAT => write!(f, "{} {}", a, b)?,
+                Opcode::OP_LOADFALSE
+                | Opcode::OP_LFALSESKIP
+                | Opcode::OP_LOADTRUE
+                | Opcode::OP_CLOSE
+                | Opcode::OP_TBC
+                | Opcode::OP_RETURN1
+                | Opcode::OP_VARARGPREP => write!(f, "{}", a)?,
+                Opcode::OP_LOADNIL => write!(f, "{} {}\t; {} out", a, b, b as u32 + 1)?,
+                Opcode::OP_GETUPVAL | Opcode::OP_SETUPVAL => {
+                    write!(f, "{} {}\t; {}", a, b, upvalue(b.into()))?
+                }
+                Opcode::OP_GETTABUP => write!(
+                    f,
+                    "{} {} {}\t; {} {}",
+                    a,
+                    b,
+                    c,
+                    upvalue(b.into()),
+                    constant(c.into())
+                )?,
+                Opcode::OP_GETTABLE | Opcode::OP_GETI => write!(f, "{} {} {}", a, b, c)?,
+                Opcode::OP_GETFIELD => write!(f, "{} {} {}\t; {}", a, b, c, constant(c.into()))?,
+                Opcode::OP_SETTABUP => {
+                    write!(f, "{} {} {}{}", a, b, c, isk)?;
+                    write!(f, "\t; {} {}", upvalue(a.into()), constant(b.into()))?;
+                    if k != 0 {
+                        write!(f, " {}", constant(c.into()))?;
+                    }
+                }
+                Opcode::OP_SETTABLE | Opcode::OP_SETI | Opcode::OP_SELF => {
+                    write!(f, "{} {} {}{}", a, b, c, isk)?;
+                    if k != 0 {
+                        write!(f, "\t; {}", constant(c.into()))?;
+                    }
+                }
+                Opcode::OP_SETFIELD => {
+                    write!(f, "{} {} {}{}\t; {}", a, b, c, isk, constant(b.into()))?;
+                    if k != 0 {
+                        write!(f, " {}", constant(c.into()))?;
+                    }
+                }
+                Opcode::OP_NEWTABLE => write!(
+                    f,
+                    "{} {} {}\t; {}",
+                    a,
+                    b,
+                    c,
+                    c as u64 + extra_arg() as u64 * (u8::MAX as u64 + 1)
+                )?,
+                Opcode::OP_ADDI | Opcode::OP_SHRI | Opcode::OP_SHLI => {
+                    write!(f, "{} {} {}", a, b, sc)?
+                }
+                Opcode::OP_ADDK
+                | Opcode::OP_SUBK
+                | Opcode::OP_MULK
+                | Opcode::OP_MODK
+                | Opcode::OP_POWK
+                | Opcode::OP_DIVK
+                | Opcode::OP_IDIVK
+                | Opcode::OP_BANDK
+                | Opcode::OP_BORK
+                | Opcode::OP_BXORK => write!(f, "{} {} {}\t; {}", a, b, c, constant(c.into()))?,
+                Opcode::OP_MMBIN => {
+                    write!(f, "{} {} {}\t; {}", a, b, c, event_name(c))?;
+                }
+                Opcode::OP_MMBINI => {
+                    write!(f, "{} {} {} {}\t; {}", a, sb, c, k, event_name(c))?;
+                    if k != 0 {
+                        f.write_str(" flip")?;
+                    }
+                }
+                Opcode::OP_MMBINK => {
+                    write!(f, "{} {} {} {}", a, b, c, k)?;
+                    write!(f, "\t; {} {}", event_name(c), constant(b.into()))?;
+                    if k != 0 {
+                        f.write_str(" flip")?;
+                    }
+                }
+                Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE | Opcode::OP_TESTSET => {
+                    write!(f, "{} {} {}", a, b, k)?
+                }
+                Opcode::OP_EQK => write!(f, "{} {} {}\t; {}", a, b, k, constant(b.into()))?,
+                Opcode::OP_EQI
+                | Opcode::OP_LTI
+                | Opcode::OP_LEI
+                | Opcode::OP_GTI
+                | Opcode::OP_GEI => write!(f, "{} {} {}", a, sb, k)?,
+                Opcode::OP_TEST => write!(f, "{} {}", a, k)?,
+                Opcode::OP_CALL => {
+                    write!(f, "{} {} {}\t; ", a, b, c)?;
+                    write!(f, "{} in {} out", Count(b), Count(c))?;
+                }
+                Opcode::OP_TAILCALL => {
+                    write!(f, "{} {} {}{}\t; {} in", a, b, c, isk, b as i32 - 1)?
+                }
+                Opcode::OP_RETURN => write!(f, "{} {} {}{}\t; {} out", a, b, c, isk, Count(b))?,
+                Opcode::OP_RETURN0 => {}
+                Opcode::OP_TFORCALL => write!(f, "{} {}", a, c)?,
+                Opcode::OP_SETLIST => {
+                    write!(f, "{} {} {}", a, b, c)?;
+                    if k != 0 {
+                        let count = c as u64 + extra_arg() as u64 * (u8::MAX as u64 + 1);
+                        write!(f, "\t; {}", count)?;
+                    }
+                }
+                Opcode::OP_VARARG => write!(f, "{} {}\t; {} out", a, c, Count(c))?,
+                // Arithmetic between registers
+                _ => write!(f, "{} {} {}", a, b, c)?,
+            }
+        }
+        Instruction::iABx(opcode, a, bx) => match opcode {
+            Opcode::OP_LOADK => write!(f, "{} {}\t; {}", a, bx, constant(bx))?,
+            Opcode::OP_LOADKX => write!(f, "{}\t; {}", a, constant(extra_arg()))?,
+            Opcode::OP_CLOSURE => {
+                write!(f, "{} {}", a, bx)?;
+                if let Some(child) = prototype.prototypes.data.get(bx as usize) {
+                    let source = self::source(child, source);
+                    write!(f, "\t; {}", header(child, source).location())?;
+                }
+            }
+            _ => write!(f, "{} {}", a, bx)?,
+        },
+        Instruction::iAsBx(opcode, a, sbx) => {
+            // Loop jumps are unsigned, luac lists them as such
+            let bx = sbx as i64 + OFFSET_SBX as i64;
+            let pc = pc as i64;
+            match opcode {
+                Opcode::OP_FORLOOP | Opcode::OP_TFORLOOP => {
+                    write!(f, "{} {}\t; to {}", a, bx, pc - bx + 2)?
+                }
+                Opcode::OP_FORPREP => write!(f, "{} {}\t; exit to {}", a, bx, pc + bx + 3)?,
+                Opcode::OP_TFORPREP => write!(f, "{} {}\t; to {}", a, bx, pc + bx + 2)?,
+                _ => write!(f, "{} {}", a, sbx)?,
+            }
+        }
+        Instruction::iAx(_, ax) => write!(f, "{}", ax)?,
+        Instruction::isJ(_, sj) => write!(f, "{}\t; to {}", sj, sj as i64 + pc as i64 + 2)?,
+    }
+
+    Ok(())
+}
+
+fn write_debug(f: &mut impl Write, prototype: &Prototype) -> fmt::Result {
+    let constants = &prototype.constants.data;
+    writeln!(f, "constants ({}):", constants.len())?;
+    for (index, value) in constants.iter().enumerate() {
+        let kind = match value {
+            Constant::Nil => "N",
+            Constant::Boolean(_) => "B",
+            Constant::Float(_) => "F",
+            Constant::Integer(_) => "I",
+            Constant::String(_) => "S",
+        };
+        writeln!(f, "\t{}\t{}\t{}", index, kind, ConstantAt(prototype, index))?;
+    }
+
+    let locals = &prototype.debug_info.local_variables.data;
+    writeln!(f, "locals ({}):", locals.len())?;
+    for (index, local) in locals.iter().enumerate() {
+        writeln!(
+            f,
+            "\t{}\t{}\t{}\t{}",
+            index,
+            String::from_utf8_lossy(&local.name.data),
+            local.start_pc.value + 1,
+            local.end_pc.value + 1
+        )?;
+    }
+
+    let upvalues = &prototype.upvalues.data;
+    writeln!(f, "upvalues ({}):", upvalues.len())?;
+    for (index, upvalue) in upvalues.iter().enumerate() {
+        writeln!(
+            f,
+            "\t{}\t{}\t{}\t{}",
+            index,
+            UpvalueName(prototype, index),
+            upvalue.in_stack,
+            upvalue.index
+        )?;
+    }
+
+    Ok(())
+}
+
+fn event_name(index: u8) -> &'static str {
+    EVENT_NAMES.get(index as usize).copied().unwrap_or("?")
+}
+
+/// An argument or result count, where `0` means everything up to the top of the stack.
+struct Count(u8);
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => f.write_str("all"),
+            count => write!(f, "{}", count - 1),
+        }
+    }
+}
+
+/// A constant of a prototype, as `luac` prints it.
+struct ConstantAt<'p, 'a>(&'p Prototype<'a>, usize);
+
+impl fmt::Display for ConstantAt<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.constants.data.get(self.1) {
+            Some(Constant::Nil) => f.write_str("nil"),
+            Some(Constant::Boolean(value)) => write!(f, "{}", value),
+            Some(Constant::Float(value)) => f.write_str(&format_float(*value)),
+            Some(Constant::Integer(value)) => write!(f, "{}", value),
+            Some(Constant::String(value)) => write!(f, "{}", Quoted(&value.data)),
+            None => write!(f, "? index={}", self.1),
+        }
+    }
+}
+
+/// The name of an upvalue, `-` when the chunk is stripped.
+struct UpvalueName<'p, 'a>(&'p Prototype<'a>, usize);
+
+impl fmt::Display for UpvalueName<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.debug_info.upvalues.data.get(self.1) {
+            Some(name) => f.write_str(&String::from_utf8_lossy(&name.data)),
+            None => f.write_str("-"),
+        }
+    }
+}
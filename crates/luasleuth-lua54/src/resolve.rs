@@ -0,0 +1,254 @@
+//! Resolution of instruction operands against their prototype.
+
+use luasleuth_common::resolve::{local_name, ResolvedOperand};
+
+use crate::types::{
+    constants::Constant,
+    instructions::{constants::*, Instruction, Opcode},
+    Prototype,
+};
+
+/// Excess-K bias of the signed `sB` and `sC` operands
+const OFFSET_SC: i64 = (1 << (SIZE_C - 1)) - 1;
+
+/// An operand of a Lua 5.4 instruction.
+pub type Operand<'p, 'a> = ResolvedOperand<'p, &'p Constant<'a>>;
+
+/// Resolve the operands of the instruction at `pc`, in the order they are encoded.
+///
+/// Operands the instruction doesn't use are left out, and the `k` bit only shows up as a literal
+/// for the instructions it is a flag of. `sB`, `sC` and `sBx` literals are unbiased, the loop
+/// instructions resolve to the pc they jump to. Returns `None` when `pc` is out of range.
+pub fn resolve<'p, 'a>(prototype: &'p Prototype<'a>, pc: usize) -> Option<Vec<Operand<'p, 'a>>> {
+    let instruction = *prototype.instructions.data.get(pc)?;
+    let resolver = Resolver { prototype, pc };
+
+    let operands = match instruction {
+        Instruction::iABC(opcode, a, b, c, k) => {
+            let (a, b, c) = (a as u32, b as u32, c as u32);
+            let (sb, sc) = (b as i64 - OFFSET_SC, c as i64 - OFFSET_SC);
+            let k_flag = Operand::Literal(k as i64);
+
+            match opcode {
+                Opcode::OP_MOVE
+                | Opcode::OP_UNM
+                | Opcode::OP_BNOT
+                | Opcode::OP_NOT
+                | Opcode::OP_LEN => vec![resolver.register(a), resolver.register(b)],
+                Opcode::OP_LOADKX => match resolver.extra_arg() {
+                    Some(ax) => vec![resolver.register(a), resolver.constant(ax)],
+                    None => vec![resolver.register(a)],
+                },
+                Opcode::OP_LOADFALSE
+                | Opcode::OP_LFALSESKIP
+                | Opcode::OP_LOADTRUE
+                | Opcode::OP_CLOSE
+                | Opcode::OP_TBC
+                | Opcode::OP_RETURN1 => vec![resolver.register(a)],
+                Opcode::OP_LOADNIL | Opcode::OP_CONCAT => {
+                    vec![resolver.register(a), Operand::Literal(b as i64)]
+                }
+                Opcode::OP_GETUPVAL | Opcode::OP_SETUPVAL => {
+                    vec![resolver.register(a), resolver.upvalue(b)]
+                }
+                Opcode::OP_GETTABUP => vec![
+                    resolver.register(a),
+                    resolver.upvalue(b),
+                    resolver.constant(c),
+                ],
+                Opcode::OP_GETTABLE => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    resolver.register(c),
+                ],
+                Opcode::OP_GETI => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    Operand::Literal(c as i64),
+                ],
+                Opcode::OP_GETFIELD => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    resolver.constant(c),
+                ],
+                Opcode::OP_SETTABUP => vec![
+                    resolver.upvalue(a),
+                    resolver.constant(b),
+                    resolver.register_or_constant(c, k),
+                ],
+                Opcode::OP_SETTABLE | Opcode::OP_SELF => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    resolver.register_or_constant(c, k),
+                ],
+                Opcode::OP_SETI => vec![
+                    resolver.register(a),
+                    Operand::Literal(b as i64),
+                    resolver.register_or_constant(c, k),
+                ],
+                Opcode::OP_SETFIELD => vec![
+                    resolver.register(a),
+                    resolver.constant(b),
+                    resolver.register_or_constant(c, k),
+                ],
+                Opcode::OP_NEWTABLE | Opcode::OP_SETLIST => {
+                    // `C` continues in the following `EXTRAARG` when `k` is set
+                    let extra = match k {
+                        0 => 0,
+                        _ => resolver.extra_arg().unwrap_or_default() as i64,
+                    };
+                    vec![
+                        resolver.register(a),
+                        Operand::Literal(b as i64),
+                        Operand::Literal(c as i64 + extra * (u8::MAX as i64 + 1)),
+                    ]
+                }
+                Opcode::OP_ADDI | Opcode::OP_SHRI | Opcode::OP_SHLI => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    Operand::Literal(sc),
+                ],
+                Opcode::OP_ADDK
+                | Opcode::OP_SUBK
+                | Opcode::OP_MULK
+                | Opcode::OP_MODK
+                | Opcode::OP_POWK
+                | Opcode::OP_DIVK
+                | Opcode::OP_IDIVK
+                | Opcode::OP_BANDK
+                | Opcode::OP_BORK
+                | Opcode::OP_BXORK => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    resolver.constant(c),
+                ],
+                Opcode::OP_MMBIN => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    Operand::Literal(c as i64),
+                ],
+                Opcode::OP_MMBINI => vec![
+                    resolver.register(a),
+                    Operand::Literal(sb),
+                    Operand::Literal(c as i64),
+                    k_flag,
+                ],
+                Opcode::OP_MMBINK => vec![
+                    resolver.register(a),
+                    resolver.constant(b),
+                    Operand::Literal(c as i64),
+                    k_flag,
+                ],
+                Opcode::OP_EQ | Opcode::OP_LT | Opcode::OP_LE => {
+                    vec![resolver.register(a), resolver.register(b), k_flag]
+                }
+                Opcode::OP_EQK => vec![resolver.register(a), resolver.constant(b), k_flag],
+                Opcode::OP_EQI
+                | Opcode::OP_LTI
+                | Opcode::OP_LEI
+                | Opcode::OP_GTI
+                | Opcode::OP_GEI => vec![resolver.register(a), Operand::Literal(sb), k_flag],
+                Opcode::OP_TEST => vec![resolver.register(a), k_flag],
+                Opcode::OP_TESTSET => vec![resolver.register(a), resolver.register(b), k_flag],
+                Opcode::OP_RETURN0 => vec![],
+                Opcode::OP_TFORCALL | Opcode::OP_VARARG => {
+                    vec![resolver.register(a), Operand::Literal(c as i64)]
+                }
+                Opcode::OP_CALL | Opcode::OP_TAILCALL | Opcode::OP_RETURN => vec![
+                    resolver.register(a),
+                    Operand::Literal(b as i64),
+                    Operand::Literal(c as i64),
+                ],
+                Opcode::OP_VARARGPREP => vec![Operand::Literal(a as i64)],
+                // Arithmetic between registers
+                _ => vec![
+                    resolver.register(a),
+                    resolver.register(b),
+                    resolver.register(c),
+                ],
+            }
+        }
+        Instruction::iABx(opcode, a, bx) => match opcode {
+            Opcode::OP_CLOSURE => vec![
+                resolver.register(a as u32),
+                Operand::Prototype { index: bx },
+            ],
+            _ => vec![resolver.register(a as u32), resolver.constant(bx)],
+        },
+        Instruction::iAsBx(opcode, a, sbx) => {
+            // Loop jumps are unsigned distances, read with the `sBx` bias
+            let bx = sbx as i64 + OFFSET_SBX as i64;
+            let pc = pc as i64;
+            let operand = match opcode {
+                Opcode::OP_FORLOOP | Opcode::OP_TFORLOOP => Operand::Jump {
+                    target: pc + 1 - bx,
+                },
+                Opcode::OP_FORPREP => Operand::Jump {
+                    target: pc + bx + 2,
+                },
+                Opcode::OP_TFORPREP => Operand::Jump {
+                    target: pc + bx + 1,
+                },
+                _ => Operand::Literal(sbx as i64),
+            };
+            vec![resolver.register(a as u32), operand]
+        }
+        Instruction::iAx(_, ax) => vec![Operand::Literal(ax as i64)],
+        Instruction::isJ(_, sj) => vec![Operand::Jump {
+            target: pc as i64 + 1 + sj as i64,
+        }],
+    };
+
+    Some(operands)
+}
+
+struct Resolver<'p, 'a> {
+    prototype: &'p Prototype<'a>,
+    pc: usize,
+}
+
+impl<'p, 'a> Resolver<'p, 'a> {
+    fn register(&self, index: u32) -> Operand<'p, 'a> {
+        let locals = self.prototype.debug_info.local_variables.data.iter();
+        let locals = locals.map(|local| {
+            let (start_pc, end_pc) = (local.start_pc.value as u64, local.end_pc.value as u64);
+            (local.name.as_bytes(), start_pc, end_pc)
+        });
+
+        Operand::Register {
+            index,
+            name: local_name(locals, index, self.pc as u64),
+        }
+    }
+
+    fn constant(&self, index: u32) -> Operand<'p, 'a> {
+        Operand::Constant {
+            index,
+            value: self.prototype.constants.data.get(index as usize),
+        }
+    }
+
+    /// Resolve a `C` operand that the `k` bit marks as a constant index.
+    fn register_or_constant(&self, operand: u32, k: u8) -> Operand<'p, 'a> {
+        match k {
+            0 => self.register(operand),
+            _ => self.constant(operand),
+        }
+    }
+
+    fn upvalue(&self, index: u32) -> Operand<'p, 'a> {
+        let name = self.prototype.debug_info.upvalues.data.get(index as usize);
+        Operand::Upvalue {
+            index,
+            name: name.map(|name| name.as_bytes()),
+        }
+    }
+
+    /// Returns the argument of the `EXTRAARG` following the instruction.
+    fn extra_arg(&self) -> Option<u32> {
+        match *self.prototype.instructions.data.get(self.pc + 1)? {
+            Instruction::iAx(Opcode::OP_EXTRAARG, ax) => Some(ax),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Building Lua 5.4 chunks from assembly, see [`luasleuth_common::text`].
+
+use luasleuth_common::{
+    text::{Chunk, Function, Item, Mnemonics, Operand, Scope, Statement, TextError},
+    types::{LuaString, LuaUnsigned, Packable},
+};
+
+use crate::types::{
+    constants::Constant,
+    debug_info::{AbsLineInfo, DebugInfo, LocalVariable},
+    instructions::{constants::*, Instruction, Opcode},
+    upvalues::Upvalue,
+    Bytecode, Header, Prototype,
+};
+
+/// Value of `is_vararg` for functions declared with a bare `.vararg`
+const VARARG_ISVARARG: u8 = 1;
+
+/// Line differences that do not fit in a byte are stored as absolute lines
+const LIMLINEDIFF: i64 = 0x80;
+
+/// Marks an instruction whose line is in the absolute line information
+const ABSLINEINFO: u8 = 0x80;
+
+/// Most instructions in a row without absolute line information
+const MAXIWTHABS: usize = 128;
+
+/// Build a chunk from assembly, with the header of a stock 64-bit build.
+pub fn build(chunk: &Chunk) -> Result<Bytecode<'_>, TextError> {
+    let mnemonics = Mnemonics::new();
+
+    let prototype = build_prototype(&chunk.main, &mnemonics)?;
+
+    Ok(Bytecode {
+        header: Header::default(),
+        size_of_upvalues: prototype.upvalues.size as u8,
+        prototype,
+    })
+}
+
+fn build_prototype<'a>(
+    function: &'a Function,
+    mnemonics: &Mnemonics<Opcode>,
+) -> Result<Prototype<'a>, TextError> {
+    let scope = function.scope()?;
+
+    let mut source = None;
+    let mut line_defined = 0;
+    let mut last_line_defined = 0;
+    let mut number_of_parameters = 0;
+    let mut is_vararg = 0;
+    let mut max_stack_size = 2;
+    let mut code = Vec::new();
+    let mut constants = Vec::new();
+    let mut prototypes = Vec::new();
+    let mut lines = Vec::new();
+    let mut local_variables = Vec::new();
+    let mut upvalues = Vec::new();
+    let mut upvalue_names = Vec::new();
+
+    // The line of the instructions that follow, set by `.line`
+    let mut line = None;
+    for item in &function.items {
+        match item {
+            Item::Directive(directive) => {
+                let at = directive.line;
+                match directive.name.as_str() {
+                    "source" => {
+                        directive.expect_at_most(1)?;
+                        source = Some(LuaString::from(directive.operand(0)?.string(at)?));
+                    }
+                    "lines" => {
+                        directive.expect_at_most(2)?;
+                        line_defined = directive.operand(0)?.integer(at)?;
+                        last_line_defined = directive.operand(1)?.integer(at)?;
+                    }
+                    "params" => {
+                        directive.expect_at_most(1)?;
+                        number_of_parameters = directive.operand(0)?.integer(at)?;
+                    }
+                    "vararg" => {
+                        directive.expect_at_most(1)?;
+                        is_vararg = match directive.operands.first() {
+                            Some(operand) => operand.integer(at)?,
+                            None => VARARG_ISVARARG,
+                        };
+                    }
+                    "stack" => {
+                        directive.expect_at_most(1)?;
+                        max_stack_size = directive.operand(0)?.integer(at)?;
+                    }
+                    "upval" => {
+                        directive.expect_at_most(4)?;
+                        upvalue_names.push(LuaString::from(directive.operand(0)?.identifier(at)?));
+                        upvalues.push(Upvalue {
+                            in_stack: directive.operand(1)?.integer(at)?,
+                            index: directive.operand(2)?.integer(at)?,
+                            kind: match directive.operands.get(3) {
+                                Some(operand) => operand.integer(at)?,
+                                None => 0,
+                            },
+                        });
+                    }
+                    "const" => {
+                        directive.expect_at_most(1)?;
+                        constants.push(build_constant(directive.operand(0)?, at)?);
+                    }
+                    "local" => {
+                        directive.expect_at_most(3)?;
+                        local_variables.push(LocalVariable {
+                            name: LuaString::from(directive.operand(0)?.identifier(at)?),
+                            start_pc: pc_operand(&scope, directive.operand(1)?, at)?,
+                            end_pc: pc_operand(&scope, directive.operand(2)?, at)?,
+                        });
+                    }
+                    "line" => {
+                        directive.expect_at_most(1)?;
+                        line = Some(directive.operand(0)?.integer(at)?);
+                    }
+                    _ => return Err(directive.unknown_directive()),
+                }
+            }
+            Item::Instruction(instruction) => {
+                code.push(build_instruction(
+                    instruction,
+                    code.len(),
+                    &scope,
+                    mnemonics,
+                )?);
+                lines.push(line);
+            }
+            Item::Function(child) => prototypes.push(build_prototype(child, mnemonics)?),
+            Item::Label { .. } => {}
+        }
+    }
+
+    // Line information is left out unless `.line` was used, like in stripped chunks
+    let (line_info, abs_line_info) = match lines.iter().any(Option::is_some) {
+        true => encode_lines(&lines, line_defined),
+        false => (Vec::new(), Vec::new()),
+    };
+
+    Ok(Prototype {
+        source,
+        line_defined: LuaUnsigned::new(line_defined),
+        last_line_defined: LuaUnsigned::new(last_line_defined),
+        number_of_parameters,
+        is_vararg,
+        max_stack_size,
+        instructions: code.into(),
+        constants: constants.into(),
+        upvalues: upvalues.into(),
+        prototypes: prototypes.into(),
+        debug_info: DebugInfo {
+            line_info: line_info.into(),
+            abs_line_info: abs_line_info.into(),
+            local_variables: local_variables.into(),
+            upvalues: upvalue_names.into(),
+        },
+    })
+}
+
+fn build_constant(operand: &Operand, line: usize) -> Result<Constant<'_>, TextError> {
+    let constant = match operand {
+        Operand::Name(name) if name == "nil" => Constant::Nil,
+        Operand::Name(name) if name == "true" => Constant::Boolean(true),
+        Operand::Name(name) if name == "false" => Constant::Boolean(false),
+        Operand::String(value) => Constant::String(LuaString::from(value.as_slice())),
+        Operand::Integer(value) => Constant::Integer(*value),
+        operand => Constant::Float(operand.number(line)?),
+    };
+
+    Ok(constant)
+}
+
+fn build_instruction(
+    instruction: &Statement,
+    pc: usize,
+    scope: &Scope,
+    mnemonics: &Mnemonics<Opcode>,
+) -> Result<Instruction, TextError> {
+    let line = instruction.line;
+    let opcode = mnemonics.find(instruction)?;
+
+    // Every opcode has a fixed format, the one its decoder picks
+    let format = Instruction::decode(u8::from(opcode) as u32)
+        .map_err(|error| instruction.error(error.to_string()))?;
+
+    let built = match format {
+        Instruction::iABC(..) => {
+            instruction.expect_at_most(4)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let b = instruction.operand(1)?.unsigned(line, SIZE_B)?;
+            let c = instruction.operand(2)?.unsigned(line, SIZE_C)?;
+            let k = match instruction.operands.get(3) {
+                Some(operand) => operand.unsigned(line, 1)?,
+                None => 0,
+            };
+            Instruction::iABC(opcode, a as u8, b as u8, c as u8, k as u8)
+        }
+        Instruction::iABx(..) => {
+            instruction.expect_at_most(2)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let bx = match opcode {
+                Opcode::OP_CLOSURE => scope.child(instruction.operand(1)?, line)?,
+                _ => instruction.operand(1)?.integer(line)?,
+            };
+            Instruction::iABx(
+                opcode,
+                a as u8,
+                Operand::Integer(bx).unsigned(line, SIZE_BX)?,
+            )
+        }
+        Instruction::iAsBx(..) if is_loop(opcode) => {
+            instruction.expect_at_most(2)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let bx = loop_distance(opcode, scope, instruction.operand(1)?, pc, line)?;
+            let bx = Operand::Integer(bx)
+                .unsigned(line, SIZE_BX)
+                .map_err(|_| instruction.error(format!("Jump of {} is out of range", bx)))?;
+            // The decoder reads the unsigned distance as a signed operand
+            Instruction::iAsBx(opcode, a as u8, bx as i32 - OFFSET_SBX as i32)
+        }
+        Instruction::iAsBx(..) => {
+            instruction.expect_at_most(2)?;
+            let a = instruction.operand(0)?.unsigned(line, SIZE_A)?;
+            let sbx = scope.jump(instruction.operand(1)?, pc, line)?;
+            let biased = Operand::Integer(sbx + OFFSET_SBX as i64).unsigned(line, SIZE_BX);
+            biased.map_err(|_| instruction.error(format!("Jump of {} is out of range", sbx)))?;
+            Instruction::iAsBx(opcode, a as u8, sbx as i32)
+        }
+        Instruction::iAx(..) => {
+            instruction.expect_at_most(1)?;
+            let ax = instruction.operand(0)?.unsigned(line, SIZE_AX)?;
+            Instruction::iAx(opcode, ax)
+        }
+        Instruction::isJ(..) => {
+            instruction.expect_at_most(1)?;
+            let sj = scope.jump(instruction.operand(0)?, pc, line)?;
+            let biased = Operand::Integer(sj + OFFSET_S_J as i64).unsigned(line, SIZE_S_J);
+            biased.map_err(|_| instruction.error(format!("Jump of {} is out of range", sj)))?;
+            Instruction::isJ(opcode, sj as i32)
+        }
+    };
+
+    Ok(built)
+}
+
+/// Returns whether an opcode is a loop instruction, which stores an unsigned jump distance.
+fn is_loop(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        Opcode::OP_FORPREP | Opcode::OP_FORLOOP | Opcode::OP_TFORPREP | Opcode::OP_TFORLOOP
+    )
+}
+
+/// Returns the `Bx` distance of a loop instruction to a label, or a raw distance.
+///
+/// `FORPREP` skips the loop to after its `FORLOOP`, the `*LOOP` instructions jump backwards.
+fn loop_distance(
+    opcode: Opcode,
+    scope: &Scope,
+    operand: &Operand,
+    pc: usize,
+    line: usize,
+) -> Result<i64, TextError> {
+    let Operand::Name(_) = operand else {
+        return operand.integer(line);
+    };
+
+    let target = scope.position(operand, line)?;
+    let pc = pc as i64;
+    Ok(match opcode {
+        Opcode::OP_FORPREP => target - pc - 2,
+        Opcode::OP_TFORPREP => target - pc - 1,
+        _ => pc + 1 - target,
+    })
+}
+
+/// Returns the instruction index of a `.local` bound, given as a label or an index.
+fn pc_operand(scope: &Scope, operand: &Operand, line: usize) -> Result<LuaUnsigned, TextError> {
+    let pc = Operand::Integer(scope.position(operand, line)?).integer(line)?;
+    Ok(LuaUnsigned::new(pc))
+}
+
+/// Encode the line of each instruction as the difference to the previous one.
+///
+/// Lines too far from the previous one, and one in every [`MAXIWTHABS`] instructions,
+/// are stored as absolute lines instead, as `luaK_fixline` does.
+fn encode_lines(lines: &[Option<usize>], line_defined: usize) -> (Vec<u8>, Vec<AbsLineInfo>) {
+    let mut line_info = Vec::with_capacity(lines.len());
+    let mut abs_line_info = Vec::new();
+
+    let mut previous = line_defined as i64;
+    let mut without_abs = 0;
+    for (pc, line) in lines.iter().enumerate() {
+        let line = line.unwrap_or(line_defined);
+        let difference = line as i64 - previous;
+
+        if difference.abs() >= LIMLINEDIFF || without_abs >= MAXIWTHABS {
+            abs_line_info.push(AbsLineInfo {
+                pc: LuaUnsigned::new(pc),
+                line: LuaUnsigned::new(line),
+            });
+            line_info.push(ABSLINEINFO);
+            without_abs = 1;
+        } else {
+            line_info.push(difference as i8 as u8);
+            without_abs += 1;
+        }
+        previous = line as i64;
+    }
+
+    (line_info, abs_line_info)
+}
@@ -1,13 +1,14 @@
-use luasleuth_common::{types::LuaString, CommonCtx};
+use luasleuth_common::{types::LuaString, CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
 const LUAI_MAXSHORTLEN: usize = 40;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Constant<'a> {
     Nil,
     Boolean(bool),
-    Float(f64),
+    Float(#[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64),
     Integer(i64),
     String(LuaString<'a>),
 }
@@ -33,7 +34,7 @@ impl Constant<'_> {
             Constant::Float(_) => 19,
             Constant::Integer(_) => 3,
             Constant::String(string) => {
-                if string.size >= LUAI_MAXSHORTLEN {
+                if string.size > LUAI_MAXSHORTLEN {
                     20
                 } else {
                     4
@@ -44,25 +45,22 @@ impl Constant<'_> {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Constant<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let tag: u8 = src.gread_with(offset, ctx.endianness)?;
+        let tag: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "tag")?;
         let constant = match tag {
             0 => Constant::Nil,
             1 => Constant::Boolean(false),
             17 => Constant::Boolean(true),
-            19 => Constant::Float(src.gread_with(offset, ctx.endianness)?),
-            3 => Constant::Integer(src.gread_with(offset, ctx.endianness)?),
-            4 | 20 => Constant::String(src.gread_with(offset, ctx)?),
-            _ => {
-                return Err(scroll::Error::BadInput {
-                    size: 1,
-                    msg: "Invalid constant type",
-                })
-            }
+            19 => Constant::Float(ctx.read_number(src, offset).within(*offset, "value")?),
+            3 => Constant::Integer(ctx.read_integer(src, offset).within(*offset, "value")?),
+            4 | 20 => Constant::String(src.gread_with(offset, ctx).within(*offset, "value")?),
+            _ => return Err(Error::invalid("Invalid constant type", 0).within(0, "tag")),
         };
 
         Ok((constant, *offset))
@@ -82,10 +80,10 @@ impl ctx::TryIntoCtx<CommonCtx> for Constant<'_> {
             Constant::Nil => {}
             Constant::Boolean(_) => {} // `true` and `false` are different constant types in Lua 5.4, this is handled by `Constant::get_type`
             Constant::Float(value) => {
-                dst.gwrite_with(value, offset, ctx.endianness)?;
+                ctx.write_number(dst, offset, value)?;
             }
             Constant::Integer(value) => {
-                dst.gwrite_with(value, offset, ctx.endianness)?;
+                ctx.write_integer(dst, offset, value)?;
             }
             Constant::String(value) => {
                 dst.gwrite_with(value, offset, ctx)?;
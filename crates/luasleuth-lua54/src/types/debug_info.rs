@@ -1,10 +1,11 @@
 use luasleuth_common::{
     types::{Array, LuaString, LuaUnsigned},
-    CommonCtx,
+    CommonCtx, Error, ErrorContext,
 };
 use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DebugInfo<'a> {
     pub line_info: Array<u8>,
     pub abs_line_info: Array<AbsLineInfo>,
@@ -12,13 +13,15 @@ pub struct DebugInfo<'a> {
     pub upvalues: Array<LuaString<'a>>,
 }
 
-#[derive(Debug, Pread)]
+#[derive(Debug, Clone, Pread)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AbsLineInfo {
     pub pc: LuaUnsigned,
     pub line: LuaUnsigned,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LocalVariable<'a> {
     pub name: LuaString<'a>,
     /// first point where variable is active
@@ -27,16 +30,56 @@ pub struct LocalVariable<'a> {
     pub end_pc: LuaUnsigned,
 }
 
+impl DebugInfo<'_> {
+    /// Returns the number of elements in the arrays of the debug information.
+    pub fn element_count(&self) -> usize {
+        self.line_info.size
+            + self.abs_line_info.size
+            + self.local_variables.size
+            + self.upvalues.size
+    }
+
+    /// Returns the source line of the instruction at `pc`, `None` when the chunk is stripped.
+    ///
+    /// Lines are deltas from the previous instruction, starting from the closest absolute
+    /// line before `pc` or else from `line_defined`.
+    pub fn line_for_pc(&self, line_defined: usize, pc: usize) -> Option<i64> {
+        if pc >= self.line_info.size {
+            return None;
+        }
+
+        let (base_pc, mut line) = self
+            .abs_line_info
+            .data
+            .iter()
+            .take_while(|info| info.pc.value <= pc)
+            .last()
+            .map_or((0, line_defined as i64), |info| {
+                (info.pc.value + 1, info.line.value as i64)
+            });
+
+        for &delta in &self.line_info.data[base_pc..=pc] {
+            line += delta as i8 as i64;
+        }
+
+        Some(line)
+    }
+}
+
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for DebugInfo<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let line_info: Array<u8> = src.gread_with(offset, ctx)?;
-        let abs_line_info: Array<AbsLineInfo> = src.gread_with(offset, ctx)?;
-        let local_variables: Array<LocalVariable> = src.gread_with(offset, ctx)?;
-        let upvalues: Array<LuaString> = src.gread_with(offset, ctx)?;
+        let line_info: Array<u8> = src.gread_with(offset, ctx).within(*offset, "line_info")?;
+        let abs_line_info: Array<AbsLineInfo> = src
+            .gread_with(offset, ctx)
+            .within(*offset, "abs_line_info")?;
+        let local_variables: Array<LocalVariable> = src
+            .gread_with(offset, ctx)
+            .within(*offset, "local_variables")?;
+        let upvalues: Array<LuaString> = src.gread_with(offset, ctx).within(*offset, "upvalues")?;
 
         Ok((
             DebugInfo {
@@ -66,27 +109,35 @@ impl ctx::TryIntoCtx<CommonCtx> for DebugInfo<'_> {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for AbsLineInfo {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let pc: LuaUnsigned = src.gread_with(offset, ctx.endianness)?;
-        let line: LuaUnsigned = src.gread_with(offset, ctx.endianness)?;
+        let pc: LuaUnsigned = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "pc")?;
+        let line: LuaUnsigned = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "line")?;
 
         Ok((Self { pc, line }, *offset))
     }
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for LocalVariable<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let name: LuaString = src.gread_with(offset, ctx)?;
-        let start_pc: LuaUnsigned = src.gread_with(offset, ctx.endianness)?;
-        let end_pc: LuaUnsigned = src.gread_with(offset, ctx.endianness)?;
+        let name: LuaString = src.gread_with(offset, ctx).within(*offset, "name")?;
+        let start_pc: LuaUnsigned = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "start_pc")?;
+        let end_pc: LuaUnsigned = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "end_pc")?;
 
         Ok((
             LocalVariable {
@@ -1,5 +1,5 @@
-use luasleuth_common::{mask, types::Packable, CommonCtx};
-use scroll::{ctx, Pread, Pwrite};
+use luasleuth_common::{mask, types::Packable, CommonCtx, Error, InvalidOpcode};
+use scroll::ctx;
 
 pub mod constants {
     //! Constants related to Lua 5.4 instructions
@@ -24,9 +24,13 @@ pub mod constants {
 
     pub const MAXARG_BX: u32 = (1 << SIZE_BX) - 1;
     pub const OFFSET_SBX: u32 = MAXARG_BX >> 1;
+
+    pub const MAXARG_S_J: u32 = (1 << SIZE_S_J) - 1;
+    pub const OFFSET_S_J: u32 = MAXARG_S_J >> 1;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum Instruction {
     iABC(Opcode, u8, u8, u8, u8), // Op, A, B, C, K
@@ -36,7 +40,8 @@ pub enum Instruction {
     isJ(Opcode, i32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 pub enum Opcode {
@@ -131,9 +136,11 @@ impl From<Opcode> for u8 {
     }
 }
 
-impl From<u8> for Opcode {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for Opcode {
+    type Error = InvalidOpcode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let opcode = match value {
             0 => Opcode::OP_MOVE,
             1 => Opcode::OP_LOADI,
             2 => Opcode::OP_LOADF,
@@ -217,21 +224,23 @@ impl From<u8> for Opcode {
             80 => Opcode::OP_VARARG,
             81 => Opcode::OP_VARARGPREP,
             82 => Opcode::OP_EXTRAARG,
-            _ => unimplemented!("Opcode::from({})", value),
-        }
+            _ => return Err(InvalidOpcode(value)),
+        };
+
+        Ok(opcode)
     }
 }
 
 impl Packable for Instruction {
-    fn decode(raw: u32) -> Instruction {
+    fn decode(raw: u32) -> Result<Instruction, InvalidOpcode> {
         use constants::*;
         use Opcode::*;
 
-        let opcode: Opcode = ((raw & mask!(SIZE_OP, 0)) as u8).into();
+        let opcode = Opcode::try_from((raw & mask!(SIZE_OP, 0)) as u8)?;
         let a = ((raw >> POS_A) & mask!(SIZE_A, 0)) as u8;
         let k = ((raw >> POS_K) & 0x1) as u8;
 
-        match opcode {
+        let instruction = match opcode {
             // iABC instructions
             OP_MOVE | OP_LOADNIL | OP_GETUPVAL | OP_SETUPVAL | OP_GETTABUP | OP_GETTABLE
             | OP_GETI | OP_GETFIELD | OP_SETTABUP | OP_SETTABLE | OP_SETI | OP_SETFIELD
@@ -242,7 +251,7 @@ impl Packable for Instruction {
             | OP_CLOSE | OP_TBC | OP_TEST | OP_TESTSET | OP_CALL | OP_TAILCALL | OP_RETURN
             | OP_RETURN0 | OP_RETURN1 | OP_TFORCALL | OP_SETLIST | OP_VARARG | OP_VARARGPREP
             | OP_EQ | OP_LT | OP_LE | OP_EQK | OP_EQI | OP_LTI | OP_LEI | OP_GTI | OP_GEI
-            | OP_ADDI | OP_SHRI | OP_SHLI => {
+            | OP_ADDI | OP_SHRI | OP_SHLI | OP_LOADFALSE | OP_LFALSESKIP | OP_LOADTRUE => {
                 let b = ((raw >> POS_B) & mask!(SIZE_B, 0)) as u8;
                 let c = ((raw >> POS_C) & mask!(SIZE_C, 0)) as u8;
                 Instruction::iABC(opcode, a, b, c, k)
@@ -268,13 +277,12 @@ impl Packable for Instruction {
 
             // isJ instructions
             OP_JMP => {
-                let sj = (((raw >> POS_S_J) & mask!(SIZE_S_J, 0)) as i32) - (OFFSET_SBX as i32);
+                let sj = (((raw >> POS_S_J) & mask!(SIZE_S_J, 0)) as i32) - (OFFSET_S_J as i32);
                 Instruction::isJ(opcode, sj)
             }
+        };
 
-            // Simple instructions (no additional arguments needed)
-            OP_LOADFALSE | OP_LFALSESKIP | OP_LOADTRUE => Instruction::iABC(opcode, a, 0, 0, 0),
-        }
+        Ok(instruction)
     }
 
     fn encode(inst: Instruction) -> u32 {
@@ -309,7 +317,7 @@ impl Packable for Instruction {
             }
             Instruction::isJ(opcode, sj) => {
                 let opcode = opcode as u32;
-                let sj = ((sj + OFFSET_SBX as i32) as u32) << POS_S_J;
+                let sj = ((sj + OFFSET_S_J as i32) as u32) << POS_S_J;
                 opcode | sj
             }
         }
@@ -317,12 +325,12 @@ impl Packable for Instruction {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Instruction {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
-        let instruction: u32 = src.gread_with(offset, ctx.endianness)?;
-        let instruction = Instruction::decode(instruction);
+        let instruction = ctx.read_instruction(src, offset)?;
+        let instruction = Instruction::decode(instruction)?;
 
         Ok((instruction, *offset))
     }
@@ -335,7 +343,7 @@ impl ctx::TryIntoCtx<CommonCtx> for Instruction {
         let offset = &mut 0;
 
         let n = Instruction::encode(self);
-        src.gwrite_with(n, offset, ctx.endianness)?;
+        ctx.write_instruction(src, offset, n)?;
 
         Ok(*offset)
     }
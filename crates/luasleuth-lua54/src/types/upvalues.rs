@@ -1,7 +1,8 @@
-use luasleuth_common::CommonCtx;
+use luasleuth_common::{CommonCtx, Error, ErrorContext};
 use scroll::{ctx, Pread, Pwrite};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Upvalue {
     pub in_stack: u8,
     pub index: u8,
@@ -9,14 +10,20 @@ pub struct Upvalue {
 }
 
 impl<'a> ctx::TryFromCtx<'a, CommonCtx> for Upvalue {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: CommonCtx) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let in_stack: u8 = src.gread_with(offset, ctx.endianness)?;
-        let index: u8 = src.gread_with(offset, ctx.endianness)?;
-        let kind: u8 = src.gread_with(offset, ctx.endianness)?;
+        let in_stack: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "in_stack")?;
+        let index: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "index")?;
+        let kind: u8 = src
+            .gread_with(offset, ctx.endianness)
+            .within(*offset, "kind")?;
 
         Ok((
             Self {
@@ -1,5 +1,13 @@
-use luasleuth_common::disassembler::Disassemble as _;
+use luasleuth_common::{assembler::Assemble as _, disassembler::Disassemble as _, types::Packable};
+use luasleuth_lua54::assembler::Assembler;
 use luasleuth_lua54::disassembler::Disassembler;
+use luasleuth_lua54::types::{
+    constants::Constant,
+    instructions::{Instruction, Opcode},
+    upvalues::Upvalue,
+    Header,
+};
+use scroll::{ctx::TryFromCtx, Pread, Pwrite};
 
 #[test]
 fn test_can_parse_bytecode_file() {
@@ -19,10 +27,176 @@ fn test_can_parse_bytecode_file() {
 
     // Check prototype related values
     let prototype = bytecode.prototype;
-    assert_eq!(prototype.source, "@.\\example.lua"); // Checks size and actual data
+    assert_eq!(
+        prototype.source.expect("Missing source name"),
+        "@.\\example.lua"
+    ); // Checks size and actual data
     assert_eq!(prototype.line_defined, 0);
     assert_eq!(prototype.last_line_defined, 0);
     assert_eq!(prototype.number_of_parameters, 0);
     assert_eq!(prototype.is_vararg, 1);
     assert_eq!(prototype.max_stack_size, 2);
 }
+
+/// Returns the message of a failed header check.
+fn header_error(bytes: &[u8]) -> String {
+    match bytes.pread::<Header>(0) {
+        Err(error) => error.kind().to_string(),
+        other => panic!("Expected a header check to fail, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rejects_corrupted_header_check_data() {
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+
+    let mut corrupted = bytes.to_vec();
+    corrupted[8] = b'\n'; // CRLF translated into LF
+    assert!(header_error(&corrupted).starts_with("LUAC_DATA"));
+
+    let mut corrupted = bytes.to_vec();
+    corrupted[15] ^= 0xff;
+    assert!(header_error(&corrupted).starts_with("LUAC_INT"));
+
+    let mut corrupted = bytes.to_vec();
+    corrupted[23] ^= 0xff;
+    assert!(header_error(&corrupted).starts_with("LUAC_NUM"));
+
+    let mut corrupted = bytes.to_vec();
+    corrupted[0] = b'L';
+    assert!(header_error(&corrupted).starts_with("Signature"));
+}
+
+#[test]
+fn test_infers_layout_of_big_endian_32_bit_header() {
+    let mut bytes = vec![0x1b, b'L', b'u', b'a', 0x54, 0];
+    bytes.extend(b"\x19\x93\r\n\x1a\n");
+    bytes.extend([4, 4, 4]);
+    bytes.extend(0x5678i32.to_be_bytes());
+    bytes.extend(370.5f32.to_be_bytes());
+
+    let (header, size) = Header::try_from_ctx(&bytes, ()).expect("Failed to read header");
+    assert_eq!(size, bytes.len());
+    assert_eq!(header.endianness, scroll::BE);
+    assert_eq!(header.size_of_integer, 4);
+    assert_eq!(header.size_of_number, 4);
+    assert_eq!(header.luac_int, 0x5678);
+    assert_eq!(header.luac_num, 370.5);
+
+    let mut written = vec![0; bytes.len()];
+    let size = written.pwrite(header, 0).expect("Failed to write header");
+    assert_eq!(&written[..size], bytes);
+}
+
+#[test]
+fn test_malformed_input_returns_error_instead_of_panicking() {
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+
+    for length in 0..bytes.len() {
+        let result = Disassembler::new(&bytes[..length]).disassemble();
+        assert!(result.is_err(), "Truncated to {} bytes", length);
+    }
+
+    for index in 0..bytes.len() {
+        for value in [0x00, 0x7f, 0x80, 0xff] {
+            let mut bytes = bytes.to_vec();
+            bytes[index] = value;
+            let _ = Disassembler::new(&bytes).disassemble();
+        }
+    }
+}
+
+#[test]
+fn test_assembles_bytecode_file_back_to_the_same_bytes() {
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+    let bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    assert_eq!(assembled, bytes);
+}
+
+#[test]
+fn test_assembles_modified_bytecode() {
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+    let mut bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Nest a copy of the main function, which luac dumps without a source name
+    let mut child = bytecode.prototype.clone();
+    child.source = None;
+    child.instructions.data[0] = Instruction::isJ(Opcode::OP_JMP, -3);
+    bytecode.prototype.prototypes = vec![child].into();
+
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    let bytecode = Disassembler::new(&assembled)
+        .disassemble()
+        .expect("Failed to read assembled bytecode");
+
+    let child = &bytecode.prototype.prototypes.data[0];
+    assert!(child.source.is_none());
+    assert!(matches!(
+        child.instructions.data[0],
+        Instruction::isJ(Opcode::OP_JMP, -3)
+    ));
+
+    let reassembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    assert_eq!(reassembled, assembled);
+}
+
+#[test]
+fn test_assembles_sizes_from_edited_data() {
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+    let mut bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Only the data is edited, every stored size is left as it was read
+    let prototype = &mut bytecode.prototype;
+    let Constant::String(greeting) = &mut prototype.constants.data[1] else {
+        panic!("Expected a string constant");
+    };
+    greeting.data = b"Hello, Lua 5.4!".to_vec().into();
+    prototype.constants.data.push(Constant::Integer(42));
+    prototype.upvalues.data.push(Upvalue {
+        in_stack: 1,
+        index: 0,
+        kind: 0,
+    });
+
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    let bytecode = Disassembler::new(&assembled)
+        .disassemble()
+        .expect("Failed to read assembled bytecode");
+
+    assert_eq!(bytecode.size_of_upvalues, 2);
+    let prototype = &bytecode.prototype;
+    assert_eq!(prototype.upvalues.size, 2);
+    assert_eq!(prototype.constants.size, 3);
+    assert!(matches!(prototype.constants.data[2], Constant::Integer(42)));
+    let Constant::String(greeting) = &prototype.constants.data[1] else {
+        panic!("Expected a string constant");
+    };
+    assert_eq!(greeting.size, 15);
+    assert_eq!(greeting.as_str(), Some("Hello, Lua 5.4!"));
+}
+
+#[test]
+fn test_instruction_encoding_round_trips() {
+    let mut raw = 0x2545_f491u32;
+    for _ in 0..100_000 {
+        raw = raw.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        if let Ok(instruction) = Instruction::decode(raw) {
+            assert_eq!(Instruction::encode(instruction), raw, "{:?}", instruction);
+        }
+    }
+}
@@ -1,22 +1,126 @@
+use luasleuth_common::Limits;
 use scroll::Endian;
 
+/// Flag to determine whether or not bytecode is big endian
+pub const BYTECODE_IS_BIG_ENDIAN: u64 = 0x01;
+
 /// Flag to determine whether or not bytecode is stripped
 pub const BYTECODE_IS_STRIPPED: u64 = 0x02;
 
+/// Flag to determine whether or not bytecode uses FFI features, like cdata constants
+pub const BYTECODE_HAS_FFI: u64 = 0x04;
+
+/// Flag to determine whether or not bytecode was written by a two-slot frame (FR2/GC64) build
+///
+/// Only used by LuaJIT 2.1.
+pub const BYTECODE_IS_FR2: u64 = 0x08;
+
+/// The LuaJIT release a bytecode dump was written by
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Release {
+    /// LuaJIT 2.0.x, bytecode version 1
+    LuaJit20,
+    /// LuaJIT 2.1.x, bytecode version 2
+    LuaJit21,
+}
+
+impl Release {
+    /// Determine the release from the version byte in the bytecode header
+    pub fn from_version(version: u8) -> Option<Self> {
+        match version {
+            1 => Some(Release::LuaJit20),
+            2 => Some(Release::LuaJit21),
+            _ => None,
+        }
+    }
+
+    /// Returns the version byte written to the bytecode header
+    pub fn version(self) -> u8 {
+        match self {
+            Release::LuaJit20 => 1,
+            Release::LuaJit21 => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for Release {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Release::LuaJit20 => f.write_str("LuaJIT 2.0"),
+            Release::LuaJit21 => f.write_str("LuaJIT 2.1"),
+        }
+    }
+}
+
 /// A common ctx used between each LuaJIT version
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct BytecodeContext {
+    /// Bytecode version from the header
+    pub version: u8,
+
     /// Bytecode flags
     pub flags: u64,
 
     /// The endian to read with (when needed)
     pub endian: Endian,
+
+    /// The limits the bytecode is read with
+    pub limits: Limits,
+}
+
+impl Default for BytecodeContext {
+    /// The context of a little endian LuaJIT 2.1 dump without any flags.
+    fn default() -> Self {
+        Self::new(Release::LuaJit21.version(), 0)
+    }
 }
 
 impl BytecodeContext {
+    /// Create a context from the version and flags in the bytecode header,
+    /// picking the endianness from the flags
+    pub fn new(version: u8, flags: u64) -> Self {
+        let endian = if (flags & BYTECODE_IS_BIG_ENDIAN) != 0 {
+            Endian::Big
+        } else {
+            Endian::Little
+        };
+
+        Self {
+            version,
+            flags,
+            endian,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Determine the LuaJIT release that wrote the bytecode
+    #[inline]
+    pub fn release(&self) -> Option<Release> {
+        Release::from_version(self.version)
+    }
+
+    /// Determine if the bytecode is big endian
+    #[inline]
+    pub fn is_big_endian(&self) -> bool {
+        (self.flags & BYTECODE_IS_BIG_ENDIAN) != 0
+    }
+
     /// Determine if the bytecode is stripped of debug information
     #[inline]
     pub fn is_stripped(&self) -> bool {
         (self.flags & BYTECODE_IS_STRIPPED) != 0
     }
+
+    /// Determine if the bytecode uses FFI features
+    #[inline]
+    pub fn has_ffi(&self) -> bool {
+        (self.flags & BYTECODE_HAS_FFI) != 0
+    }
+
+    /// Determine if the bytecode was written by a two-slot frame (FR2/GC64) build
+    #[inline]
+    pub fn is_fr2(&self) -> bool {
+        (self.flags & BYTECODE_IS_FR2) != 0
+    }
 }
@@ -1,33 +1,94 @@
 //! A string type for LuaJIT v1 and v2 bytecode
-//! A string type for LuaJIT v1 and v2 bytecode
-use scroll::{
-    ctx::{self, StrCtx},
-    Pread, Uleb128,
-};
+use luasleuth_common::types::leb128;
+use luasleuth_common::types::Escaped;
+use scroll::{ctx, Pread, Pwrite, Uleb128};
+use std::{borrow::Cow, fmt};
 
 /// String type identifier constant
 const BCDUMP_STR_TYPE: u64 = 5;
 
-#[derive(Debug)]
+/// A string as stored in LuaJIT bytecode, which can hold arbitrary bytes.
+#[derive(Clone)]
 pub struct JitString<'a> {
     pub size: usize,
-    pub data: &'a str,
+    pub data: Cow<'a, [u8]>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for JitString<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        luasleuth_common::serialize::sized_string("JitString", self.size, &self.data, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for JitString<'_> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = luasleuth_common::serialize::sized_string_data(deserializer)?;
+        Ok(Self {
+            size: data.len(),
+            data: data.into(),
+        })
+    }
 }
 
 impl<'a> JitString<'a> {
     /// Create a new JitString from a string slice
     #[inline]
     pub fn new(data: &'a str) -> Self {
-        Self {
-            size: data.len(),
-            data,
-        }
+        Self::from(data.as_bytes())
     }
 
     /// Create an empty JitString
     #[inline]
     pub fn empty() -> Self {
-        Self { size: 0, data: "" }
+        Self {
+            size: 0,
+            data: Cow::Borrowed(b""),
+        }
+    }
+
+    /// Returns the raw bytes of the string.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the string if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+
+    /// Returns the string with invalid UTF-8 sequences replaced by `U+FFFD`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.data)
+    }
+
+    /// Returns a displayable form of the string with control characters and invalid
+    /// UTF-8 escaped, as they would be in a Lua string literal.
+    pub fn escaped(&self) -> Escaped<'_> {
+        Escaped(&self.data)
+    }
+
+    /// Read a string whose length is not biased by the string type identifier.
+    ///
+    /// This is how the chunk name in the header is stored.
+    pub fn read_unbiased(src: &'a [u8], offset: &mut usize) -> Result<Self, scroll::Error> {
+        let size: Uleb128 = src.gread_with(offset, ())?;
+        let size = u64::from(size) as usize;
+
+        let data: &[u8] = src.gread_with(offset, size)?;
+        Ok(Self {
+            size,
+            data: data.into(),
+        })
+    }
+
+    /// Write a string whose length is not biased by the string type identifier.
+    pub fn write_unbiased(&self, dst: &mut [u8], offset: &mut usize) -> Result<(), scroll::Error> {
+        leb128::Uleb128::write(dst, offset, self.data.len() as u64)?;
+        dst.gwrite_with(&*self.data, offset, ())?;
+
+        Ok(())
     }
 }
 
@@ -50,17 +111,29 @@ impl<'a> ctx::TryFromCtx<'a, ()> for JitString<'a> {
             return Ok((JitString::empty(), *offset));
         }
 
-        let data: &str = src.gread_with(offset, StrCtx::Length(size))?;
+        let data: &[u8] = src.gread_with(offset, size)?;
 
-        Ok((Self { size, data }, *offset))
+        Ok((
+            Self {
+                size,
+                data: data.into(),
+            },
+            *offset,
+        ))
     }
 }
 
 impl<'a> From<&'a str> for JitString<'a> {
     fn from(value: &'a str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<'a> From<&'a [u8]> for JitString<'a> {
+    fn from(value: &'a [u8]) -> Self {
         Self {
             size: value.len(),
-            data: value,
+            data: value.into(),
         }
     }
 }
@@ -71,8 +144,30 @@ impl PartialEq for JitString<'_> {
     }
 }
 
+impl PartialEq<&str> for JitString<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        *self.data == *other.as_bytes() && self.size == other.len()
+    }
+}
+
 impl Eq for JitString<'_> {}
 
+impl fmt::Debug for JitString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JitString")
+            .field("size", &self.size)
+            .field("data", &format_args!("\"{}\"", self.escaped()))
+            .finish()
+    }
+}
+
+impl fmt::Display for JitString<'_> {
+    /// Writes the string lossily, see [`JitString::to_string_lossy`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
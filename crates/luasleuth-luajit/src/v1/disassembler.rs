@@ -0,0 +1,29 @@
+use crate::{common::ctx::Release, v2, v2::types::Bytecode};
+use luasleuth_common::{disassembler::Disassemble, Error, Limits};
+
+/// Reads LuaJIT 2.0 dumps with the [`v2`] disassembler, rejecting those of other releases.
+pub struct Disassembler<'a> {
+    inner: v2::disassembler::Disassembler<'a>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            inner: v2::disassembler::Disassembler::new(bytes).with_release(Release::LuaJit20),
+        }
+    }
+
+    /// Set the limits the bytecode is read with, instead of the default ones.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.inner = self.inner.with_limits(limits);
+        self
+    }
+}
+
+impl<'a> Disassemble<Bytecode<'a>> for Disassembler<'a> {
+    type Error = Error;
+
+    fn disassemble(self) -> Result<Bytecode<'a>, Self::Error> {
+        self.inner.disassemble()
+    }
+}
@@ -0,0 +1,8 @@
+//! LuaJIT bytecode dump version 1, as written by LuaJIT 2.0.
+//!
+//! The format only differs from version 2 by its opcode numbering, which [`crate::v2`] decodes
+//! from the header. This module pins the release to LuaJIT 2.0 and otherwise reuses it.
+pub mod disassembler;
+pub mod text;
+
+pub use crate::v2::{assembler, cfg, dot, listing, resolve, types};
@@ -0,0 +1,13 @@
+//! Building LuaJIT 2.0 chunks from the assembly language, see [`crate::v2::text`].
+
+use luasleuth_common::text::{Chunk, TextError};
+
+use crate::{common::ctx::Release, v2::text::build_release, v2::types::Bytecode};
+
+/// Build a chunk from assembly, as dumped by LuaJIT 2.0.
+///
+/// The chunk keeps its debug information when the main function has a `.source`,
+/// otherwise it is stripped.
+pub fn build(chunk: &Chunk) -> Result<Bytecode<'_>, TextError> {
+    build_release(chunk, Release::LuaJit20)
+}
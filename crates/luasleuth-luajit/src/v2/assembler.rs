@@ -0,0 +1,28 @@
+use crate::{common::ctx::BytecodeContext, v2::types::Bytecode};
+use luasleuth_common::{
+    assembler::{write_to_vec, Assemble},
+    Error,
+};
+use scroll::Pwrite;
+
+pub struct Assembler<'a> {
+    bytecode: Bytecode<'a>,
+}
+
+impl<'a> Assembler<'a> {
+    pub fn new(bytecode: Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl<'a> Assemble<Bytecode<'a>> for Assembler<'a> {
+    type Error = Error;
+
+    fn assemble(self) -> Result<Vec<u8>, Self::Error> {
+        let header = &self.bytecode.header;
+        let ctx = BytecodeContext::new(header.version, header.flags.into());
+        let bytes = write_to_vec(|dst| dst.pwrite_with(self.bytecode.clone(), 0, ctx))?;
+
+        Ok(bytes)
+    }
+}
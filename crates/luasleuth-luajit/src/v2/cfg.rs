@@ -0,0 +1,70 @@
+//! Control flow graphs of LuaJIT prototypes.
+
+use luasleuth_common::{
+    cfg::{skip_next, ControlFlowGraph, Edge, EdgeKind},
+    resolve::jump_target,
+};
+
+use crate::v2::{
+    resolve::resolve,
+    types::{instructions::Opcode, Prototype},
+};
+
+/// Build the control flow graph of a prototype, leaving its children out.
+///
+/// Pcs are indexes into [`Prototype::instructions`].
+pub fn build(prototype: &Prototype) -> ControlFlowGraph {
+    ControlFlowGraph::build(prototype.instructions.len(), |pc| successors(prototype, pc))
+}
+
+/// Returns the edges leaving the instruction at `pc`, to the pcs control can go to next.
+///
+/// Comparisons and tests go on with the `JMP` following them when they hold and skip it otherwise.
+pub fn successors(prototype: &Prototype, pc: usize) -> Vec<Edge> {
+    let Some(instruction) = prototype.instructions.get(pc) else {
+        return Vec::new();
+    };
+
+    let jump = |kind| {
+        let operands = resolve(prototype, pc)?;
+        Some(Edge::new(jump_target(&operands)?, kind))
+    };
+
+    match instruction.opcode() {
+        Opcode::ISLT
+        | Opcode::ISGE
+        | Opcode::ISLE
+        | Opcode::ISGT
+        | Opcode::ISEQV
+        | Opcode::ISNEV
+        | Opcode::ISEQS
+        | Opcode::ISNES
+        | Opcode::ISEQN
+        | Opcode::ISNEN
+        | Opcode::ISEQP
+        | Opcode::ISNEP
+        | Opcode::ISTC
+        | Opcode::ISFC
+        | Opcode::IST
+        | Opcode::ISF => skip_next(pc),
+        Opcode::JMP | Opcode::UCLO | Opcode::ISNEXT => jump(EdgeKind::Jump).into_iter().collect(),
+        // Skips the loop when it doesn't run at all
+        Opcode::FORI | Opcode::JFORI => [Edge::new(pc + 1, EdgeKind::True)]
+            .into_iter()
+            .chain(jump(EdgeKind::False))
+            .collect(),
+        // Jump back to the loop body while the loop goes on
+        Opcode::FORL | Opcode::IFORL | Opcode::ITERL | Opcode::IITERL => jump(EdgeKind::True)
+            .into_iter()
+            .chain([Edge::new(pc + 1, EdgeKind::False)])
+            .collect(),
+        Opcode::RETM
+        | Opcode::RET
+        | Opcode::RET0
+        | Opcode::RET1
+        | Opcode::CALLMT
+        | Opcode::CALLT => Vec::new(),
+        // `LOOP` only marks the loop for the JIT compiler, its jump to the loop exit isn't taken
+        _ => vec![Edge::new(pc + 1, EdgeKind::Fallthrough)],
+    }
+}
@@ -1,35 +1,126 @@
 use crate::{
-    common::ctx::BytecodeContext,
+    common::ctx::*,
     v2::types::{Bytecode, Header, Prototype},
 };
-use luasleuth_common::disassembler::Disassemble;
+use luasleuth_common::{
+    disassembler::Disassemble, types::leb128::Uleb128, Error, ErrorContext, Limits,
+};
 use scroll::Pread;
 
+/// Flags each release of the bytecode format can have set
+const KNOWN_FLAGS_20: u64 = BYTECODE_IS_BIG_ENDIAN | BYTECODE_IS_STRIPPED | BYTECODE_HAS_FFI;
+const KNOWN_FLAGS_21: u64 = KNOWN_FLAGS_20 | BYTECODE_IS_FR2;
+
 pub struct Disassembler<'a> {
     bytes: &'a [u8],
+    limits: Limits,
+    release: Option<Release>,
 }
 
 impl<'a> Disassembler<'a> {
     pub fn new(bytes: &'a [u8]) -> Self {
-        Self { bytes }
+        Self {
+            bytes,
+            limits: Limits::default(),
+            release: None,
+        }
+    }
+
+    /// Only accept bytecode written by the given release, instead of either of them.
+    pub fn with_release(mut self, release: Release) -> Self {
+        self.release = Some(release);
+        self
+    }
+
+    /// Set the limits the bytecode is read with, instead of the default ones.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
     }
 }
 
 impl<'a> Disassemble<Bytecode<'a>> for Disassembler<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn disassemble(self) -> Result<Bytecode<'a>, Self::Error> {
         let offset = &mut 0;
 
-        let header: Header = self.bytes.gread_with(offset, scroll::LE)?;
-        println!("{:#?}", header);
-        let ctx = BytecodeContext {
-            flags: header.flags.into(),
-            endian: scroll::LE, // cursed but alright, fix this laterrr
+        let header: Header = self
+            .bytes
+            .gread_with(offset, scroll::LE)
+            .within(*offset, "header")?;
+        let mut ctx = BytecodeContext::new(header.version, header.flags.into());
+        ctx.limits = self.limits;
+        let release = ctx
+            .release()
+            .filter(|&release| self.release.is_none_or(|expected| expected == release))
+            .ok_or_else(|| Error::invalid("Unsupported LuaJIT bytecode version", 3))?;
+
+        let known_flags = match release {
+            Release::LuaJit20 => KNOWN_FLAGS_20,
+            Release::LuaJit21 => KNOWN_FLAGS_21,
         };
-        let prototype: Prototype = self.bytes.gread_with(offset, ctx)?;
-        println!("{:#?}", prototype);
+        if (ctx.flags & !known_flags) != 0 {
+            return Err(Error::invalid("Unknown bytecode flags", 4));
+        }
+
+        // Prototypes are dumped children first, the main chunk being the last one.
+        // A child is claimed by the first prototype after it that references it.
+        // Each prototype on the stack is kept along with how deeply prototypes are nested in it.
+        let mut stack: Vec<(Prototype, usize)> = Vec::new();
+        let mut elements = 0;
+        let mut index = 0;
+        while *offset < self.bytes.len() {
+            let start = *offset;
+            let length: Uleb128 =
+                self.bytes
+                    .pread(start)
+                    .within_element(start, "prototypes", index)?;
+            if u64::from(length) == 0 {
+                break;
+            }
+
+            let mut prototype: Prototype =
+                self.bytes
+                    .gread_with(offset, ctx)
+                    .within_element(start, "prototypes", index)?;
+            elements += prototype.element_count() + 1;
+            self.limits
+                .check_elements(elements)
+                .within_element(start, "prototypes", index)?;
+
+            let mut depth = 0;
+            for _ in 0..prototype.child_count() {
+                let (child, child_depth) = stack.pop().ok_or_else(|| {
+                    Error::invalid("Child prototype referenced before being defined", 0)
+                        .within_element(start, "prototypes", index)
+                })?;
+                depth = depth.max(child_depth + 1);
+                prototype.prototypes.push(child);
+            }
+            self.limits
+                .check_depth(depth)
+                .within_element(start, "prototypes", index)?;
+
+            stack.push((prototype, depth));
+            index += 1;
+        }
+
+        let (prototype, _) = stack
+            .pop()
+            .ok_or_else(|| Error::invalid("Bytecode does not contain any prototypes", *offset))?;
+        if !stack.is_empty() {
+            return Err(Error::invalid(
+                "Bytecode contains unreferenced prototypes",
+                *offset,
+            ));
+        }
 
-        Ok(Bytecode { header, prototype })
+        Ok(Bytecode {
+            header,
+            release,
+            context: ctx,
+            prototype,
+        })
     }
 }
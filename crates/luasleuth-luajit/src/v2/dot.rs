@@ -0,0 +1,78 @@
+//! Control flow graphs of a chunk rendered as Graphviz DOT.
+
+use std::fmt::{self, Write};
+
+use luasleuth_common::dot::{write_cluster, write_graph_end, write_graph_start};
+
+use crate::v2::{
+    cfg::build,
+    listing::{jump_targets, location, write_instruction},
+    types::{constants::GcConstant, Bytecode, Prototype},
+};
+
+/// The control flow graphs of a chunk, with a cluster per prototype.
+pub struct Dot<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Dot<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Dot<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chunk_name = self.bytecode.header.chunk_name.as_ref();
+        let chunk_name = chunk_name.map(|name| name.as_bytes());
+        write_graph_start(f)?;
+        write_prototype(f, &self.bytecode.prototype, chunk_name, &mut 0)?;
+        write_graph_end(f)
+    }
+}
+
+/// Write the cluster of a prototype, then those of its children in the order they are created.
+fn write_prototype(
+    f: &mut impl Write,
+    prototype: &Prototype,
+    chunk_name: Option<&[u8]>,
+    id: &mut usize,
+) -> fmt::Result {
+    let graph = build(prototype);
+    let targets = jump_targets(prototype);
+
+    let first_line = prototype
+        .debug_metadata
+        .as_ref()
+        .map_or(0, |metadata| metadata.first_line.into());
+    let title = location(chunk_name, first_line);
+    write_cluster(f, *id, &title, &graph, |block| {
+        let mut text = String::new();
+        for pc in block.start..block.end {
+            if let Some(&instruction) = prototype.instructions.get(pc) {
+                let target = targets.contains(&(pc + 1));
+                write_instruction(
+                    &mut text,
+                    prototype,
+                    chunk_name,
+                    pc + 1,
+                    instruction,
+                    target,
+                )?;
+            }
+        }
+        Ok(text)
+    })?;
+    *id += 1;
+
+    // Children are found from the last GC constant backwards
+    for constant in prototype.gc_constants.iter().rev() {
+        if let GcConstant::Child(index) = constant {
+            if let Some(child) = prototype.prototypes.get(*index) {
+                write_prototype(f, child, chunk_name, id)?;
+            }
+        }
+    }
+
+    Ok(())
+}
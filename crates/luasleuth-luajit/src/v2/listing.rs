@@ -0,0 +1,220 @@
+//! Listing of a chunk in the layout of `luajit -bl`.
+
+use std::fmt::{self, Write};
+
+use luasleuth_common::listing::format_number;
+
+use crate::v2::types::{
+    constants::{GcConstant, NumConstant},
+    instructions::{Instruction, Opcode, OperandKind},
+    Bytecode, Prototype,
+};
+
+/// Strings longer than this are cut short, like `jit.bc` does
+const MAX_STRING_LENGTH: usize = 40;
+
+/// Bias of the numeric constant of `TSETM`, which stores its index in the low bits of a double
+const TSETM_BIAS: f64 = 4503599627370496.0;
+
+/// A chunk rendered like `luajit -bl` does.
+pub struct Listing<'b, 'a> {
+    bytecode: &'b Bytecode<'a>,
+}
+
+impl<'b, 'a> Listing<'b, 'a> {
+    pub fn new(bytecode: &'b Bytecode<'a>) -> Self {
+        Self { bytecode }
+    }
+}
+
+impl fmt::Display for Listing<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chunk_name = self.bytecode.header.chunk_name.as_ref();
+        let chunk_name = chunk_name.map(|name| name.as_bytes());
+        write_prototype(f, &self.bytecode.prototype, chunk_name)
+    }
+}
+
+/// Write a prototype after its children, the order `luajit -bl` lists them in.
+pub(crate) fn write_prototype(
+    f: &mut impl Write,
+    prototype: &Prototype,
+    chunk_name: Option<&[u8]>,
+) -> fmt::Result {
+    // Children are found from the last GC constant backwards
+    for constant in prototype.gc_constants.iter().rev() {
+        if let GcConstant::Child(index) = constant {
+            if let Some(child) = prototype.prototypes.get(*index) {
+                write_prototype(f, child, chunk_name)?;
+            }
+        }
+    }
+
+    let (first_line, num_lines) = prototype
+        .debug_metadata
+        .as_ref()
+        .map_or((0, 0), |metadata| {
+            (metadata.first_line.into(), metadata.num_lines.into())
+        });
+    let last_line: u64 = first_line + num_lines;
+    writeln!(
+        f,
+        "-- BYTECODE -- {}-{}",
+        location(chunk_name, first_line),
+        last_line
+    )?;
+
+    let targets = jump_targets(prototype);
+    for (pc, &instruction) in prototype.instructions.iter().enumerate() {
+        let target = targets.contains(&(pc + 1));
+        write_instruction(f, prototype, chunk_name, pc + 1, instruction, target)?;
+    }
+
+    f.write_str("\n")
+}
+
+/// Returns the pcs jumped to by the instructions of a prototype, counting from 1 like the listing.
+pub(crate) fn jump_targets(prototype: &Prototype) -> Vec<usize> {
+    (prototype.instructions.iter().enumerate())
+        .filter_map(|(pc, instruction)| match *instruction {
+            Instruction::AJ(_, _, j) => Some((pc as i64 + 1 + j as i64 + 1) as usize),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the `name:line` location of a function, as `jit.util.funcinfo` does.
+pub(crate) fn location(chunk_name: Option<&[u8]>, first_line: u64) -> String {
+    let name = match chunk_name {
+        Some([b'@' | b'=', name @ ..]) => String::from_utf8_lossy(name).into_owned(),
+        Some(source) => {
+            let line = source.split(|&b| b == b'\n').next().unwrap_or_default();
+            let ellipsis = if line.len() < source.len() { "..." } else { "" };
+            format!("[string \"{}{}\"]", String::from_utf8_lossy(line), ellipsis)
+        }
+        None => "?".to_string(),
+    };
+
+    format!("{}:{}", name, first_line)
+}
+
+/// Write one instruction, `pc` counting from 1 as the function header is left out.
+pub(crate) fn write_instruction(
+    f: &mut impl Write,
+    prototype: &Prototype,
+    chunk_name: Option<&[u8]>,
+    pc: usize,
+    instruction: Instruction,
+    target: bool,
+) -> fmt::Result {
+    let opcode = instruction.opcode();
+    let (a, b, d) = match instruction {
+        Instruction::ABC(_, a, b, c) => (a, Some(b), c as u16),
+        Instruction::AD(_, a, d) => (a, None, d),
+        Instruction::AJ(_, a, _) => (a, None, 0),
+    };
+
+    let a_operand = match opcode.mode_a() {
+        OperandKind::None => String::new(),
+        _ => a.to_string(),
+    };
+    let prefix = if target { "=>" } else { "  " };
+    let start = format!(
+        "{:04} {} {:<6} {:>3} ",
+        pc,
+        prefix,
+        format!("{:?}", opcode),
+        a_operand
+    );
+
+    if let Instruction::AJ(_, _, j) = instruction {
+        return writeln!(f, "{}=> {:04}", start, pc as i64 + j as i64 + 1);
+    }
+
+    let kind = opcode.mode_d();
+    if b.is_none() && kind == OperandKind::None {
+        return writeln!(f, "{}", start);
+    }
+
+    let upvalue_name = |index: u16| {
+        prototype
+            .debug_info
+            .as_ref()
+            .and_then(|info| info.upvalue_names.get(index as usize))
+            .map(|name| name.to_string())
+    };
+
+    let mut comment = match kind {
+        OperandKind::Str => match prototype.gc_constant(d as usize) {
+            Some(GcConstant::String(string)) => Some(quote(&string.data)),
+            _ => None,
+        },
+        OperandKind::Num => {
+            prototype
+                .num_constants
+                .get(d as usize)
+                .map(|number| match (opcode, number) {
+                    (Opcode::TSETM, NumConstant::Number(value)) => {
+                        format_number(value - TSETM_BIAS)
+                    }
+                    (_, NumConstant::Number(value)) => format_number(*value),
+                    (_, NumConstant::Integer(value)) => value.to_string(),
+                })
+        }
+        OperandKind::Func => match prototype.gc_constant(d as usize) {
+            Some(GcConstant::Child(index)) => prototype.prototypes.get(*index).map(|child| {
+                let first_line = child
+                    .debug_metadata
+                    .as_ref()
+                    .map_or(0, |metadata| metadata.first_line.into());
+                location(chunk_name, first_line)
+            }),
+            _ => None,
+        },
+        OperandKind::Uv => upvalue_name(d),
+        _ => None,
+    };
+
+    if opcode.mode_a() == OperandKind::Uv {
+        if let Some(name) = upvalue_name(a as u16) {
+            comment = Some(match comment {
+                Some(comment) => format!("{} ; {}", name, comment),
+                None => name,
+            });
+        }
+    }
+
+    match (b, comment) {
+        (Some(b), Some(comment)) => writeln!(f, "{}{:3} {:3}  ; {}", start, b, d, comment),
+        (Some(b), None) => writeln!(f, "{}{:3} {:3}", start, b, d),
+        (None, Some(comment)) => writeln!(f, "{}{:3}      ; {}", start, d, comment),
+        (None, None) if kind == OperandKind::Lits => writeln!(f, "{}{:3}", start, d as i16),
+        (None, None) => writeln!(f, "{}{:3}", start, d),
+    }
+}
+
+/// Quote a string constant, escaping control characters and cutting long strings short.
+fn quote(string: &[u8]) -> String {
+    let mut escaped = String::new();
+    for chunk in string.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                c if c.is_ascii_control() => {
+                    let _ = write!(escaped, "\\{:03}", c as u32);
+                }
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "\\{:03}", byte);
+        }
+    }
+
+    match escaped.char_indices().nth(MAX_STRING_LENGTH) {
+        Some((end, _)) => format!("\"{}\"~", &escaped[..end]),
+        None => format!("\"{}\"", escaped),
+    }
+}
@@ -0,0 +1,106 @@
+//! Resolution of instruction operands against their prototype, driven by the operand modes.
+
+use luasleuth_common::resolve::ResolvedOperand;
+
+use crate::v2::types::{
+    constants::{GcConstant, NumConstant},
+    instructions::{Instruction, OperandKind},
+    Prototype,
+};
+
+/// A constant an operand refers to, LuaJIT keeping GC and numeric constants apart.
+#[derive(Debug, Clone, Copy)]
+pub enum Constant<'p, 'a> {
+    Gc(&'p GcConstant<'a>),
+    Num(&'p NumConstant),
+}
+
+/// An operand of a LuaJIT instruction.
+pub type Operand<'p, 'a> = ResolvedOperand<'p, Constant<'p, 'a>>;
+
+/// Resolve the operands of the instruction at `pc`, in the order they are encoded.
+///
+/// `pc` is an index into [`Prototype::instructions`], and so are jump targets. Operands the
+/// instruction doesn't use are left out. Returns `None` when `pc` is out of range.
+pub fn resolve<'p, 'a>(prototype: &'p Prototype<'a>, pc: usize) -> Option<Vec<Operand<'p, 'a>>> {
+    let instruction = *prototype.instructions.get(pc)?;
+    let opcode = instruction.opcode();
+
+    let operands = match instruction {
+        Instruction::ABC(_, a, b, c) => vec![
+            (opcode.mode_a(), a as i64),
+            (opcode.mode_b(), b as i64),
+            (opcode.mode_c(), c as i64),
+        ],
+        Instruction::AD(_, a, d) => {
+            let d = match opcode.mode_d() {
+                OperandKind::Lits => d as i16 as i64,
+                _ => d as i64,
+            };
+            vec![(opcode.mode_a(), a as i64), (opcode.mode_d(), d)]
+        }
+        Instruction::AJ(_, a, j) => vec![
+            (opcode.mode_a(), a as i64),
+            (OperandKind::Jump, pc as i64 + 1 + j as i64),
+        ],
+    };
+
+    let operands = operands
+        .into_iter()
+        .filter(|&(kind, _)| kind != OperandKind::None)
+        .map(|(kind, value)| resolve_operand(prototype, pc, kind, value))
+        .collect();
+
+    Some(operands)
+}
+
+fn resolve_operand<'p, 'a>(
+    prototype: &'p Prototype<'a>,
+    pc: usize,
+    kind: OperandKind,
+    value: i64,
+) -> Operand<'p, 'a> {
+    let index = value as u32;
+    match kind {
+        kind if kind.is_register() => Operand::Register {
+            index,
+            name: prototype.debug_info.as_ref().and_then(|info| {
+                let locals = info.locals_at_pc(pc);
+                locals
+                    .get(index as usize)
+                    .map(|local| local.name.as_bytes())
+            }),
+        },
+        OperandKind::Uv => Operand::Upvalue {
+            index,
+            name: prototype
+                .debug_info
+                .as_ref()
+                .and_then(|info| info.upvalue_names.get(index as usize))
+                .map(|name| name.as_bytes()),
+        },
+        OperandKind::Num => Operand::Constant {
+            index,
+            value: prototype
+                .num_constants
+                .get(index as usize)
+                .map(Constant::Num),
+        },
+        OperandKind::Jump => Operand::Jump { target: value },
+        kind if kind.is_gc_constant() => {
+            // GC constants are addressed from the end
+            let constant = prototype.gc_constant(index as usize);
+
+            match (kind, constant) {
+                (OperandKind::Func, Some(GcConstant::Child(child))) => Operand::Prototype {
+                    index: *child as u32,
+                },
+                _ => Operand::Constant {
+                    index,
+                    value: constant.map(Constant::Gc),
+                },
+            }
+        }
+        _ => Operand::Literal(value),
+    }
+}
@@ -0,0 +1,332 @@
+//! Building LuaJIT chunks from assembly, see [`luasleuth_common::text`].
+
+use luasleuth_common::text::{
+    Chunk, Function, Item, Mnemonics, Operand, Scope, Statement, TextError,
+};
+
+use crate::common::{
+    ctx::{BytecodeContext, Release, BYTECODE_IS_FR2, BYTECODE_IS_STRIPPED},
+    jitstring::JitString,
+};
+use crate::v2::types::{
+    constants::{GcConstant, NumConstant},
+    debug_info::{DebugInfo, DebugInfoMetadata, VariableInfo, VariableName},
+    instructions::{constants::*, Instruction, Opcode, OperandKind},
+    Bytecode, Header, Prototype, PROTO_CHILD, PROTO_VARARG,
+};
+
+/// The signature of LuaJIT bytecode dumps
+const SIGNATURE: [u8; 3] = *b"\x1bLJ";
+
+/// Builtin variable names, which are spelled the way LuaJIT reports them
+const BUILTIN_NAMES: [VariableName<'static>; 6] = [
+    VariableName::ForIndex,
+    VariableName::ForStop,
+    VariableName::ForStep,
+    VariableName::ForGenerator,
+    VariableName::ForState,
+    VariableName::ForControl,
+];
+
+/// Build a chunk from assembly, as dumped by a stock 64-bit (GC64) LuaJIT 2.1 build.
+///
+/// The chunk keeps its debug information when the main function has a `.source`,
+/// otherwise it is stripped.
+pub fn build(chunk: &Chunk) -> Result<Bytecode<'_>, TextError> {
+    build_release(chunk, Release::LuaJit21)
+}
+
+/// Build a chunk from assembly for the given release, only LuaJIT 2.1 using two-slot frames.
+pub fn build_release(chunk: &Chunk, release: Release) -> Result<Bytecode<'_>, TextError> {
+    let mut chunk_name = None;
+    for directive in directives(&chunk.main).filter(|directive| directive.name == "source") {
+        directive.expect_at_most(1)?;
+        chunk_name = Some(JitString::from(
+            directive.operand(0)?.string(directive.line)?,
+        ));
+    }
+
+    let mut flags = match release {
+        Release::LuaJit20 => 0,
+        Release::LuaJit21 => BYTECODE_IS_FR2,
+    };
+    if chunk_name.is_none() {
+        flags |= BYTECODE_IS_STRIPPED;
+    }
+    let context = BytecodeContext::new(release.version(), flags);
+
+    let mnemonics = Mnemonics::new();
+    let prototype = build_prototype(&chunk.main, true, context, &mnemonics)?;
+
+    Ok(Bytecode {
+        header: Header {
+            signature: SIGNATURE,
+            version: release.version(),
+            flags: flags.into(),
+            chunk_name,
+        },
+        release,
+        context,
+        prototype,
+    })
+}
+
+fn directives(function: &Function) -> impl Iterator<Item = &Statement> {
+    function.items.iter().filter_map(|item| match item {
+        Item::Directive(directive) => Some(directive),
+        _ => None,
+    })
+}
+
+fn build_prototype<'a>(
+    function: &'a Function,
+    is_main: bool,
+    ctx: BytecodeContext,
+    mnemonics: &Mnemonics<Opcode>,
+) -> Result<Prototype<'a>, TextError> {
+    let scope = function.scope()?;
+
+    let mut flags = 0;
+    let mut parameter_count = 0;
+    let mut frame_size = 2;
+    let mut first_line: u64 = 0;
+    let mut last_line: u64 = 0;
+    let mut upvalues = Vec::new();
+    let mut upvalue_names = Vec::new();
+    let mut gc_constants = Vec::new();
+    let mut num_constants = Vec::new();
+    let mut prototypes = Vec::new();
+    let mut variables = Vec::new();
+    let mut instructions = Vec::new();
+
+    // Constants come first, as instructions can refer to ones declared after them
+    let mut line = None;
+    for item in &function.items {
+        match item {
+            Item::Directive(directive) => {
+                let at = directive.line;
+                match directive.name.as_str() {
+                    "source" if is_main => {}
+                    "source" => {
+                        return Err(
+                            directive.error("LuaJIT only has a source for the main function")
+                        )
+                    }
+                    "lines" => {
+                        directive.expect_at_most(2)?;
+                        first_line = directive.operand(0)?.integer(at)?;
+                        last_line = directive.operand(1)?.integer(at)?;
+                    }
+                    "params" => {
+                        directive.expect_at_most(1)?;
+                        parameter_count = directive.operand(0)?.integer(at)?;
+                    }
+                    "vararg" => {
+                        directive.expect_at_most(0)?;
+                        flags |= PROTO_VARARG;
+                    }
+                    "flags" => {
+                        directive.expect_at_most(1)?;
+                        flags |= directive.operand(0)?.integer::<u8>(at)?;
+                    }
+                    "stack" => {
+                        directive.expect_at_most(1)?;
+                        frame_size = directive.operand(0)?.integer(at)?;
+                    }
+                    "upval" => {
+                        directive.expect_at_most(2)?;
+                        upvalue_names.push(JitString::from(directive.operand(0)?.identifier(at)?));
+                        upvalues.push(directive.operand(1)?.integer(at)?);
+                    }
+                    "const" => {
+                        directive.expect_at_most(1)?;
+                        match directive.operand(0)? {
+                            Operand::String(value) => gc_constants
+                                .push(GcConstant::String(JitString::from(value.as_slice()))),
+                            Operand::Integer(value) => {
+                                num_constants.push(match i32::try_from(*value) {
+                                    Ok(value) => NumConstant::Integer(value),
+                                    Err(_) => NumConstant::Number(*value as f64),
+                                })
+                            }
+                            operand => num_constants.push(NumConstant::Number(operand.number(at)?)),
+                        }
+                    }
+                    "local" => {
+                        directive.expect_at_most(3)?;
+                        let name = directive.operand(0)?.identifier(at)?;
+                        let name = BUILTIN_NAMES
+                            .into_iter()
+                            .find(|builtin| builtin.as_bytes() == name)
+                            .unwrap_or(VariableName::Named(JitString::from(name)));
+                        variables.push(VariableInfo {
+                            name,
+                            start_pc: pc_operand(&scope, directive.operand(1)?, at)?,
+                            end_pc: pc_operand(&scope, directive.operand(2)?, at)?,
+                        });
+                    }
+                    "line" => {
+                        directive.expect_at_most(1)?;
+                        line = Some(directive.operand(0)?.integer::<u64>(at)?);
+                    }
+                    _ => return Err(directive.unknown_directive()),
+                }
+            }
+            Item::Instruction(instruction) => instructions.push((instruction, line)),
+            Item::Function(child) => {
+                gc_constants.push(GcConstant::Child(prototypes.len()));
+                prototypes.push(build_prototype(child, false, ctx, mnemonics)?);
+            }
+            Item::Label { .. } => {}
+        }
+    }
+    if !prototypes.is_empty() {
+        flags |= PROTO_CHILD;
+    }
+
+    let constants = Constants {
+        gc_constants: &gc_constants,
+        scope: &scope,
+    };
+    let mut code = Vec::with_capacity(instructions.len());
+    let mut line_info = Vec::with_capacity(instructions.len());
+    for (pc, (instruction, line)) in instructions.iter().enumerate() {
+        code.push(build_instruction(instruction, pc, &constants, mnemonics)?);
+
+        let line = line.unwrap_or(first_line);
+        let delta = line
+            .checked_sub(first_line)
+            .ok_or_else(|| instruction.error("Line is before the first line of the function"))?;
+        line_info.push(Operand::Integer(delta as i64).integer(instruction.line)?);
+    }
+
+    let (debug_metadata, debug_info) = match ctx.is_stripped() {
+        true => (None, None),
+        false => {
+            let num_lines = line_info
+                .iter()
+                .map(|&delta| delta as u64)
+                .fold(last_line.saturating_sub(first_line), u64::max);
+            let metadata = DebugInfoMetadata {
+                size: 0u64.into(),
+                first_line: first_line.into(),
+                num_lines: num_lines.into(),
+            };
+            let debug_info = DebugInfo {
+                first_line,
+                line_info,
+                upvalue_names,
+                variables,
+            };
+            (Some(metadata), Some(debug_info))
+        }
+    };
+
+    Ok(Prototype {
+        prototype_length: 0u64.into(),
+        flags,
+        parameter_count,
+        frame_size,
+        upvalue_count: upvalues.len() as u8,
+        gc_constant_count: gc_constants.len().into(),
+        num_constant_count: num_constants.len().into(),
+        instruction_count: code.len().into(),
+        debug_metadata,
+        instructions: code,
+        upvalues,
+        gc_constants,
+        num_constants,
+        debug_info,
+        prototypes,
+    })
+}
+
+/// What instruction operands can refer to by name or value.
+struct Constants<'s, 'a> {
+    gc_constants: &'s [GcConstant<'a>],
+    scope: &'s Scope<'s>,
+}
+
+impl Constants<'_, '_> {
+    /// Returns the index of the GC constant an operand refers to, counted from the end.
+    ///
+    /// Strings and child functions can be given by value and name, any operand by index.
+    fn gc_index(&self, operand: &Operand, line: usize) -> Result<i64, TextError> {
+        let position = match operand {
+            Operand::String(value) => self.gc_constants.iter().position(|constant| {
+                matches!(constant, GcConstant::String(string) if string.data == value.as_slice())
+            }),
+            Operand::Name(_) => {
+                let child = self.scope.child(operand, line)? as usize;
+                self.gc_constants
+                    .iter()
+                    .position(|constant| matches!(constant, GcConstant::Child(index) if *index == child))
+            }
+            operand => return operand.integer(line),
+        };
+
+        let position = position.ok_or_else(|| {
+            TextError::new(line, format!("{} is not declared with .const", operand))
+        })?;
+        Ok((self.gc_constants.len() - 1 - position) as i64)
+    }
+
+    /// Returns the value of an operand of the given kind.
+    fn operand(&self, kind: OperandKind, operand: &Operand, line: usize) -> Result<i64, TextError> {
+        match kind {
+            kind if kind.is_gc_constant() => self.gc_index(operand, line),
+            _ => operand.integer(line),
+        }
+    }
+}
+
+fn build_instruction(
+    instruction: &Statement,
+    pc: usize,
+    constants: &Constants,
+    mnemonics: &Mnemonics<Opcode>,
+) -> Result<Instruction, TextError> {
+    let line = instruction.line;
+    let opcode = mnemonics.find(instruction)?;
+    let a = instruction.operand(0)?.unsigned(line, SIZE_A)? as u8;
+
+    let built = if opcode.is_jump() {
+        instruction.expect_at_most(2)?;
+        let j = constants.scope.jump(instruction.operand(1)?, pc, line)?;
+        let biased = Operand::Integer(j + BCBIAS_J as i64).unsigned(line, SIZE_D);
+        biased.map_err(|_| instruction.error(format!("Jump of {} is out of range", j)))?;
+        Instruction::AJ(opcode, a, j as i32)
+    } else if opcode.uses_ad_format() {
+        instruction.expect_at_most(2)?;
+        let d = constants.operand(opcode.mode_d(), instruction.operand(1)?, line)?;
+        Instruction::AD(
+            opcode,
+            a,
+            Operand::Integer(d).unsigned(line, SIZE_D)? as u16,
+        )
+    } else {
+        instruction.expect_at_most(3)?;
+        let b = constants.operand(opcode.mode_b(), instruction.operand(1)?, line)?;
+        let c = constants.operand(opcode.mode_c(), instruction.operand(2)?, line)?;
+        Instruction::ABC(
+            opcode,
+            a,
+            Operand::Integer(b).unsigned(line, SIZE_B)? as u8,
+            Operand::Integer(c).unsigned(line, SIZE_C)? as u8,
+        )
+    };
+
+    Ok(built)
+}
+
+/// Returns the bytecode position of a `.local` bound, given as a label or a position.
+///
+/// Labels are shifted by one, as positions count the function header LuaJIT leaves out.
+fn pc_operand(scope: &Scope, operand: &Operand, line: usize) -> Result<u32, TextError> {
+    let pc = match operand {
+        Operand::Name(_) => scope.position(operand, line)? + 1,
+        operand => operand.integer(line)?,
+    };
+
+    Operand::Integer(pc).integer(line)
+}
@@ -1,15 +1,23 @@
+pub mod constants;
 pub mod debug_info;
 pub mod instructions;
 
-use luasleuth_common::{
-    try_gread_vec_with,
-    types::{leb128::Uleb128, Bytecode as BytecodeTrait},
+use luasleuth_common::types::{leb128::Uleb128, Bytecode as BytecodeTrait};
+use luasleuth_common::{assembler::write_to_vec, Error, ErrorContext};
+use scroll::{ctx, Endian, Pread, Pwrite};
+
+use crate::common::{
+    ctx::{BytecodeContext, Release},
+    jitstring::JitString,
 };
-use scroll::{ctx, Endian, Pread};
 
-use crate::common::{ctx::BytecodeContext, jitstring::JitString};
+/// Prototype flag set when the prototype has child prototypes
+pub const PROTO_CHILD: u8 = 0x01;
+/// Prototype flag set when the prototype takes a variable number of arguments
+pub const PROTO_VARARG: u8 = 0x02;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header<'a> {
     pub signature: [u8; 3],
     pub version: u8,
@@ -17,8 +25,9 @@ pub struct Header<'a> {
     pub chunk_name: Option<JitString<'a>>,
 }
 
-#[derive(Debug)]
-pub struct Prototype {
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Prototype<'a> {
     /// Total size of the prototype
     pub prototype_length: Uleb128,
 
@@ -38,31 +47,87 @@ pub struct Prototype {
 
     pub debug_metadata: Option<debug_info::DebugInfoMetadata>,
     pub instructions: Vec<instructions::Instruction>,
+
+    /// Upvalue references into the enclosing function
+    pub upvalues: Vec<u16>,
+
+    /// GC constants in the order they are stored in the dump.
+    ///
+    /// Instructions refer to these from the end, see [`Prototype::gc_constant`].
+    pub gc_constants: Vec<constants::GcConstant<'a>>,
+    pub num_constants: Vec<constants::NumConstant>,
+
+    /// Debug information, absent when the bytecode is stripped
+    pub debug_info: Option<debug_info::DebugInfo<'a>>,
+
+    /// Child prototypes, in the order of their `GcConstant::Child` constants
+    pub prototypes: Vec<Prototype<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bytecode<'a> {
     pub header: Header<'a>,
-    pub prototype: Prototype,
+    /// The LuaJIT release that wrote the bytecode, which decides the opcode numbering
+    pub release: Release,
+    /// The flavour of the bytecode, as described by the header flags
+    ///
+    /// It is not serialized, deserializing leaves the default context instead of the one
+    /// of the header.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub context: BytecodeContext,
+    /// The main chunk of the bytecode
+    pub prototype: Prototype<'a>,
+}
+
+impl<'a> Prototype<'a> {
+    /// Returns the GC constant an instruction operand refers to.
+    ///
+    /// LuaJIT addresses GC constants backwards, operand `0` being the last one in the dump.
+    pub fn gc_constant(&self, index: usize) -> Option<&constants::GcConstant<'a>> {
+        let count = self.gc_constants.len();
+        if index >= count {
+            return None;
+        }
+
+        self.gc_constants.get(count - 1 - index)
+    }
+
+    /// Returns the number of elements in the arrays of this prototype, without its children.
+    pub fn element_count(&self) -> usize {
+        self.instructions.len()
+            + self.upvalues.len()
+            + self.gc_constants.len()
+            + self.num_constants.len()
+            + self
+                .debug_info
+                .as_ref()
+                .map_or(0, debug_info::DebugInfo::element_count)
+    }
+
+    /// Returns the amount of child prototypes referenced by the GC constants.
+    pub fn child_count(&self) -> usize {
+        self.gc_constants
+            .iter()
+            .filter(|constant| matches!(constant, constants::GcConstant::Child(_)))
+            .count()
+    }
 }
 
 impl<'a> ctx::TryFromCtx<'a, Endian> for Header<'a> {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: Endian) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let signature: [u8; 3] = src.gread_with(offset, ctx)?;
-        let version: u8 = src.gread_with(offset, ctx)?;
-        let flags: Uleb128 = src.gread_with(offset, ())?;
+        let signature: [u8; 3] = src.gread_with(offset, ctx).within(*offset, "signature")?;
+        let version: u8 = src.gread_with(offset, ctx).within(*offset, "version")?;
+        let flags: Uleb128 = src.gread_with(offset, ()).within(*offset, "flags")?;
 
-        let context = BytecodeContext {
-            flags: flags.into(),
-            endian: ctx,
-        };
+        let context = BytecodeContext::new(version, flags.into());
 
         let chunk_name: Option<JitString> = if !context.is_stripped() {
-            Some(src.gread_with(offset, ())?)
+            Some(JitString::read_unbiased(src, offset).within(*offset, "chunk_name")?)
         } else {
             None
         };
@@ -79,32 +144,130 @@ impl<'a> ctx::TryFromCtx<'a, Endian> for Header<'a> {
     }
 }
 
-impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for Prototype {
-    type Error = scroll::Error;
+impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for Prototype<'a> {
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let prototype_length: Uleb128 = src.gread_with(offset, ())?;
-        let flags: u8 = src.gread_with(offset, ctx.endian)?;
-        let parameter_count: u8 = src.gread_with(offset, ctx.endian)?;
-        let frame_size: u8 = src.gread_with(offset, ctx.endian)?;
+        let prototype_length: Uleb128 = src
+            .gread_with(offset, ())
+            .within(*offset, "prototype_length")?;
+        let start = *offset;
+
+        let flags: u8 = src
+            .gread_with(offset, ctx.endian)
+            .within(*offset, "flags")?;
+        let parameter_count: u8 = src
+            .gread_with(offset, ctx.endian)
+            .within(*offset, "parameter_count")?;
+        let frame_size: u8 = src
+            .gread_with(offset, ctx.endian)
+            .within(*offset, "frame_size")?;
 
-        let upvalue_count: u8 = src.gread_with(offset, ctx.endian)?;
-        let gc_constant_count: Uleb128 = src.gread_with(offset, ())?;
-        let num_constant_count: Uleb128 = src.gread_with(offset, ())?;
+        let upvalue_count: u8 = src
+            .gread_with(offset, ctx.endian)
+            .within(*offset, "upvalue_count")?;
+        let gc_constant_count: Uleb128 = src
+            .gread_with(offset, ())
+            .within(*offset, "gc_constant_count")?;
+        let num_constant_count: Uleb128 = src
+            .gread_with(offset, ())
+            .within(*offset, "num_constant_count")?;
 
-        let instruction_count: Uleb128 = src.gread_with(offset, ())?;
+        let instruction_count: Uleb128 = src
+            .gread_with(offset, ())
+            .within(*offset, "instruction_count")?;
         let instruction_count: usize = instruction_count.into();
 
         let debug_metadata: Option<debug_info::DebugInfoMetadata> = if !ctx.is_stripped() {
-            Some(src.gread_with(offset, ctx)?)
+            Some(
+                src.gread_with(offset, ctx)
+                    .within(*offset, "debug_metadata")?,
+            )
         } else {
             None
         };
 
-        let instructions: Vec<instructions::Instruction> =
-            try_gread_vec_with!(src, offset, instruction_count, ctx);
+        let instruction_count = ctx
+            .limits
+            .check_count(instruction_count as u64, src.len() - *offset)
+            .within(*offset, "instructions")?;
+        let mut instructions = Vec::with_capacity(instruction_count);
+        for index in 0..instruction_count {
+            let instruction: instructions::Instruction = src
+                .gread_with(offset, ctx)
+                .within_element(*offset, "instructions", index)?;
+            instructions.push(instruction);
+        }
+
+        let mut upvalues = Vec::with_capacity(upvalue_count as usize);
+        for index in 0..upvalue_count as usize {
+            let upvalue: u16 = src
+                .gread_with(offset, ctx.endian)
+                .within_element(*offset, "upvalues", index)?;
+            upvalues.push(upvalue);
+        }
+
+        let gc_constant_count: usize = gc_constant_count.into();
+        let gc_constant_count = ctx
+            .limits
+            .check_count(gc_constant_count as u64, src.len() - *offset)
+            .within(*offset, "gc_constants")?;
+        let mut gc_constants = Vec::with_capacity(gc_constant_count);
+        let mut child_index = 0;
+        for index in 0..gc_constant_count {
+            let constant =
+                src.gread_with(offset, ctx)
+                    .within_element(*offset, "gc_constants", index)?;
+            let constant = match constant {
+                constants::GcConstant::Child(_) => {
+                    child_index += 1;
+                    constants::GcConstant::Child(child_index - 1)
+                }
+                constant => constant,
+            };
+            gc_constants.push(constant);
+        }
+
+        let num_constant_count: usize = num_constant_count.into();
+        let num_constant_count = ctx
+            .limits
+            .check_count(num_constant_count as u64, src.len() - *offset)
+            .within(*offset, "num_constants")?;
+        let mut num_constants = Vec::with_capacity(num_constant_count);
+        for index in 0..num_constant_count {
+            let constant: constants::NumConstant =
+                src.gread_with(offset, ctx)
+                    .within_element(*offset, "num_constants", index)?;
+            num_constants.push(constant);
+        }
+
+        let debug_info: Option<debug_info::DebugInfo> = match &debug_metadata {
+            Some(metadata) if u64::from(metadata.size) != 0 => {
+                let start = *offset;
+                let data: &[u8] = src
+                    .gread_with(offset, usize::from(metadata.size))
+                    .within(*offset, "debug_info")?;
+                let debug_ctx = debug_info::DebugInfoCtx {
+                    bytecode: ctx,
+                    first_line: metadata.first_line.into(),
+                    num_lines: metadata.num_lines.into(),
+                    instruction_count,
+                    upvalue_count: upvalue_count as usize,
+                };
+
+                Some(data.pread_with(0, debug_ctx).within(start, "debug_info")?)
+            }
+            _ => None,
+        };
+
+        if (*offset - start) as u64 != u64::from(prototype_length) {
+            return Err(Error::invalid(
+                "Prototype length does not match its contents",
+                0,
+            ));
+        }
 
         Ok((
             Self {
@@ -113,17 +276,130 @@ impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for Prototype {
                 parameter_count,
                 frame_size,
                 upvalue_count,
-                gc_constant_count,
-                num_constant_count,
+                gc_constant_count: gc_constant_count.into(),
+                num_constant_count: num_constant_count.into(),
                 instruction_count: instruction_count.into(),
                 debug_metadata,
                 instructions,
+                upvalues,
+                gc_constants,
+                num_constants,
+                debug_info,
+                prototypes: Vec::new(),
             },
             *offset,
         ))
     }
 }
 
+impl Prototype<'_> {
+    /// Write everything after the prototype length, which depends on the size of this.
+    fn write_body(&self, dst: &mut [u8], ctx: BytecodeContext) -> Result<usize, scroll::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.flags, offset, ctx.endian)?;
+        dst.gwrite_with(self.parameter_count, offset, ctx.endian)?;
+        dst.gwrite_with(self.frame_size, offset, ctx.endian)?;
+        dst.gwrite_with(self.upvalues.len() as u8, offset, ctx.endian)?;
+        Uleb128::write(dst, offset, self.gc_constants.len() as u64)?;
+        Uleb128::write(dst, offset, self.num_constants.len() as u64)?;
+        Uleb128::write(dst, offset, self.instructions.len() as u64)?;
+
+        let debug_info = match (&self.debug_metadata, &self.debug_info) {
+            (Some(metadata), Some(debug_info)) if !ctx.is_stripped() => {
+                let debug_ctx = debug_info::DebugInfoCtx {
+                    bytecode: ctx,
+                    first_line: metadata.first_line.into(),
+                    num_lines: metadata.num_lines.into(),
+                    instruction_count: self.instructions.len(),
+                    upvalue_count: self.upvalues.len(),
+                };
+                write_to_vec(|dst| dst.pwrite_with(debug_info.clone(), 0, debug_ctx))?
+            }
+            _ => Vec::new(),
+        };
+        if !ctx.is_stripped() {
+            Uleb128::write(dst, offset, debug_info.len() as u64)?;
+            if let (Some(metadata), false) = (&self.debug_metadata, debug_info.is_empty()) {
+                Uleb128::write(dst, offset, metadata.first_line.into())?;
+                Uleb128::write(dst, offset, metadata.num_lines.into())?;
+            }
+        }
+
+        for instruction in &self.instructions {
+            dst.gwrite_with(*instruction, offset, ctx)?;
+        }
+        for upvalue in &self.upvalues {
+            dst.gwrite_with(*upvalue, offset, ctx.endian)?;
+        }
+        for constant in &self.gc_constants {
+            dst.gwrite_with(constant.clone(), offset, ctx)?;
+        }
+        for constant in &self.num_constants {
+            dst.gwrite_with(*constant, offset, ctx)?;
+        }
+        dst.gwrite_with(debug_info.as_slice(), offset, ())?;
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<Endian> for Header<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: Endian) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.signature, offset, ctx)?;
+        dst.gwrite_with(self.version, offset, ctx)?;
+        Uleb128::write(dst, offset, self.flags.into())?;
+
+        let context = BytecodeContext::new(self.version, self.flags.into());
+        if !context.is_stripped() {
+            self.chunk_name
+                .unwrap_or_else(JitString::empty)
+                .write_unbiased(dst, offset)?;
+        }
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<BytecodeContext> for Prototype<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(mut self, dst: &mut [u8], ctx: BytecodeContext) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        // Children are dumped before their parent, the one referenced by the last constant first
+        let prototypes = std::mem::take(&mut self.prototypes);
+        for child in prototypes.into_iter().rev() {
+            dst.gwrite_with(child, offset, ctx)?;
+        }
+
+        let body = write_to_vec(|dst| self.write_body(dst, ctx))?;
+        Uleb128::write(dst, offset, body.len() as u64)?;
+        dst.gwrite_with(body.as_slice(), offset, ())?;
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<BytecodeContext> for Bytecode<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: BytecodeContext) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        dst.gwrite_with(self.header, offset, scroll::LE)?;
+        dst.gwrite_with(self.prototype, offset, ctx)?;
+        // A zero length marks the end of the prototypes
+        dst.gwrite_with(0u8, offset, ctx.endian)?;
+
+        Ok(*offset)
+    }
+}
+
 impl BytecodeTrait for Bytecode<'_> {
     fn identifier() -> &'static str {
         "luajit-v2"
@@ -0,0 +1,393 @@
+use luasleuth_common::types::leb128::Uleb128;
+use luasleuth_common::{Error, ErrorContext, Limits};
+use scroll::{ctx, Pread, Pwrite};
+
+use crate::common::{ctx::BytecodeContext, jitstring::JitString};
+
+/// Reference to a child function prototype
+pub const BCDUMP_KGC_CHILD: u64 = 0;
+/// A table constant
+pub const BCDUMP_KGC_TAB: u64 = 1;
+/// A 64-bit signed integer (FFI)
+pub const BCDUMP_KGC_I64: u64 = 2;
+/// A 64-bit unsigned integer (FFI)
+pub const BCDUMP_KGC_U64: u64 = 3;
+/// A complex number (FFI)
+pub const BCDUMP_KGC_COMPLEX: u64 = 4;
+/// A string constant, with the length encoded in the type value
+pub const BCDUMP_KGC_STR: u64 = 5;
+
+pub const BCDUMP_KTAB_NIL: u64 = 0;
+pub const BCDUMP_KTAB_FALSE: u64 = 1;
+pub const BCDUMP_KTAB_TRUE: u64 = 2;
+pub const BCDUMP_KTAB_INT: u64 = 3;
+pub const BCDUMP_KTAB_NUM: u64 = 4;
+pub const BCDUMP_KTAB_STR: u64 = 5;
+
+/// A garbage collected constant of a prototype.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GcConstant<'a> {
+    /// A child prototype, stored as an index into `Prototype::prototypes`
+    Child(usize),
+    Table(TableConstant<'a>),
+    I64(i64),
+    U64(u64),
+    Complex(
+        #[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64,
+        #[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64,
+    ),
+    String(JitString<'a>),
+}
+
+/// A template table constant, used by `TDUP`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableConstant<'a> {
+    /// Values of the array part, starting at index 0
+    pub array: Vec<TableValue<'a>>,
+    /// Key/value pairs of the hash part
+    pub hash: Vec<(TableValue<'a>, TableValue<'a>)>,
+}
+
+/// A value stored inside of a template table constant.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TableValue<'a> {
+    Nil,
+    False,
+    True,
+    Integer(i32),
+    Number(#[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64),
+    String(JitString<'a>),
+}
+
+/// A numeric constant of a prototype.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NumConstant {
+    Integer(i32),
+    Number(#[cfg_attr(feature = "serde", serde(with = "luasleuth_common::serialize::float"))] f64),
+}
+
+impl GcConstant<'_> {
+    /// Returns the type of the constant in the LuaJIT bytecode format.
+    ///
+    /// For strings this is `BCDUMP_KGC_STR`, the length is not added on top.
+    pub fn get_type(&self) -> u64 {
+        match self {
+            GcConstant::Child(_) => BCDUMP_KGC_CHILD,
+            GcConstant::Table(_) => BCDUMP_KGC_TAB,
+            GcConstant::I64(_) => BCDUMP_KGC_I64,
+            GcConstant::U64(_) => BCDUMP_KGC_U64,
+            GcConstant::Complex(_, _) => BCDUMP_KGC_COMPLEX,
+            GcConstant::String(_) => BCDUMP_KGC_STR,
+        }
+    }
+}
+
+/// Read a string whose length was encoded in an already read type value.
+fn read_string<'a>(
+    src: &'a [u8],
+    offset: &mut usize,
+    tag: u64,
+    limits: Limits,
+) -> Result<JitString<'a>, Error> {
+    let size = limits.check_string_length(tag - BCDUMP_KGC_STR, src.len() - *offset)?;
+    if size == 0 {
+        return Ok(JitString::empty());
+    }
+
+    let data: &[u8] = src.gread_with(offset, size)?;
+    Ok(JitString {
+        size,
+        data: data.into(),
+    })
+}
+
+/// Write a string, adding its length to the type value written before it.
+fn write_string(
+    dst: &mut [u8],
+    offset: &mut usize,
+    tag: u64,
+    string: JitString,
+) -> Result<(), scroll::Error> {
+    Uleb128::write(dst, offset, tag + string.data.len() as u64)?;
+    dst.gwrite_with(&*string.data, offset, ())?;
+
+    Ok(())
+}
+
+/// Read a 64-bit value that is stored as two ULEB128 encoded 32-bit halves.
+fn read_u64_halves(src: &[u8], offset: &mut usize) -> Result<u64, scroll::Error> {
+    let lo = Uleb128::read(src, offset)? as u32;
+    let hi = Uleb128::read(src, offset)? as u32;
+
+    Ok(((hi as u64) << 32) | lo as u64)
+}
+
+/// Write a 64-bit value as two ULEB128 encoded 32-bit halves.
+fn write_u64_halves(dst: &mut [u8], offset: &mut usize, value: u64) -> Result<(), scroll::Error> {
+    Uleb128::write(dst, offset, value & 0xffff_ffff)?;
+    Uleb128::write(dst, offset, value >> 32)
+}
+
+/// Read a ULEB128 value whose first byte carries an extra flag in its lowest bit.
+///
+/// Returns the flag and the decoded 32-bit value.
+fn read_uleb128_33(src: &[u8], offset: &mut usize) -> Result<(bool, u32), scroll::Error> {
+    let first: u8 = src.gread(offset)?;
+    let flag = (first & 1) != 0;
+
+    let mut value = (first >> 1) as u32;
+    if value >= 0x40 {
+        value &= 0x3f;
+        let mut shift = 6;
+        loop {
+            let byte: u8 = src.gread(offset)?;
+            if shift < 32 {
+                value |= ((byte & 0x7f) as u32) << shift;
+            }
+            shift += 7;
+
+            if byte < 0x80 {
+                break;
+            }
+        }
+    }
+
+    Ok((flag, value))
+}
+
+/// Write a ULEB128 value with an extra flag in the lowest bit of its first byte.
+fn write_uleb128_33(
+    dst: &mut [u8],
+    offset: &mut usize,
+    flag: bool,
+    value: u32,
+) -> Result<(), scroll::Error> {
+    Uleb128::write(dst, offset, ((value as u64) << 1) | flag as u64)
+}
+
+impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for GcConstant<'a> {
+    type Error = Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let tag = Uleb128::read(src, offset).within(*offset, "tag")?;
+
+        if matches!(tag, BCDUMP_KGC_I64 | BCDUMP_KGC_U64 | BCDUMP_KGC_COMPLEX) && !ctx.has_ffi() {
+            return Err(Error::invalid(
+                "cdata constant in bytecode without the FFI flag",
+                0,
+            ));
+        }
+
+        let constant = match tag {
+            BCDUMP_KGC_CHILD => GcConstant::Child(0),
+            BCDUMP_KGC_TAB => {
+                GcConstant::Table(src.gread_with(offset, ctx).within(*offset, "value")?)
+            }
+            BCDUMP_KGC_I64 => {
+                GcConstant::I64(read_u64_halves(src, offset).within(*offset, "value")? as i64)
+            }
+            BCDUMP_KGC_U64 => {
+                GcConstant::U64(read_u64_halves(src, offset).within(*offset, "value")?)
+            }
+            BCDUMP_KGC_COMPLEX => {
+                let re = f64::from_bits(read_u64_halves(src, offset).within(*offset, "re")?);
+                let im = f64::from_bits(read_u64_halves(src, offset).within(*offset, "im")?);
+                GcConstant::Complex(re, im)
+            }
+            _ => GcConstant::String(
+                read_string(src, offset, tag, ctx.limits).within(*offset, "value")?,
+            ),
+        };
+
+        Ok((constant, *offset))
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for TableConstant<'a> {
+    type Error = Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+
+        let array_count = Uleb128::read(src, offset).within(*offset, "array_count")?;
+        let hash_count = Uleb128::read(src, offset).within(*offset, "hash_count")?;
+
+        let array_count = ctx
+            .limits
+            .check_count(array_count, src.len() - *offset)
+            .within(*offset, "array")?;
+        let mut array = Vec::with_capacity(array_count);
+        for index in 0..array_count {
+            let value: TableValue = src
+                .gread_with(offset, ctx)
+                .within_element(*offset, "array", index)?;
+            array.push(value);
+        }
+
+        let hash_count = ctx
+            .limits
+            .check_count(hash_count, src.len() - *offset)
+            .within(*offset, "hash")?;
+        let mut hash = Vec::with_capacity(hash_count);
+        for index in 0..hash_count {
+            let start = *offset;
+            let key: TableValue = src
+                .gread_with(offset, ctx)
+                .within(0, "key")
+                .within_element(start, "hash", index)?;
+            let value: TableValue = src
+                .gread_with(offset, ctx)
+                .within(*offset - start, "value")
+                .within_element(start, "hash", index)?;
+            hash.push((key, value));
+        }
+
+        Ok((Self { array, hash }, *offset))
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for TableValue<'a> {
+    type Error = Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let tag = Uleb128::read(src, offset).within(*offset, "tag")?;
+
+        let value = match tag {
+            BCDUMP_KTAB_NIL => TableValue::Nil,
+            BCDUMP_KTAB_FALSE => TableValue::False,
+            BCDUMP_KTAB_TRUE => TableValue::True,
+            BCDUMP_KTAB_INT => TableValue::Integer(
+                Uleb128::read(src, offset).within(*offset, "value")? as u32 as i32,
+            ),
+            BCDUMP_KTAB_NUM => {
+                let bits = read_u64_halves(src, offset).within(*offset, "value")?;
+                TableValue::Number(f64::from_bits(bits))
+            }
+            _ => TableValue::String(
+                read_string(src, offset, tag, ctx.limits).within(*offset, "value")?,
+            ),
+        };
+
+        Ok((value, *offset))
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for NumConstant {
+    type Error = Error;
+
+    fn try_from_ctx(src: &'a [u8], _ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+
+        let (is_number, lo) = read_uleb128_33(src, offset).within(*offset, "lo")?;
+        let constant = if is_number {
+            let hi = Uleb128::read(src, offset).within(*offset, "hi")? as u32;
+            NumConstant::Number(f64::from_bits(((hi as u64) << 32) | lo as u64))
+        } else {
+            NumConstant::Integer(lo as i32)
+        };
+
+        Ok((constant, *offset))
+    }
+}
+
+impl ctx::TryIntoCtx<BytecodeContext> for GcConstant<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: BytecodeContext) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        match self {
+            GcConstant::Child(_) => Uleb128::write(dst, offset, BCDUMP_KGC_CHILD)?,
+            GcConstant::Table(table) => {
+                Uleb128::write(dst, offset, BCDUMP_KGC_TAB)?;
+                dst.gwrite_with(table, offset, ctx)?;
+            }
+            GcConstant::I64(value) => {
+                Uleb128::write(dst, offset, BCDUMP_KGC_I64)?;
+                write_u64_halves(dst, offset, value as u64)?;
+            }
+            GcConstant::U64(value) => {
+                Uleb128::write(dst, offset, BCDUMP_KGC_U64)?;
+                write_u64_halves(dst, offset, value)?;
+            }
+            GcConstant::Complex(re, im) => {
+                Uleb128::write(dst, offset, BCDUMP_KGC_COMPLEX)?;
+                write_u64_halves(dst, offset, re.to_bits())?;
+                write_u64_halves(dst, offset, im.to_bits())?;
+            }
+            GcConstant::String(string) => write_string(dst, offset, BCDUMP_KGC_STR, string)?,
+        }
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<BytecodeContext> for TableConstant<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], ctx: BytecodeContext) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        Uleb128::write(dst, offset, self.array.len() as u64)?;
+        Uleb128::write(dst, offset, self.hash.len() as u64)?;
+        for value in self.array {
+            dst.gwrite_with(value, offset, ctx)?;
+        }
+        for (key, value) in self.hash {
+            dst.gwrite_with(key, offset, ctx)?;
+            dst.gwrite_with(value, offset, ctx)?;
+        }
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<BytecodeContext> for TableValue<'_> {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], _ctx: BytecodeContext) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        match self {
+            TableValue::Nil => Uleb128::write(dst, offset, BCDUMP_KTAB_NIL)?,
+            TableValue::False => Uleb128::write(dst, offset, BCDUMP_KTAB_FALSE)?,
+            TableValue::True => Uleb128::write(dst, offset, BCDUMP_KTAB_TRUE)?,
+            TableValue::Integer(value) => {
+                Uleb128::write(dst, offset, BCDUMP_KTAB_INT)?;
+                Uleb128::write(dst, offset, value as u32 as u64)?;
+            }
+            TableValue::Number(value) => {
+                Uleb128::write(dst, offset, BCDUMP_KTAB_NUM)?;
+                write_u64_halves(dst, offset, value.to_bits())?;
+            }
+            TableValue::String(string) => write_string(dst, offset, BCDUMP_KTAB_STR, string)?,
+        }
+
+        Ok(*offset)
+    }
+}
+
+impl ctx::TryIntoCtx<BytecodeContext> for NumConstant {
+    type Error = scroll::Error;
+
+    fn try_into_ctx(self, dst: &mut [u8], _ctx: BytecodeContext) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+
+        match self {
+            NumConstant::Integer(value) => write_uleb128_33(dst, offset, false, value as u32)?,
+            NumConstant::Number(value) => {
+                let bits = value.to_bits();
+                write_uleb128_33(dst, offset, true, bits as u32)?;
+                Uleb128::write(dst, offset, bits >> 32)?;
+            }
+        }
+
+        Ok(*offset)
+    }
+}
@@ -1,27 +1,209 @@
-use scroll::{ctx, Pread};
+use scroll::{ctx, Pread, Pwrite};
 
-use crate::common::ctx::BytecodeContext;
+use crate::common::{ctx::BytecodeContext, jitstring::JitString};
 use luasleuth_common::types::leb128::Uleb128;
+use luasleuth_common::{Error, ErrorContext};
 
-#[derive(Debug)]
+/// Marks the end of the variable info stream
+pub const VARNAME_END: u8 = 0;
+pub const VARNAME_FOR_IDX: u8 = 1;
+pub const VARNAME_FOR_STOP: u8 = 2;
+pub const VARNAME_FOR_STEP: u8 = 3;
+pub const VARNAME_FOR_GEN: u8 = 4;
+pub const VARNAME_FOR_STATE: u8 = 5;
+pub const VARNAME_FOR_CTL: u8 = 6;
+/// Names starting with a byte below this value are builtin names
+pub const VARNAME__MAX: u8 = 7;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DebugInfoMetadata {
     pub size: Uleb128,
     pub first_line: Uleb128,
     pub num_lines: Uleb128,
 }
 
-#[derive(Debug)]
-pub struct DebugInfo {}
+/// Context required to decode the debug information of a prototype.
+#[derive(Copy, Clone)]
+pub struct DebugInfoCtx {
+    pub bytecode: BytecodeContext,
+    pub first_line: u64,
+    pub num_lines: u64,
+    pub instruction_count: usize,
+    pub upvalue_count: usize,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DebugInfo<'a> {
+    /// The line the prototype was defined on
+    pub first_line: u64,
+
+    /// Line of each instruction, relative to `first_line`
+    pub line_info: Vec<u32>,
+
+    /// Upvalue names
+    pub upvalue_names: Vec<JitString<'a>>,
+
+    /// Information about local variables
+    pub variables: Vec<VariableInfo<'a>>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VariableInfo<'a> {
+    pub name: VariableName<'a>,
+    /// First point where variable is active
+    ///
+    /// Bytecode positions count the function header LuaJIT omits from the dump,
+    /// so the first instruction of [`Prototype::instructions`](super::Prototype) is at `1`.
+    pub start_pc: u32,
+    /// First point where variable is dead
+    pub end_pc: u32,
+}
+
+/// Name of a local variable, either one of the builtin names or a regular string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VariableName<'a> {
+    ForIndex,
+    ForStop,
+    ForStep,
+    ForGenerator,
+    ForState,
+    ForControl,
+    Named(JitString<'a>),
+}
+
+impl VariableName<'_> {
+    /// Returns the name as LuaJIT reports it in its debug API.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            VariableName::ForIndex => b"(for index)",
+            VariableName::ForStop => b"(for limit)",
+            VariableName::ForStep => b"(for step)",
+            VariableName::ForGenerator => b"(for generator)",
+            VariableName::ForState => b"(for state)",
+            VariableName::ForControl => b"(for control)",
+            VariableName::Named(name) => &name.data,
+        }
+    }
+}
+
+impl std::fmt::Display for VariableName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl<'a> DebugInfo<'a> {
+    /// Returns the number of line, upvalue name and variable entries.
+    pub fn element_count(&self) -> usize {
+        self.line_info.len() + self.upvalue_names.len() + self.variables.len()
+    }
+
+    /// Returns the source line of the instruction at `pc`, an index into the instructions.
+    pub fn line_for_pc(&self, pc: usize) -> Option<u64> {
+        self.line_info
+            .get(pc)
+            .map(|&delta| self.first_line.wrapping_add(delta as u64))
+    }
+
+    /// Returns the local variables that are active at the instruction at `pc`.
+    ///
+    /// The variables are returned in register order.
+    pub fn locals_at_pc(&self, pc: usize) -> Vec<&VariableInfo<'a>> {
+        let pc = pc as u64 + 1; // Skip the function header
+
+        self.variables
+            .iter()
+            .filter(|variable| variable.start_pc as u64 <= pc && pc < variable.end_pc as u64)
+            .collect()
+    }
+}
+
+/// Read a zero terminated string.
+fn read_cstring<'a>(src: &'a [u8], offset: &mut usize) -> Result<JitString<'a>, scroll::Error> {
+    let size = src
+        .get(*offset..)
+        .unwrap_or_default()
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(scroll::Error::BadInput {
+            size: src.len(),
+            msg: "Unterminated debug info string",
+        })?;
+
+    let data: &[u8] = src.gread_with(offset, size)?;
+    *offset += 1; // null terminator
+
+    Ok(JitString {
+        size,
+        data: data.into(),
+    })
+}
+
+/// Write a zero terminated string, returning the amount of bytes written.
+fn write_cstring(
+    dst: &mut [u8],
+    offset: &mut usize,
+    string: JitString,
+) -> Result<usize, scroll::Error> {
+    let size = dst.gwrite_with(&*string.data, offset, ())?;
+    dst.gwrite(0u8, offset)?;
+
+    Ok(size + 1)
+}
+
+/// Read a single variable, or `None` at the end of the variable info.
+///
+/// `last_pc` is the start of the previous variable, which the start of this one is relative to.
+fn read_variable(src: &[u8], last_pc: u32) -> Result<(Option<VariableInfo<'_>>, usize), Error> {
+    let offset = &mut 0;
+
+    let kind: u8 = src.pread(*offset).within(*offset, "name")?;
+    let name = match kind {
+        VARNAME_END => return Ok((None, 1)),
+        VARNAME_FOR_IDX => VariableName::ForIndex,
+        VARNAME_FOR_STOP => VariableName::ForStop,
+        VARNAME_FOR_STEP => VariableName::ForStep,
+        VARNAME_FOR_GEN => VariableName::ForGenerator,
+        VARNAME_FOR_STATE => VariableName::ForState,
+        VARNAME_FOR_CTL => VariableName::ForControl,
+        _ => VariableName::Named(read_cstring(src, offset).within(*offset, "name")?),
+    };
+    if kind < VARNAME__MAX {
+        *offset += 1;
+    }
+
+    // Both positions are deltas, the start relative to the previous variable
+    let start_pc = Uleb128::read(src, offset).within(*offset, "start_pc")? as u32;
+    let start_pc = last_pc.wrapping_add(start_pc);
+    let end_pc = Uleb128::read(src, offset).within(*offset, "end_pc")? as u32;
+    let end_pc = start_pc.wrapping_add(end_pc);
+
+    let variable = VariableInfo {
+        name,
+        start_pc,
+        end_pc,
+    };
+    Ok((Some(variable), *offset))
+}
 
 impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for DebugInfoMetadata {
-    type Error = scroll::Error;
+    type Error = Error;
 
-    fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(src: &'a [u8], _ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
-        let size: Uleb128 = src.gread_with(offset, ())?;
-        let first_line: Uleb128 = src.gread_with(offset, ())?;
-        let num_lines: Uleb128 = src.gread_with(offset, ())?;
+        let size: Uleb128 = src.gread_with(offset, ()).within(*offset, "size")?;
+
+        // The line range is only written when there is debug information to go along with it
+        let (first_line, num_lines): (Uleb128, Uleb128) = if u64::from(size) != 0 {
+            (src.gread_with(offset, ())?, src.gread_with(offset, ())?)
+        } else {
+            (0u64.into(), 0u64.into())
+        };
 
         Ok((
             Self {
@@ -34,10 +216,97 @@ impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for DebugInfoMetadata {
     }
 }
 
-impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for DebugInfo {
+impl<'a> ctx::TryFromCtx<'a, DebugInfoCtx> for DebugInfo<'a> {
+    type Error = Error;
+
+    fn try_from_ctx(src: &'a [u8], ctx: DebugInfoCtx) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let endian = ctx.bytecode.endian;
+
+        // The width of each line entry depends on how many lines the prototype spans
+        let mut line_info = Vec::with_capacity(ctx.instruction_count.min(src.len()));
+        for index in 0..ctx.instruction_count {
+            let delta = match ctx.num_lines {
+                0..256 => src.gread_with::<u8>(offset, endian).map(u32::from),
+                256..65536 => src.gread_with::<u16>(offset, endian).map(u32::from),
+                _ => src.gread_with::<u32>(offset, endian),
+            };
+            line_info.push(delta.within_element(*offset, "line_info", index)?);
+        }
+
+        let mut upvalue_names = Vec::with_capacity(ctx.upvalue_count);
+        for index in 0..ctx.upvalue_count {
+            let name = read_cstring(src, offset).within_element(*offset, "upvalue_names", index)?;
+            upvalue_names.push(name);
+        }
+
+        let mut variables = Vec::new();
+        let mut last_pc = 0u32;
+        for index in 0.. {
+            let start = *offset;
+            let (variable, size) = read_variable(src.get(start..).unwrap_or_default(), last_pc)
+                .within_element(start, "variables", index)?;
+            *offset += size;
+
+            let Some(variable) = variable else {
+                break;
+            };
+            last_pc = variable.start_pc;
+            variables.push(variable);
+        }
+
+        Ok((
+            Self {
+                first_line: ctx.first_line,
+                line_info,
+                upvalue_names,
+                variables,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl ctx::TryIntoCtx<DebugInfoCtx> for DebugInfo<'_> {
     type Error = scroll::Error;
 
-    fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
-        todo!()
+    fn try_into_ctx(self, dst: &mut [u8], ctx: DebugInfoCtx) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+        let endian = ctx.bytecode.endian;
+
+        for delta in self.line_info {
+            match ctx.num_lines {
+                0..256 => dst.gwrite_with(delta as u8, offset, endian)?,
+                256..65536 => dst.gwrite_with(delta as u16, offset, endian)?,
+                _ => dst.gwrite_with(delta, offset, endian)?,
+            };
+        }
+
+        for name in self.upvalue_names {
+            write_cstring(dst, offset, name)?;
+        }
+
+        let mut last_pc = 0u32;
+        for variable in self.variables {
+            match variable.name {
+                VariableName::ForIndex => dst.gwrite(VARNAME_FOR_IDX, offset)?,
+                VariableName::ForStop => dst.gwrite(VARNAME_FOR_STOP, offset)?,
+                VariableName::ForStep => dst.gwrite(VARNAME_FOR_STEP, offset)?,
+                VariableName::ForGenerator => dst.gwrite(VARNAME_FOR_GEN, offset)?,
+                VariableName::ForState => dst.gwrite(VARNAME_FOR_STATE, offset)?,
+                VariableName::ForControl => dst.gwrite(VARNAME_FOR_CTL, offset)?,
+                VariableName::Named(name) => write_cstring(dst, offset, name)?,
+            };
+
+            // Both positions are deltas, the start relative to the previous variable
+            let start_pc = variable.start_pc.wrapping_sub(last_pc);
+            let end_pc = variable.end_pc.wrapping_sub(variable.start_pc);
+            Uleb128::write(dst, offset, start_pc as u64)?;
+            Uleb128::write(dst, offset, end_pc as u64)?;
+            last_pc = variable.start_pc;
+        }
+        dst.gwrite(VARNAME_END, offset)?;
+
+        Ok(*offset)
     }
 }
@@ -1,7 +1,8 @@
-use luasleuth_common::{mask, types::Packable};
+use luasleuth_common::Error;
+use luasleuth_common::{mask, types::Packable, InvalidOpcode};
 use scroll::{ctx, Pread, Pwrite};
 
-use crate::common::ctx::BytecodeContext;
+use crate::common::ctx::{BytecodeContext, Release};
 
 pub mod constants {
     //! Constants related to LuaJIT v2 bytecode format
@@ -26,22 +27,199 @@ pub mod constants {
     // Special register values
     pub const NO_REG: u8 = 0xff;
 
+    // Bytecode operand mode fields position and width in bits
+    pub const POS_MODE_A: u8 = 0;
+    pub const SIZE_MODE_A: u8 = 3;
+    pub const POS_MODE_B: u8 = 3;
+    pub const SIZE_MODE_B: u8 = 4;
+    pub const POS_MODE_C: u8 = 7;
+    pub const SIZE_MODE_C: u8 = 4;
+
     /// Lookup table for bytecode operation modes
-    /// This maps each operation to its mode (what kind of operands it takes)
+    ///
+    /// This maps each operation to its mode (what kind of operands it takes), packed as
+    /// `A | B << 3 | C << 7` using the [`OperandKind`](super::OperandKind) discriminants.
+    /// LuaJIT additionally stores the metamethod of an operation in the upper bits, these are
+    /// left out as their numbering depends on how LuaJIT was built.
     pub static BCMODE_TABLE: &[u16] = &[
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
-        0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001, 0x0001,
+        0x0183, // ISLT
+        0x0183, // ISGE
+        0x0183, // ISLE
+        0x0183, // ISGT
+        0x0183, // ISEQV
+        0x0183, // ISNEV
+        0x0503, // ISEQS
+        0x0503, // ISNES
+        0x0483, // ISEQN
+        0x0483, // ISNEN
+        0x0403, // ISEQP
+        0x0403, // ISNEP
+        0x0181, // ISTC
+        0x0181, // ISFC
+        0x0180, // IST
+        0x0180, // ISF
+        0x0303, // ISTYPE
+        0x0303, // ISNUM
+        0x0181, // MOV
+        0x0181, // NOT
+        0x0181, // UNM
+        0x0181, // LEN
+        0x0499, // ADDVN
+        0x0499, // SUBVN
+        0x0499, // MULVN
+        0x0499, // DIVVN
+        0x0499, // MODVN
+        0x0499, // ADDNV
+        0x0499, // SUBNV
+        0x0499, // MULNV
+        0x0499, // DIVNV
+        0x0499, // MODNV
+        0x0199, // ADDVV
+        0x0199, // SUBVV
+        0x0199, // MULVV
+        0x0199, // DIVVV
+        0x0199, // MODVV
+        0x0199, // POW
+        0x0221, // CAT
+        0x0501, // KSTR
+        0x0701, // KCDATA
+        0x0381, // KSHORT
+        0x0481, // KNUM
+        0x0401, // KPRI
+        0x0102, // KNIL
+        0x0281, // UGET
+        0x0185, // USETV
+        0x0505, // USETS
+        0x0485, // USETN
+        0x0405, // USETP
+        0x0684, // UCLO
+        0x0601, // FNEW
+        0x0301, // TNEW
+        0x0581, // TDUP
+        0x0501, // GGET
+        0x0503, // GSET
+        0x0199, // TGETV
+        0x0519, // TGETS
+        0x0319, // TGETB
+        0x0199, // TGETR
+        0x019b, // TSETV
+        0x051b, // TSETS
+        0x031b, // TSETB
+        0x0482, // TSETM
+        0x019b, // TSETR
+        0x0332, // CALLM
+        0x0332, // CALL
+        0x0302, // CALLMT
+        0x0302, // CALLT
+        0x0332, // ITERC
+        0x0332, // ITERN
+        0x0332, // VARG
+        0x0682, // ISNEXT
+        0x0302, // RETM
+        0x0304, // RET
+        0x0304, // RET0
+        0x0304, // RET1
+        0x0682, // FORI
+        0x0682, // JFORI
+        0x0682, // FORL
+        0x0682, // IFORL
+        0x0302, // JFORL
+        0x0682, // ITERL
+        0x0682, // IITERL
+        0x0302, // JITERL
+        0x0684, // LOOP
+        0x0684, // ILOOP
+        0x0304, // JLOOP
+        0x0684, // JMP
+        0x0004, // FUNCF
+        0x0004, // IFUNCF
+        0x0304, // JFUNCF
+        0x0004, // FUNCV
+        0x0004, // IFUNCV
+        0x0304, // JFUNCV
+        0x0004, // FUNCC
+        0x0004, // FUNCCW
     ];
 }
 
+/// Kind of value an instruction operand refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OperandKind {
+    /// The operand is unused
+    None,
+    /// Destination register
+    Dst,
+    /// Base register of a register range
+    Base,
+    /// Variable register
+    Var,
+    /// Base register, only read
+    RBase,
+    /// Upvalue index
+    Uv,
+    /// Unsigned literal
+    Lit,
+    /// Signed literal
+    Lits,
+    /// Primitive, `0` for nil, `1` for false and `2` for true
+    Pri,
+    /// Numeric constant index
+    Num,
+    /// String constant index
+    Str,
+    /// Template table constant index
+    Tab,
+    /// Child prototype constant index
+    Func,
+    /// Biased jump offset
+    Jump,
+    /// cdata constant index
+    Cdata,
+}
+
+impl OperandKind {
+    fn from_mode(mode: u16) -> Self {
+        match mode {
+            1 => Self::Dst,
+            2 => Self::Base,
+            3 => Self::Var,
+            4 => Self::RBase,
+            5 => Self::Uv,
+            6 => Self::Lit,
+            7 => Self::Lits,
+            8 => Self::Pri,
+            9 => Self::Num,
+            10 => Self::Str,
+            11 => Self::Tab,
+            12 => Self::Func,
+            13 => Self::Jump,
+            14 => Self::Cdata,
+            _ => Self::None,
+        }
+    }
+
+    /// Returns whether the operand refers to a register
+    pub fn is_register(self) -> bool {
+        matches!(self, Self::Dst | Self::Base | Self::Var | Self::RBase)
+    }
+
+    /// Returns whether the operand refers to a GC or numeric constant
+    pub fn is_constant(self) -> bool {
+        matches!(
+            self,
+            Self::Num | Self::Str | Self::Tab | Self::Func | Self::Cdata
+        )
+    }
+
+    /// Returns whether the operand refers to a GC constant, which are addressed from the end
+    pub fn is_gc_constant(self) -> bool {
+        matches!(self, Self::Str | Self::Tab | Self::Func | Self::Cdata)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum Instruction {
     /// Format ABC: OP A B C
@@ -53,7 +231,11 @@ pub enum Instruction {
 }
 
 /// LuaJIT bytecode operations
+///
+/// The discriminants follow the LuaJIT 2.1 numbering. LuaJIT 2.0 lacks `ISTYPE`, `ISNUM`,
+/// `TGETR` and `TSETR`, shifting everything after them, see [`Opcode::from_release`].
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 pub enum Opcode {
@@ -178,19 +360,81 @@ pub enum Opcode {
 }
 
 impl Opcode {
-    pub fn uses_ad_format(self) -> bool {
-        match self {
-            Self::CALL | Self::CALLM | Self::CALLMT | Self::CALLT | Self::ITERC | Self::ITERN => {
-                false
-            }
-            _ => (constants::BCMODE_TABLE[self as usize] & 0x78) == 0,
+    /// Decode an opcode using the numbering of the given LuaJIT release.
+    pub fn from_release(value: u8, release: Release) -> Option<Self> {
+        let value = match release {
+            Release::LuaJit21 => value,
+            Release::LuaJit20 => match value {
+                0..=15 => value,
+                16..=56 => value + 2,
+                57..=60 => value + 3,
+                61..=92 => value + 4,
+                _ => return None,
+            },
+        };
+
+        Self::try_from(value).ok()
+    }
+
+    /// Encode an opcode using the numbering of the given LuaJIT release.
+    ///
+    /// Returns `None` if the opcode does not exist in that release.
+    pub fn to_release(self, release: Release) -> Option<u8> {
+        let value = self as u8;
+
+        match release {
+            Release::LuaJit21 => Some(value),
+            Release::LuaJit20 => match self {
+                Self::ISTYPE | Self::ISNUM | Self::TGETR | Self::TSETR => None,
+                _ => match value {
+                    0..=15 => Some(value),
+                    18..=58 => Some(value - 2),
+                    60..=63 => Some(value - 3),
+                    _ => Some(value - 4),
+                },
+            },
         }
     }
+
+    /// Returns the kind of the `A` operand
+    pub fn mode_a(self) -> OperandKind {
+        use constants::*;
+        OperandKind::from_mode((BCMODE_TABLE[self as usize] >> POS_MODE_A) & mask!(SIZE_MODE_A, 0))
+    }
+
+    /// Returns the kind of the `B` operand, [`OperandKind::None`] for instructions in AD format
+    pub fn mode_b(self) -> OperandKind {
+        use constants::*;
+        OperandKind::from_mode((BCMODE_TABLE[self as usize] >> POS_MODE_B) & mask!(SIZE_MODE_B, 0))
+    }
+
+    /// Returns the kind of the `C` operand
+    pub fn mode_c(self) -> OperandKind {
+        use constants::*;
+        OperandKind::from_mode((BCMODE_TABLE[self as usize] >> POS_MODE_C) & mask!(SIZE_MODE_C, 0))
+    }
+
+    /// Returns the kind of the `D` operand, which shares its bits with `C`
+    pub fn mode_d(self) -> OperandKind {
+        self.mode_c()
+    }
+
+    /// Returns whether the instruction has a `D` operand instead of `B` and `C`
+    pub fn uses_ad_format(self) -> bool {
+        self.mode_b() == OperandKind::None
+    }
+
+    /// Returns whether the `D` operand of the instruction is a jump target
+    pub fn is_jump(self) -> bool {
+        self.mode_d() == OperandKind::Jump
+    }
 }
 
-impl From<u8> for Opcode {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for Opcode {
+    type Error = InvalidOpcode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let opcode = match value {
             0 => Self::ISLT,
             1 => Self::ISGE,
             2 => Self::ISLE,
@@ -288,8 +532,10 @@ impl From<u8> for Opcode {
             94 => Self::JFUNCV,
             95 => Self::FUNCC,
             96 => Self::FUNCCW,
-            _ => panic!("Invalid opcode value: {}", value),
-        }
+            _ => return Err(InvalidOpcode(value)),
+        };
+
+        Ok(opcode)
     }
 }
 
@@ -299,20 +545,45 @@ impl From<Opcode> for u8 {
     }
 }
 
-impl Packable for Instruction {
-    fn decode(raw: u32) -> Self {
+impl Instruction {
+    /// Decode an instruction using the opcode numbering of the given LuaJIT release.
+    pub fn decode_with(raw: u32, release: Release) -> Option<Self> {
+        use constants::*;
+
+        let opcode = Opcode::from_release((raw & mask!(SIZE_OP, 0)) as u8, release)?;
+        Some(Self::decode_operands(opcode, raw))
+    }
+
+    /// Encode an instruction using the opcode numbering of the given LuaJIT release.
+    ///
+    /// Returns `None` if the opcode does not exist in that release.
+    pub fn encode_with(inst: Self, release: Release) -> Option<u32> {
+        use constants::*;
+
+        let opcode = inst.opcode().to_release(release)? as u32;
+        Some((Self::encode(inst) & !mask!(SIZE_OP, 0)) | opcode)
+    }
+
+    /// Returns the opcode of the instruction
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            Instruction::ABC(opcode, ..) => *opcode,
+            Instruction::AD(opcode, ..) => *opcode,
+            Instruction::AJ(opcode, ..) => *opcode,
+        }
+    }
+
+    fn decode_operands(opcode: Opcode, raw: u32) -> Self {
         use constants::*;
 
-        // Extract opcode and operands
-        let opcode: Opcode = ((raw & mask!(SIZE_OP, 0)) as u8).into();
         let a = ((raw >> POS_A) & mask!(SIZE_A, 0)) as u8;
 
         if opcode.uses_ad_format() {
             // Format AD
             let d = ((raw >> POS_D) & mask!(SIZE_D, 0)) as u16;
 
-            // Special case: JMP instruction is handled as format AJ
-            if opcode == Opcode::JMP {
+            // Jumps are handled as format AJ
+            if opcode.is_jump() {
                 // Convert biased value to signed
                 let j = (d as i32) - BCBIAS_J;
                 return Instruction::AJ(opcode, a, j);
@@ -326,6 +597,15 @@ impl Packable for Instruction {
             Instruction::ABC(opcode, a, b, c)
         }
     }
+}
+
+impl Packable for Instruction {
+    fn decode(raw: u32) -> Result<Self, InvalidOpcode> {
+        use constants::*;
+
+        let opcode = Opcode::try_from((raw & mask!(SIZE_OP, 0)) as u8)?;
+        Ok(Self::decode_operands(opcode, raw))
+    }
 
     fn encode(inst: Self) -> u32 {
         use constants::*;
@@ -355,13 +635,15 @@ impl Packable for Instruction {
 }
 
 impl<'a> ctx::TryFromCtx<'a, BytecodeContext> for Instruction {
-    type Error = scroll::Error;
+    type Error = Error;
 
     fn try_from_ctx(src: &'a [u8], ctx: BytecodeContext) -> Result<(Self, usize), Self::Error> {
         let offset = &mut 0;
 
+        let release = ctx.release().unwrap_or(Release::LuaJit21);
         let instruction: u32 = src.gread_with(offset, ctx.endian)?;
-        let instruction = Instruction::decode(instruction);
+        let instruction = Instruction::decode_with(instruction, release)
+            .ok_or_else(|| Error::invalid("Invalid opcode value", 0))?;
 
         Ok((instruction, *offset))
     }
@@ -373,7 +655,11 @@ impl ctx::TryIntoCtx<BytecodeContext> for Instruction {
     fn try_into_ctx(self, src: &mut [u8], ctx: BytecodeContext) -> Result<usize, Self::Error> {
         let offset = &mut 0;
 
-        let n = Instruction::encode(self);
+        let release = ctx.release().unwrap_or(Release::LuaJit21);
+        let n = Instruction::encode_with(self, release).ok_or(scroll::Error::BadInput {
+            size: 4,
+            msg: "Opcode does not exist in this LuaJIT release",
+        })?;
         src.gwrite_with(n, offset, ctx.endian)?;
 
         Ok(*offset)
@@ -0,0 +1,393 @@
+use luasleuth_common::{assembler::Assemble as _, disassembler::Disassemble as _};
+use luasleuth_luajit::{
+    common::{
+        ctx::{Release, BYTECODE_HAS_FFI},
+        jitstring::JitString,
+    },
+    v2::{
+        assembler::Assembler,
+        disassembler::Disassembler,
+        types::{
+            constants::{GcConstant, NumConstant, TableConstant, TableValue},
+            instructions::{Instruction, Opcode},
+        },
+    },
+};
+
+/// Stripped bytecode of a main chunk with a single child prototype
+#[rustfmt::skip]
+const CHILD_PROTOTYPES: &[u8] = &[
+    0x1b, 0x4c, 0x4a, 0x02, 0x02,
+    // Child prototype with the numeric constants 1.5 and 7
+    0x12, 0x00, 0x00, 0x02, 0x00, 0x00, 0x02, 0x01,
+    0x4c, 0x00, 0x02, 0x00,
+    0x01, 0x80, 0x80, 0xe0, 0xff, 0x03, 0x0e,
+    // Main chunk creating a closure of the child prototype
+    0x0c, 0x01, 0x00, 0x02, 0x00, 0x01, 0x00, 0x01,
+    0x33, 0x00, 0x00, 0x00,
+    0x00,
+    0x00,
+];
+
+/// Main chunk named `=test` with line and variable debug info
+#[rustfmt::skip]
+const DEBUG_INFO: &[u8] = &[
+    0x1b, 0x4c, 0x4a, 0x02, 0x00,
+    0x05, b'=', b't', b'e', b's', b't',
+    0x1c, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02,
+    // Debug info size, first line and line count
+    0x0a, 0x03, 0x01,
+    0x29, 0x00, 0x01, 0x00,
+    0x4c, 0x00, 0x02, 0x00,
+    // Line info, `a` and `(for index)` alive for the second instruction
+    0x00, 0x01,
+    b'a', 0x00, 0x02, 0x01,
+    0x01, 0x00, 0x01,
+    0x00,
+    0x00,
+];
+
+#[test]
+fn test_can_parse_bytecode_file() {
+    let bytes = include_bytes!("../../../data/bytecode/luajitv2.bin");
+    let bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Check if most important parts of the disassembly is valid
+    assert_eq!(&bytecode.header.signature, b"\x1bLJ");
+    assert_eq!(bytecode.header.version, 2);
+    assert_eq!(bytecode.release, Release::LuaJit21);
+    assert!(bytecode.header.chunk_name.is_none());
+
+    // Check prototype related values
+    let prototype = bytecode.prototype;
+    assert_eq!(prototype.parameter_count, 0);
+    assert_eq!(prototype.frame_size, 3);
+    assert_eq!(prototype.instructions.len(), 4);
+    assert!(prototype.upvalues.is_empty());
+    assert!(prototype.num_constants.is_empty());
+    assert!(prototype.prototypes.is_empty());
+
+    // GC constants are referenced from the end of the list
+    assert_eq!(prototype.gc_constants.len(), 2);
+    assert!(matches!(prototype.gc_constant(0), Some(GcConstant::String(s)) if *s == "print"));
+    assert!(
+        matches!(prototype.gc_constant(1), Some(GcConstant::String(s)) if *s == "Hello, World!")
+    );
+}
+
+#[test]
+fn test_can_parse_child_prototypes() {
+    let bytecode = Disassembler::new(CHILD_PROTOTYPES)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    let prototype = bytecode.prototype;
+    assert_eq!(prototype.prototypes.len(), 1);
+    assert!(matches!(
+        prototype.gc_constant(0),
+        Some(GcConstant::Child(0))
+    ));
+
+    let child = &prototype.prototypes[0];
+    assert_eq!(
+        child.num_constants,
+        [NumConstant::Number(1.5), NumConstant::Integer(7)]
+    );
+}
+
+#[test]
+fn test_can_parse_debug_info() {
+    let bytecode = Disassembler::new(DEBUG_INFO)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+    assert_eq!(
+        bytecode
+            .header
+            .chunk_name
+            .as_ref()
+            .and_then(|name| name.as_str()),
+        Some("=test")
+    );
+
+    let debug_info = bytecode
+        .prototype
+        .debug_info
+        .expect("Prototype is missing debug info");
+    assert_eq!(debug_info.line_for_pc(0), Some(3));
+    assert_eq!(debug_info.line_for_pc(1), Some(4));
+    assert_eq!(debug_info.line_for_pc(2), None);
+
+    assert!(debug_info.locals_at_pc(0).is_empty());
+    let locals: Vec<String> = debug_info
+        .locals_at_pc(1)
+        .iter()
+        .map(|local| local.name.to_string())
+        .collect();
+    assert_eq!(locals, ["a", "(for index)"]);
+}
+
+#[test]
+fn test_can_parse_big_endian_bytecode() {
+    let mut bytes = include_bytes!("../../../data/bytecode/luajitv2.bin").to_vec();
+
+    // Set the big endian flag and swap each instruction word over
+    bytes[4] |= 0x01;
+    for instruction in bytes[13..29].chunks_mut(4) {
+        instruction.reverse();
+    }
+
+    let bytecode = Disassembler::new(&bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+    assert!(bytecode.context.is_big_endian());
+    assert!(bytecode.context.is_stripped());
+    assert!(!bytecode.context.has_ffi());
+    assert!(bytecode.context.is_fr2()); // Dumped by a GC64 build
+
+    let little = include_bytes!("../../../data/bytecode/luajitv2.bin");
+    let little = Disassembler::new(little).disassemble().unwrap();
+    assert_eq!(
+        format!("{:?}", bytecode.prototype.instructions),
+        format!("{:?}", little.prototype.instructions)
+    );
+}
+
+#[test]
+fn test_rejects_unknown_flags() {
+    let mut bytes = include_bytes!("../../../data/bytecode/luajitv2.bin").to_vec();
+    bytes[4] |= 0x10;
+
+    assert!(Disassembler::new(&bytes).disassemble().is_err());
+}
+
+#[test]
+fn test_decodes_luajit_20_opcodes() {
+    let bytes = include_bytes!("../../../data/bytecode/luajitv1.bin");
+    let bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+    assert_eq!(bytecode.release, Release::LuaJit20);
+
+    let opcodes: Vec<Opcode> = bytecode
+        .prototype
+        .instructions
+        .iter()
+        .map(Instruction::opcode)
+        .collect();
+    assert_eq!(
+        opcodes,
+        [Opcode::GGET, Opcode::KSTR, Opcode::CALL, Opcode::RET0]
+    );
+}
+
+#[test]
+fn test_release_opcode_numbering_round_trips() {
+    for value in 0..=u8::MAX {
+        for release in [Release::LuaJit20, Release::LuaJit21] {
+            if let Some(opcode) = Opcode::from_release(value, release) {
+                assert_eq!(opcode.to_release(release), Some(value));
+            }
+        }
+    }
+
+    assert_eq!(
+        Opcode::from_release(92, Release::LuaJit20),
+        Some(Opcode::FUNCCW)
+    );
+    assert_eq!(Opcode::from_release(93, Release::LuaJit20), None);
+    assert_eq!(Opcode::ISTYPE.to_release(Release::LuaJit20), None);
+}
+
+#[test]
+fn test_opcode_operand_kinds() {
+    use luasleuth_luajit::v2::types::instructions::OperandKind;
+
+    assert_eq!(Opcode::KSTR.mode_a(), OperandKind::Dst);
+    assert_eq!(Opcode::KSTR.mode_d(), OperandKind::Str);
+    assert!(Opcode::KSTR.uses_ad_format());
+
+    assert_eq!(Opcode::TGETS.mode_b(), OperandKind::Var);
+    assert_eq!(Opcode::TGETS.mode_c(), OperandKind::Str);
+    assert!(!Opcode::TGETS.uses_ad_format());
+
+    assert_eq!(Opcode::CALL.mode_b(), OperandKind::Lit);
+    assert!(!Opcode::CALL.uses_ad_format());
+    assert!(Opcode::CALLT.uses_ad_format());
+
+    assert_eq!(Opcode::KPRI.mode_d(), OperandKind::Pri);
+    assert!(Opcode::FORL.is_jump());
+    assert!(!Opcode::JFORL.is_jump());
+    assert_eq!(Opcode::FUNCF.mode_d(), OperandKind::None);
+}
+
+#[test]
+fn test_malformed_input_returns_error_instead_of_panicking() {
+    use luasleuth_luajit::v1::disassembler::Disassembler as V1Disassembler;
+
+    for bytes in [
+        &include_bytes!("../../../data/bytecode/luajitv1.bin")[..],
+        &include_bytes!("../../../data/bytecode/luajitv2.bin")[..],
+    ] {
+        for length in 0..bytes.len() {
+            let _ = Disassembler::new(&bytes[..length]).disassemble();
+            let _ = V1Disassembler::new(&bytes[..length]).disassemble();
+        }
+
+        for index in 0..bytes.len() {
+            for value in [0x00, 0x7f, 0x80, 0xff] {
+                let mut bytes = bytes.to_vec();
+                bytes[index] = value;
+                let _ = Disassembler::new(&bytes).disassemble();
+                let _ = V1Disassembler::new(&bytes).disassemble();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rejects_unknown_opcodes() {
+    use luasleuth_common::{types::Packable, InvalidOpcode};
+
+    assert_eq!(Opcode::try_from(96), Ok(Opcode::FUNCCW));
+    assert_eq!(Opcode::try_from(97), Err(InvalidOpcode(97)));
+    assert_eq!(Instruction::decode(0xff).unwrap_err(), InvalidOpcode(0xff));
+}
+
+#[test]
+fn test_enforces_limits() {
+    use luasleuth_common::Limits;
+
+    let nesting = Limits {
+        max_depth: 0,
+        ..Limits::default()
+    };
+    let error = Disassembler::new(CHILD_PROTOTYPES)
+        .with_limits(nesting)
+        .disassemble()
+        .unwrap_err();
+    assert_eq!(error.field_path(), "prototypes[1]");
+    assert!(error
+        .to_string()
+        .contains("nested deeper than the limit of 0"));
+
+    let elements = Limits {
+        max_elements: 4,
+        ..Limits::default()
+    };
+    let error = Disassembler::new(CHILD_PROTOTYPES)
+        .with_limits(elements)
+        .disassemble()
+        .unwrap_err();
+    assert!(error.to_string().contains("exceeding the limit of 4"));
+
+    // A string constant claiming more bytes than there are left
+    let mut bytes = CHILD_PROTOTYPES.to_vec();
+    bytes[30] = 0x7f;
+    let error = Disassembler::new(&bytes).disassemble().unwrap_err();
+    assert!(error.to_string().contains("larger than the"));
+}
+
+#[test]
+fn test_assembles_chunks_back_to_the_same_bytes() {
+    let mut big_endian = include_bytes!("../../../data/bytecode/luajitv2.bin").to_vec();
+    big_endian[4] |= 0x01;
+    for instruction in big_endian[13..29].chunks_mut(4) {
+        instruction.reverse();
+    }
+
+    let chunks: [&[u8]; 5] = [
+        include_bytes!("../../../data/bytecode/luajitv2.bin"),
+        include_bytes!("../../../data/bytecode/luajitv1.bin"),
+        &big_endian,
+        CHILD_PROTOTYPES,
+        DEBUG_INFO,
+    ];
+    for bytes in chunks {
+        let bytecode = Disassembler::new(bytes)
+            .disassemble()
+            .expect("Failed to read bytecode data");
+        let assembled = Assembler::new(bytecode)
+            .assemble()
+            .expect("Failed to assemble bytecode");
+        assert_eq!(assembled, bytes);
+    }
+}
+
+#[test]
+fn test_assembles_modified_bytecode() {
+    let mut bytecode = Disassembler::new(CHILD_PROTOTYPES)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    let table = TableConstant {
+        array: vec![TableValue::Nil, TableValue::Integer(-2)],
+        hash: vec![
+            (TableValue::String(JitString::new("key")), TableValue::True),
+            (TableValue::Number(0.25), TableValue::False),
+        ],
+    };
+    let prototype = &mut bytecode.prototype;
+    prototype.gc_constants.push(GcConstant::Table(table));
+    prototype
+        .gc_constants
+        .push(GcConstant::String(JitString::new("name")));
+    prototype.gc_constants.push(GcConstant::Complex(1.0, -1.5));
+    prototype.num_constants.push(NumConstant::Integer(-1));
+    prototype.upvalues.push(0xc001);
+
+    // The complex number constant is only allowed in bytecode using the FFI
+    let flags = u64::from(bytecode.header.flags) | BYTECODE_HAS_FFI;
+    bytecode.header.flags = flags.into();
+
+    let assembled = Assembler::new(bytecode)
+        .assemble()
+        .expect("Failed to assemble bytecode");
+    let bytecode = Disassembler::new(&assembled)
+        .disassemble()
+        .expect("Failed to read assembled bytecode");
+
+    let prototype = &bytecode.prototype;
+    assert_eq!(prototype.prototypes.len(), 1);
+    assert_eq!(prototype.upvalues, [0xc001]);
+    assert_eq!(prototype.num_constants, [NumConstant::Integer(-1)]);
+    assert!(matches!(
+        prototype.gc_constants.as_slice(),
+        [
+            GcConstant::Child(0),
+            GcConstant::Table(_),
+            GcConstant::String(name),
+            GcConstant::Complex(re, im),
+        ] if *name == "name" && *re == 1.0 && *im == -1.5
+    ));
+
+    let GcConstant::Table(table) = &prototype.gc_constants[1] else {
+        unreachable!();
+    };
+    assert!(matches!(
+        table.array.as_slice(),
+        [TableValue::Nil, TableValue::Integer(-2)]
+    ));
+    assert!(matches!(
+        table.hash.as_slice(),
+        [
+            (TableValue::String(key), TableValue::True),
+            (TableValue::Number(number), TableValue::False),
+        ] if *key == "key" && *number == 0.25
+    ));
+}
+
+#[test]
+fn test_instruction_encoding_round_trips() {
+    use luasleuth_common::types::Packable;
+
+    // Sample the instruction space with a simple linear congruential generator
+    let mut raw: u32 = 1;
+    for _ in 0..100_000 {
+        raw = raw.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        if let Ok(instruction) = Instruction::decode(raw) {
+            assert_eq!(Instruction::encode(instruction), raw, "{raw:#010x}");
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use luasleuth_common::disassembler::Disassemble as _;
+use luasleuth_luajit::v1::{
+    disassembler::Disassembler,
+    types::{
+        constants::GcConstant,
+        instructions::{Instruction, Opcode},
+    },
+};
+
+#[test]
+fn test_can_parse_bytecode_file() {
+    let bytes = include_bytes!("../../../data/bytecode/luajitv1.bin");
+    let bytecode = Disassembler::new(bytes)
+        .disassemble()
+        .expect("Failed to read bytecode data");
+
+    // Check if most important parts of the disassembly is valid
+    assert_eq!(&bytecode.header.signature, b"\x1bLJ");
+    assert_eq!(bytecode.header.version, 1);
+    assert!(bytecode.header.chunk_name.is_none());
+
+    // Check prototype related values
+    let prototype = bytecode.prototype;
+    assert_eq!(prototype.parameter_count, 0);
+    assert_eq!(prototype.frame_size, 2);
+    assert!(prototype.prototypes.is_empty());
+
+    // The 2.0 opcode numbering differs from 2.1
+    let opcodes: Vec<Opcode> = prototype
+        .instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::ABC(opcode, ..)
+            | Instruction::AD(opcode, ..)
+            | Instruction::AJ(opcode, ..) => *opcode,
+        })
+        .collect();
+    assert_eq!(
+        opcodes,
+        [Opcode::GGET, Opcode::KSTR, Opcode::CALL, Opcode::RET0]
+    );
+
+    assert!(matches!(prototype.gc_constant(0), Some(GcConstant::String(s)) if *s == "print"));
+    assert!(
+        matches!(prototype.gc_constant(1), Some(GcConstant::String(s)) if *s == "Hello, World!")
+    );
+}
+
+#[test]
+fn test_rejects_other_versions() {
+    let bytes = include_bytes!("../../../data/bytecode/luajitv2.bin");
+    assert!(Disassembler::new(bytes).disassemble().is_err());
+}
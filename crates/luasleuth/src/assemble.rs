@@ -0,0 +1,130 @@
+//! Version-agnostic assembling of chunks written in the assembly language.
+
+use luasleuth_common::{
+    assembler::Assemble,
+    text::{self, TextError},
+    Error,
+};
+
+use crate::{types::LuaVersion, AnyBytecode};
+
+/// Errors returned by [`assemble_text`], [`assemble_json`] and [`AnyBytecode::assemble`].
+#[derive(Debug)]
+pub enum AssembleError {
+    /// The source is not valid assembly for the format
+    Syntax(TextError),
+    /// The source is not a valid JSON document
+    #[cfg(feature = "serde")]
+    Json(serde_json::Error),
+    /// The format has no assembler, or its cargo feature is disabled
+    Unsupported(LuaVersion),
+    /// The chunk could not be written
+    Write(Error),
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssembleError::Syntax(error) => error.fmt(f),
+            #[cfg(feature = "serde")]
+            AssembleError::Json(error) => write!(f, "Invalid JSON document: {}", error),
+            AssembleError::Unsupported(version) => {
+                write!(f, "Assembling {:?} bytecode is not supported", version)
+            }
+            AssembleError::Write(error) => write!(f, "Failed to write bytecode: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssembleError::Syntax(error) => Some(error),
+            #[cfg(feature = "serde")]
+            AssembleError::Json(error) => Some(error),
+            AssembleError::Write(error) => Some(error),
+            AssembleError::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<TextError> for AssembleError {
+    fn from(value: TextError) -> Self {
+        AssembleError::Syntax(value)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for AssembleError {
+    fn from(value: serde_json::Error) -> Self {
+        AssembleError::Json(value)
+    }
+}
+
+impl From<Error> for AssembleError {
+    fn from(value: Error) -> Self {
+        AssembleError::Write(value)
+    }
+}
+
+impl AnyBytecode<'_> {
+    /// Write the bytecode back into a binary chunk.
+    pub fn assemble(self) -> Result<Vec<u8>, AssembleError> {
+        let bytes = match self {
+            #[cfg(feature = "lua51")]
+            AnyBytecode::Lua51(bytecode) => {
+                luasleuth_lua51::assembler::Assembler::new(bytecode).assemble()?
+            }
+            #[cfg(feature = "lua52")]
+            AnyBytecode::Lua52(bytecode) => {
+                luasleuth_lua52::assembler::Assembler::new(bytecode).assemble()?
+            }
+            #[cfg(feature = "lua53")]
+            AnyBytecode::Lua53(bytecode) => {
+                luasleuth_lua53::assembler::Assembler::new(bytecode).assemble()?
+            }
+            #[cfg(feature = "lua54")]
+            AnyBytecode::Lua54(bytecode) => {
+                luasleuth_lua54::assembler::Assembler::new(bytecode).assemble()?
+            }
+            #[cfg(feature = "luajit")]
+            AnyBytecode::LuaJitV1(bytecode) | AnyBytecode::LuaJitV2(bytecode) => {
+                luasleuth_luajit::v2::assembler::Assembler::new(bytecode).assemble()?
+            }
+            #[allow(unreachable_patterns)]
+            bytecode => return Err(AssembleError::Unsupported(bytecode.version())),
+        };
+
+        Ok(bytes)
+    }
+}
+
+/// Assemble a chunk written in the assembly language into bytecode of the given format.
+pub fn assemble_text(source: &str, version: LuaVersion) -> Result<Vec<u8>, AssembleError> {
+    let chunk = text::parse(source)?;
+
+    let bytecode = match version {
+        #[cfg(feature = "lua51")]
+        LuaVersion::Lua51 => AnyBytecode::Lua51(luasleuth_lua51::text::build(&chunk)?),
+        #[cfg(feature = "lua52")]
+        LuaVersion::Lua52 => AnyBytecode::Lua52(luasleuth_lua52::text::build(&chunk)?),
+        #[cfg(feature = "lua53")]
+        LuaVersion::Lua53 => AnyBytecode::Lua53(luasleuth_lua53::text::build(&chunk)?),
+        #[cfg(feature = "lua54")]
+        LuaVersion::Lua54 => AnyBytecode::Lua54(luasleuth_lua54::text::build(&chunk)?),
+        #[cfg(feature = "luajit")]
+        LuaVersion::Luajitv1 => AnyBytecode::LuaJitV1(luasleuth_luajit::v1::text::build(&chunk)?),
+        #[cfg(feature = "luajit")]
+        LuaVersion::Luajitv2 => AnyBytecode::LuaJitV2(luasleuth_luajit::v2::text::build(&chunk)?),
+        #[allow(unreachable_patterns)]
+        version => return Err(AssembleError::Unsupported(version)),
+    };
+
+    bytecode.assemble()
+}
+
+/// Assemble a JSON document written by [`AnyBytecode::to_json`], see `docs/json.md`.
+#[cfg(feature = "serde")]
+pub fn assemble_json(source: &str) -> Result<Vec<u8>, AssembleError> {
+    AnyBytecode::from_json(source)?.assemble()
+}
@@ -0,0 +1,272 @@
+//! Version-agnostic parsing of bytecode chunks.
+
+use luasleuth_common::{disassembler::Disassemble, Error, Limits};
+
+use crate::{detect, types::LuaVersion};
+
+#[cfg(all(feature = "luajit", feature = "serde"))]
+use luasleuth_luajit::common::ctx::BytecodeContext;
+
+#[cfg(not(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+)))]
+compile_error!("At least one bytecode format feature must be enabled");
+
+/// Bytecode of any of the supported formats.
+///
+/// Only the formats whose cargo features are enabled have a variant.
+///
+/// With the `serde` feature it serializes as `{ "version": ..., "bytecode": ... }`, the version
+/// being named like [`LuaVersion`].
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "version", content = "bytecode", rename_all = "lowercase")
+)]
+pub enum AnyBytecode<'a> {
+    #[cfg(feature = "lua51")]
+    Lua51(luasleuth_lua51::types::Bytecode<'a>),
+    #[cfg(feature = "lua52")]
+    Lua52(luasleuth_lua52::types::Bytecode<'a>),
+    #[cfg(feature = "lua53")]
+    Lua53(luasleuth_lua53::types::Bytecode<'a>),
+    #[cfg(feature = "lua54")]
+    Lua54(luasleuth_lua54::types::Bytecode<'a>),
+    #[cfg(feature = "luajit")]
+    LuaJitV1(luasleuth_luajit::v1::types::Bytecode<'a>),
+    #[cfg(feature = "luajit")]
+    LuaJitV2(luasleuth_luajit::v2::types::Bytecode<'a>),
+}
+
+/// Errors returned by [`parse`] and [`parse_as`].
+#[derive(Debug)]
+pub enum ParseError {
+    /// The chunk does not start with a known signature
+    UnknownFormat,
+    /// The format was recognized, but its cargo feature is disabled
+    Unsupported(LuaVersion),
+    /// The chunk is malformed
+    Malformed(Error),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnknownFormat => f.write_str("Unknown bytecode format"),
+            ParseError::Unsupported(version) => {
+                write!(f, "Support for {:?} bytecode is not enabled", version)
+            }
+            ParseError::Malformed(error) => write!(f, "Malformed bytecode: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Malformed(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for ParseError {
+    fn from(value: Error) -> Self {
+        ParseError::Malformed(value)
+    }
+}
+
+impl AnyBytecode<'_> {
+    /// Returns the format of the bytecode.
+    pub fn version(&self) -> LuaVersion {
+        match self {
+            #[cfg(feature = "lua51")]
+            AnyBytecode::Lua51(_) => LuaVersion::Lua51,
+            #[cfg(feature = "lua52")]
+            AnyBytecode::Lua52(_) => LuaVersion::Lua52,
+            #[cfg(feature = "lua53")]
+            AnyBytecode::Lua53(_) => LuaVersion::Lua53,
+            #[cfg(feature = "lua54")]
+            AnyBytecode::Lua54(_) => LuaVersion::Lua54,
+            #[cfg(feature = "luajit")]
+            AnyBytecode::LuaJitV1(_) => LuaVersion::Luajitv1,
+            #[cfg(feature = "luajit")]
+            AnyBytecode::LuaJitV2(_) => LuaVersion::Luajitv2,
+        }
+    }
+
+    /// Returns the bytecode as a pretty printed JSON document, see `docs/json.md` for its schema.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Read bytecode from a JSON document written by [`AnyBytecode::to_json`].
+    ///
+    /// Sizes and counts are derived from the data they describe, instead of read from the document.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        #[allow(unused_mut)]
+        let mut bytecode: AnyBytecode = serde_json::from_str(json)?;
+
+        // The context is not part of the document, it follows from the header
+        #[cfg(feature = "luajit")]
+        match &mut bytecode {
+            AnyBytecode::LuaJitV1(bytecode) | AnyBytecode::LuaJitV2(bytecode) => {
+                let header = &bytecode.header;
+                bytecode.context = BytecodeContext::new(header.version, header.flags.into());
+            }
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+
+        Ok(bytecode)
+    }
+
+    /// Returns a listing of the bytecode, laid out like `luac -l -l` or `luajit -bl`.
+    pub fn listing(&self) -> String {
+        match self {
+            #[cfg(feature = "lua51")]
+            AnyBytecode::Lua51(bytecode) => {
+                luasleuth_lua51::listing::Listing::new(bytecode).to_string()
+            }
+            #[cfg(feature = "lua52")]
+            AnyBytecode::Lua52(bytecode) => {
+                luasleuth_lua52::listing::Listing::new(bytecode).to_string()
+            }
+            #[cfg(feature = "lua53")]
+            AnyBytecode::Lua53(bytecode) => {
+                luasleuth_lua53::listing::Listing::new(bytecode).to_string()
+            }
+            #[cfg(feature = "lua54")]
+            AnyBytecode::Lua54(bytecode) => {
+                luasleuth_lua54::listing::Listing::new(bytecode).to_string()
+            }
+            #[cfg(feature = "luajit")]
+            AnyBytecode::LuaJitV1(bytecode) | AnyBytecode::LuaJitV2(bytecode) => {
+                luasleuth_luajit::v2::listing::Listing::new(bytecode).to_string()
+            }
+        }
+    }
+
+    /// Returns the control flow graphs of every prototype as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        match self {
+            #[cfg(feature = "lua51")]
+            AnyBytecode::Lua51(bytecode) => luasleuth_lua51::dot::Dot::new(bytecode).to_string(),
+            #[cfg(feature = "lua52")]
+            AnyBytecode::Lua52(bytecode) => luasleuth_lua52::dot::Dot::new(bytecode).to_string(),
+            #[cfg(feature = "lua53")]
+            AnyBytecode::Lua53(bytecode) => luasleuth_lua53::dot::Dot::new(bytecode).to_string(),
+            #[cfg(feature = "lua54")]
+            AnyBytecode::Lua54(bytecode) => luasleuth_lua54::dot::Dot::new(bytecode).to_string(),
+            #[cfg(feature = "luajit")]
+            AnyBytecode::LuaJitV1(bytecode) | AnyBytecode::LuaJitV2(bytecode) => {
+                luasleuth_luajit::v2::dot::Dot::new(bytecode).to_string()
+            }
+        }
+    }
+
+    /// Returns the source of the chunk, or `None` when its version can't be decompiled.
+    pub fn decompile(&self) -> Option<String> {
+        match self {
+            #[cfg(feature = "lua51")]
+            AnyBytecode::Lua51(bytecode) => {
+                Some(luasleuth_lua51::decompiler::Source::new(bytecode).to_string())
+            }
+            #[cfg(feature = "lua54")]
+            AnyBytecode::Lua54(bytecode) => {
+                Some(luasleuth_lua54::decompiler::Source::new(bytecode).to_string())
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Debug for AnyBytecode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "lua51")]
+            AnyBytecode::Lua51(bytecode) => bytecode.fmt(f),
+            #[cfg(feature = "lua52")]
+            AnyBytecode::Lua52(bytecode) => bytecode.fmt(f),
+            #[cfg(feature = "lua53")]
+            AnyBytecode::Lua53(bytecode) => bytecode.fmt(f),
+            #[cfg(feature = "lua54")]
+            AnyBytecode::Lua54(bytecode) => bytecode.fmt(f),
+            #[cfg(feature = "luajit")]
+            AnyBytecode::LuaJitV1(bytecode) | AnyBytecode::LuaJitV2(bytecode) => bytecode.fmt(f),
+        }
+    }
+}
+
+/// Parse a chunk, detecting its format with [`detect`].
+pub fn parse(bytes: &[u8]) -> Result<AnyBytecode<'_>, ParseError> {
+    parse_with_limits(bytes, Limits::default())
+}
+
+/// Parse a chunk as the given format.
+pub fn parse_as(bytes: &[u8], version: LuaVersion) -> Result<AnyBytecode<'_>, ParseError> {
+    parse_as_with_limits(bytes, version, Limits::default())
+}
+
+/// Parse a chunk with the given limits, detecting its format with [`detect`].
+pub fn parse_with_limits(bytes: &[u8], limits: Limits) -> Result<AnyBytecode<'_>, ParseError> {
+    let detection = detect(bytes).ok_or(ParseError::UnknownFormat)?;
+    parse_as_with_limits(bytes, detection.version, limits)
+}
+
+/// Parse a chunk as the given format, with the given limits.
+pub fn parse_as_with_limits(
+    bytes: &[u8],
+    version: LuaVersion,
+    limits: Limits,
+) -> Result<AnyBytecode<'_>, ParseError> {
+    let bytecode = match version {
+        #[cfg(feature = "lua51")]
+        LuaVersion::Lua51 => AnyBytecode::Lua51(
+            luasleuth_lua51::disassembler::Disassembler::new(bytes)
+                .with_limits(limits)
+                .disassemble()?,
+        ),
+        #[cfg(feature = "lua52")]
+        LuaVersion::Lua52 => AnyBytecode::Lua52(
+            luasleuth_lua52::disassembler::Disassembler::new(bytes)
+                .with_limits(limits)
+                .disassemble()?,
+        ),
+        #[cfg(feature = "lua53")]
+        LuaVersion::Lua53 => AnyBytecode::Lua53(
+            luasleuth_lua53::disassembler::Disassembler::new(bytes)
+                .with_limits(limits)
+                .disassemble()?,
+        ),
+        #[cfg(feature = "lua54")]
+        LuaVersion::Lua54 => AnyBytecode::Lua54(
+            luasleuth_lua54::disassembler::Disassembler::new(bytes)
+                .with_limits(limits)
+                .disassemble()?,
+        ),
+        #[cfg(feature = "luajit")]
+        LuaVersion::Luajitv1 => AnyBytecode::LuaJitV1(
+            luasleuth_luajit::v1::disassembler::Disassembler::new(bytes)
+                .with_limits(limits)
+                .disassemble()?,
+        ),
+        #[cfg(feature = "luajit")]
+        LuaVersion::Luajitv2 => AnyBytecode::LuaJitV2(
+            luasleuth_luajit::v2::disassembler::Disassembler::new(bytes)
+                .with_limits(limits)
+                .disassemble()?,
+        ),
+        #[allow(unreachable_patterns)]
+        version => return Err(ParseError::Unsupported(version)),
+    };
+
+    Ok(bytecode)
+}
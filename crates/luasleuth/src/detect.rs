@@ -0,0 +1,131 @@
+//! Detection of the bytecode format of a chunk.
+
+use luasleuth_common::header;
+
+use crate::types::LuaVersion;
+
+/// The signature of LuaJIT bytecode dumps
+const LUAJIT_SIGNATURE: [u8; 3] = *b"\x1bLJ";
+
+/// Flags known to LuaJIT 2.0 dumps: big endian, stripped and FFI
+const LUAJIT_FLAGS_V1: u8 = 0x07;
+/// Flags known to LuaJIT 2.1 dumps, which add the two-slot frame flag
+const LUAJIT_FLAGS_V2: u8 = 0x0f;
+
+/// How certain [`detect`] is about a detected format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Only the signature matched, the header is truncated
+    Low,
+    /// The signature matched, but some header fields are not what the official tools write
+    Medium,
+    /// The signature matched and every header field checked out
+    High,
+}
+
+/// The result of [`detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    pub version: LuaVersion,
+    pub confidence: Confidence,
+}
+
+/// Detect the bytecode format of `bytes` from its signature and header.
+///
+/// Returns `None` when no known signature matches.
+pub fn detect(bytes: &[u8]) -> Option<Detection> {
+    if let Some(rest) = bytes.strip_prefix(&header::LUA_SIGNATURE) {
+        // The size of the header and how to check it, past the signature, version and format
+        let (version, header_size, layout_is_valid): (_, _, fn(&[u8]) -> bool) =
+            match rest.first()? {
+                0x51 => (LuaVersion::Lua51, 12, |bytes| {
+                    lua51_layout_is_valid(&bytes[6..12])
+                }),
+                0x52 => (LuaVersion::Lua52, 18, |bytes| {
+                    lua51_layout_is_valid(&bytes[6..12]) && luac_data_is_valid(&bytes[12..])
+                }),
+                // The check values are variable in size, only require the fixed part
+                0x53 => (LuaVersion::Lua53, 17, |bytes| {
+                    check_data_is_valid(bytes, 17, bytes[15], bytes[16])
+                }),
+                0x54 => (LuaVersion::Lua54, 15, |bytes| {
+                    check_data_is_valid(bytes, 15, bytes[13], bytes[14])
+                }),
+                _ => return None,
+            };
+
+        let confidence = lua_confidence(bytes, header_size, layout_is_valid);
+        return Some(Detection {
+            version,
+            confidence,
+        });
+    }
+
+    if let Some(rest) = bytes.strip_prefix(&LUAJIT_SIGNATURE) {
+        let (version, known_flags) = match rest.first()? {
+            1 => (LuaVersion::Luajitv1, LUAJIT_FLAGS_V1),
+            2 => (LuaVersion::Luajitv2, LUAJIT_FLAGS_V2),
+            _ => return None,
+        };
+
+        let confidence = match rest.get(1) {
+            None => Confidence::Low,
+            Some(flags) if flags & !known_flags == 0 => Confidence::High,
+            Some(_) => Confidence::Medium,
+        };
+        return Some(Detection {
+            version,
+            confidence,
+        });
+    }
+
+    None
+}
+
+/// Rate the header of an official Lua chunk, `layout_is_valid` checking the fields after its
+/// format once `header_size` bytes are there.
+fn lua_confidence(
+    bytes: &[u8],
+    header_size: usize,
+    layout_is_valid: fn(&[u8]) -> bool,
+) -> Confidence {
+    if bytes.len() < header_size {
+        return Confidence::Low;
+    }
+
+    // Official chunks always use format 0
+    if bytes[5] == 0 && layout_is_valid(bytes) {
+        Confidence::High
+    } else {
+        Confidence::Medium
+    }
+}
+
+/// Check the endianness flag, type sizes and integral flag of a Lua 5.1/5.2 header.
+fn lua51_layout_is_valid(layout: &[u8]) -> bool {
+    let [endianness, int, size_t, instruction, number, integral] = layout else {
+        return false;
+    };
+
+    *endianness <= 1
+        && matches!(int, 2 | 4 | 8)
+        && matches!(size_t, 4 | 8)
+        && matches!(instruction, 4 | 8)
+        && matches!(number, 4 | 8)
+        && *integral <= 1
+}
+
+/// Check that `bytes` starts with `LUAC_DATA`.
+fn luac_data_is_valid(bytes: &[u8]) -> bool {
+    bytes.starts_with(&header::LUAC_DATA)
+}
+
+/// Check `LUAC_DATA`, `LUAC_INT` and `LUAC_NUM` of a Lua 5.3/5.4 header.
+fn check_data_is_valid(bytes: &[u8], mut offset: usize, integer: u8, number: u8) -> bool {
+    let offset = &mut offset;
+
+    luac_data_is_valid(&bytes[6..])
+        && header::read_luac_int(bytes, offset, integer)
+            .and_then(|(_, endian)| header::read_luac_num(bytes, offset, number, endian))
+            .is_ok()
+}
@@ -1,3 +1,15 @@
+mod assemble;
+mod bytecode;
+mod detect;
+pub mod types;
+
+#[cfg(feature = "serde")]
+pub use assemble::assemble_json;
+pub use assemble::{assemble_text, AssembleError};
+pub use bytecode::{
+    parse, parse_as, parse_as_with_limits, parse_with_limits, AnyBytecode, ParseError,
+};
+pub use detect::{detect, Confidence, Detection};
 pub use luasleuth_common as common;
 
 #[cfg(feature = "lua51")]
@@ -8,3 +20,5 @@ pub use luasleuth_lua52 as lua52;
 pub use luasleuth_lua53 as lua53;
 #[cfg(feature = "lua54")]
 pub use luasleuth_lua54 as lua54;
+#[cfg(feature = "luajit")]
+pub use luasleuth_luajit as luajit;
@@ -1,15 +1,15 @@
-mod types;
-
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use clap::Parser;
-use luasleuth_common::disassembler::Disassemble;
-
-use luasleuth_lua51::disassembler::Disassembler as Lua51Disassembler;
-use luasleuth_lua52::disassembler::Disassembler as Lua52Disassembler;
-use luasleuth_lua53::disassembler::Disassembler as Lua53Disassembler;
-use luasleuth_lua54::disassembler::Disassembler as Lua54Disassembler;
-use luasleuth_luajit::v2::disassembler::Disassembler as LuajitV2Disassembler;
+use luasleuth::{
+    assemble_text, detect, parse_as,
+    types::{GraphFormat, LuaVersion, OutputFormat},
+    AnyBytecode, AssembleError, Confidence, ParseError,
+};
 
 #[derive(Debug, Parser)]
 struct Cli {
@@ -23,8 +23,59 @@ enum Subcommand {
         #[clap(short, long)]
         path: PathBuf,
 
+        /// The bytecode format, detected from the file when omitted
+        #[clap(short, long)]
+        version: Option<LuaVersion>,
+
+        /// How to print the bytecode
+        #[clap(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Print the control flow graph of every function
+    Cfg {
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// The bytecode format, detected from the file when omitted
+        #[clap(short, long)]
+        version: Option<LuaVersion>,
+
+        /// How to print the graphs
+        #[clap(short, long, value_enum, default_value_t)]
+        format: GraphFormat,
+    },
+    /// Print the source of a chunk, only Lua 5.1 and 5.4 chunks being supported
+    Decompile {
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// The bytecode format, detected from the file when omitted
+        #[clap(short, long)]
+        version: Option<LuaVersion>,
+    },
+    /// Assemble a chunk written in the assembly language, see `docs/assembly.md`
+    Assemble {
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// The bytecode format to write
         #[clap(short, long)]
-        version: types::LuaVersion,
+        version: LuaVersion,
+
+        /// Where to write the chunk, next to the source with a `.luac` extension when omitted
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Build a chunk from a JSON document written by `disassemble --format json`
+    #[cfg(feature = "serde")]
+    Build {
+        /// The JSON document, see `docs/json.md`
+        #[clap(short, long)]
+        from: PathBuf,
+
+        /// Where to write the chunk, next to the document with a `.luac` extension when omitted
+        #[clap(short, long)]
+        output: Option<PathBuf>,
     },
 }
 
@@ -32,50 +83,113 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Cli::parse();
 
     match args.subcommand {
-        Subcommand::Disassemble { path, version } => match version {
-            types::LuaVersion::Lua51 => {
-                let mut file = File::open(path)?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
-
-                let bytecode = Lua51Disassembler::new(&buffer).disassemble()?;
-                println!("{:#?}", bytecode);
+        Subcommand::Disassemble {
+            path,
+            version,
+            format,
+        } => {
+            let buffer = read(&path)?;
+            let bytecode = parse(&buffer, version)?;
+            match format {
+                OutputFormat::Debug => println!("{:#?}", bytecode),
+                OutputFormat::Listing => print!("{}", bytecode.listing()),
+                #[cfg(feature = "serde")]
+                OutputFormat::Json => println!("{}", bytecode.to_json()?),
             }
-            types::LuaVersion::Lua52 => {
-                let mut file = File::open(path)?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
-
-                let bytecode = Lua52Disassembler::new(&buffer).disassemble()?;
-                println!("{:#?}", bytecode);
+        }
+        Subcommand::Cfg {
+            path,
+            version,
+            format,
+        } => {
+            let buffer = read(&path)?;
+            let bytecode = parse(&buffer, version)?;
+            match format {
+                GraphFormat::Dot => print!("{}", bytecode.to_dot()),
             }
-            types::LuaVersion::Lua53 => {
-                let mut file = File::open(path)?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
+        }
+        Subcommand::Decompile { path, version } => {
+            let buffer = read(&path)?;
+            let bytecode = parse(&buffer, version)?;
+            let source = bytecode
+                .decompile()
+                .ok_or("Decompiling is only supported for Lua 5.1 and 5.4 chunks")?;
+            print!("{}", source);
+        }
+        Subcommand::Assemble {
+            path,
+            version,
+            output,
+        } => {
+            let source = std::fs::read_to_string(&path)?;
 
-                let bytecode = Lua53Disassembler::new(&buffer).disassemble()?;
-                println!("{:#?}", bytecode);
-            }
-            types::LuaVersion::Lua54 => {
-                let mut file = File::open(path)?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
+            let bytes = match assemble_text(&source, version) {
+                Ok(bytes) => bytes,
+                Err(AssembleError::Syntax(error)) => {
+                    eprintln!("{}:{}: {}", path.display(), error.line, error.message);
+                    std::process::exit(1);
+                }
+                Err(error) => return Err(error.into()),
+            };
 
-                let bytecode = Lua54Disassembler::new(&buffer).disassemble()?;
-                println!("{:#?}", bytecode);
-            }
-            types::LuaVersion::Luajitv2 => {
-                let mut file = File::open(path)?;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer)?;
+            let output = output.unwrap_or_else(|| path.with_extension("luac"));
+            std::fs::write(output, bytes)?;
+        }
+        #[cfg(feature = "serde")]
+        Subcommand::Build { from, output } => {
+            let source = std::fs::read_to_string(&from)?;
 
-                let bytecode = LuajitV2Disassembler::new(&buffer).disassemble()?;
-                println!("{:#?}", bytecode);
-            }
-            _ => todo!(),
-        },
+            let bytes = match luasleuth::assemble_json(&source) {
+                Ok(bytes) => bytes,
+                Err(AssembleError::Json(error)) => {
+                    eprintln!("{}: {}", from.display(), error);
+                    std::process::exit(1);
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let output = output.unwrap_or_else(|| from.with_extension("luac"));
+            std::fs::write(output, bytes)?;
+        }
     };
 
     Ok(())
 }
+
+fn read(path: &Path) -> Result<Vec<u8>, std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Parse a chunk, detecting its format unless given, exiting with a report when it is malformed.
+fn parse(
+    buffer: &[u8],
+    version: Option<LuaVersion>,
+) -> Result<AnyBytecode<'_>, Box<dyn std::error::Error>> {
+    let version = match version {
+        Some(version) => version,
+        None => {
+            let detection = detect(buffer)
+                .ok_or("Unable to detect the bytecode format, pass it with --version")?;
+            if detection.confidence < Confidence::High {
+                eprintln!(
+                    "warning: detected {:?} with {:?} confidence",
+                    detection.version, detection.confidence
+                );
+            }
+
+            detection.version
+        }
+    };
+
+    match parse_as(buffer, version) {
+        Ok(bytecode) => Ok(bytecode),
+        Err(ParseError::Malformed(error)) => {
+            eprint!("{}", error.report(buffer));
+            std::process::exit(1);
+        }
+        Err(error) => Err(error.into()),
+    }
+}
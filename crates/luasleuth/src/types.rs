@@ -1,6 +1,6 @@
 use clap::ValueEnum;
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum LuaVersion {
     Lua51,
     Lua52,
@@ -9,3 +9,24 @@ pub enum LuaVersion {
     Luajitv1,
     Luajitv2,
 }
+
+/// How the `disassemble` subcommand prints the bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// The parsed structures, as Rust debug output
+    #[default]
+    Debug,
+    /// A listing laid out like `luac -l -l`, or `luajit -bl` for LuaJIT
+    Listing,
+    /// The parsed structures as JSON, described in `docs/json.md`
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// How the `cfg` subcommand prints the control flow graphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum GraphFormat {
+    /// A Graphviz digraph with a cluster per function
+    #[default]
+    Dot,
+}
@@ -0,0 +1,213 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(dead_code, unused_imports, irrefutable_let_patterns)
+)]
+
+use luasleuth::{assemble_text, parse_as, types::LuaVersion, AnyBytecode, AssembleError};
+
+const LUA51: &str = r#"
+; print("Hello, World!")
+.function main
+    .source "@.\\example.lua"
+    .vararg
+    .const "print"
+    .const "Hello, World!"
+    .line 1
+    GETGLOBAL 0 -1
+    LOADK 1 -2
+    CALL 0 2 1
+    RETURN 0 1 0
+.end
+"#;
+
+const LUA52: &str = r#"
+.function main
+    .source "@.\\example.lua"
+    .vararg
+    .upval _ENV 1 0
+    .const "print"
+    .const "Hello, World!"
+    .line 1
+    GETTABUP 0 0 -1
+    LOADK 1 -2
+    CALL 0 2 1
+    RETURN 0 1 0
+.end
+"#;
+
+const LUA54: &str = r#"
+.function main
+    .source "@.\\example.lua"
+    .vararg
+    .upval _ENV 1 0
+    .const "print"
+    .const "Hello, World!"
+    .line 1
+    VARARGPREP 0 0 0
+    GETTABUP 0 0 0
+    LOADK 1 1
+    CALL 0 2 1
+    RETURN 0 1 1
+.end
+"#;
+
+const LUAJIT: &str = r#"
+.function main
+    .vararg
+    .stack 3
+    .const "Hello, World!"
+    .const "print"
+    GGET 0 "print"
+    KSTR 2 "Hello, World!"
+    CALL 0 1 2
+    RET0 0 1
+.end
+"#;
+
+// LuaJIT 2.0 has no two-slot frames, the arguments start right after the function
+const LUAJIT20: &str = r#"
+.function main
+    .vararg
+    .stack 2
+    .const "Hello, World!"
+    .const "print"
+    GGET 0 "print"
+    KSTR 1 "Hello, World!"
+    CALL 0 1 2
+    RET0 0 1
+.end
+"#;
+
+#[test]
+#[cfg(all(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
+fn test_assembles_text_to_the_same_bytes_as_the_fixtures() {
+    let sources: [(&str, LuaVersion, &[u8]); 6] = [
+        (
+            LUA51,
+            LuaVersion::Lua51,
+            include_bytes!("../../../data/bytecode/lua51.bin"),
+        ),
+        (
+            LUA52,
+            LuaVersion::Lua52,
+            include_bytes!("../../../data/bytecode/lua52.bin"),
+        ),
+        (
+            LUA52,
+            LuaVersion::Lua53,
+            include_bytes!("../../../data/bytecode/lua53.bin"),
+        ),
+        (
+            LUA54,
+            LuaVersion::Lua54,
+            include_bytes!("../../../data/bytecode/lua54.bin"),
+        ),
+        (
+            LUAJIT20,
+            LuaVersion::Luajitv1,
+            include_bytes!("../../../data/bytecode/luajitv1.bin"),
+        ),
+        (
+            LUAJIT,
+            LuaVersion::Luajitv2,
+            include_bytes!("../../../data/bytecode/luajitv2.bin"),
+        ),
+    ];
+
+    for (source, version, expected) in sources {
+        let bytes = assemble_text(source, version).expect("Failed to assemble text");
+        assert_eq!(bytes, expected, "{:?}", version);
+    }
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_resolves_labels_and_functions() {
+    let source = r#"
+    .function main
+        .vararg
+        .stack 2
+        .upval _ENV 1 0
+        VARARGPREP 0 0 0
+        LOADI 0 0
+    loop: JMP loop
+        FORPREP 0 done
+        FORLOOP 0 loop
+    done:
+        CLOSURE 1 child
+        RETURN 0 1 1
+        .function child
+            .lines 3 5
+            .local x start 1
+        start:
+            RETURN0 0 0 0
+        .end
+    .end
+    "#;
+
+    let bytes = assemble_text(source, LuaVersion::Lua54).expect("Failed to assemble text");
+    let AnyBytecode::Lua54(bytecode) = parse_as(&bytes, LuaVersion::Lua54).unwrap() else {
+        unreachable!();
+    };
+
+    let main = &bytecode.prototype;
+    let code = format!("{:?}", main.instructions.data);
+    assert!(code.contains("isJ(OP_JMP, -1)"), "{}", code);
+    // Loop instructions store an unsigned distance, which the decoder reads as signed
+    assert!(code.contains("iAsBx(OP_FORPREP, 0, -65535)"), "{}", code);
+    assert!(code.contains("iAsBx(OP_FORLOOP, 0, -65532)"), "{}", code);
+    assert!(code.contains("iABx(OP_CLOSURE, 1, 0)"), "{}", code);
+
+    let child = &main.prototypes.data[0];
+    assert_eq!(child.line_defined.value, 3);
+    assert_eq!(child.debug_info.local_variables.data[0].name, "x");
+    assert_eq!(child.debug_info.local_variables.data[0].end_pc.value, 1);
+}
+
+#[test]
+#[cfg(all(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "luajit"
+))]
+fn test_reports_errors_with_their_line() {
+    let error = |source: &str, version| match assemble_text(source, version) {
+        Err(AssembleError::Syntax(error)) => error,
+        other => panic!("Expected a syntax error, got {:?}", other.map(|_| ())),
+    };
+
+    let unknown = error(".function main\n    FOO 0 0 0\n.end\n", LuaVersion::Lua53);
+    assert_eq!(unknown.line, 2);
+    assert_eq!(unknown.message, "Unknown mnemonic FOO");
+
+    let label = error(
+        ".function main\n    JMP 0 nowhere\n.end\n",
+        LuaVersion::Lua51,
+    );
+    assert_eq!(label.message, "Unknown label nowhere");
+
+    let range = error(
+        ".function main\n    MOVE 256 0 0\n.end\n",
+        LuaVersion::Lua52,
+    );
+    assert_eq!(range.message, "Operand 256 does not fit in 8 bits");
+
+    let constant = error(
+        ".function main\n    KSTR 0 \"x\"\n.end\n",
+        LuaVersion::Luajitv2,
+    );
+    assert_eq!(constant.message, "\"x\" is not declared with .const");
+}
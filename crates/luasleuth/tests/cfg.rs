@@ -0,0 +1,263 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(dead_code, unused_imports, irrefutable_let_patterns)
+)]
+
+use luasleuth::{
+    assemble_text,
+    common::cfg::{Edge, EdgeKind},
+    parse_as,
+    types::LuaVersion,
+    AnyBytecode,
+};
+
+fn parse(bytes: &[u8], version: LuaVersion) -> AnyBytecode<'_> {
+    parse_as(bytes, version).unwrap()
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_branches_and_numeric_loops() {
+    use luasleuth::lua51::cfg::{build, successors};
+
+    let source = r#"
+.function main
+    .const 1
+    .stack 4
+    EQ 1 0 -1
+    JMP 0 other
+    LOADBOOL 0 1 1
+    LOADBOOL 0 0 0
+other:
+    FORPREP 1 check
+body:
+    MOVE 0 1 0
+check:
+    FORLOOP 1 body
+    RETURN 0 1 0
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua51).unwrap();
+    let AnyBytecode::Lua51(bytecode) = parse(&bytes, LuaVersion::Lua51) else {
+        panic!("Expected a Lua 5.1 chunk");
+    };
+    let prototype = &bytecode.prototype;
+
+    assert_eq!(
+        successors(prototype, 0),
+        [Edge::new(1, EdgeKind::True), Edge::new(2, EdgeKind::False)]
+    );
+    assert_eq!(successors(prototype, 1), [Edge::new(4, EdgeKind::Jump)]);
+    assert_eq!(successors(prototype, 2), [Edge::new(4, EdgeKind::Jump)]);
+    assert_eq!(successors(prototype, 4), [Edge::new(6, EdgeKind::Jump)]);
+    assert_eq!(
+        successors(prototype, 6),
+        [Edge::new(5, EdgeKind::True), Edge::new(7, EdgeKind::False)]
+    );
+    assert!(successors(prototype, 7).is_empty());
+
+    let graph = build(prototype);
+    assert_eq!(graph.blocks().len(), 8);
+
+    // The loop is entered at its FORLOOP, which the body falls through to
+    let body = graph.block_at(5).unwrap();
+    let check = graph.block_at(6).unwrap();
+    let loops = graph.loops();
+    assert_eq!(loops.len(), 1);
+    assert_eq!(loops[0].header, check);
+    assert_eq!(loops[0].latches, [body]);
+    assert!(graph.is_back_edge(body, check));
+
+    // Nothing jumps to the second LOADBOOL
+    assert!(graph.blocks()[graph.block_at(3).unwrap()]
+        .predecessors
+        .is_empty());
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_skips_setlist_block_numbers() {
+    let source = r#"
+.function main
+    .stack 3
+    NEWTABLE 0 1 0
+    LOADNIL 1 1 0
+    SETLIST 0 1 0
+    JMP 0 1
+    RETURN 0 1 0
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua51).unwrap();
+    let AnyBytecode::Lua51(bytecode) = parse(&bytes, LuaVersion::Lua51) else {
+        panic!("Expected a Lua 5.1 chunk");
+    };
+
+    // The `JMP` stands in for the block number, it is never executed
+    let successors = luasleuth::lua51::cfg::successors(&bytecode.prototype, 3);
+    assert_eq!(successors, [Edge::new(4, EdgeKind::Fallthrough)]);
+    assert_eq!(
+        luasleuth::lua51::cfg::build(&bytecode.prototype)
+            .blocks()
+            .len(),
+        1
+    );
+}
+
+#[test]
+#[cfg(all(feature = "lua52", feature = "lua53"))]
+fn test_lua52_and_lua53_fixtures_are_one_block() {
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/lua52.bin"),
+        LuaVersion::Lua52,
+    );
+    let AnyBytecode::Lua52(bytecode) = bytecode else {
+        panic!("Expected a Lua 5.2 chunk");
+    };
+    let graph = luasleuth::lua52::cfg::build(&bytecode.prototype);
+    assert_eq!(graph.blocks().len(), 1);
+    assert!(graph.blocks()[0].successors.is_empty());
+
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/lua53.bin"),
+        LuaVersion::Lua53,
+    );
+    let AnyBytecode::Lua53(bytecode) = bytecode else {
+        panic!("Expected a Lua 5.3 chunk");
+    };
+    let graph = luasleuth::lua53::cfg::build(&bytecode.prototype);
+    assert_eq!(graph.blocks().len(), 1);
+    assert!(graph.loops().is_empty());
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_conditionals_and_loops() {
+    use luasleuth::lua54::cfg::{build, successors};
+
+    let source = r#"
+.function main
+    .vararg
+    .stack 4
+    .const 1
+    EQK 0 0 1
+    JMP skip
+    LFALSESKIP 1 0 0
+skip:
+    LOADTRUE 1 0 0
+    FORPREP 0 done
+top:
+    FORLOOP 0 top
+done:
+    RETURN 0 1 1
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua54).unwrap();
+    let AnyBytecode::Lua54(bytecode) = parse(&bytes, LuaVersion::Lua54) else {
+        panic!("Expected a Lua 5.4 chunk");
+    };
+    let prototype = &bytecode.prototype;
+
+    assert_eq!(
+        successors(prototype, 0),
+        [Edge::new(1, EdgeKind::True), Edge::new(2, EdgeKind::False)]
+    );
+    assert_eq!(successors(prototype, 1), [Edge::new(3, EdgeKind::Jump)]);
+    assert_eq!(successors(prototype, 2), [Edge::new(4, EdgeKind::Jump)]);
+    assert_eq!(
+        successors(prototype, 4),
+        [Edge::new(5, EdgeKind::True), Edge::new(6, EdgeKind::False)]
+    );
+    assert_eq!(
+        successors(prototype, 5),
+        [Edge::new(5, EdgeKind::True), Edge::new(6, EdgeKind::False)]
+    );
+
+    let graph = build(prototype);
+    let top = graph.block_at(5).unwrap();
+    let loops = graph.loops();
+    assert_eq!(loops.len(), 1);
+    assert_eq!((loops[0].header, &loops[0].body[..]), (top, &[top][..]));
+    assert_eq!(
+        graph.immediate_dominators()[graph.block_at(6).unwrap()],
+        graph.block_at(4)
+    );
+}
+
+#[test]
+#[cfg(feature = "luajit")]
+fn test_luajit_loops() {
+    use luasleuth::luajit::v2::cfg::{build, successors};
+
+    let source = r#"
+.function main
+    .stack 4
+    KSHORT 0 1
+    KSHORT 1 3
+    KSHORT 2 1
+    FORI 0 exit
+body:
+    MOV 3 3
+    FORL 0 body
+exit:
+    KPRI 0 2
+top:
+    ISF 0 0
+    JMP 1 out
+    LOOP 1 out
+    JMP 1 top
+out:
+    RET0 0 1
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Luajitv2).unwrap();
+    let AnyBytecode::LuaJitV2(bytecode) = parse(&bytes, LuaVersion::Luajitv2) else {
+        panic!("Expected a LuaJIT v2 chunk");
+    };
+    let prototype = &bytecode.prototype;
+
+    assert_eq!(
+        successors(prototype, 3),
+        [Edge::new(4, EdgeKind::True), Edge::new(6, EdgeKind::False)]
+    );
+    assert_eq!(
+        successors(prototype, 5),
+        [Edge::new(4, EdgeKind::True), Edge::new(6, EdgeKind::False)]
+    );
+    assert_eq!(
+        successors(prototype, 7),
+        [Edge::new(8, EdgeKind::True), Edge::new(9, EdgeKind::False)]
+    );
+    assert_eq!(
+        successors(prototype, 9),
+        [Edge::new(10, EdgeKind::Fallthrough)]
+    );
+    assert!(successors(prototype, 11).is_empty());
+
+    let graph = build(prototype);
+    let headers: Vec<usize> = graph
+        .loops()
+        .iter()
+        .map(|l| graph.blocks()[l.header].start)
+        .collect();
+    assert_eq!(headers, [4, 7]);
+
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/luajitv1.bin"),
+        LuaVersion::Luajitv1,
+    );
+    let AnyBytecode::LuaJitV1(bytecode) = bytecode else {
+        panic!("Expected a LuaJIT v1 chunk");
+    };
+    assert_eq!(
+        luasleuth::luajit::v1::cfg::build(&bytecode.prototype)
+            .blocks()
+            .len(),
+        1
+    );
+}
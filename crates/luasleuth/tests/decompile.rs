@@ -0,0 +1,893 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(dead_code)
+)]
+
+use luasleuth::{assemble_text, parse_as, types::LuaVersion};
+
+fn decompile(source: &str, version: LuaVersion) -> String {
+    let bytes = assemble_text(source, version).unwrap();
+    parse_as(&bytes, version).unwrap().decompile().unwrap()
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_decompile_names_locals_from_debug_info() {
+    let source = r##"
+.function main
+    .vararg 2
+    .stack 10
+    .const "select"
+    .const "#"
+    .const "f"
+    .const "d"
+    .const 1
+    .const "print"
+    .const "x"
+    .const "obj"
+    .const "method"
+    .const "!"
+    .const "g"
+    .const 10
+    .local t lt end
+    .local n ln end
+    .local a lab end
+    .local b lab end
+    .local s ls end
+    NEWTABLE 0 0 0
+    VARARG 1 0 0
+    SETLIST 0 0 1
+lt:
+    GETGLOBAL 1 -1
+    LOADK 2 -2
+    VARARG 3 0 0
+    CALL 1 0 2
+ln:
+    GETGLOBAL 2 -3
+    CALL 2 1 3
+lab:
+    TESTSET 4 2 1
+    JMP 0 ls
+    LOADK 4 -4
+ls:
+    TEST 2 0 0
+    JMP 0 ntest
+    TEST 3 0 1
+    JMP 0 then
+ntest:
+    LT 0 -5 1
+    JMP 0 elif
+then:
+    GETGLOBAL 5 -6
+    LOADK 6 -7
+    CALL 5 2 1
+    JMP 0 endif
+elif:
+    TEST 3 0 0
+    JMP 0 else
+    GETGLOBAL 5 -8
+    SELF 5 5 -9
+    MOVE 7 2 0
+    MOVE 8 3 0
+    LOADK 9 -10
+    CONCAT 7 7 9
+    CALL 5 3 1
+    JMP 0 endif
+else:
+    LOADNIL 5 5 0
+    SETGLOBAL 5 -11
+endif:
+wh:
+    LT 0 -12 1
+    JMP 0 skip
+    JMP 0 exit
+skip:
+    ADD 1 1 -5
+    JMP 0 wh
+exit:
+    RETURN 0 1 0
+end:
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua51),
+        r##"local t = {...}
+local n = select("#", ...)
+local a, b = f()
+local s = a or "d"
+if a and b or n > 1 then
+    print("x")
+elseif b then
+    obj:method(a .. b .. "!")
+else
+    g = nil
+end
+while true do
+    if n > 10 then
+        break
+    end
+    n = n + 1
+end
+"##
+    );
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_decompile_names_locals_of_stripped_chunks() {
+    let source = r##"
+.function main
+    .vararg 2
+    .stack 10
+    .const 1
+    .const 2
+    .const 3
+    .const "n"
+    .const "x"
+    .const 0
+    .const "pairs"
+    .const "print"
+    .const 10
+    NEWTABLE 0 3 1
+    LOADK 1 -1
+    LOADK 2 -2
+    LOADK 3 -3
+    SETTABLE 0 -4 -5
+    SETLIST 0 3 1
+    LOADK 1 -6
+    LOADK 2 -1
+    LEN 3 0 0
+    LOADK 4 -1
+    FORPREP 2 fl
+fb:
+    GETTABLE 6 0 5
+    ADD 1 1 6
+fl:
+    FORLOOP 2 fb
+    GETGLOBAL 2 -7
+    MOVE 3 0 0
+    CALL 2 2 4
+    JMP 0 tf
+gb:
+    GETGLOBAL 7 -8
+    MOVE 8 5 0
+    MOVE 9 6 0
+    CALL 7 3 1
+tf:
+    TFORLOOP 2 0 2
+    JMP 0 gb
+wh:
+    LT 0 -6 1
+    JMP 0 we
+    SUB 1 1 -1
+    JMP 0 wh
+we:
+rp:
+    ADD 1 1 -1
+    LE 0 -9 1
+    JMP 0 rp
+    TEST 1 0 0
+    JMP 0 l2
+    LOADK 2 -1
+    JMP 0 l3
+l2:
+    LOADK 2 -2
+l3:
+    CLOSURE 3 f
+    MOVE 0 1 0
+    GETGLOBAL 4 -8
+    MOVE 5 3 0
+    MOVE 6 2 0
+    CALL 5 2 0
+    CALL 4 0 1
+    RETURN 0 1 0
+    .function f
+        .vararg 2
+        .upval s
+        .stack 3
+        GETUPVAL 0 0 0
+        VARARG 1 0 0
+        RETURN 0 0 0
+        RETURN 0 1 0
+    .end
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua51),
+        r#"local r0 = {n = "x", 1, 2, 3}
+local r1 = 0
+for i5 = 1, #r0 do
+    r1 = r1 + r0[i5]
+end
+for k5, v6 in pairs(r0) do
+    print(k5, v6)
+end
+while r1 > 0 do
+    r1 = r1 - 1
+end
+repeat
+    r1 = r1 + 1
+until r1 >= 10
+print((function(...)
+    return r1, ...
+end)(r1 and 1 or 2))
+"#
+    );
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_decompile_closures_with_upvalues() {
+    let source = r##"
+.function main
+    .vararg 2
+    .stack 10
+    .const 3
+    .const 10
+    .const 0
+    .const 1
+    .const -1
+    .const 5
+    .local fact 0 end
+    .local c 9 end
+    .local count 10 end
+    .local inc 12 end
+    .local (for_index) 15 19
+    .local (for_limit) 15 19
+    .local (for_step) 15 19
+    .local i 16 18
+    CLOSURE 0 fact
+    MOVE 0 0 0
+    MOVE 1 0 0
+    LOADK 2 -1
+    CALL 1 2 2
+    LT 1 1 -2
+    JMP 0 t
+    LOADBOOL 1 0 1
+t:
+    LOADBOOL 1 1 0
+    LOADK 2 -3
+    CLOSURE 3 inc
+    MOVE 0 2 0
+    LOADK 4 -2
+    LOADK 5 -4
+    LOADK 6 -5
+    FORPREP 4 fl
+fb:
+    MOVE 8 3 0
+    CALL 8 1 1
+fl:
+    FORLOOP 4 fb
+    MOVE 4 0 0
+    LOADK 5 -6
+    CALL 4 2 2
+    MOVE 5 1 0
+    RETURN 4 3 0
+    RETURN 0 1 0
+end:
+    .function fact
+        .params 1
+        .upval fact
+        .stack 3
+        .const 1
+        .local n 0 end
+        LE 0 0 -1
+        JMP 0 skip
+        LOADK 1 -1
+        RETURN 1 2 0
+skip:
+        GETUPVAL 1 0 0
+        SUB 2 0 -1
+        CALL 1 2 2
+        MUL 1 0 1
+        RETURN 1 2 0
+        RETURN 0 1 0
+end:
+    .end
+    .function inc
+        .upval count
+        .stack 2
+        .const 1
+        GETUPVAL 0 0 0
+        ADD 0 0 -1
+        SETUPVAL 0 0 0
+        GETUPVAL 0 0 0
+        RETURN 0 2 0
+        RETURN 0 1 0
+    .end
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua51),
+        r#"local function fact(n)
+    if n <= 1 then
+        return 1
+    end
+    return n * fact(n - 1)
+end
+local c = fact(3) < 10
+local count = 0
+local inc = function()
+    count = count + 1
+    return count
+end
+for i = 10, 1, -1 do
+    inc()
+end
+return fact(5), c
+"#
+    );
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_decompile_while_condition_with_prelude() {
+    let source = r##"
+.function main
+    .stack 6
+    .const "t"
+    .const 1
+    .const 10
+    .const "print"
+    .local i 1 end
+    LOADK 0 -2
+wh:
+    GETGLOBAL 1 -1
+    GETTABLE 1 1 0
+    EQ 1 1 -1
+    JMP 0 out
+    LT 0 0 -3
+    JMP 0 out
+    EQ 0 0 -3
+    JMP 0 els
+    JMP 0 out
+    JMP 0 cont
+els:
+    GETGLOBAL 1 -4
+    MOVE 2 0 0
+    CALL 1 2 1
+cont:
+    ADD 0 0 -2
+    JMP 0 wh
+out:
+    RETURN 0 1 0
+end:
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua51),
+        r#"local i = 1
+while t[i] ~= "t" and i < 10 do
+    if i == 10 then
+        break
+    else
+        print(i)
+    end
+    i = i + 1
+end
+"#
+    );
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_decompile_tells_values_from_conditions() {
+    let source = r#"
+.function main
+    .stack 4
+    .const "a"
+    .const "b"
+    .const "c"
+    .const "print"
+    GETGLOBAL 0 -1
+    TEST 0 0 1
+    JMP 0 l1
+    GETGLOBAL 0 -2
+l1:
+    TEST 0 0 0
+    JMP 0 e
+    GETGLOBAL 0 -3
+e:
+    GETGLOBAL 1 -4
+    MOVE 2 0 0
+    CALL 1 2 1
+    GETGLOBAL 0 -1
+    TEST 0 0 1
+    JMP 0 then
+    GETGLOBAL 0 -2
+    TEST 0 0 0
+    JMP 0 done
+then:
+    GETGLOBAL 0 -4
+    CALL 0 1 1
+done:
+    RETURN 0 1 0
+.end
+"#;
+
+    // The same tests compute a value, then choose a branch
+    assert_eq!(
+        decompile(source, LuaVersion::Lua51),
+        "print((a or b) and c)\nif a or b then\n    print()\nend\n"
+    );
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_decompile_keeps_deep_elseif_chains_flat() {
+    // Each arm nests the next one, deeper than any chunk `luac` writes
+    let arms = 250;
+    let mut source = String::from(".function main\n    .params 1\n    .stack 2\n");
+    for arm in 1..=arms {
+        source += &format!("    .const {arm}\n");
+    }
+    for arm in 1..=arms {
+        source += &format!("a{arm}:\n    EQ 0 0 {}\n    JMP 0 a{}\n", -arm, arm + 1);
+        source += &format!("    LOADK 1 {}\n    JMP 0 done\n", -arm);
+    }
+    source += &format!("a{}:\ndone:\n    RETURN 0 1 0\n.end\n", arms + 1);
+
+    let decompiled = decompile(&source, LuaVersion::Lua51);
+    assert!(decompiled.starts_with(
+        "-- if a0 ~= 1 then jump to instruction 5\nlocal r1 = 1\n-- jump to instruction 1001\n"
+    ));
+    assert!(decompiled.ends_with("-- if a0 ~= 250 then jump to instruction 1001\nlocal r1 = 250\n"));
+    assert_eq!(decompiled.lines().count(), 3 * arms as usize - 1);
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_decompile_integers_and_attributes() {
+    let source = r##"
+.function main
+    .vararg
+    .stack 16
+    .upval _ENV 1 0
+    .const "setmetatable"
+    .const "mt"
+    .const 2
+    .const "print"
+    .local t l3 l35
+    .local n l4 l35
+    .local f l9 l35
+    .local x l12 l35
+    .local y l14 l35
+    .local z l16 l35
+    .local w l18 l35
+    .local s l20 l35
+    .local get l21 l35
+    .local "(for state)" l24 l34
+    .local "(for state)" l24 l34
+    .local "(for state)" l24 l34
+    .local i l25 l33
+    VARARGPREP 0 0 0
+    NEWTABLE 0 0 0
+    EXTRAARG 0
+l3: LOADI 1 10
+l4: GETTABUP 2 0 0
+    NEWTABLE 3 0 0
+    EXTRAARG 0
+    GETTABUP 4 0 1
+    CALL 2 3 2
+l9: TBC 2 0 0
+    ADDI 3 1 128
+    MMBINI 1 128 6 0
+l12: ADDI 4 1 126
+    MMBINI 1 128 7 0
+l14: MULK 5 1 2
+    MMBINK 1 2 8 1
+l16: SHRI 6 1 126
+    MMBINI 1 128 16 0
+l18: SHLI 7 1 128
+    MMBINI 1 128 16 1
+l20: CLOSURE 8 get
+l21: LOADI 9 1
+    MOVE 10 1 0
+    LOADI 11 1
+l24: FORPREP 9 l34
+l25: MODK 13 12 2
+    MMBINK 12 2 9 0
+    EQI 13 127 0 0
+    JMP l30
+    JMP l33
+l30: GETTABUP 13 0 3
+    MOVE 14 12 0
+    CALL 13 2 1
+l33: FORLOOP 9 l25
+l34: RETURN 9 1 1 1
+l35:
+    .function get
+        .upval t 1 0 1
+        GETUPVAL 0 0 0
+        RETURN1 0 0 0
+        RETURN0 0 0 0
+    .end
+.end
+"##;
+
+    // `n - 1` and `n << 1` use the immediates of `+` and `>>`, `MMBINI` telling them apart
+    assert_eq!(
+        decompile(source, LuaVersion::Lua54),
+        r##"local t <const> = {}
+local n = 10
+local f <close> = setmetatable({}, mt)
+local x = n + 1
+local y = n - 1
+local z = 2 * n
+local w = n << 1
+local s = 1 << n
+local function get()
+    return t
+end
+for i = 1, n do
+    if i % 2 ~= 0 then
+        print(i)
+    end
+end
+"##
+    );
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_decompile_loops_and_conditions() {
+    let source = r##"
+.function main
+    .vararg
+    .stack 16
+    .upval _ENV 1 0
+    .const "pairs"
+    .const "print"
+    .const "a"
+    .const "b"
+    .const "c"
+    .local t l3 l50
+    .local "(for state)" l6 l14
+    .local "(for state)" l6 l14
+    .local "(for state)" l6 l14
+    .local "(for state)" l6 l14
+    .local k l7 l12
+    .local v l7 l12
+    .local i l16 l50
+    .local a l34 l50
+    VARARGPREP 0 0 0
+    NEWTABLE 0 0 0
+    EXTRAARG 0
+l3: GETTABUP 1 0 0
+    MOVE 2 0 0
+    CALL 1 2 5
+l6: TFORPREP 1 l12
+l7: TEST 6 0 0 1
+    JMP l14
+    GETTABUP 7 0 1
+    MOVE 8 5 0
+    CALL 7 2 1
+l12: TFORCALL 1 0 2
+    TFORLOOP 1 l7
+l14: CLOSE 1 0 0
+    LOADI 1 0
+l16: LTI 1 137 0 0
+    JMP l21
+    ADDI 1 1 128
+    MMBINI 1 128 6 0
+    JMP l16
+l21: ADDI 1 1 126
+    MMBINI 1 128 7 0
+    EQI 1 127 0 0
+    JMP l21
+l25: ADDI 1 1 128
+    MMBINI 1 128 6 0
+    LTI 1 132 0 0
+    JMP l30
+    JMP l25
+l30: GTI 1 130 0 1
+    JMP l33
+    LFALSESKIP 2 0 0
+l33: LOADTRUE 2 0 0
+l34: EQI 1 128 0 0
+    JMP l40
+    GETTABUP 3 0 1
+    LOADK 4 2
+    CALL 3 2 1
+    JMP l49
+l40: EQI 1 129 0 0
+    JMP l46
+    GETTABUP 3 0 1
+    LOADK 4 3
+    CALL 3 2 1
+    JMP l49
+l46: GETTABUP 3 0 1
+    LOADK 4 4
+    CALL 3 2 1
+l49: RETURN 3 1 1 0
+l50:
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua54),
+        r##"local t = {}
+for k, v in pairs(t) do
+    if v then
+        break
+    end
+    print(k)
+end
+local i = 0
+while i < 10 do
+    i = i + 1
+end
+repeat
+    i = i - 1
+until i == 0
+while true do
+    i = i + 1
+    if not (i < 5) then
+        break
+    end
+end
+local a = i > 3
+if i == 1 then
+    print("a")
+elseif i == 2 then
+    print("b")
+else
+    print("c")
+end
+"##
+    );
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_decompile_goto_out_of_nested_loops() {
+    let source = r##"
+.function main
+    .vararg
+    .stack 16
+    .upval _ENV 1 0
+    .const "print"
+    .const "x"
+    .local "(for state)" l4 l14
+    .local "(for state)" l4 l14
+    .local "(for state)" l4 l14
+    .local i l5 l13
+    .local "(for state)" l8 l13
+    .local "(for state)" l8 l13
+    .local "(for state)" l8 l13
+    .local j l9 l12
+    VARARGPREP 0 0 0
+    LOADI 0 1
+    LOADI 1 3
+    LOADI 2 1
+l4: FORPREP 0 l14
+l5: LOADI 4 1
+    LOADI 5 3
+    LOADI 6 1
+l8: FORPREP 4 l13
+l9: EQ 3 7 0 0
+    JMP l12
+    JMP l14
+l12: FORLOOP 4 l9
+l13: FORLOOP 0 l5
+l14: GETTABUP 0 0 0
+    LOADK 1 1
+    CALL 0 2 1
+    RETURN 0 1 1 0
+.end
+"##;
+
+    // The jump out of both loops fits no statement and becomes a `goto`
+    assert_eq!(
+        decompile(source, LuaVersion::Lua54),
+        r##"for i = 1, 3 do
+    for j = 1, 3 do
+        if i == j then
+            goto label_15
+        end
+    end
+end
+::label_15::
+print("x")
+"##
+    );
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_decompile_closures_and_methods() {
+    let source = r##"
+.function main
+    .vararg
+    .stack 16
+    .upval _ENV 1 0
+    .const "name"
+    .const "x"
+    .const "greet"
+    .const 2
+    .local fact l2 l21
+    .local obj l8 l21
+    VARARGPREP 0 0 0
+    CLOSURE 0 fact
+l2: NEWTABLE 1 1 2
+    EXTRAARG 0
+    SETFIELD 1 0 1 1
+    LOADI 2 1
+    LOADI 3 2
+    SETLIST 1 2 0
+l8: CLOSURE 2 greet
+    SETFIELD 1 2 2
+    SELF 2 1 2 1
+    MOVE 4 0 0
+    LOADI 5 5
+    CALL 4 2 2
+    UNM 4 4 0
+    IDIVK 4 4 3
+    MMBINK 4 3 12 0
+    LEN 5 1 0
+    NOT 6 1 0
+    CALL 2 5 1
+    RETURN 2 1 1 0
+l21:
+    .function fact
+        .params 1
+        .stack 4
+        .upval fact 1 0
+        .local n 0 12
+        LEI 0 128 0 0
+        JMP l4
+        LOADI 1 1
+        RETURN1 1 0 0
+    l4: GETUPVAL 1 0 0
+        ADDI 2 0 126
+        MMBINI 0 128 7 0
+        CALL 1 2 2
+        MUL 1 0 1
+        MMBIN 0 1 8
+        RETURN1 1 0 0
+        RETURN0 0 0 0
+    .end
+    .function greet
+        .params 1
+        .vararg
+        .stack 8
+        .upval _ENV 0 0
+        .const "hi "
+        .const "name"
+        .const "print"
+        .local self 0 10
+        .local msg 4 10
+        VARARGPREP 1 0 0
+        LOADK 1 0
+        GETFIELD 2 0 1
+        CONCAT 1 2 0
+        GETTABUP 2 0 2
+        MOVE 3 1 0
+        VARARG 4 0 0
+        TAILCALL 2 0 0
+        RETURN 2 0 0
+        RETURN 2 1 2 1
+    .end
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua54),
+        r##"local function fact(n)
+    if n <= 1 then
+        return 1
+    end
+    return n * fact(n - 1)
+end
+local obj = {name = "x", 1, 2}
+function obj:greet(...)
+    local msg = "hi " .. self.name
+    return print(msg, ...)
+end
+obj:greet(-fact(5) // 2, #obj, not obj)
+"##
+    );
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_decompile_short_circuit_values() {
+    let source = r##"
+.function main
+    .vararg
+    .stack 16
+    .upval _ENV 1 0
+    .const 1.5
+    .const "s"
+    .local a l2 l19
+    .local b l2 l19
+    .local c l7 l19
+    .local d l11 l19
+    .local e l16 l19
+    .local f l17 l19
+    VARARGPREP 0 0 0
+    VARARG 0 0 3
+l2: TEST 0 0 0 0
+    JMP l6
+    TESTSET 2 1 0 1
+    JMP l7
+l6: LOADK 2 0
+l7: EQK 0 1 0 1
+    JMP l10
+    LFALSESKIP 3 0 0
+l10: LOADTRUE 3 0 0
+l11: NEWTABLE 4 0 1
+    EXTRAARG 0
+    MOVE 5 0 0
+    VARARG 6 0 0
+    SETLIST 4 0 0
+l16: LOADF 5 2
+l17: RETURN 2 3 1 1
+    RETURN 2 1 1 0
+l19:
+.end
+"##;
+
+    assert_eq!(
+        decompile(source, LuaVersion::Lua54),
+        r##"local a, b = ...
+local c = a and b or 1.5
+local d = a == "s"
+local e = {a, ...}
+local f = 2.0
+return c, d
+"##
+    );
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_decompile_keeps_deep_elseif_chains_as_gotos() {
+    // Each arm nests the next one, deeper than any chunk `luac` writes
+    let arms = 250;
+    let mut source = String::from(".function main\n    .params 1\n    .stack 2\n");
+    for arm in 1..=arms {
+        source += &format!("    .const {arm}\n");
+    }
+    for arm in 1..=arms {
+        source += &format!("a{arm}:\n    EQK 0 {} 0\n    JMP a{}\n", arm - 1, arm + 1);
+        source += &format!("    LOADK 1 {}\n    JMP done\n", arm - 1);
+    }
+    source += &format!("a{}:\ndone:\n    RETURN0 0 0 0\n.end\n", arms + 1);
+
+    let decompiled = decompile(&source, LuaVersion::Lua54);
+    assert!(decompiled.starts_with(
+        "if a0 ~= 1 then\n    goto label_5\nend\nlocal r1 = 1\ngoto label_1001\n::label_5::\n"
+    ));
+    assert!(decompiled.ends_with(
+        "::label_997::\nif a0 ~= 250 then\n    goto label_1001\nend\nlocal r1 = 250\n::label_1001::\n"
+    ));
+    assert_eq!(decompiled.lines().count(), 6 * arms - 1);
+}
+
+#[test]
+#[cfg(feature = "lua53")]
+fn test_decompile_is_only_supported_for_lua51_and_lua54() {
+    let source = r##"
+.function main
+    .vararg
+    RETURN 0 1 0
+.end
+"##;
+    let bytes = assemble_text(source, LuaVersion::Lua53).unwrap();
+
+    assert_eq!(
+        parse_as(&bytes, LuaVersion::Lua53).unwrap().decompile(),
+        None
+    );
+}
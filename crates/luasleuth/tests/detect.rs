@@ -0,0 +1,72 @@
+use luasleuth::{detect, types::LuaVersion, Confidence, Detection};
+
+fn high(version: LuaVersion) -> Option<Detection> {
+    Some(Detection {
+        version,
+        confidence: Confidence::High,
+    })
+}
+
+#[test]
+fn test_detects_bytecode_files() {
+    let files: [(&[u8], LuaVersion); 6] = [
+        (
+            include_bytes!("../../../data/bytecode/lua51.bin"),
+            LuaVersion::Lua51,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua52.bin"),
+            LuaVersion::Lua52,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua53.bin"),
+            LuaVersion::Lua53,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua54.bin"),
+            LuaVersion::Lua54,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/luajitv1.bin"),
+            LuaVersion::Luajitv1,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/luajitv2.bin"),
+            LuaVersion::Luajitv2,
+        ),
+    ];
+
+    for (bytes, version) in files {
+        assert_eq!(detect(bytes), high(version), "{:?}", version);
+    }
+}
+
+#[test]
+fn test_detection_confidence() {
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+
+    // Truncated right after the signature
+    let detection = detect(&bytes[..5]).unwrap();
+    assert_eq!(detection.version, LuaVersion::Lua54);
+    assert_eq!(detection.confidence, Confidence::Low);
+
+    // Mangled by a text-mode transfer
+    let mut mangled = bytes.to_vec();
+    mangled.remove(8);
+    let detection = detect(&mangled).unwrap();
+    assert_eq!(detection.confidence, Confidence::Medium);
+
+    // Unknown LuaJIT flags
+    assert_eq!(
+        detect(b"\x1bLJ\x02\x80").unwrap().confidence,
+        Confidence::Medium
+    );
+}
+
+#[test]
+fn test_rejects_unknown_formats() {
+    assert_eq!(detect(b""), None);
+    assert_eq!(detect(b"\x1bLua\x50"), None);
+    assert_eq!(detect(b"\x1bLJ\x03"), None);
+    assert_eq!(detect(b"print('hello')"), None);
+}
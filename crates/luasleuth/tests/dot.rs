@@ -0,0 +1,112 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(dead_code)
+)]
+
+use luasleuth::{assemble_text, parse_as, types::LuaVersion};
+
+fn dot(source: &str, version: LuaVersion) -> String {
+    let bytes = assemble_text(source, version).unwrap();
+    parse_as(&bytes, version).unwrap().to_dot()
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_dot_has_a_cluster_per_function() {
+    let source = r#"
+.function main
+    .stack 4
+    .const 1
+    CLOSURE 0 inner
+    EQ 1 0 -1
+    JMP 0 done
+    LOADNIL 1 1 0
+done:
+    RETURN 0 1 0
+    .function inner
+        .lines 1 3
+        .stack 2
+        RETURN 0 1 0
+    .end
+.end
+"#;
+    let dot = dot(source, LuaVersion::Lua51);
+
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("subgraph cluster_0 {\n        label=\"main <?:0,0>\";\n"));
+    assert!(dot.contains("subgraph cluster_1 {\n        label=\"function <?:1,3>\";\n"));
+    assert!(dot.contains("f0b0 -> f0b1 [label=\"true\"];"));
+    assert!(dot.contains("f0b0 -> f0b2 [label=\"false\"];"));
+    assert!(dot.contains("f0b1 -> f0b3 [label=\"jump\"];"));
+    assert!(dot.contains("f0b2 -> f0b3 [label=\"fallthrough\"];"));
+    // Block text is the listing, its tabs expanded and lines left aligned
+    assert!(dot.contains("JMP             1       ; to 5\\l\"];"));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_dot_labels_loop_back_edges() {
+    let source = r#"
+.function main
+    .vararg
+    .stack 4
+    LOADI 0 1
+    LOADI 1 3
+    LOADI 2 1
+    FORPREP 0 done
+top:
+    MOVE 3 0 0
+    FORLOOP 0 top
+done:
+    RETURN 0 1 1
+.end
+"#;
+    let dot = dot(source, LuaVersion::Lua54);
+
+    assert!(dot.contains("f0b1 -> f0b1 [label=\"loop-back\"];"));
+    assert!(dot.contains("f0b1 -> f0b2 [label=\"false\"];"));
+    assert!(dot.contains("f0b0 -> f0b2 [label=\"false\"];"));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_dot_largest_extra_arguments() {
+    let source = r#"
+.function main
+    .stack 2
+    NEWTABLE 0 0 0 1
+    EXTRAARG 33554431
+    RETURN0 0 0 0
+.end
+"#;
+    let dot = dot(source, LuaVersion::Lua54);
+
+    assert!(dot.contains("NEWTABLE        0 0 0   ; 8589934336\\l"));
+}
+
+#[test]
+#[cfg(feature = "luajit")]
+fn test_luajit_dot_marks_unreachable_blocks() {
+    let source = r#"
+.function main
+    .stack 2
+    JMP 1 done
+    KPRI 0 2
+done:
+    RET0 0 1
+.end
+"#;
+    let dot = dot(source, LuaVersion::Luajitv2);
+
+    assert!(dot.contains("label=\"?:0\";"));
+    assert!(dot.contains("f0b1 [label=\"0002    KPRI     0   2\\l\", style=dashed];"));
+    assert!(dot.contains("f0b0 -> f0b2 [label=\"jump\"];"));
+    assert!(dot.contains("0003 => RET0"));
+}
@@ -0,0 +1,219 @@
+#![cfg(feature = "serde")]
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(unused_imports, irrefutable_let_patterns)
+)]
+
+use luasleuth::{
+    assemble_json, assemble_text, common::types::LuaString, parse_as, types::LuaVersion,
+    AnyBytecode, AssembleError,
+};
+use serde_json::{json, Value};
+
+fn document(bytes: &[u8], version: LuaVersion) -> Value {
+    let json = parse_as(bytes, version).unwrap().to_json().unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_json() {
+    let document = document(
+        include_bytes!("../../../data/bytecode/lua51.bin"),
+        LuaVersion::Lua51,
+    );
+
+    assert_eq!(document["version"], "lua51");
+    let prototype = &document["bytecode"]["prototype"];
+    assert_eq!(prototype["code"]["size"], 4);
+    assert_eq!(
+        prototype["code"]["data"][0],
+        json!({ "iABx": ["OP_GETGLOBAL", 0, 0] })
+    );
+    assert_eq!(
+        prototype["constants"]["data"][1],
+        json!({ "String": { "size": 13, "data": "Hello, World!" } })
+    );
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_json() {
+    let document = document(
+        include_bytes!("../../../data/bytecode/lua54.bin"),
+        LuaVersion::Lua54,
+    );
+
+    assert_eq!(document["version"], "lua54");
+    let bytecode = &document["bytecode"];
+    assert_eq!(bytecode["header"]["endianness"], "little");
+    assert_eq!(
+        bytecode["header"]["version"],
+        json!({ "major": 5, "minor": 4 })
+    );
+
+    let prototype = &bytecode["prototype"];
+    assert_eq!(prototype["line_defined"], 0);
+    assert_eq!(prototype["source"]["data"], "@.\\example.lua");
+    assert_eq!(
+        prototype["instructions"]["data"][0],
+        json!({ "iABC": ["OP_VARARGPREP", 0, 0, 0, 0] })
+    );
+    assert_eq!(
+        prototype["debug_info"]["upvalues"]["data"][0]["data"],
+        "_ENV"
+    );
+}
+
+#[test]
+#[cfg(feature = "luajit")]
+fn test_luajit_json() {
+    let document = document(
+        include_bytes!("../../../data/bytecode/luajitv2.bin"),
+        LuaVersion::Luajitv2,
+    );
+
+    assert_eq!(document["version"], "luajitv2");
+    let bytecode = &document["bytecode"];
+    assert_eq!(bytecode["release"], "LuaJit21");
+    assert!(bytecode.get("context").is_none());
+
+    let prototype = &bytecode["prototype"];
+    assert_eq!(
+        prototype["instructions"][1],
+        json!({ "AD": ["KSTR", 2, 1] })
+    );
+    assert_eq!(
+        prototype["gc_constants"][1],
+        json!({ "String": { "size": 5, "data": "print" } })
+    );
+}
+
+#[test]
+fn test_strings_that_are_not_utf8_are_bytes() {
+    let string = LuaString::from(&b"a\xff"[..]);
+    assert_eq!(
+        serde_json::to_value(string).unwrap(),
+        json!({ "size": 2, "data": [97, 255] })
+    );
+}
+
+#[test]
+#[cfg(all(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
+fn test_json_round_trips_to_identical_bytes() {
+    let files: [(&[u8], LuaVersion); 6] = [
+        (
+            include_bytes!("../../../data/bytecode/lua51.bin"),
+            LuaVersion::Lua51,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua52.bin"),
+            LuaVersion::Lua52,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua53.bin"),
+            LuaVersion::Lua53,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua54.bin"),
+            LuaVersion::Lua54,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/luajitv1.bin"),
+            LuaVersion::Luajitv1,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/luajitv2.bin"),
+            LuaVersion::Luajitv2,
+        ),
+    ];
+
+    for (bytes, version) in files {
+        let json = parse_as(bytes, version).unwrap().to_json().unwrap();
+        assert_eq!(assemble_json(&json).unwrap(), bytes, "{:?}", version);
+    }
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_edited_json_rederives_sizes() {
+    use luasleuth::lua54::types::constants::Constant;
+
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+    let mut document = document(bytes, LuaVersion::Lua54);
+
+    // Sizes are left stale or removed, as someone editing the document by hand would
+    let constants = &mut document["bytecode"]["prototype"]["constants"];
+    constants["data"][1]["String"] = json!({ "size": 13, "data": "Hallo, Welt! \u{1F30D}" });
+    constants["data"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!({ "String": { "data": [0xff, 0x00] } }));
+    constants.as_object_mut().unwrap().remove("size");
+
+    let built = assemble_json(&document.to_string()).unwrap();
+    let AnyBytecode::Lua54(bytecode) = parse_as(&built, LuaVersion::Lua54).unwrap() else {
+        unreachable!();
+    };
+
+    let constants = &bytecode.prototype.constants;
+    assert_eq!(constants.size, 3);
+    let Constant::String(greeting) = &constants.data[1] else {
+        panic!("Expected a string, got {:?}", constants.data[1]);
+    };
+    assert_eq!(greeting.as_str(), Some("Hallo, Welt! \u{1F30D}"));
+    assert_eq!(greeting.size, 17);
+    let Constant::String(binary) = &constants.data[2] else {
+        panic!("Expected a string, got {:?}", constants.data[2]);
+    };
+    assert_eq!(binary.as_bytes(), b"\xff\x00");
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_non_finite_numbers_round_trip() {
+    let source = r#"
+.function main
+    .const inf
+    .const -inf
+    .const nan
+    .const -nan
+    RETURN0 0 0 0
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua54).unwrap();
+    let json = parse_as(&bytes, LuaVersion::Lua54)
+        .unwrap()
+        .to_json()
+        .unwrap();
+
+    let document: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        document["bytecode"]["prototype"]["constants"]["data"],
+        json!([
+            { "Float": "inf" },
+            { "Float": "-inf" },
+            { "Float": "nan" },
+            { "Float": "0xfff8000000000000" }
+        ])
+    );
+    assert_eq!(assemble_json(&json).unwrap(), bytes);
+}
+
+#[test]
+fn test_invalid_json_is_reported() {
+    let error = assemble_json(r#"{ "version": "lua55", "bytecode": {} }"#).unwrap_err();
+    assert!(matches!(error, AssembleError::Json(_)), "{:?}", error);
+}
@@ -0,0 +1,144 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(unused_imports)
+)]
+
+use luasleuth::{assemble_text, parse_as, types::LuaVersion};
+
+fn listing(bytes: &[u8], version: LuaVersion) -> String {
+    parse_as(bytes, version).unwrap().listing()
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_listing() {
+    let listing = listing(
+        include_bytes!("../../../data/bytecode/lua51.bin"),
+        LuaVersion::Lua51,
+    );
+
+    assert!(listing.starts_with("\nmain <.\\example.lua:0,0> (4 instructions, 16 bytes)\n"));
+    assert!(
+        listing.contains("\n0+ params, 2 slots, 0 upvalues, 0 locals, 2 constants, 0 functions\n")
+    );
+    assert!(listing.contains("\t1\t[1]\tGETGLOBAL\t0 -1\t; print\n"));
+    assert!(listing.contains("\t2\t[1]\tLOADK    \t1 -2\t; \"Hello, World!\"\n"));
+    assert!(listing.contains("\t4\t[1]\tRETURN   \t0 1\n"));
+    assert!(listing.contains("constants (2):\n\t1\t\"print\"\n\t2\t\"Hello, World!\"\n"));
+}
+
+#[test]
+#[cfg(feature = "lua52")]
+fn test_lua52_listing() {
+    let listing = listing(
+        include_bytes!("../../../data/bytecode/lua52.bin"),
+        LuaVersion::Lua52,
+    );
+
+    assert!(listing.starts_with("\nmain <.\\example.lua:0,0> (4 instructions)\n"));
+    assert!(listing.contains("\t1\t[1]\tGETTABUP \t0 0 -1\t; _ENV \"print\"\n"));
+    assert!(listing.contains("upvalues (1):\n\t0\t_ENV\t1\t0\n"));
+}
+
+#[test]
+#[cfg(feature = "lua53")]
+fn test_lua53_listing() {
+    let listing = listing(
+        include_bytes!("../../../data/bytecode/lua53.bin"),
+        LuaVersion::Lua53,
+    );
+
+    assert!(listing.contains("\t1\t[1]\tGETTABUP \t0 0 -1\t; _ENV \"print\"\n"));
+    assert!(listing.contains("\t3\t[1]\tCALL     \t0 2 1\n"));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_listing() {
+    let listing = listing(
+        include_bytes!("../../../data/bytecode/lua54.bin"),
+        LuaVersion::Lua54,
+    );
+
+    assert!(listing.starts_with("\nmain <.\\example.lua:0,0> (5 instructions)\n"));
+    assert!(listing.contains("\t1\t[1]\tVARARGPREP\t0\n"));
+    assert!(listing.contains("\t2\t[1]\tGETTABUP \t0 0 0\t; _ENV \"print\"\n"));
+    assert!(listing.contains("\t4\t[1]\tCALL     \t0 2 1\t; 1 in 0 out\n"));
+    assert!(listing.contains("constants (2):\n\t0\tS\t\"print\"\n\t1\tS\t\"Hello, World!\"\n"));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_listing_nested_functions() {
+    let source = r#"
+.function main
+    .source "@loops.lua"
+    .vararg
+    .line 1
+    CLOSURE 0 inner
+    FORPREP 0 done
+top:
+    FORLOOP 0 top
+done:
+    RETURN 0 1 1
+
+    .function inner
+        .lines 2 3
+        .params 1
+        RETURN0 0 0 0
+    .end
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua54).unwrap();
+    let listing = listing(&bytes, LuaVersion::Lua54);
+
+    assert!(listing.contains("\t1\t[1]\tCLOSURE  \t0 0\t; <loops.lua:2,3>\n"));
+    assert!(listing.contains("\t2\t[1]\tFORPREP  \t0 0\t; exit to 4\n"));
+    assert!(listing.contains("\t3\t[1]\tFORLOOP  \t0 1\t; to 3\n"));
+    assert!(listing.contains("\nfunction <loops.lua:2,3> (1 instruction)\n1 param, "));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_listing_largest_extra_arguments() {
+    let source = r#"
+.function main
+    .stack 2
+    NEWTABLE 0 0 0 1
+    EXTRAARG 33554431
+    SETLIST 0 0 0 1
+    EXTRAARG 33554431
+    RETURN0 0 0 0
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua54).unwrap();
+    let listing = listing(&bytes, LuaVersion::Lua54);
+
+    // `C` continues in the 25 bits of `Ax`, past what a `u32` holds
+    assert!(listing.contains("\t1\t[-]\tNEWTABLE \t0 0 0\t; 8589934336\n"));
+    assert!(listing.contains("\t3\t[-]\tSETLIST  \t0 0 0\t; 8589934336\n"));
+}
+
+#[test]
+#[cfg(feature = "luajit")]
+fn test_luajit_listing() {
+    let v1 = listing(
+        include_bytes!("../../../data/bytecode/luajitv1.bin"),
+        LuaVersion::Luajitv1,
+    );
+    let v2 = listing(
+        include_bytes!("../../../data/bytecode/luajitv2.bin"),
+        LuaVersion::Luajitv2,
+    );
+
+    assert!(v1.starts_with("-- BYTECODE -- ?:0-0\n"));
+    assert!(v1.contains("0001    GGET     0   0      ; \"print\"\n"));
+    assert!(v2.contains("0002    KSTR     2   1      ; \"Hello, World!\"\n"));
+    assert!(v2.contains("0004    RET0     0   1\n"));
+}
@@ -0,0 +1,75 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(unused_imports)
+)]
+
+use luasleuth::{parse, parse_as, types::LuaVersion, AnyBytecode, ParseError};
+
+#[test]
+#[cfg(all(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
+fn test_parses_mixed_corpus() {
+    let files: [(&[u8], LuaVersion); 6] = [
+        (
+            include_bytes!("../../../data/bytecode/lua51.bin"),
+            LuaVersion::Lua51,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua52.bin"),
+            LuaVersion::Lua52,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua53.bin"),
+            LuaVersion::Lua53,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/lua54.bin"),
+            LuaVersion::Lua54,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/luajitv1.bin"),
+            LuaVersion::Luajitv1,
+        ),
+        (
+            include_bytes!("../../../data/bytecode/luajitv2.bin"),
+            LuaVersion::Luajitv2,
+        ),
+    ];
+
+    for (bytes, version) in files {
+        let bytecode = parse(bytes).expect("Failed to parse bytecode");
+        assert_eq!(bytecode.version(), version);
+    }
+
+    let bytes = include_bytes!("../../../data/bytecode/lua51.bin");
+    match parse(bytes).unwrap() {
+        AnyBytecode::Lua51(bytecode) => assert_eq!(bytecode.prototype.max_stack_size, 2),
+        other => panic!("Expected Lua 5.1 bytecode, got {:?}", other.version()),
+    }
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_parse_errors() {
+    assert!(matches!(
+        parse(b"not bytecode"),
+        Err(ParseError::UnknownFormat)
+    ));
+
+    let bytes = include_bytes!("../../../data/bytecode/lua54.bin");
+    assert!(matches!(
+        parse_as(bytes, LuaVersion::Lua51),
+        Err(ParseError::Malformed(_))
+    ));
+}
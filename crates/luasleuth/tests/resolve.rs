@@ -0,0 +1,304 @@
+#![cfg_attr(
+    not(all(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    )),
+    allow(dead_code, unused_imports, irrefutable_let_patterns)
+)]
+
+use luasleuth::{
+    assemble_text, common::resolve::ResolvedOperand, parse_as, types::LuaVersion, AnyBytecode,
+};
+
+fn parse(bytes: &[u8], version: LuaVersion) -> AnyBytecode<'_> {
+    parse_as(bytes, version).unwrap()
+}
+
+#[test]
+#[cfg(feature = "lua51")]
+fn test_lua51_resolves_rk_operands_locals_and_upvalues() {
+    use luasleuth::lua51::{resolve::resolve, types::constants::Constant};
+
+    let source = r#"
+.function main
+    .upval counter
+    .const "print"
+    .const 2
+    .local x 1 4
+    GETUPVAL 0 0 0
+    ADD 0 0 -2
+    JMP 0 done
+    GETGLOBAL 1 -1
+done:
+    RETURN 0 1 0
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua51).unwrap();
+    let AnyBytecode::Lua51(bytecode) = parse(&bytes, LuaVersion::Lua51) else {
+        panic!("Expected a Lua 5.1 chunk");
+    };
+    let prototype = &bytecode.prototype;
+
+    let operands = resolve(prototype, 0).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register {
+                index: 0,
+                name: None
+            },
+            ResolvedOperand::Upvalue {
+                index: 0,
+                name: Some(b"counter")
+            },
+        ]
+    ));
+
+    let operands = resolve(prototype, 1).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register {
+                index: 0,
+                name: Some(b"x")
+            },
+            ResolvedOperand::Register {
+                index: 0,
+                name: Some(b"x")
+            },
+            ResolvedOperand::Constant {
+                index: 1,
+                value: Some(Constant::Number(2.0))
+            },
+        ]
+    ));
+
+    let operands = resolve(prototype, 2).unwrap();
+    assert!(matches!(
+        operands[..],
+        [ResolvedOperand::Jump { target: 4 }]
+    ));
+
+    let operands = resolve(prototype, 3).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register { index: 1, name: None },
+            ResolvedOperand::Constant { index: 0, value: Some(Constant::String(ref name)) },
+        ] if name.as_bytes() == b"print"
+    ));
+
+    assert!(resolve(prototype, 5).is_none());
+}
+
+#[test]
+#[cfg(all(feature = "lua52", feature = "lua53"))]
+fn test_lua52_and_lua53_resolve_table_upvalues() {
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/lua52.bin"),
+        LuaVersion::Lua52,
+    );
+    let AnyBytecode::Lua52(bytecode) = bytecode else {
+        panic!("Expected a Lua 5.2 chunk");
+    };
+    let operands = luasleuth::lua52::resolve::resolve(&bytecode.prototype, 0).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register { index: 0, .. },
+            ResolvedOperand::Upvalue {
+                index: 0,
+                name: Some(b"_ENV")
+            },
+            ResolvedOperand::Constant {
+                index: 0,
+                value: Some(_)
+            },
+        ]
+    ));
+
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/lua53.bin"),
+        LuaVersion::Lua53,
+    );
+    let AnyBytecode::Lua53(bytecode) = bytecode else {
+        panic!("Expected a Lua 5.3 chunk");
+    };
+    let operands = luasleuth::lua53::resolve::resolve(&bytecode.prototype, 2).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register { index: 0, .. },
+            ResolvedOperand::Literal(2),
+            ResolvedOperand::Literal(1),
+        ]
+    ));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_resolves_k_bit_and_signed_operands() {
+    use luasleuth::lua54::{resolve::resolve, types::constants::Constant};
+
+    let source = r#"
+.function main
+    .vararg
+    .upval _ENV 1 0
+    .const "x"
+    .const 42
+    .local t 1 8
+    VARARGPREP 0 0 0
+    NEWTABLE 0 0 0 0
+    EXTRAARG 0
+    SETFIELD 0 0 1 1
+    SETFIELD 0 0 1 0
+    ADDI 1 0 126
+    JMP done
+    LOADI 1 -5
+done:
+    RETURN 0 1 1
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua54).unwrap();
+    let AnyBytecode::Lua54(bytecode) = parse(&bytes, LuaVersion::Lua54) else {
+        panic!("Expected a Lua 5.4 chunk");
+    };
+    let prototype = &bytecode.prototype;
+
+    let operands = resolve(prototype, 3).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register {
+                index: 0,
+                name: Some(b"t")
+            },
+            ResolvedOperand::Constant {
+                index: 0,
+                value: Some(Constant::String(_))
+            },
+            ResolvedOperand::Constant {
+                index: 1,
+                value: Some(Constant::Integer(42))
+            },
+        ]
+    ));
+
+    let operands = resolve(prototype, 4).unwrap();
+    assert!(matches!(
+        operands[2],
+        ResolvedOperand::Register {
+            index: 1,
+            name: None
+        }
+    ));
+
+    let operands = resolve(prototype, 5).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register { index: 1, .. },
+            ResolvedOperand::Register {
+                index: 0,
+                name: Some(b"t")
+            },
+            ResolvedOperand::Literal(-1),
+        ]
+    ));
+
+    let operands = resolve(prototype, 6).unwrap();
+    assert!(matches!(
+        operands[..],
+        [ResolvedOperand::Jump { target: 8 }]
+    ));
+
+    let operands = resolve(prototype, 7).unwrap();
+    assert!(matches!(operands[1], ResolvedOperand::Literal(-5)));
+}
+
+#[test]
+#[cfg(feature = "lua54")]
+fn test_lua54_resolves_loop_targets() {
+    let source = r#"
+.function main
+    .vararg
+    FORPREP 0 done
+top:
+    FORLOOP 0 top
+done:
+    RETURN 0 1 1
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Lua54).unwrap();
+    let AnyBytecode::Lua54(bytecode) = parse(&bytes, LuaVersion::Lua54) else {
+        panic!("Expected a Lua 5.4 chunk");
+    };
+    let resolve = |pc| luasleuth::lua54::resolve::resolve(&bytecode.prototype, pc).unwrap();
+
+    assert!(matches!(resolve(0)[1], ResolvedOperand::Jump { target: 2 }));
+    assert!(matches!(resolve(1)[1], ResolvedOperand::Jump { target: 1 }));
+}
+
+#[test]
+#[cfg(feature = "luajit")]
+fn test_luajit_resolves_gc_constants_and_jumps() {
+    use luasleuth::luajit::v2::{resolve::Constant as JitConstant, types::constants::GcConstant};
+
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/luajitv2.bin"),
+        LuaVersion::Luajitv2,
+    );
+    let AnyBytecode::LuaJitV2(bytecode) = bytecode else {
+        panic!("Expected a LuaJIT v2 chunk");
+    };
+    let operands = luasleuth::luajit::v2::resolve::resolve(&bytecode.prototype, 0).unwrap();
+    assert!(matches!(
+        operands[..],
+        [
+            ResolvedOperand::Register { index: 0, .. },
+            ResolvedOperand::Constant {
+                index: 0,
+                value: Some(JitConstant::Gc(GcConstant::String(ref name))),
+            },
+        ] if name.as_bytes() == b"print"
+    ));
+
+    let bytecode = parse(
+        include_bytes!("../../../data/bytecode/luajitv1.bin"),
+        LuaVersion::Luajitv1,
+    );
+    let AnyBytecode::LuaJitV1(bytecode) = bytecode else {
+        panic!("Expected a LuaJIT v1 chunk");
+    };
+    let operands = luasleuth::luajit::v1::resolve::resolve(&bytecode.prototype, 0).unwrap();
+    assert!(matches!(
+        operands[1],
+        ResolvedOperand::Constant {
+            index: 0,
+            value: Some(JitConstant::Gc(_))
+        }
+    ));
+
+    let source = r#"
+.function main
+    .stack 2
+    KSHORT 0 65533
+    JMP 1 done
+    KPRI 0 2
+done:
+    RET0 0 1
+.end
+"#;
+    let bytes = assemble_text(source, LuaVersion::Luajitv2).unwrap();
+    let AnyBytecode::LuaJitV2(bytecode) = parse(&bytes, LuaVersion::Luajitv2) else {
+        panic!("Expected a LuaJIT v2 chunk");
+    };
+    let resolve = |pc| luasleuth::luajit::v2::resolve::resolve(&bytecode.prototype, pc).unwrap();
+
+    assert!(matches!(resolve(0)[1], ResolvedOperand::Literal(-3)));
+    assert!(matches!(resolve(1)[1], ResolvedOperand::Jump { target: 3 }));
+    assert!(matches!(resolve(2)[1], ResolvedOperand::Literal(2)));
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use luasleuth_common::disassembler::Disassemble;
+use luasleuth_lua52::disassembler::Disassembler;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Disassembler::new(data).disassemble();
+});
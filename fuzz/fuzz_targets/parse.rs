@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Goes through format detection, like the CLI does for user supplied files
+fuzz_target!(|data: &[u8]| {
+    let _ = luasleuth::parse(data);
+});